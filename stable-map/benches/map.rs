@@ -0,0 +1,314 @@
+use {
+    criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion},
+    hashbrown::HashMap,
+    indexmap::IndexMap,
+    slotmap::{DefaultKey, SlotMap},
+    stable_map::StableMap,
+    std::hint::black_box,
+};
+
+const SIZE: usize = 10_000;
+
+/// Percentages of entries removed before the measured operation.
+const FRAGMENTATION: [usize; 3] = [0, 50, 90];
+
+fn is_removed(i: usize, fragmentation: usize) -> bool {
+    i % 100 < fragmentation
+}
+
+fn stable_map(fragmentation: usize) -> StableMap<usize, usize> {
+    let mut map = StableMap::with_capacity(SIZE);
+    for i in 0..SIZE {
+        map.insert(i, i);
+    }
+    for i in 0..SIZE {
+        if is_removed(i, fragmentation) {
+            map.remove(&i);
+        }
+    }
+    map
+}
+
+fn hash_map(fragmentation: usize) -> HashMap<usize, usize> {
+    let mut map = HashMap::with_capacity(SIZE);
+    for i in 0..SIZE {
+        map.insert(i, i);
+    }
+    for i in 0..SIZE {
+        if is_removed(i, fragmentation) {
+            map.remove(&i);
+        }
+    }
+    map
+}
+
+fn index_map(fragmentation: usize) -> IndexMap<usize, usize> {
+    let mut map = IndexMap::with_capacity(SIZE);
+    for i in 0..SIZE {
+        map.insert(i, i);
+    }
+    for i in 0..SIZE {
+        if is_removed(i, fragmentation) {
+            map.swap_remove(&i);
+        }
+    }
+    map
+}
+
+fn slot_map(fragmentation: usize) -> (SlotMap<DefaultKey, usize>, Vec<DefaultKey>) {
+    let mut map = SlotMap::with_capacity(SIZE);
+    let mut keys = Vec::with_capacity(SIZE);
+    for i in 0..SIZE {
+        keys.push(map.insert(i));
+    }
+    for (i, key) in keys.iter().enumerate() {
+        if is_removed(i, fragmentation) {
+            map.remove(*key);
+        }
+    }
+    (map, keys)
+}
+
+fn insert(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert");
+    for fragmentation in FRAGMENTATION {
+        group.bench_with_input(
+            BenchmarkId::new("StableMap", fragmentation),
+            &fragmentation,
+            |b, &f| {
+                b.iter_batched_ref(
+                    || stable_map(f),
+                    |map| {
+                        for i in SIZE..2 * SIZE {
+                            map.insert(i, i);
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("HashMap", fragmentation),
+            &fragmentation,
+            |b, &f| {
+                b.iter_batched_ref(
+                    || hash_map(f),
+                    |map| {
+                        for i in SIZE..2 * SIZE {
+                            map.insert(i, i);
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("IndexMap", fragmentation),
+            &fragmentation,
+            |b, &f| {
+                b.iter_batched_ref(
+                    || index_map(f),
+                    |map| {
+                        for i in SIZE..2 * SIZE {
+                            map.insert(i, i);
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("SlotMap", fragmentation),
+            &fragmentation,
+            |b, &f| {
+                b.iter_batched_ref(
+                    || slot_map(f).0,
+                    |map| {
+                        for i in SIZE..2 * SIZE {
+                            map.insert(i);
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup");
+    for fragmentation in FRAGMENTATION {
+        let map = stable_map(fragmentation);
+        group.bench_function(BenchmarkId::new("StableMap", fragmentation), |b| {
+            b.iter(|| {
+                for i in 0..SIZE {
+                    black_box(map.get(&i));
+                }
+            })
+        });
+        let map = hash_map(fragmentation);
+        group.bench_function(BenchmarkId::new("HashMap", fragmentation), |b| {
+            b.iter(|| {
+                for i in 0..SIZE {
+                    black_box(map.get(&i));
+                }
+            })
+        });
+        let map = index_map(fragmentation);
+        group.bench_function(BenchmarkId::new("IndexMap", fragmentation), |b| {
+            b.iter(|| {
+                for i in 0..SIZE {
+                    black_box(map.get(&i));
+                }
+            })
+        });
+        let (map, keys) = slot_map(fragmentation);
+        group.bench_function(BenchmarkId::new("SlotMap", fragmentation), |b| {
+            b.iter(|| {
+                for key in &keys {
+                    black_box(map.get(*key));
+                }
+            })
+        });
+    }
+    group.finish();
+}
+
+fn remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("remove");
+    for fragmentation in FRAGMENTATION {
+        group.bench_with_input(
+            BenchmarkId::new("StableMap", fragmentation),
+            &fragmentation,
+            |b, &f| {
+                b.iter_batched_ref(
+                    || stable_map(f),
+                    |map| {
+                        for i in 0..SIZE {
+                            black_box(map.remove(&i));
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("HashMap", fragmentation),
+            &fragmentation,
+            |b, &f| {
+                b.iter_batched_ref(
+                    || hash_map(f),
+                    |map| {
+                        for i in 0..SIZE {
+                            black_box(map.remove(&i));
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("IndexMap", fragmentation),
+            &fragmentation,
+            |b, &f| {
+                b.iter_batched_ref(
+                    || index_map(f),
+                    |map| {
+                        for i in 0..SIZE {
+                            black_box(map.swap_remove(&i));
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("SlotMap", fragmentation),
+            &fragmentation,
+            |b, &f| {
+                b.iter_batched_ref(
+                    || slot_map(f),
+                    |(map, keys)| {
+                        for key in keys.iter() {
+                            black_box(map.remove(*key));
+                        }
+                    },
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+fn iterate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iterate");
+    for fragmentation in FRAGMENTATION {
+        let map = stable_map(fragmentation);
+        group.bench_function(BenchmarkId::new("StableMap", fragmentation), |b| {
+            b.iter(|| map.values().copied().sum::<usize>())
+        });
+        group.bench_function(BenchmarkId::new("StableMap/index", fragmentation), |b| {
+            b.iter(|| {
+                (0..map.index_len())
+                    .filter_map(|i| map.get_by_index(i))
+                    .copied()
+                    .sum::<usize>()
+            })
+        });
+        let map = hash_map(fragmentation);
+        group.bench_function(BenchmarkId::new("HashMap", fragmentation), |b| {
+            b.iter(|| map.values().copied().sum::<usize>())
+        });
+        let map = index_map(fragmentation);
+        group.bench_function(BenchmarkId::new("IndexMap", fragmentation), |b| {
+            b.iter(|| map.values().copied().sum::<usize>())
+        });
+        let (map, _) = slot_map(fragmentation);
+        group.bench_function(BenchmarkId::new("SlotMap", fragmentation), |b| {
+            b.iter(|| map.values().copied().sum::<usize>())
+        });
+    }
+    group.finish();
+}
+
+fn compaction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compaction");
+    for fragmentation in FRAGMENTATION {
+        group.bench_with_input(
+            BenchmarkId::new("StableMap", fragmentation),
+            &fragmentation,
+            |b, &f| {
+                b.iter_batched_ref(
+                    || stable_map(f),
+                    |map| map.force_compact(),
+                    BatchSize::LargeInput,
+                )
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Growing and cloning a map with long string keys. Compare the results with and without
+/// the `cached-hash` feature.
+fn rehash(c: &mut Criterion) {
+    let keys: Vec<_> = (0..SIZE).map(|i| format!("{i:0>64}")).collect();
+    let mut group = c.benchmark_group("rehash");
+    group.bench_function("grow", |b| {
+        b.iter(|| {
+            let mut map = StableMap::new();
+            for (i, key) in keys.iter().enumerate() {
+                map.insert(key.as_str(), i);
+            }
+            map
+        })
+    });
+    let map: StableMap<_, _> = keys.iter().cloned().zip(0..SIZE).collect();
+    group.bench_function("clone", |b| b.iter(|| black_box(&map).clone()));
+    group.finish();
+}
+
+criterion_group!(benches, insert, lookup, remove, iterate, compaction, rehash);
+criterion_main!(benches);
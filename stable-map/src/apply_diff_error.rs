@@ -0,0 +1,66 @@
+use {
+    crate::diff_index_error::DiffIndexError,
+    core::fmt::{Display, Formatter},
+};
+
+/// The error returned by [`apply_diff`](crate::StableMap::apply_diff) when the change
+/// set cannot be applied.
+///
+/// In either case, the map is left unchanged.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{ApplyDiffError, DiffEntry, MapDiff, StableMap};
+///
+/// let mut map = StableMap::with_max_len(1);
+/// map.insert(1, "a");
+///
+/// let diff = MapDiff {
+///     added: vec![DiffEntry {
+///         index: 1,
+///         key: 2,
+///         value: "b",
+///     }],
+///     removed: vec![],
+///     changed: vec![],
+/// };
+/// let res = map.apply_diff(diff);
+/// assert_eq!(res, Err(ApplyDiffError::MaxLen { len: 2, max_len: 1 }));
+/// assert_eq!(map.len(), 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyDiffError {
+    /// The change set records an index that is too large.
+    Index(DiffIndexError),
+    /// Applying the change set would increase the number of elements beyond
+    /// [max_len](crate::StableMap::max_len).
+    MaxLen {
+        /// The number of elements the map would contain after applying the change set.
+        len: usize,
+        /// The maximum number of elements in the map.
+        max_len: usize,
+    },
+}
+
+impl Display for ApplyDiffError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ApplyDiffError::Index(e) => e.fmt(f),
+            ApplyDiffError::MaxLen { len, max_len } => {
+                write!(
+                    f,
+                    "the change set would increase the length of the map to {} but the \
+                     maximum length is {}",
+                    len, max_len,
+                )
+            }
+        }
+    }
+}
+
+impl From<DiffIndexError> for ApplyDiffError {
+    fn from(value: DiffIndexError) -> Self {
+        Self::Index(value)
+    }
+}
@@ -0,0 +1,717 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::{
+        linear_storage::LinearStorage,
+        pos_vec::pos::{InUse, Pos},
+    },
+    alloc::collections::{btree_map, BTreeMap},
+    core::{
+        borrow::Borrow,
+        fmt::{Debug, Formatter},
+        mem,
+        ops::{Index, RangeBounds},
+    },
+};
+
+/// An ordered map with temporarily-stable indices.
+///
+/// This is the ordered counterpart of [`StableMap`](crate::StableMap). It splits a
+/// [`BTreeMap<K, V>`] into two parts:
+///
+/// - `BTreeMap<K, usize>`
+/// - `Vec<V>`
+///
+/// Keys are kept in sorted order and can be queried with [range](Self::range). The
+/// index of each key stays the same unless the key is removed from the map or the map is
+/// explicitly compacted.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableBTreeMap;
+///
+/// let mut map = StableBTreeMap::new();
+/// map.insert(3, "c");
+/// map.insert(1, "a");
+/// map.insert(2, "b");
+///
+/// let index = map.get_index(&2).unwrap();
+/// map.remove(&1);
+/// assert_eq!(map.get_by_index(index), Some(&"b"));
+///
+/// let range: Vec<_> = map.range(2..).collect();
+/// assert_eq!(range, [(&2, &"b"), (&3, &"c")]);
+/// ```
+//
+// This type upholds the following invariants:
+//
+// - key_to_pos contains only valid Pos<InUse> returned by storage.
+//
+// SAFETY:
+// - LinearStorage::clear invalidates existing Pos<InUse> without consuming them.
+// - Code calling LinearStorage::clear must explain how it upholds the invariant.
+pub struct StableBTreeMap<K, V> {
+    key_to_pos: BTreeMap<K, Pos<InUse>>,
+    storage: LinearStorage<V>,
+}
+
+impl<K, V> StableBTreeMap<K, V> {
+    /// Creates an empty `StableBTreeMap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    /// let mut map: StableBTreeMap<&str, i32> = StableBTreeMap::new();
+    /// assert_eq!(map.len(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn new() -> Self {
+        Self {
+            key_to_pos: BTreeMap::new(),
+            storage: LinearStorage::with_capacity(0),
+        }
+    }
+
+    /// Clears the map, removing all key-value pairs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, "a");
+    /// map.clear();
+    /// assert!(map.is_empty());
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn clear(&mut self) {
+        self.key_to_pos.clear();
+        self.storage.clear();
+        // SAFETY(invariants):
+        // - We have cleared key_to_pos.
+    }
+
+    /// Returns `true` if the map contains a value for the specified key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, "a");
+    /// assert!(map.contains_key(&1));
+    /// assert!(!map.contains_key(&2));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.key_to_pos.contains_key(key)
+    }
+
+    /// Returns the first key-value pair in the map. The key in this pair is the minimum
+    /// key in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// assert_eq!(map.first_key_value(), None);
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// assert_eq!(map.first_key_value(), Some((&1, &"a")));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn first_key_value(&self) -> Option<(&K, &V)>
+    where
+        K: Ord,
+    {
+        let (k, pos) = self.key_to_pos.first_key_value()?;
+        let v = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.get_unchecked(pos)
+        };
+        Some((k, v))
+    }
+
+    /// Returns a reference to the value corresponding to the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get(&1), Some(&"a"));
+    /// assert_eq!(map.get(&2), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        let pos = self.key_to_pos.get(key)?;
+        let v = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.get_unchecked(pos)
+        };
+        Some(v)
+    }
+
+    /// Returns the key-value pair corresponding to the supplied key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get_key_value(&1), Some((&1, &"a")));
+    /// assert_eq!(map.get_key_value(&2), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &V)>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        let (k, pos) = self.key_to_pos.get_key_value(key)?;
+        let v = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.get_unchecked(pos)
+        };
+        Some((k, v))
+    }
+
+    /// Returns a mutable reference to the value corresponding to the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, "a");
+    /// if let Some(x) = map.get_mut(&1) {
+    ///     *x = "b";
+    /// }
+    /// assert_eq!(map[&1], "b");
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut V>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        let pos = self.key_to_pos.get(key)?;
+        let v = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.get_unchecked_mut(pos)
+        };
+        Some(v)
+    }
+
+    /// Inserts a key-value pair into the map.
+    ///
+    /// If the map did not have this key present, [`None`] is returned.
+    ///
+    /// If the map did have this key present, the value is updated, and the old value is
+    /// returned. The key is not updated and its index does not change.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// assert_eq!(map.insert(37, "a"), None);
+    /// assert_eq!(map.insert(37, "b"), Some("a"));
+    /// assert_eq!(map[&37], "b");
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert(&mut self, key: K, value: V) -> Option<V>
+    where
+        K: Ord,
+    {
+        match self.key_to_pos.entry(key) {
+            btree_map::Entry::Occupied(occupied) => {
+                let prev = unsafe {
+                    // SAFETY:
+                    // - By the invariants, occupied.get() is valid
+                    self.storage.get_unchecked_mut(occupied.get())
+                };
+                Some(mem::replace(prev, value))
+            }
+            btree_map::Entry::Vacant(vacant) => {
+                let pos = self.storage.insert(value);
+                vacant.insert(pos);
+                None
+            }
+        }
+    }
+
+    /// Returns `true` if the map contains no elements.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// assert!(map.is_empty());
+    /// map.insert(1, "a");
+    /// assert!(!map.is_empty());
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn is_empty(&self) -> bool {
+        self.key_to_pos.is_empty()
+    }
+
+    /// An iterator visiting all key-value pairs, sorted by key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// let entries: Vec<_> = map.iter().collect();
+    /// assert_eq!(entries, [(&1, &"a"), (&2, &"b")]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn iter(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (&K, &V)> + ExactSizeIterator + use<'_, K, V> {
+        let storage = &self.storage;
+        self.key_to_pos.iter().map(move |(k, pos)| {
+            let v = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                storage.get_unchecked(pos)
+            };
+            (k, v)
+        })
+    }
+
+    /// An iterator visiting all keys in sorted order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// let keys: Vec<_> = map.keys().collect();
+    /// assert_eq!(keys, [&1, &2]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn keys(&self) -> impl DoubleEndedIterator<Item = &K> + ExactSizeIterator + use<'_, K, V> {
+        self.key_to_pos.keys()
+    }
+
+    /// Returns the last key-value pair in the map. The key in this pair is the maximum
+    /// key in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// assert_eq!(map.last_key_value(), None);
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// assert_eq!(map.last_key_value(), Some((&2, &"b")));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn last_key_value(&self) -> Option<(&K, &V)>
+    where
+        K: Ord,
+    {
+        let (k, pos) = self.key_to_pos.last_key_value()?;
+        let v = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.get_unchecked(pos)
+        };
+        Some((k, v))
+    }
+
+    /// Returns the number of elements in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// assert_eq!(map.len(), 0);
+    /// map.insert(1, "a");
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn len(&self) -> usize {
+        self.key_to_pos.len()
+    }
+
+    /// Constructs a double-ended iterator over a sub-range of the key-value pairs in the
+    /// map, sorted by key.
+    ///
+    /// # Panics
+    ///
+    /// Panics under the same conditions as [`BTreeMap::range`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let map: StableBTreeMap<_, _> = [(3, "a"), (5, "b"), (8, "c")].into_iter().collect();
+    /// let range: Vec<_> = map.range(4..).collect();
+    /// assert_eq!(range, [(&5, &"b"), (&8, &"c")]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn range<Q, R>(
+        &self,
+        range: R,
+    ) -> impl DoubleEndedIterator<Item = (&K, &V)> + use<'_, K, V, Q, R>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+        R: RangeBounds<Q>,
+    {
+        let storage = &self.storage;
+        self.key_to_pos.range(range).map(move |(k, pos)| {
+            let v = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                storage.get_unchecked(pos)
+            };
+            (k, v)
+        })
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key was
+    /// previously in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.remove(&1), Some("a"));
+    /// assert_eq!(map.remove(&1), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, v)| v)
+    }
+
+    /// Removes a key from the map, returning the stored key and value if the key was
+    /// previously in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.remove_entry(&1), Some((1, "a")));
+    /// assert_eq!(map.remove_entry(&1), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        let (k, pos) = self.key_to_pos.remove_entry(key)?;
+        let value = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.take_unchecked(pos)
+        };
+        Some((k, value))
+    }
+
+    /// An iterator visiting all values in the order of their keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(2, "b");
+    /// map.insert(1, "a");
+    /// let values: Vec<_> = map.values().collect();
+    /// assert_eq!(values, [&"a", &"b"]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn values(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = &V> + ExactSizeIterator + use<'_, K, V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Returns one more than the highest possible index of this map.
+    ///
+    /// Using [get_by_index](Self::get_by_index) with higher indices will always return
+    /// `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, 1);
+    /// map.insert(2, 2);
+    /// map.remove(&1);
+    /// assert_eq!(map.index_len(), 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn index_len(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Returns the index that the key maps to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(2, 2);
+    /// map.insert(1, 1);
+    /// assert_eq!(map.get_index(&2), Some(0));
+    /// assert_eq!(map.get_index(&1), Some(1));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_index<Q>(&self, key: &Q) -> Option<usize>
+    where
+        K: Borrow<Q> + Ord,
+        Q: Ord + ?Sized,
+    {
+        self.key_to_pos.get(key).map(|v| unsafe {
+            // SAFETY:
+            // - By the invariants, v is valid
+            self.storage.index_unchecked(v)
+        })
+    }
+
+    /// Returns a reference to the value corresponding to the index.
+    ///
+    /// This function returns `Some` if and only if there is a key, `key`, for which
+    /// [get_index](Self::get_index) returns this index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, "a");
+    /// let index = map.get_index(&1).unwrap();
+    /// assert_eq!(map.get_by_index(index), Some(&"a"));
+    /// ```
+    #[inline]
+    pub fn get_by_index(&self, index: usize) -> Option<&V> {
+        self.storage.get(index)
+    }
+
+    /// Returns a mutable reference to the value corresponding to the index.
+    ///
+    /// This function returns `Some` if and only if there is a key, `key`, for which
+    /// [get_index](Self::get_index) returns this index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, "a");
+    /// let index = map.get_index(&1).unwrap();
+    /// *map.get_by_index_mut(index).unwrap() = "b";
+    /// assert_eq!(map[&1], "b");
+    /// ```
+    #[inline]
+    pub fn get_by_index_mut(&mut self, index: usize) -> Option<&mut V> {
+        self.storage.get_mut(index)
+    }
+
+    /// Maybe compacts the map, removing indices for which `get_by_index` would return
+    /// `None`.
+    ///
+    /// This function does nothing if there are no more than 8 indices for which
+    /// [get_by_index](Self::get_by_index) returns `None` or if at least half of the
+    /// indices are in use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// for i in 0..32 {
+    ///     map.insert(i, i);
+    /// }
+    /// for i in 0..17 {
+    ///     map.remove(&i);
+    /// }
+    /// map.compact();
+    /// assert_eq!(map.index_len(), 15);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn compact(&mut self) {
+        self.storage.compact();
+    }
+
+    /// Compacts the map, removing indices for which `get_by_index` would return `None`.
+    ///
+    /// After this function returns, [index_len](Self::index_len) will be the same as
+    /// [len](Self::len).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableBTreeMap;
+    ///
+    /// let mut map = StableBTreeMap::new();
+    /// map.insert(1, 1);
+    /// map.remove(&1);
+    /// assert_eq!(map.index_len(), 1);
+    /// map.force_compact();
+    /// assert_eq!(map.index_len(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn force_compact(&mut self) {
+        self.storage.force_compact();
+    }
+}
+
+impl<K, V> Default for StableBTreeMap<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K, V> Clone for StableBTreeMap<K, V>
+where
+    K: Ord + Clone,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        self.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+}
+
+impl<K, V> Debug for StableBTreeMap<K, V>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<K, V> PartialEq for StableBTreeMap<K, V>
+where
+    K: PartialEq,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
+impl<K, V> Eq for StableBTreeMap<K, V>
+where
+    K: Eq,
+    V: Eq,
+{
+}
+
+impl<K, Q, V> Index<&Q> for StableBTreeMap<K, V>
+where
+    K: Borrow<Q> + Ord,
+    Q: Ord + ?Sized,
+{
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry found for key")
+    }
+}
+
+impl<K, V> Extend<(K, V)> for StableBTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn extend<T: IntoIterator<Item = (K, V)>>(&mut self, iter: T) {
+        for (k, v) in iter {
+            self.insert(k, v);
+        }
+    }
+}
+
+impl<K, V> FromIterator<(K, V)> for StableBTreeMap<K, V>
+where
+    K: Ord,
+{
+    fn from_iter<T: IntoIterator<Item = (K, V)>>(iter: T) -> Self {
+        let mut map = Self::new();
+        map.extend(iter);
+        map
+    }
+}
+
+// SAFETY:
+// - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
+//   but this API prevents this.
+unsafe impl<K, V> Send for StableBTreeMap<K, V>
+where
+    K: Send,
+    V: Send,
+{
+}
+
+// SAFETY:
+// - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
+//   but this API prevents this.
+unsafe impl<K, V> Sync for StableBTreeMap<K, V>
+where
+    K: Sync,
+    V: Sync,
+{
+}
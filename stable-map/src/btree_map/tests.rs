@@ -0,0 +1,52 @@
+use {crate::StableBTreeMap, alloc::vec::Vec};
+
+#[test]
+fn insert_remove() {
+    let mut map = StableBTreeMap::new();
+    for i in (0..8).rev() {
+        assert_eq!(map.insert(i, i * 10), None);
+    }
+    assert_eq!(map.insert(3, 33), Some(30));
+    assert_eq!(map.len(), 8);
+    assert_eq!(map.get_index(&7), Some(0));
+    assert_eq!(map.get_index(&3), Some(4));
+    assert_eq!(map.remove(&7), Some(70));
+    assert_eq!(map.remove(&7), None);
+    assert_eq!(map.get_by_index(0), None);
+    assert_eq!(map.get_by_index(4), Some(&33));
+    map.insert(8, 80);
+    assert_eq!(map.get_index(&8), Some(0));
+    assert_eq!(map.index_len(), 8);
+}
+
+#[test]
+fn ordered() {
+    let map: StableBTreeMap<_, _> = [(5, 'b'), (1, 'a'), (9, 'c')].into_iter().collect();
+    assert_eq!(map.keys().copied().collect::<Vec<_>>(), [1, 5, 9]);
+    assert_eq!(map.values().copied().collect::<Vec<_>>(), ['a', 'b', 'c']);
+    assert_eq!(
+        map.range(2..=9).collect::<Vec<_>>(),
+        [(&5, &'b'), (&9, &'c')]
+    );
+    assert_eq!(map.first_key_value(), Some((&1, &'a')));
+    assert_eq!(map.last_key_value(), Some((&9, &'c')));
+}
+
+#[test]
+fn force_compact() {
+    let mut map = StableBTreeMap::new();
+    for i in 0..6 {
+        map.insert(i, i);
+    }
+    map.remove(&1);
+    map.remove(&2);
+    map.force_compact();
+    assert_eq!(map.index_len(), 4);
+    for i in [0, 3, 4, 5] {
+        assert_eq!(map.get_by_index(map.get_index(&i).unwrap()), Some(&i));
+    }
+    assert_eq!(map.clone(), map);
+    map.clear();
+    assert!(map.is_empty());
+    assert_eq!(map.index_len(), 0);
+}
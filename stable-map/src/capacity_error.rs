@@ -0,0 +1,45 @@
+use core::fmt::{Debug, Display, Formatter};
+
+/// The error returned by [`try_insert_bounded`](crate::StableMap::try_insert_bounded)
+/// when the map is full.
+///
+/// Contains the key and the value that were not inserted.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{CapacityError, StableMap};
+///
+/// let mut map = StableMap::with_max_len(1);
+/// map.try_insert_bounded("a", 10).unwrap();
+///
+/// match map.try_insert_bounded("b", 20) {
+///     Err(CapacityError { key, value }) => {
+///         assert_eq!(key, "b");
+///         assert_eq!(value, 20);
+///     }
+///     _ => unreachable!(),
+/// }
+/// assert_eq!(map.get("b"), None);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapacityError<K, V> {
+    /// The key which was not inserted, because the map was full.
+    pub key: K,
+    /// The value which was not inserted, because the map was full.
+    pub value: V,
+}
+
+impl<K, V> Display for CapacityError<K, V>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "failed to insert {:?}, the map is full and does not contain the key {:?}",
+            self.value, self.key,
+        )
+    }
+}
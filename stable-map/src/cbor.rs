@@ -0,0 +1,47 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::StableMap,
+    allocator_api2::alloc::Allocator,
+    core::hash::{BuildHasher, Hash},
+    minicbor::{
+        decode::{self, Decode, Decoder},
+        encode::{self, Encode, Encoder, Write},
+    },
+};
+
+impl<C, K, V, S, A: Allocator> Encode<C> for StableMap<K, V, S, A>
+where
+    K: Encode<C>,
+    V: Encode<C>,
+{
+    fn encode<W>(&self, e: &mut Encoder<W>, ctx: &mut C) -> Result<(), encode::Error<W::Error>>
+    where
+        W: Write,
+    {
+        e.map(self.len() as u64)?;
+        for (key, value) in self {
+            key.encode(e, ctx)?;
+            value.encode(e, ctx)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'b, C, K, V, S, A> Decode<'b, C> for StableMap<K, V, S, A>
+where
+    K: Eq + Hash + Decode<'b, C>,
+    V: Decode<'b, C>,
+    S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
+{
+    fn decode(d: &mut Decoder<'b>, ctx: &mut C) -> Result<Self, decode::Error> {
+        let mut map = StableMap::default();
+        for entry in d.map_iter_with(ctx)? {
+            let (key, value) = entry?;
+            map.insert(key, value);
+        }
+        Ok(map)
+    }
+}
@@ -0,0 +1,24 @@
+use {crate::StableMap, alloc::vec::Vec};
+
+#[test]
+fn test() {
+    let mut map1 = StableMap::new();
+    map1.insert(1, 11);
+    map1.insert(2, 22);
+    map1.insert(3, 33);
+    map1.remove(&2);
+    let bytes = minicbor::to_vec(&map1).unwrap();
+    let map2: StableMap<i32, i32> = minicbor::decode(&bytes).unwrap();
+    assert_eq!(map1, map2);
+}
+
+#[test]
+fn duplicate_keys() {
+    let mut e = minicbor::Encoder::new(Vec::new());
+    e.map(2).unwrap().u8(1).unwrap().u8(11).unwrap();
+    e.u8(1).unwrap().u8(12).unwrap();
+    let buf = e.into_writer();
+    let map: StableMap<u8, u8> = minicbor::decode(&buf).unwrap();
+    assert_eq!(map.len(), 1);
+    assert_eq!(map[&1], 12);
+}
@@ -3,24 +3,36 @@ mod tests;
 
 use {
     crate::StableMap,
+    allocator_api2::alloc::Allocator,
     core::hash::{BuildHasher, Hash},
 };
 
-impl<K, V, S> Clone for StableMap<K, V, S>
+impl<K, V, S, A: Allocator + Clone> Clone for StableMap<K, V, S, A>
 where
     K: Eq + Hash + Clone,
     V: Clone,
     S: BuildHasher + Clone,
 {
     fn clone(&self) -> Self {
-        let mut map = Self::with_capacity_and_hasher(self.len(), self.hasher().clone());
-        for (k, v) in self {
+        let mut map = Self::with_capacity_and_hasher_in(
+            self.len(),
+            self.hasher().clone(),
+            self.allocator().clone(),
+        );
+        map.set_amortized_compaction(self.amortized_compaction());
+        map.set_auto_shrink(self.auto_shrink());
+        map.set_max_vacant(self.max_vacant());
+        map.set_reuse_policy(self.reuse_policy());
+        for (hash, k, v) in self.iter_hashed() {
             unsafe {
                 // SAFETY:
                 // - All k are part of the same hash map so they must be distinct.
-                map.insert_unique_unchecked(k.clone(), v.clone());
+                // - The clone of the hasher produces the same hashes.
+                map.insert_unique_hashed_unchecked(hash, k.clone(), v.clone());
             }
         }
+        // The map might contain more than max_len elements.
+        map.set_max_len(self.max_len());
         map
     }
 }
@@ -1,9 +1,15 @@
+#[cfg(test)]
+mod tests;
+
+#[cfg(feature = "debug-sorted")]
+use alloc::vec::Vec;
 use {
     crate::StableMap,
+    allocator_api2::alloc::Allocator,
     core::fmt::{Debug, Formatter},
 };
 
-impl<K, V, S> Debug for StableMap<K, V, S>
+impl<K, V, S, A: Allocator> Debug for StableMap<K, V, S, A>
 where
     K: Debug,
     V: Debug,
@@ -16,3 +22,49 @@ where
         map.finish()
     }
 }
+
+#[cfg(feature = "debug-sorted")]
+impl<K, V, S, A: Allocator> StableMap<K, V, S, A> {
+    /// Returns an object whose `Debug` implementation prints the entries sorted by key.
+    ///
+    /// Unlike the output of the `Debug` implementation of the map, the output does not
+    /// depend on the hasher. This is useful for snapshot tests.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(3, "c");
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// assert_eq!(
+    ///     format!("{:?}", map.debug_sorted()),
+    ///     r#"{1: "a", 2: "b", 3: "c"}"#,
+    /// );
+    /// ```
+    pub fn debug_sorted(&self) -> impl Debug + use<'_, K, V, S, A>
+    where
+        K: Debug + Ord,
+        V: Debug,
+    {
+        DebugSorted(self)
+    }
+}
+
+#[cfg(feature = "debug-sorted")]
+struct DebugSorted<'a, K, V, S, A: Allocator>(&'a StableMap<K, V, S, A>);
+
+#[cfg(feature = "debug-sorted")]
+impl<K, V, S, A: Allocator> Debug for DebugSorted<'_, K, V, S, A>
+where
+    K: Debug + Ord,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let mut entries: Vec<_> = self.0.iter().collect();
+        entries.sort_unstable_by_key(|(k, _)| *k);
+        f.debug_map().entries(entries).finish()
+    }
+}
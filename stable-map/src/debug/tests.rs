@@ -0,0 +1,28 @@
+use {crate::StableMap, alloc::format};
+
+#[test]
+fn debug() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    assert_eq!(format!("{:?}", map), "{1: 11}");
+}
+
+#[cfg(feature = "debug-sorted")]
+#[test]
+fn sorted() {
+    // The Debug impl of the map does not require K: Ord.
+    #[derive(Debug, PartialEq, Eq, Hash)]
+    struct Key;
+    let mut map = StableMap::new();
+    map.insert(Key, 1);
+    assert_eq!(format!("{:?}", map), "{Key: 1}");
+
+    let mut map = StableMap::new();
+    for i in (0..10).rev() {
+        map.insert(i, i * 10);
+    }
+    assert_eq!(
+        format!("{:?}", map.debug_sorted()),
+        "{0: 0, 1: 10, 2: 20, 3: 30, 4: 40, 5: 50, 6: 60, 7: 70, 8: 80, 9: 90}",
+    );
+}
@@ -1,10 +1,10 @@
-use crate::map::StableMap;
+use {crate::map::StableMap, allocator_api2::alloc::Allocator};
 
-impl<K, V, S> Default for StableMap<K, V, S>
+impl<K, V, S, A: Allocator + Clone + Default> Default for StableMap<K, V, S, A>
 where
     S: Default,
 {
     fn default() -> Self {
-        Self::with_hasher(S::default())
+        Self::with_hasher_in(S::default(), A::default())
     }
 }
@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod tests;
+
+use alloc::vec::Vec;
+
+/// A change set between two `StableMap`s.
+///
+/// This `struct` is created by the [`diff`] method on [`StableMap`]. It describes the
+/// changes required to turn the first map into the second map. See its documentation
+/// for more.
+///
+/// [`diff`]: crate::StableMap::diff
+/// [`StableMap`]: crate::StableMap
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{DiffEntry, StableMap};
+///
+/// let old: StableMap<_, _> = [(1, "a"), (2, "b")].into();
+/// let mut new = old.clone();
+/// new.remove(&1);
+/// new.insert(2, "c");
+///
+/// let diff = old.diff(&new);
+/// assert!(diff.added.is_empty());
+/// assert_eq!(diff.removed, [(old.get_index(&1).unwrap(), 1)]);
+/// assert_eq!(
+///     diff.changed,
+///     [DiffEntry {
+///         index: new.get_index(&2).unwrap(),
+///         key: 2,
+///         value: "c",
+///     }],
+/// );
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MapDiff<K, V> {
+    /// The entries of the second map whose keys are not contained in the first map.
+    ///
+    /// The indices are the indices in the second map.
+    pub added: Vec<DiffEntry<K, V>>,
+    /// The keys of the first map that are not contained in the second map.
+    ///
+    /// The indices are the indices in the first map.
+    pub removed: Vec<(usize, K)>,
+    /// The entries of the second map whose values differ from the values in the first
+    /// map.
+    ///
+    /// The indices are the indices in the second map.
+    pub changed: Vec<DiffEntry<K, V>>,
+}
+
+/// A key-value pair recorded in a [`MapDiff`], together with its index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffEntry<K, V> {
+    /// The index of the entry.
+    pub index: usize,
+    /// The key of the entry.
+    pub key: K,
+    /// The value of the entry.
+    pub value: V,
+}
+
+impl<K, V> MapDiff<K, V> {
+    /// Returns `true` if the change set contains no changes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let map: StableMap<_, _> = [(1, "a"), (2, "b")].into();
+    /// assert!(map.diff(&map.clone()).is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl<K, V> Default for MapDiff<K, V> {
+    fn default() -> Self {
+        Self {
+            added: Vec::new(),
+            removed: Vec::new(),
+            changed: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,35 @@
+use crate::{DiffEntry, StableMap};
+
+#[test]
+fn test() {
+    let mut map1 = StableMap::new();
+    map1.insert(1, 11);
+    map1.insert(2, 22);
+    map1.insert(3, 33);
+    let mut map2 = StableMap::new();
+    map2.insert(2, 22);
+    map2.insert(3, 34);
+    map2.insert(4, 44);
+    let diff = map1.diff(&map2);
+    assert!(!diff.is_empty());
+    assert_eq!(
+        diff.added,
+        [DiffEntry {
+            index: 2,
+            key: 4,
+            value: 44,
+        }],
+    );
+    assert_eq!(diff.removed, [(0, 1)]);
+    assert_eq!(
+        diff.changed,
+        [DiffEntry {
+            index: 1,
+            key: 3,
+            value: 34,
+        }],
+    );
+    let diff = map2.diff(&map1);
+    assert_eq!(diff.removed, [(2, 4)]);
+    assert!(map1.diff(&map1).is_empty());
+}
@@ -0,0 +1,54 @@
+use core::fmt::{Display, Formatter};
+
+/// The error contained in [`ApplyDiffError::Index`](crate::ApplyDiffError::Index) when
+/// the change set passed to [`apply_diff`](crate::StableMap::apply_diff) records an
+/// index that is too large.
+///
+/// An index is too large if it is greater than or equal to the
+/// [index_len](crate::StableMap::index_len) of the map plus the number of entries in
+/// `added` and `changed`.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{ApplyDiffError, DiffEntry, DiffIndexError, MapDiff, StableMap};
+///
+/// let mut map = StableMap::new();
+/// map.insert(1, "a");
+///
+/// let diff = MapDiff {
+///     added: vec![DiffEntry {
+///         index: 1_000_000,
+///         key: 2,
+///         value: "b",
+///     }],
+///     removed: vec![],
+///     changed: vec![],
+/// };
+/// let res = map.apply_diff(diff);
+/// assert_eq!(
+///     res,
+///     Err(ApplyDiffError::Index(DiffIndexError {
+///         index: 1_000_000,
+///         bound: 2,
+///     })),
+/// );
+/// assert_eq!(map.len(), 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffIndexError {
+    /// The index recorded in the change set.
+    pub index: usize,
+    /// The exclusive upper bound for indices in the change set.
+    pub bound: usize,
+}
+
+impl Display for DiffIndexError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "the change set records index {} but indices must be less than {}",
+            self.index, self.bound,
+        )
+    }
+}
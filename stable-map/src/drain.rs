@@ -6,11 +6,12 @@ use {
         linear_storage::LinearStorage,
         pos_vec::pos::{InUse, Pos},
     },
+    allocator_api2::alloc::{Allocator, Global},
     core::{
         fmt::{Debug, Formatter},
         iter::FusedIterator,
     },
-    hashbrown::hash_map,
+    hashbrown::hash_table,
 };
 
 /// A draining iterator over the entries of a `StableMap` in arbitrary
@@ -41,20 +42,20 @@ use {
 /// assert_eq!(drain_iter.next(), None);
 /// assert_eq!(drain_iter.next(), None);
 /// ```
-pub struct Drain<'a, K, V> {
-    pub(crate) drain: hash_map::Drain<'a, K, Pos<InUse>>,
-    pub(crate) entries: &'a mut LinearStorage<V>,
+pub struct Drain<'a, K, V, A: Allocator = Global> {
+    pub(crate) drain: hash_table::Drain<'a, (K, Pos<InUse>), A>,
+    pub(crate) entries: &'a mut LinearStorage<V, A>,
 }
 
-impl<K, V> Drop for Drain<'_, K, V> {
+impl<K, V, A: Allocator> Drop for Drain<'_, K, V, A> {
     fn drop(&mut self) {
         self.entries.clear();
         // SAFETY(invariants):
-        // - Dropping hash_map::Drain clears key_to_pos.
+        // - Dropping hash_table::Drain clears key_to_pos.
     }
 }
 
-impl<K, V> Iterator for Drain<'_, K, V> {
+impl<K, V, A: Allocator> Iterator for Drain<'_, K, V, A> {
     type Item = (K, V);
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -71,36 +72,55 @@ impl<K, V> Iterator for Drain<'_, K, V> {
     }
 }
 
-impl<K, V> Debug for Drain<'_, K, V> {
+impl<K, V, A: Allocator> Debug for Drain<'_, K, V, A>
+where
+    V: Debug,
+{
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
-        f.debug_struct("Drain").finish_non_exhaustive()
+        struct Values<'a, V, A: Allocator>(&'a LinearStorage<V, A>);
+        impl<V, A: Allocator> Debug for Values<'_, V, A>
+        where
+            V: Debug,
+        {
+            fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+                f.debug_list().entries(self.0.iter()).finish()
+            }
+        }
+        // The keys are owned by the hash map drain which does not expose them. The
+        // storage contains exactly the values that have not yet been yielded.
+        f.debug_struct("Drain")
+            .field("remaining", &self.len())
+            .field("values", &Values(self.entries))
+            .finish_non_exhaustive()
     }
 }
 
-impl<K, V> ExactSizeIterator for Drain<'_, K, V> {
+impl<K, V, A: Allocator> ExactSizeIterator for Drain<'_, K, V, A> {
     fn len(&self) -> usize {
         self.drain.len()
     }
 }
 
-impl<K, V> FusedIterator for Drain<'_, K, V> {}
+impl<K, V, A: Allocator> FusedIterator for Drain<'_, K, V, A> {}
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V> Send for Drain<'_, K, V>
+unsafe impl<K, V, A: Allocator> Send for Drain<'_, K, V, A>
 where
     K: Send,
     V: Send,
+    A: Send,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V> Sync for Drain<'_, K, V>
+unsafe impl<K, V, A: Allocator> Sync for Drain<'_, K, V, A>
 where
     K: Sync,
     V: Sync,
+    A: Sync,
 {
 }
@@ -1,4 +1,7 @@
-use {crate::StableMap, alloc::vec::Vec};
+use {
+    crate::StableMap,
+    alloc::{format, vec::Vec},
+};
 
 #[test]
 fn drain() {
@@ -16,3 +19,31 @@ fn drain() {
     drop(drain);
     assert!(map.is_empty());
 }
+
+#[test]
+fn debug() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    let mut drain = map.drain();
+    let (k, _) = drain.next().unwrap();
+    let expected = match k {
+        1 => "Drain { remaining: 1, values: [22], .. }",
+        _ => "Drain { remaining: 1, values: [11], .. }",
+    };
+    assert_eq!(format!("{:?}", drain), expected);
+}
+
+#[test]
+fn drain_into() {
+    let mut map = StableMap::new();
+    let mut buf = Vec::new();
+    buf.push((0, 0));
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.drain_into(&mut buf);
+    assert!(map.is_empty());
+    assert_eq!(map.index_len(), 0);
+    buf[1..].sort();
+    assert_eq!(buf, [(0, 0), (1, 11), (2, 22)]);
+}
@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::{
+        linear_storage::LinearStorage,
+        pos_vec::pos::{InUse, Pos},
+    },
+    allocator_api2::alloc::{Allocator, Global},
+    core::{
+        fmt::{Debug, Formatter},
+        iter::FusedIterator,
+    },
+    hashbrown::hash_table,
+};
+
+/// A draining iterator over the entries of a `StableMap` and their indices in arbitrary
+/// order. The iterator element type is `(usize, K, V)`.
+///
+/// This `struct` is created by the [`drain_indexed`] method on [`StableMap`]. See its
+/// documentation for more.
+///
+/// [`drain_indexed`]: crate::StableMap::drain_indexed
+/// [`StableMap`]: crate::StableMap
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMap;
+///
+/// let mut map: StableMap<_, _> = [(1, "a"), (2, "b"), (3, "c")].into();
+///
+/// let mut drain_iter = map.drain_indexed();
+/// let mut vec = vec![drain_iter.next(), drain_iter.next(), drain_iter.next()];
+///
+/// // The `DrainIndexed` iterator produces items in arbitrary order, so the
+/// // items must be sorted to test them against a sorted array.
+/// vec.sort_unstable();
+/// assert_eq!(vec, [Some((0, 1, "a")), Some((1, 2, "b")), Some((2, 3, "c"))]);
+///
+/// // It is fused iterator
+/// assert_eq!(drain_iter.next(), None);
+/// assert_eq!(drain_iter.next(), None);
+/// ```
+pub struct DrainIndexed<'a, K, V, A: Allocator = Global> {
+    pub(crate) drain: hash_table::Drain<'a, (K, Pos<InUse>), A>,
+    pub(crate) entries: &'a mut LinearStorage<V, A>,
+}
+
+impl<K, V, A: Allocator> Drop for DrainIndexed<'_, K, V, A> {
+    fn drop(&mut self) {
+        self.entries.clear();
+        // SAFETY(invariants):
+        // - Dropping hash_table::Drain clears key_to_pos.
+    }
+}
+
+impl<K, V, A: Allocator> Iterator for DrainIndexed<'_, K, V, A> {
+    type Item = (usize, K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (k, pos) = self.drain.next()?;
+        let (index, value) = unsafe {
+            // SAFETY: By the invariants, pos is valid.
+            // Since no values are moved while draining, index is the index that the
+            // entry had when the drain was created.
+            (
+                self.entries.index_unchecked(&pos),
+                self.entries.take_unchecked_no_reclaim(pos),
+            )
+        };
+        Some((index, k, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.drain.size_hint()
+    }
+}
+
+impl<K, V, A: Allocator> Debug for DrainIndexed<'_, K, V, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("DrainIndexed")
+            .field("remaining", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<K, V, A: Allocator> ExactSizeIterator for DrainIndexed<'_, K, V, A> {
+    fn len(&self) -> usize {
+        self.drain.len()
+    }
+}
+
+impl<K, V, A: Allocator> FusedIterator for DrainIndexed<'_, K, V, A> {}
+
+// SAFETY:
+// - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
+//   but this API prevents this.
+unsafe impl<K, V, A: Allocator> Send for DrainIndexed<'_, K, V, A>
+where
+    K: Send,
+    V: Send,
+    A: Send,
+{
+}
+
+// SAFETY:
+// - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
+//   but this API prevents this.
+unsafe impl<K, V, A: Allocator> Sync for DrainIndexed<'_, K, V, A>
+where
+    K: Sync,
+    V: Sync,
+    A: Sync,
+{
+}
@@ -0,0 +1,35 @@
+use {crate::StableMap, alloc::vec::Vec};
+
+#[test]
+fn drain_indexed() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.insert(3, 33);
+    map.remove(&2);
+    let mut drained = map.drain_indexed().collect::<Vec<_>>();
+    drained.sort();
+    assert_eq!(&drained, &[(0, 1, 11), (2, 3, 33)]);
+    assert!(map.is_empty());
+    assert_eq!(map.index_len(), 0);
+    map.insert(1, 11);
+    map.insert(2, 22);
+    let mut drain = map.drain_indexed();
+    assert_eq!(drain.len(), 2);
+    assert!(drain.next().is_some());
+    drop(drain);
+    assert!(map.is_empty());
+}
+
+#[test]
+fn max_vacant() {
+    let mut map = StableMap::new();
+    for i in 0..8 {
+        map.insert(i, i * 10);
+    }
+    map.set_max_vacant(0);
+    let mut drained = map.drain_indexed().collect::<Vec<_>>();
+    drained.sort();
+    let expected: Vec<_> = (0..8).map(|i| (i, i, i * 10)).collect();
+    assert_eq!(drained, expected);
+}
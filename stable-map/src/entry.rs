@@ -3,16 +3,20 @@ mod tests;
 
 use {
     crate::{
+        capacity_error::CapacityError,
         linear_storage::LinearStorage,
         pos_vec::pos::{InUse, Pos},
     },
+    alloc::borrow::ToOwned,
+    allocator_api2::alloc::{Allocator, Global},
     core::{
         borrow::Borrow,
         fmt::{Debug, Formatter},
         hash::{BuildHasher, Hash},
+        marker::PhantomData,
         mem::{self},
     },
-    hashbrown::hash_map,
+    hashbrown::{hash_table, Equivalent},
 };
 
 /// A view into a single entry in a map, which may either be vacant or occupied.
@@ -58,7 +62,7 @@ use {
 /// vec.sort_unstable();
 /// assert_eq!(vec, [("a", 1), ("b", 2), ("c", 3), ("d", 4), ("e", 5), ("f", 6)]);
 /// ```
-pub enum Entry<'a, K, V, S> {
+pub enum Entry<'a, K, V, S, A: Allocator = Global> {
     /// An occupied entry.
     ///
     /// # Examples
@@ -72,7 +76,7 @@ pub enum Entry<'a, K, V, S> {
     ///     Entry::Occupied(_) => { }
     /// }
     /// ```
-    Occupied(OccupiedEntry<'a, K, V, S>),
+    Occupied(OccupiedEntry<'a, K, V, S, A>),
     /// A vacant entry.
     ///
     /// # Examples
@@ -86,7 +90,7 @@ pub enum Entry<'a, K, V, S> {
     ///     Entry::Vacant(_) => { }
     /// }
     /// ```
-    Vacant(VacantEntry<'a, K, V, S>),
+    Vacant(VacantEntry<'a, K, V, S, A>),
 }
 
 /// A view into a single entry in a map, which may either be vacant or occupied,
@@ -141,7 +145,7 @@ pub enum Entry<'a, K, V, S> {
 /// }
 /// assert_eq!(map.len(), 6);
 /// ```
-pub enum EntryRef<'a, 'b, K, Q: ?Sized, V, S> {
+pub enum EntryRef<'a, 'b, K, Q: ?Sized, V, S, A: Allocator = Global> {
     /// An occupied entry.
     ///
     /// # Examples
@@ -155,7 +159,7 @@ pub enum EntryRef<'a, 'b, K, Q: ?Sized, V, S> {
     ///     EntryRef::Occupied(_) => { }
     /// }
     /// ```
-    Occupied(OccupiedEntry<'a, K, V, S>),
+    Occupied(OccupiedEntry<'a, K, V, S, A>),
     /// A vacant entry.
     ///
     /// # Examples
@@ -169,7 +173,7 @@ pub enum EntryRef<'a, 'b, K, Q: ?Sized, V, S> {
     ///     EntryRef::Vacant(_) => { }
     /// }
     /// ```
-    Vacant(VacantEntryRef<'a, 'b, K, Q, V, S>),
+    Vacant(VacantEntryRef<'a, 'b, K, Q, V, S, A>),
 }
 
 /// A view into an occupied entry in a [`StableMap`](crate::StableMap).
@@ -210,9 +214,10 @@ pub enum EntryRef<'a, 'b, K, Q: ?Sized, V, S> {
 /// assert_eq!(map.get(&"c"), None);
 /// assert_eq!(map.len(), 2);
 /// ```
-pub struct OccupiedEntry<'a, K, V, S> {
-    pub(crate) entry: hash_map::OccupiedEntry<'a, K, Pos<InUse>, S>,
-    pub(crate) entries: &'a mut LinearStorage<V>,
+pub struct OccupiedEntry<'a, K, V, S, A: Allocator = Global> {
+    pub(crate) entry: hash_table::OccupiedEntry<'a, (K, Pos<InUse>), A>,
+    pub(crate) entries: &'a mut LinearStorage<V, A>,
+    pub(crate) _phantom: PhantomData<&'a mut S>,
 }
 
 /// A view into a vacant entry in a `StableMap`.
@@ -243,9 +248,12 @@ pub struct OccupiedEntry<'a, K, V, S> {
 /// }
 /// assert!(map[&"b"] == 20 && map.len() == 2);
 /// ```
-pub struct VacantEntry<'a, K, V, S> {
-    pub(crate) entry: hash_map::VacantEntry<'a, K, Pos<InUse>, S>,
-    pub(crate) entries: &'a mut LinearStorage<V>,
+pub struct VacantEntry<'a, K, V, S, A: Allocator = Global> {
+    pub(crate) entry: hash_table::VacantEntry<'a, (K, Pos<InUse>), A>,
+    pub(crate) hash: u64,
+    pub(crate) key: K,
+    pub(crate) entries: &'a mut LinearStorage<V, A>,
+    pub(crate) _phantom: PhantomData<&'a mut S>,
 }
 
 /// A view into a vacant entry in a `StableMap`.
@@ -276,15 +284,18 @@ pub struct VacantEntry<'a, K, V, S> {
 /// }
 /// assert!(map["b"] == 20 && map.len() == 2);
 /// ```
-pub struct VacantEntryRef<'a, 'b, K, Q, V, S>
+pub struct VacantEntryRef<'a, 'b, K, Q, V, S, A: Allocator = Global>
 where
     Q: ?Sized,
 {
-    pub(crate) entry: hash_map::VacantEntryRef<'a, 'b, K, Q, Pos<InUse>, S>,
-    pub(crate) entries: &'a mut LinearStorage<V>,
+    pub(crate) entry: hash_table::VacantEntry<'a, (K, Pos<InUse>), A>,
+    pub(crate) hash: u64,
+    pub(crate) key: &'b Q,
+    pub(crate) entries: &'a mut LinearStorage<V, A>,
+    pub(crate) _phantom: PhantomData<&'a mut S>,
 }
 
-impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
+impl<'a, K, V, S, A: Allocator> OccupiedEntry<'a, K, V, S, A> {
     /// Gets a reference to the value in the entry.
     ///
     /// # Examples
@@ -304,7 +315,7 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
     pub fn get(&self) -> &V {
         unsafe {
             // SAFETY: By the invariants, self.entry.get() is valid.
-            self.entries.get_unchecked(self.entry.get())
+            self.entries.get_unchecked(&self.entry.get().1)
         }
     }
 
@@ -336,7 +347,7 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
     pub fn get_mut(&mut self) -> &mut V {
         unsafe {
             // SAFETY: By the invariants, self.entry.get() is valid.
-            self.entries.get_unchecked_mut(self.entry.get())
+            self.entries.get_unchecked_mut(&self.entry.get().1)
         }
     }
 
@@ -389,7 +400,7 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
     pub fn into_mut(self) -> &'a mut V {
         unsafe {
             // SAFETY: By the invariants, self.entry.get() is valid.
-            self.entries.get_unchecked_mut(self.entry.get())
+            self.entries.get_unchecked_mut(&self.entry.get().1)
         }
     }
 
@@ -410,7 +421,7 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn key(&self) -> &K {
-        self.entry.key()
+        &self.entry.get().0
     }
 
     /// Takes the value out of the entry, and returns it.
@@ -437,7 +448,7 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn remove(self) -> V {
-        let pos = self.entry.remove();
+        let ((_, pos), _) = self.entry.remove();
         unsafe {
             // SAFETY: By the invariants, self.entry.get() is valid.
             self.entries.take_unchecked(pos)
@@ -469,7 +480,7 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn remove_entry(self) -> (K, V) {
-        let (k, pos) = self.entry.remove_entry();
+        let ((k, pos), _) = self.entry.remove();
         let value = unsafe {
             // SAFETY: By the invariants, self.entry.get() is valid.
             self.entries.take_unchecked(pos)
@@ -525,34 +536,53 @@ impl<'a, K, V, S> OccupiedEntry<'a, K, V, S> {
     /// assert!(!map.contains_key("poneyland"));
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn replace_entry_with<F>(self, f: F) -> Entry<'a, K, V, S>
+    pub fn replace_entry_with<F>(self, f: F) -> Entry<'a, K, V, S, A>
     where
         F: FnOnce(&K, V) -> Option<V>,
     {
-        let entry = self.entry.replace_entry_with(|k, pos| {
-            let v = unsafe { self.entries.take_unchecked(pos) };
-            match f(k, v) {
-                None => None,
-                Some(v) => Some(self.entries.insert(v)),
+        let ((key, pos), entry) = self.entry.remove();
+        // The hash is only needed to store it with the value, which does not happen
+        // without the `cached-hash` feature.
+        #[cfg(feature = "cached-hash")]
+        let hash = unsafe {
+            // SAFETY: By the invariants, pos is valid.
+            self.entries.hash_unchecked(&pos)
+        };
+        #[cfg(not(feature = "cached-hash"))]
+        let hash = 0;
+        let value = unsafe {
+            // SAFETY: By the invariants, pos is valid.
+            self.entries.take_unchecked(pos)
+        };
+        match f(&key, value) {
+            Some(value) => {
+                let pos = self.entries.insert_hashed(hash, value);
+                Entry::Occupied(OccupiedEntry {
+                    entry: entry.insert((key, pos)),
+                    entries: self.entries,
+                    _phantom: PhantomData,
+                })
             }
-        });
-        match entry {
-            hash_map::Entry::Occupied(o) => Entry::Occupied(OccupiedEntry {
-                entry: o,
-                entries: self.entries,
-            }),
-            hash_map::Entry::Vacant(v) => Entry::Vacant(VacantEntry {
-                entry: v,
+            None => Entry::Vacant(VacantEntry {
+                entry,
+                hash,
+                key,
                 entries: self.entries,
+                _phantom: PhantomData,
             }),
         }
     }
 }
 
-impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
+impl<'a, K, V, S, A: Allocator> VacantEntry<'a, K, V, S, A> {
     /// Sets the value of the entry with the [`VacantEntry`]'s key,
     /// and returns a mutable reference to it.
     ///
+    /// # Panics
+    ///
+    /// Panics if the map already contains [max_len](crate::StableMap::max_len)
+    /// elements. Use [try_insert_bounded](Self::try_insert_bounded) to handle this case.
+    ///
     /// # Examples
     ///
     /// ```
@@ -571,11 +601,84 @@ impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
         K: Hash,
         S: BuildHasher,
     {
-        let pos = self.entries.insert(value);
-        let pos = self.entry.insert(pos);
+        let pos = self.entries.insert_hashed(self.hash, value);
+        let (_, pos) = self.entry.insert((self.key, pos)).into_mut();
         unsafe { self.entries.get_unchecked_mut(pos) }
     }
 
+    /// Sets the value of the entry with the [`VacantEntry`]'s key unless the map already
+    /// contains [max_len](crate::StableMap::max_len) elements, and returns a mutable
+    /// reference to it.
+    ///
+    /// Otherwise, the map is left unchanged and the key and the value are returned in the
+    /// error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{CapacityError, Entry, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = StableMap::with_max_len(1);
+    ///
+    /// if let Entry::Vacant(v) = map.entry("poneyland") {
+    ///     assert_eq!(v.try_insert_bounded(37), Ok(&mut 37));
+    /// }
+    /// if let Entry::Vacant(v) = map.entry("horseyland") {
+    ///     assert_eq!(
+    ///         v.try_insert_bounded(38),
+    ///         Err(CapacityError { key: "horseyland", value: 38 }),
+    ///     );
+    /// }
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn try_insert_bounded(self, value: V) -> Result<&'a mut V, CapacityError<K, V>>
+    where
+        K: Hash,
+        S: BuildHasher,
+    {
+        if self.entries.is_full() {
+            return Err(CapacityError {
+                key: self.key,
+                value,
+            });
+        }
+        Ok(self.insert(value))
+    }
+
+    /// Sets the value of the entry with the [`VacantEntry`]'s key,
+    /// and returns the index of the entry and a mutable reference to the value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{Entry, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = StableMap::new();
+    /// map.insert("a", 1);
+    ///
+    /// if let Entry::Vacant(v) = map.entry("poneyland") {
+    ///     let (index, value) = v.insert_full(37);
+    ///     assert_eq!(index, 1);
+    ///     *value += 1;
+    /// }
+    /// assert_eq!(map.get_by_index(1), Some(&38));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert_full(self, value: V) -> (usize, &'a mut V)
+    where
+        K: Hash,
+        S: BuildHasher,
+    {
+        let pos = self.entries.insert_hashed(self.hash, value);
+        let (_, pos) = self.entry.insert((self.key, pos)).into_mut();
+        unsafe {
+            // SAFETY: pos was just returned by self.entries.
+            let index = self.entries.index_unchecked(pos);
+            (index, self.entries.get_unchecked_mut(pos))
+        }
+    }
+
     /// Sets the value of the entry with the [`VacantEntry`]'s key,
     /// and returns an [`OccupiedEntry`].
     ///
@@ -592,16 +695,16 @@ impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
     /// }
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S>
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S, A>
     where
         K: Hash,
         S: BuildHasher,
     {
-        let pos = self.entries.insert(value);
-        let entry = self.entry.insert_entry(pos);
+        let pos = self.entries.insert_hashed(self.hash, value);
         OccupiedEntry {
-            entry,
+            entry: self.entry.insert((self.key, pos)),
             entries: self.entries,
+            _phantom: PhantomData,
         }
     }
 
@@ -621,7 +724,7 @@ impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn into_key(self) -> K {
-        self.entry.into_key()
+        self.key
     }
 
     /// Gets a reference to the key that would be used when inserting a value
@@ -637,11 +740,11 @@ impl<'a, K, V, S> VacantEntry<'a, K, V, S> {
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn key(&self) -> &K {
-        self.entry.key()
+        &self.key
     }
 }
 
-impl<'a, K, V, S> Entry<'a, K, V, S> {
+impl<'a, K, V, S, A: Allocator> Entry<'a, K, V, S, A> {
     /// Provides in-place mutable access to an occupied entry before any
     /// potential inserts into the map.
     ///
@@ -750,7 +853,7 @@ impl<'a, K, V, S> Entry<'a, K, V, S> {
     /// assert_eq!(entry.key(), &"horseyland");
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn insert(self, value: V) -> OccupiedEntry<'a, K, V, S>
+    pub fn insert(self, value: V) -> OccupiedEntry<'a, K, V, S, A>
     where
         K: Hash,
         S: BuildHasher,
@@ -918,7 +1021,7 @@ impl<'a, K, V, S> Entry<'a, K, V, S> {
     }
 }
 
-impl<'a, 'b, K, Q, V, S> VacantEntryRef<'a, 'b, K, Q, V, S>
+impl<'a, 'b, K, Q, V, S, A: Allocator> VacantEntryRef<'a, 'b, K, Q, V, S, A>
 where
     Q: ?Sized,
 {
@@ -940,11 +1043,44 @@ where
         K: Hash + From<&'b Q>,
         S: BuildHasher,
     {
-        let pos = self.entries.insert(value);
-        let pos = self.entry.insert(pos);
+        let pos = self.entries.insert_hashed(self.hash, value);
+        let (_, pos) = self.entry.insert((K::from(self.key), pos)).into_mut();
         unsafe { self.entries.get_unchecked_mut(pos) }
     }
 
+    /// Sets the value of the entry with the [`VacantEntryRef`]'s key,
+    /// and returns the index of the entry and a mutable reference to the value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{EntryRef, StableMap};
+    ///
+    /// let mut map: StableMap<String, u32> = StableMap::new();
+    /// map.insert("a".to_string(), 1);
+    ///
+    /// if let EntryRef::Vacant(v) = map.entry_ref("poneyland") {
+    ///     let (index, value) = v.insert_full(37);
+    ///     assert_eq!(index, 1);
+    ///     *value += 1;
+    /// }
+    /// assert_eq!(map.get_by_index(1), Some(&38));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert_full(self, value: V) -> (usize, &'a mut V)
+    where
+        K: Hash + From<&'b Q>,
+        S: BuildHasher,
+    {
+        let pos = self.entries.insert_hashed(self.hash, value);
+        let (_, pos) = self.entry.insert((K::from(self.key), pos)).into_mut();
+        unsafe {
+            // SAFETY: pos was just returned by self.entries.
+            let index = self.entries.index_unchecked(pos);
+            (index, self.entries.get_unchecked_mut(pos))
+        }
+    }
+
     /// Sets the value of the entry with the [`VacantEntryRef`]'s key,
     /// and returns an [`OccupiedEntry`].
     ///
@@ -961,16 +1097,51 @@ where
     /// }
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S>
+    pub fn insert_entry(self, value: V) -> OccupiedEntry<'a, K, V, S, A>
     where
         K: Hash + From<&'b Q>,
         S: BuildHasher,
     {
-        let pos = self.entries.insert(value);
-        let entry = self.entry.insert_entry(pos);
+        let pos = self.entries.insert_hashed(self.hash, value);
         OccupiedEntry {
-            entry,
+            entry: self.entry.insert((K::from(self.key), pos)),
             entries: self.entries,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Sets the value of the entry, creating the key with [`ToOwned`], and returns a
+    /// mutable reference to the value.
+    ///
+    /// Unlike [insert](Self::insert), this function does not require `K: From<&Q>`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {
+    ///     stable_map::{EntryRef, StableMap},
+    ///     std::path::{Path, PathBuf},
+    /// };
+    ///
+    /// let mut map: StableMap<PathBuf, u32> = StableMap::new();
+    ///
+    /// if let EntryRef::Vacant(v) = map.entry_ref(Path::new("/tmp")) {
+    ///     *v.insert_owned(37) += 1;
+    /// }
+    /// assert_eq!(map[Path::new("/tmp")], 38);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert_owned(self, value: V) -> &'a mut V
+    where
+        K: Eq + Hash,
+        Q: ToOwned<Owned = K> + Equivalent<K>,
+        S: BuildHasher,
+    {
+        let pos = self.entries.insert_hashed(self.hash, value);
+        let (_, pos) = self.entry.insert((self.key.to_owned(), pos)).into_mut();
+        unsafe {
+            // SAFETY: pos was just returned by self.entries.
+            self.entries.get_unchecked_mut(pos)
         }
     }
 
@@ -988,11 +1159,57 @@ where
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn key(&self) -> &'b Q {
-        self.entry.key()
+        self.key
+    }
+
+    /// Creates an owned key from the borrowed key without inserting a value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{EntryRef, StableMap};
+    ///
+    /// let mut map: StableMap<String, u32> = StableMap::new();
+    ///
+    /// if let EntryRef::Vacant(v) = map.entry_ref("poneyland") {
+    ///     assert_eq!(v.to_key(), "poneyland");
+    /// }
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn to_key(&self) -> K
+    where
+        K: From<&'b Q>,
+    {
+        K::from(self.key)
+    }
+
+    /// Take ownership of the key.
+    ///
+    /// The owned key is created from the borrowed key with [`From`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{EntryRef, StableMap};
+    ///
+    /// let mut map: StableMap<String, u32> = StableMap::new();
+    ///
+    /// match map.entry_ref("poneyland") {
+    ///     EntryRef::Occupied(_) => panic!(),
+    ///     EntryRef::Vacant(v) => assert_eq!(v.into_key(), "poneyland"),
+    /// }
+    /// assert!(map.is_empty());
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn into_key(self) -> K
+    where
+        K: From<&'b Q>,
+    {
+        self.to_key()
     }
 }
 
-impl<'a, 'b, K, Q, V, S> EntryRef<'a, 'b, K, Q, V, S>
+impl<'a, 'b, K, Q, V, S, A: Allocator> EntryRef<'a, 'b, K, Q, V, S, A>
 where
     Q: ?Sized,
 {
@@ -1040,7 +1257,7 @@ where
     /// assert_eq!(entry.key(), "horseyland");
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn insert(self, value: V) -> OccupiedEntry<'a, K, V, S>
+    pub fn insert(self, value: V) -> OccupiedEntry<'a, K, V, S, A>
     where
         K: Hash + From<&'b Q>,
         S: BuildHasher,
@@ -1141,6 +1358,40 @@ where
         }
     }
 
+    /// Ensures a value is in the entry by inserting the default if empty, and returns
+    /// a mutable reference to the value in the entry.
+    ///
+    /// If the entry is empty, the key is created with [`ToOwned`]. Unlike
+    /// [or_insert](Self::or_insert), this function does not require `K: From<&Q>`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<Vec<u8>, u32> = StableMap::new();
+    ///
+    /// // nonexistent key
+    /// map.entry_ref(&b"poneyland"[..]).or_insert_owned(3);
+    /// assert_eq!(map[&b"poneyland"[..]], 3);
+    ///
+    /// // existing key
+    /// *map.entry_ref(&b"poneyland"[..]).or_insert_owned(10) *= 2;
+    /// assert_eq!(map[&b"poneyland"[..]], 6);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn or_insert_owned(self, value: V) -> &'a mut V
+    where
+        K: Eq + Hash,
+        Q: ToOwned<Owned = K> + Equivalent<K>,
+        S: BuildHasher,
+    {
+        match self {
+            EntryRef::Occupied(o) => o.into_mut(),
+            EntryRef::Vacant(v) => v.insert_owned(value),
+        }
+    }
+
     /// Ensures a value is in the entry by inserting the result of the default function if empty,
     /// and returns a mutable reference to the value in the entry.
     ///
@@ -1208,7 +1459,7 @@ where
     }
 }
 
-impl<K, V, S> Debug for OccupiedEntry<'_, K, V, S>
+impl<K, V, S, A: Allocator> Debug for OccupiedEntry<'_, K, V, S, A>
 where
     K: Debug,
     V: Debug,
@@ -1221,7 +1472,7 @@ where
     }
 }
 
-impl<K, V, S> Debug for VacantEntry<'_, K, V, S>
+impl<K, V, S, A: Allocator> Debug for VacantEntry<'_, K, V, S, A>
 where
     K: Debug,
     V: Debug,
@@ -1231,7 +1482,7 @@ where
     }
 }
 
-impl<K, Q, V, S> Debug for VacantEntryRef<'_, '_, K, Q, V, S>
+impl<K, Q, V, S, A: Allocator> Debug for VacantEntryRef<'_, '_, K, Q, V, S, A>
 where
     Q: Debug,
     V: Debug,
@@ -1241,7 +1492,7 @@ where
     }
 }
 
-impl<K, V, S> Debug for Entry<'_, K, V, S>
+impl<K, V, S, A: Allocator> Debug for Entry<'_, K, V, S, A>
 where
     K: Debug,
     V: Debug,
@@ -1254,7 +1505,7 @@ where
     }
 }
 
-impl<K, Q, V, S> Debug for EntryRef<'_, '_, K, Q, V, S>
+impl<K, Q, V, S, A: Allocator> Debug for EntryRef<'_, '_, K, Q, V, S, A>
 where
     Q: Debug,
     K: Debug,
@@ -1271,67 +1522,73 @@ where
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V, S> Send for OccupiedEntry<'_, K, V, S>
+unsafe impl<K, V, S, A: Allocator> Send for OccupiedEntry<'_, K, V, S, A>
 where
     K: Send,
     V: Send,
     S: Send,
+    A: Send,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V, S> Sync for OccupiedEntry<'_, K, V, S>
+unsafe impl<K, V, S, A: Allocator> Sync for OccupiedEntry<'_, K, V, S, A>
 where
     K: Sync,
     V: Sync,
     S: Sync,
+    A: Sync,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V, S> Send for VacantEntry<'_, K, V, S>
+unsafe impl<K, V, S, A: Allocator> Send for VacantEntry<'_, K, V, S, A>
 where
     K: Send,
     V: Send,
     S: Send,
+    A: Send,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V, S> Sync for VacantEntry<'_, K, V, S>
+unsafe impl<K, V, S, A: Allocator> Sync for VacantEntry<'_, K, V, S, A>
 where
     K: Sync,
     V: Sync,
     S: Sync,
+    A: Sync,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, Q, V, S> Send for VacantEntryRef<'_, '_, K, Q, V, S>
+unsafe impl<K, Q, V, S, A: Allocator> Send for VacantEntryRef<'_, '_, K, Q, V, S, A>
 where
     Q: Send,
     K: Send,
     V: Send,
     S: Send,
+    A: Send,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, Q, V, S> Sync for VacantEntryRef<'_, '_, K, Q, V, S>
+unsafe impl<K, Q, V, S, A: Allocator> Sync for VacantEntryRef<'_, '_, K, Q, V, S, A>
 where
     Q: Sync,
     K: Sync,
     V: Sync,
     S: Sync,
+    A: Sync,
 {
 }
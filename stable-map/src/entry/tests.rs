@@ -378,3 +378,67 @@ fn and_modify() {
         assert_eq!(map.get(&3), None);
     }
 }
+
+#[test]
+fn or_insert_owned() {
+    let mut map = StableMap::<alloc::string::String, i32>::new();
+    assert_eq!(map.entry_ref("a").or_insert_owned(1), &1);
+    assert_eq!(map.entry_ref("a").or_insert_owned(2), &1);
+    let EntryRef::Vacant(v) = map.entry_ref("b") else {
+        panic!();
+    };
+    *v.insert_owned(3) += 1;
+    assert_eq!(map.get("b"), Some(&4));
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn insert_owned_grows_table() {
+    // Exercises the table growth path of `insert_owned`. Run under miri to check that
+    // the owned key is inserted without aliasing the borrowed entry.
+    let mut map = StableMap::<alloc::string::String, usize>::new();
+    for i in 0..64 {
+        let key = alloc::format!("{i}");
+        let EntryRef::Vacant(v) = map.entry_ref(key.as_str()) else {
+            panic!();
+        };
+        assert_eq!(v.key(), key.as_str());
+        *v.insert_owned(i) += 1;
+    }
+    assert_eq!(map.len(), 64);
+    for i in 0..64 {
+        assert_eq!(map[alloc::format!("{i}").as_str()], i + 1);
+    }
+}
+
+#[test]
+fn insert_full() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.remove(&1);
+    let Entry::Vacant(v) = map.entry(3) else {
+        panic!();
+    };
+    let (index, value) = v.insert_full(33);
+    assert_eq!(index, 0);
+    *value += 1;
+    assert_eq!(map.get_by_index(0), Some(&34));
+    let mut map = StableMap::<I, i32>::new();
+    let EntryRef::Vacant(v) = map.entry_ref(&1) else {
+        panic!();
+    };
+    assert_eq!(v.insert_full(11), (0, &mut 11));
+    assert_eq!(map.get_index(&1), Some(0));
+}
+
+#[test]
+fn into_key_ref() {
+    let mut map = StableMap::<I, i32>::new();
+    let EntryRef::Vacant(v) = map.entry_ref(&1) else {
+        panic!();
+    };
+    assert_eq!(v.to_key(), I(1));
+    assert_eq!(v.into_key(), I(1));
+    assert!(map.is_empty());
+}
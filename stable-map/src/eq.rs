@@ -3,10 +3,11 @@ mod tests;
 
 use {
     crate::StableMap,
+    allocator_api2::alloc::Allocator,
     core::hash::{BuildHasher, Hash},
 };
 
-impl<K, V, S> Eq for StableMap<K, V, S>
+impl<K, V, S, A: Allocator> Eq for StableMap<K, V, S, A>
 where
     K: Eq + Hash,
     V: Eq,
@@ -14,7 +15,7 @@ where
 {
 }
 
-impl<K, V, S> PartialEq for StableMap<K, V, S>
+impl<K, V, S, A: Allocator> PartialEq for StableMap<K, V, S, A>
 where
     K: Eq + Hash,
     V: PartialEq,
@@ -3,10 +3,11 @@ mod tests;
 
 use {
     crate::StableMap,
+    allocator_api2::alloc::Allocator,
     core::hash::{BuildHasher, Hash},
 };
 
-impl<'a, K, V, S> Extend<&'a (K, V)> for StableMap<K, V, S>
+impl<'a, K, V, S, A: Allocator> Extend<&'a (K, V)> for StableMap<K, V, S, A>
 where
     K: Eq + Hash + Clone,
     V: Clone,
@@ -19,7 +20,7 @@ where
     }
 }
 
-impl<'a, K, V, S> Extend<(&'a K, &'a V)> for StableMap<K, V, S>
+impl<'a, K, V, S, A: Allocator> Extend<(&'a K, &'a V)> for StableMap<K, V, S, A>
 where
     K: Eq + Hash + Clone,
     V: Clone,
@@ -32,7 +33,14 @@ where
     }
 }
 
-impl<K, V, S> Extend<(K, V)> for StableMap<K, V, S>
+/// Inserts all key-value pairs from the iterator into the map.
+///
+/// # Panics
+///
+/// Panics if this would increase the number of elements beyond
+/// [max_len](StableMap::max_len). Use [try_extend_bounded](StableMap::try_extend_bounded)
+/// to handle this case.
+impl<K, V, S, A: Allocator> Extend<(K, V)> for StableMap<K, V, S, A>
 where
     K: Eq + Hash,
     S: BuildHasher,
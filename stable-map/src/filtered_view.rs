@@ -0,0 +1,182 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::StableMap,
+    allocator_api2::alloc::{Allocator, Global},
+    core::{
+        fmt::{Debug, Formatter},
+        hash::{BuildHasher, Hash},
+    },
+    hashbrown::Equivalent,
+};
+
+/// A read-only view of the entries of a `StableMap` that satisfy a predicate.
+///
+/// The predicate is evaluated lazily whenever an entry is accessed. No entries are
+/// copied.
+///
+/// This `struct` is created by the [`filtered_view`] method on [`StableMap`]. See its
+/// documentation for more.
+///
+/// [`filtered_view`]: crate::StableMap::filtered_view
+/// [`StableMap`]: crate::StableMap
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMap;
+///
+/// let map: StableMap<_, _> = [(1, "a"), (2, "b"), (3, "c")].into();
+///
+/// let view = map.filtered_view(|k, _| k % 2 == 1);
+/// assert_eq!(view.get(&1), Some(&"a"));
+/// assert_eq!(view.get(&2), None);
+/// assert_eq!(view.len(), 2);
+/// ```
+pub struct FilteredView<'a, K, V, S, F, A: Allocator = Global> {
+    pub(crate) map: &'a StableMap<K, V, S, A>,
+    pub(crate) pred: F,
+}
+
+impl<'a, K, V, S, F, A: Allocator> FilteredView<'a, K, V, S, F, A>
+where
+    F: Fn(&K, &V) -> bool,
+{
+    /// Returns a reference to the value corresponding to the key if the entry satisfies
+    /// the predicate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let map: StableMap<_, _> = [(1, 10), (2, 20)].into();
+    /// let view = map.filtered_view(|_, v| *v > 15);
+    /// assert_eq!(view.get(&1), None);
+    /// assert_eq!(view.get(&2), Some(&20));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get<Q>(&self, key: &Q) -> Option<&'a V>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        self.get_key_value(key).map(|(_, v)| v)
+    }
+
+    /// Returns the key-value pair corresponding to the key if the entry satisfies the
+    /// predicate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let map: StableMap<_, _> = [(1, 10), (2, 20)].into();
+    /// let view = map.filtered_view(|_, v| *v > 15);
+    /// assert_eq!(view.get_key_value(&1), None);
+    /// assert_eq!(view.get_key_value(&2), Some((&2, &20)));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_key_value<Q>(&self, key: &Q) -> Option<(&'a K, &'a V)>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        let (k, v) = self.map.get_key_value(key)?;
+        (self.pred)(k, v).then_some((k, v))
+    }
+
+    /// Returns `true` if the view contains an entry for the key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let map: StableMap<_, _> = [(1, 10), (2, 20)].into();
+    /// let view = map.filtered_view(|_, v| *v > 15);
+    /// assert!(!view.contains_key(&1));
+    /// assert!(view.contains_key(&2));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        self.get_key_value(key).is_some()
+    }
+
+    /// An iterator visiting all key-value pairs of the view in arbitrary order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let map: StableMap<_, _> = [(1, 10), (2, 20), (3, 30)].into();
+    /// let view = map.filtered_view(|_, v| *v > 15);
+    /// let mut vec: Vec<_> = view.iter().collect();
+    /// vec.sort_unstable();
+    /// assert_eq!(vec, [(&2, &20), (&3, &30)]);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = (&'a K, &'a V)> + use<'_, 'a, K, V, S, F, A> {
+        self.map.iter().filter(|&(k, v)| (self.pred)(k, v))
+    }
+
+    /// Returns the number of entries in the view.
+    ///
+    /// This function evaluates the predicate for every entry of the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let map: StableMap<_, _> = [(1, 10), (2, 20), (3, 30)].into();
+    /// let view = map.filtered_view(|_, v| *v > 15);
+    /// assert_eq!(view.len(), 2);
+    /// ```
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if the view contains no entries.
+    ///
+    /// This function evaluates the predicate until it finds a matching entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let map: StableMap<_, _> = [(1, 10), (2, 20)].into();
+    /// assert!(!map.filtered_view(|_, v| *v > 15).is_empty());
+    /// assert!(map.filtered_view(|_, v| *v > 25).is_empty());
+    /// ```
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Returns the underlying map.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn map(&self) -> &'a StableMap<K, V, S, A> {
+        self.map
+    }
+}
+
+impl<K, V, S, F, A: Allocator> Debug for FilteredView<'_, K, V, S, F, A>
+where
+    K: Debug,
+    V: Debug,
+    F: Fn(&K, &V) -> bool,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
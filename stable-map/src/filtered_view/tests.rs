@@ -0,0 +1,32 @@
+use {
+    crate::StableMap,
+    alloc::{format, vec::Vec},
+};
+
+#[test]
+fn test() {
+    let mut map = StableMap::new();
+    for i in 0..6 {
+        map.insert(i, i * 10);
+    }
+    let view = map.filtered_view(|k, v| k % 2 == 0 && *v < 40);
+    assert_eq!(view.len(), 2);
+    assert!(!view.is_empty());
+    assert_eq!(view.get(&0), Some(&0));
+    assert_eq!(view.get(&1), None);
+    assert_eq!(view.get(&4), None);
+    assert!(view.contains_key(&2));
+    let mut entries: Vec<_> = view.iter().collect();
+    entries.sort_unstable();
+    assert_eq!(entries, [(&0, &0), (&2, &20)]);
+    assert_eq!(view.map().len(), 6);
+}
+
+#[test]
+fn debug() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    let view = map.filtered_view(|k, _| *k == 2);
+    assert_eq!(format!("{:?}", view), "{2: 22}");
+}
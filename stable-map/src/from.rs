@@ -3,17 +3,19 @@ mod tests;
 
 use {
     crate::StableMap,
+    allocator_api2::alloc::Allocator,
     core::hash::{BuildHasher, Hash},
     hashbrown::HashMap,
 };
 
-impl<K, V, S, const N: usize> From<[(K, V); N]> for StableMap<K, V, S>
+impl<K, V, S, A: Allocator + Clone + Default, const N: usize> From<[(K, V); N]>
+    for StableMap<K, V, S, A>
 where
     K: Eq + Hash,
     S: BuildHasher + Default,
 {
     fn from(value: [(K, V); N]) -> Self {
-        let mut map = Self::with_capacity_and_hasher(N, S::default());
+        let mut map = Self::with_capacity_and_hasher_in(N, S::default(), A::default());
         for (k, v) in value {
             map.insert(k, v);
         }
@@ -21,13 +23,17 @@ where
     }
 }
 
-impl<K, V, S> From<HashMap<K, V, S>> for StableMap<K, V, S>
+impl<K, V, S, A: Allocator + Clone> From<HashMap<K, V, S, A>> for StableMap<K, V, S, A>
 where
     K: Eq + Hash,
     S: BuildHasher + Clone,
 {
-    fn from(value: HashMap<K, V, S>) -> Self {
-        let mut map = Self::with_capacity_and_hasher(value.len(), value.hasher().clone());
+    fn from(value: HashMap<K, V, S, A>) -> Self {
+        let mut map = Self::with_capacity_and_hasher_in(
+            value.len(),
+            value.hasher().clone(),
+            value.allocator().clone(),
+        );
         for (k, v) in value {
             map.insert(k, v);
         }
@@ -35,13 +41,17 @@ where
     }
 }
 
-impl<K, V, S> From<StableMap<K, V, S>> for HashMap<K, V, S>
+impl<K, V, S, A: Allocator + Clone> From<StableMap<K, V, S, A>> for HashMap<K, V, S, A>
 where
     K: Eq + Hash,
     S: BuildHasher + Clone,
 {
-    fn from(value: StableMap<K, V, S>) -> Self {
-        let mut map = Self::with_capacity_and_hasher(value.len(), value.hasher().clone());
+    fn from(value: StableMap<K, V, S, A>) -> Self {
+        let mut map = Self::with_capacity_and_hasher_in(
+            value.len(),
+            value.hasher().clone(),
+            value.allocator().clone(),
+        );
         for (k, v) in value {
             map.insert(k, v);
         }
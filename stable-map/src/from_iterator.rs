@@ -3,10 +3,11 @@ mod tests;
 
 use {
     crate::StableMap,
+    allocator_api2::alloc::Allocator,
     core::hash::{BuildHasher, Hash},
 };
 
-impl<K, V, S> FromIterator<(K, V)> for StableMap<K, V, S>
+impl<K, V, S, A: Allocator + Clone + Default> FromIterator<(K, V)> for StableMap<K, V, S, A>
 where
     K: Eq + Hash,
     S: BuildHasher + Default,
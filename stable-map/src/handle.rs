@@ -0,0 +1,40 @@
+/// A handle to an entry of a map that detects when it has become stale.
+///
+/// A handle consists of the index of the entry and a generation. Unlike a plain index, a
+/// handle stops referring to a value once the value has been removed or moved to a
+/// different index by compaction. It never refers to a value that was later inserted at
+/// the same index.
+///
+/// Handles are returned by [get_handle](crate::StableMap::get_handle).
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMap;
+///
+/// let mut map = StableMap::new();
+/// map.insert(1, "a");
+/// map.insert(2, "b");
+/// let handle = map.get_handle(&2).unwrap();
+/// assert_eq!(map.get_by_handle(handle), Some(&"b"));
+///
+/// map.remove(&1);
+/// map.force_compact();
+/// assert_eq!(map.get_index(&2), Some(0));
+/// assert_eq!(map.get_by_handle(handle), None);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Handle {
+    pub(crate) index: usize,
+    pub(crate) generation: u64,
+}
+
+impl Handle {
+    /// Returns the index that this handle refers to.
+    ///
+    /// The index is returned even if the handle has become stale.
+    #[inline]
+    pub fn index(self) -> usize {
+        self.index
+    }
+}
@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::StableMap,
+    allocator_api2::alloc::Allocator,
+    core::hash::{Hash, Hasher},
+};
+
+/// Hashes the map independently of the order of its entries.
+///
+/// Each entry is hashed with a fixed hasher and the results are combined with a
+/// commutative operation. The hash builder of the map is not used since maps with
+/// different hash builders can be equal.
+impl<K, V, S, A: Allocator> Hash for StableMap<K, V, S, A>
+where
+    K: Hash,
+    V: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let mut sum = 0u64;
+        for (k, v) in self {
+            let mut hasher = Fnv1a::default();
+            k.hash(&mut hasher);
+            v.hash(&mut hasher);
+            sum = sum.wrapping_add(hasher.finish());
+        }
+        state.write_usize(self.len());
+        state.write_u64(sum);
+    }
+}
+
+/// A 64-bit FNV-1a hasher.
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv1a {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
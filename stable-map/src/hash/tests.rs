@@ -0,0 +1,33 @@
+use {
+    crate::StableMap,
+    core::hash::BuildHasher,
+    hashbrown::{DefaultHashBuilder, HashSet},
+};
+
+#[test]
+fn order_independent() {
+    let mut map1 = StableMap::new();
+    let mut map2 = StableMap::new();
+    for i in 0..16 {
+        map1.insert(i, i * 10);
+        map2.insert(15 - i, (15 - i) * 10);
+    }
+    assert_eq!(map1, map2);
+    let state = DefaultHashBuilder::default();
+    assert_eq!(state.hash_one(&map1), state.hash_one(&map2));
+    map2.insert(3, 31);
+    assert_ne!(state.hash_one(&map1), state.hash_one(&map2));
+}
+
+#[test]
+fn hash_set() {
+    let mut map1 = StableMap::new();
+    map1.insert("a", 1);
+    let mut map2 = StableMap::new();
+    map2.insert("b", 2);
+    let mut set: HashSet<StableMap<_, _>> = HashSet::new();
+    assert!(set.insert(map1.clone()));
+    assert!(set.insert(map2));
+    assert!(!set.insert(map1.clone()));
+    assert!(set.contains(&map1));
+}
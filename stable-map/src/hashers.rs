@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests;
+
+use crate::StableMap;
+
+/// A [`StableMap`] that uses the hasher from the [`rustc-hash`] crate.
+///
+/// This hasher is very fast but not resistant to HashDoS attacks.
+///
+/// [`rustc-hash`]: https://docs.rs/rustc-hash
+#[cfg(feature = "rustc-hash")]
+pub type FxStableMap<K, V> = StableMap<K, V, rustc_hash::FxBuildHasher>;
+
+/// A [`StableMap`] that uses the hasher from the [`ahash`] crate.
+///
+/// [`ahash`]: https://docs.rs/ahash
+#[cfg(feature = "ahash")]
+pub type AHashStableMap<K, V> = StableMap<K, V, ahash::RandomState>;
+
+#[cfg(feature = "rustc-hash")]
+impl<K, V> StableMap<K, V, rustc_hash::FxBuildHasher> {
+    /// Creates an empty [`FxStableMap`].
+    ///
+    /// The map is initially created with a capacity of 0, so it will not allocate until it
+    /// is first inserted into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::FxStableMap;
+    /// let mut map: FxStableMap<&str, i32> = FxStableMap::new_fx();
+    /// assert_eq!(map.len(), 0);
+    /// assert_eq!(map.capacity(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn new_fx() -> Self {
+        Self::with_hasher(rustc_hash::FxBuildHasher)
+    }
+
+    /// Creates an empty [`FxStableMap`] with the specified capacity.
+    ///
+    /// The map will be able to hold at least `capacity` elements without
+    /// reallocating. If `capacity` is 0, the map will not allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::FxStableMap;
+    /// let mut map: FxStableMap<&str, i32> = FxStableMap::with_capacity_fx(10);
+    /// assert_eq!(map.len(), 0);
+    /// assert!(map.capacity() >= 10);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_capacity_fx(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, rustc_hash::FxBuildHasher)
+    }
+}
+
+#[cfg(feature = "ahash")]
+impl<K, V> StableMap<K, V, ahash::RandomState> {
+    /// Creates an empty [`AHashStableMap`].
+    ///
+    /// The map is initially created with a capacity of 0, so it will not allocate until it
+    /// is first inserted into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::AHashStableMap;
+    /// let mut map: AHashStableMap<&str, i32> = AHashStableMap::new_ahash();
+    /// assert_eq!(map.len(), 0);
+    /// assert_eq!(map.capacity(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn new_ahash() -> Self {
+        Self::with_hasher(ahash::RandomState::new())
+    }
+
+    /// Creates an empty [`AHashStableMap`] with the specified capacity.
+    ///
+    /// The map will be able to hold at least `capacity` elements without
+    /// reallocating. If `capacity` is 0, the map will not allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::AHashStableMap;
+    /// let mut map: AHashStableMap<&str, i32> = AHashStableMap::with_capacity_ahash(10);
+    /// assert_eq!(map.len(), 0);
+    /// assert!(map.capacity() >= 10);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_capacity_ahash(capacity: usize) -> Self {
+        Self::with_capacity_and_hasher(capacity, ahash::RandomState::new())
+    }
+}
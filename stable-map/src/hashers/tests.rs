@@ -0,0 +1,24 @@
+#[cfg(feature = "ahash")]
+use crate::AHashStableMap;
+#[cfg(feature = "rustc-hash")]
+use crate::FxStableMap;
+
+#[cfg(feature = "rustc-hash")]
+#[test]
+fn fx() {
+    let mut map = FxStableMap::new_fx();
+    map.insert(1, 2);
+    assert_eq!(map.get(&1), Some(&2));
+    let map: FxStableMap<i32, i32> = FxStableMap::with_capacity_fx(10);
+    assert!(map.capacity() >= 10);
+}
+
+#[cfg(feature = "ahash")]
+#[test]
+fn ahash() {
+    let mut map = AHashStableMap::new_ahash();
+    map.insert(1, 2);
+    assert_eq!(map.get(&1), Some(&2));
+    let map: AHashStableMap<i32, i32> = AHashStableMap::with_capacity_ahash(10);
+    assert!(map.capacity() >= 10);
+}
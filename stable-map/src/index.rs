@@ -3,6 +3,7 @@ mod tests;
 
 use {
     crate::StableMap,
+    allocator_api2::alloc::Allocator,
     core::{
         hash::{BuildHasher, Hash},
         ops::Index,
@@ -10,7 +11,7 @@ use {
     hashbrown::Equivalent,
 };
 
-impl<K, Q, V, S> Index<&Q> for StableMap<K, V, S>
+impl<K, Q, V, S, A: Allocator> Index<&Q> for StableMap<K, V, S, A>
 where
     K: Eq + Hash,
     Q: Hash + Equivalent<K> + ?Sized,
@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::{linear_storage::LinearStorage, prefetch},
+    allocator_api2::alloc::{Allocator, Global},
+    core::{
+        fmt::{Debug, Formatter},
+        pin::Pin,
+        task::{Context, Poll},
+    },
+    futures_core::{FusedStream, Stream},
+};
+
+/// A stream over the values of a `StableMap` in the order of their indices.
+/// The stream element type is `(usize, &'a V)`.
+///
+/// After visiting a configurable number of indices, the stream wakes the task and
+/// returns `Poll::Pending` once. This allows huge maps to be traversed inside async
+/// tasks without starving the executor. Unused indices count towards that number.
+///
+/// This `struct` is created by the [`index_stream`] method on [`StableMap`]. See its
+/// documentation for more.
+///
+/// [`index_stream`]: crate::StableMap::index_stream
+/// [`StableMap`]: crate::StableMap
+pub struct IndexStream<'a, V, A: Allocator = Global> {
+    pub(crate) storage: &'a LinearStorage<V, A>,
+    pub(crate) index: usize,
+    pub(crate) yield_every: usize,
+    pub(crate) budget: usize,
+}
+
+impl<'a, V, A: Allocator> Stream for IndexStream<'a, V, A> {
+    type Item = (usize, &'a V);
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        while this.index < this.storage.len() {
+            if this.budget == 0 {
+                this.budget = this.yield_every;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            this.budget -= 1;
+            let index = this.index;
+            this.index += 1;
+            this.storage.prefetch(index + prefetch::DISTANCE);
+            if let Some(value) = this.storage.get(index) {
+                return Poll::Ready(Some((index, value)));
+            }
+        }
+        Poll::Ready(None)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.storage.len() - self.index))
+    }
+}
+
+impl<V, A: Allocator> FusedStream for IndexStream<'_, V, A> {
+    fn is_terminated(&self) -> bool {
+        self.index >= self.storage.len()
+    }
+}
+
+impl<V, A: Allocator> Debug for IndexStream<'_, V, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("IndexStream")
+            .field("index", &self.index)
+            .field("index_len", &self.storage.len())
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,34 @@
+use {
+    crate::StableMap,
+    alloc::vec::Vec,
+    core::{
+        pin::Pin,
+        task::{Context, Poll, Waker},
+    },
+    futures_core::{FusedStream, Stream},
+};
+
+#[test]
+fn test() {
+    let mut map = StableMap::new();
+    for i in 0..5 {
+        map.insert(i, i * 10);
+    }
+    map.remove(&1);
+    map.remove(&2);
+    let mut stream = map.index_stream(2);
+    let mut cx = Context::from_waker(Waker::noop());
+    let mut polls = Vec::new();
+    loop {
+        match Pin::new(&mut stream).poll_next(&mut cx) {
+            Poll::Ready(Some(v)) => polls.push(Some(v)),
+            Poll::Ready(None) => break,
+            Poll::Pending => polls.push(None),
+        }
+    }
+    assert_eq!(
+        polls,
+        [Some((0, &0)), None, Some((3, &30)), None, Some((4, &40))],
+    );
+    assert!(stream.is_terminated());
+}
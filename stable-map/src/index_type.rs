@@ -0,0 +1,85 @@
+/// A type that can be used in place of `usize` to refer to indices of a map.
+///
+/// Implementing this trait for a newtype and using it as the index type of a
+/// [`TypedStableMap`](crate::TypedStableMap) prevents the indices of different maps from
+/// being mixed up.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{IndexType, TypedStableMap};
+///
+/// #[derive(Copy, Clone, Debug, PartialEq)]
+/// struct UserIdx(u32);
+///
+/// impl IndexType for UserIdx {
+///     fn from_index(index: usize) -> Self {
+///         Self(index.try_into().unwrap())
+///     }
+///
+///     fn index(self) -> usize {
+///         self.0 as usize
+///     }
+/// }
+///
+/// #[derive(Copy, Clone, Debug, PartialEq)]
+/// struct OrderIdx(u32);
+///
+/// impl IndexType for OrderIdx {
+///     fn from_index(index: usize) -> Self {
+///         Self(index.try_into().unwrap())
+///     }
+///
+///     fn index(self) -> usize {
+///         self.0 as usize
+///     }
+/// }
+///
+/// let mut users = TypedStableMap::<UserIdx, _, _>::new();
+/// users.insert("alice", 30);
+/// let mut orders = TypedStableMap::<OrderIdx, _, _>::new();
+/// orders.insert(1234, "alice");
+///
+/// let idx = users.get_index("alice").unwrap();
+/// assert_eq!(idx, UserIdx(0));
+/// assert_eq!(users.get_by_index(idx), Some(&30));
+/// ```
+///
+/// Using the index of one map with the other map does not compile:
+///
+/// ```compile_fail
+/// # use stable_map::{IndexType, TypedStableMap};
+/// # #[derive(Copy, Clone)]
+/// # struct UserIdx(u32);
+/// # impl IndexType for UserIdx {
+/// #     fn from_index(index: usize) -> Self { Self(index as u32) }
+/// #     fn index(self) -> usize { self.0 as usize }
+/// # }
+/// # #[derive(Copy, Clone)]
+/// # struct OrderIdx(u32);
+/// # impl IndexType for OrderIdx {
+/// #     fn from_index(index: usize) -> Self { Self(index as u32) }
+/// #     fn index(self) -> usize { self.0 as usize }
+/// # }
+/// let mut users = TypedStableMap::<UserIdx, &str, u32>::new();
+/// users.get_by_index(OrderIdx(0));
+/// ```
+pub trait IndexType: Copy {
+    /// Converts an index into this type.
+    fn from_index(index: usize) -> Self;
+
+    /// Converts this value back into an index.
+    fn index(self) -> usize;
+}
+
+impl IndexType for usize {
+    #[inline]
+    fn from_index(index: usize) -> Self {
+        index
+    }
+
+    #[inline]
+    fn index(self) -> usize {
+        self
+    }
+}
@@ -0,0 +1,215 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::{
+        linear_storage::LinearStorage,
+        pos_vec::pos::{InUse, Pos},
+    },
+    allocator_api2::alloc::{Allocator, Global},
+    core::{
+        fmt::{Debug, Formatter},
+        marker::PhantomData,
+        mem,
+    },
+    hashbrown::hash_table,
+};
+
+/// A view into an occupied entry in a [`StableMap`](crate::StableMap) that was looked up
+/// by its index.
+///
+/// This `struct` is constructed from the [`entry_by_index`] method on [`StableMap`].
+///
+/// [`StableMap`]: crate::StableMap
+/// [`entry_by_index`]: crate::StableMap::entry_by_index
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMap;
+///
+/// let mut map = StableMap::new();
+/// map.extend([("a", 10), ("b", 20), ("c", 30)]);
+/// let index = map.get_index("b").unwrap();
+///
+/// let mut entry = map.entry_by_index(index).unwrap();
+/// assert_eq!(entry.key(), &"b");
+/// assert_eq!(entry.index(), index);
+/// *entry.get_mut() += 1;
+/// assert_eq!(entry.insert(100), 21);
+/// assert_eq!(map[&"b"], 100);
+///
+/// let entry = map.entry_by_index(index).unwrap();
+/// assert_eq!(entry.remove_entry(), ("b", 100));
+/// assert_eq!(map.get(&"b"), None);
+/// assert_eq!(map.len(), 2);
+/// ```
+pub struct IndexedOccupiedEntry<'a, K, V, S, A: Allocator = Global> {
+    pub(crate) entry: hash_table::OccupiedEntry<'a, (K, Pos<InUse>), A>,
+    pub(crate) entries: &'a mut LinearStorage<V, A>,
+    pub(crate) index: usize,
+    pub(crate) _phantom: PhantomData<&'a mut S>,
+}
+
+impl<'a, K, V, S, A: Allocator> IndexedOccupiedEntry<'a, K, V, S, A> {
+    /// Returns the index of the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// assert_eq!(map.entry_by_index(1).unwrap().index(), 1);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Gets a reference to the key in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// assert_eq!(map.entry_by_index(1).unwrap().key(), &"b");
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn key(&self) -> &K {
+        &self.entry.get().0
+    }
+
+    /// Gets a reference to the value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// assert_eq!(map.entry_by_index(1).unwrap().get(), &200);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get(&self) -> &V {
+        unsafe {
+            // SAFETY: By the invariants, self.entry.get() is valid.
+            self.entries.get_unchecked(&self.entry.get().1)
+        }
+    }
+
+    /// Gets a mutable reference to the value in the entry.
+    ///
+    /// If you need a reference to the `IndexedOccupiedEntry` which may outlive the
+    /// destruction of the entry, see [`into_mut`](Self::into_mut).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// *map.entry_by_index(1).unwrap().get_mut() += 1;
+    /// assert_eq!(map["b"], 201);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe {
+            // SAFETY: By the invariants, self.entry.get() is valid.
+            self.entries.get_unchecked_mut(&self.entry.get().1)
+        }
+    }
+
+    /// Converts the entry into a mutable reference to the value in the entry
+    /// with a lifetime bound to the map itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// let value = map.entry_by_index(1).unwrap().into_mut();
+    /// *value += 1;
+    /// assert_eq!(map["b"], 201);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe {
+            // SAFETY: By the invariants, self.entry.get() is valid.
+            self.entries.get_unchecked_mut(&self.entry.get().1)
+        }
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// assert_eq!(map.entry_by_index(1).unwrap().insert(300), 200);
+    /// assert_eq!(map["b"], 300);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Takes the value out of the entry, and returns it.
+    ///
+    /// The key is removed from the map and the index becomes unused.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// assert_eq!(map.entry_by_index(1).unwrap().remove(), 200);
+    /// assert_eq!(map.get("b"), None);
+    /// assert_eq!(map.get_by_index(1), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Takes the ownership of the key and value from the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// assert_eq!(map.entry_by_index(1).unwrap().remove_entry(), ("b", 200));
+    /// assert_eq!(map.get("b"), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn remove_entry(self) -> (K, V) {
+        let ((k, pos), _) = self.entry.remove();
+        let value = unsafe {
+            // SAFETY: By the invariants, pos is valid.
+            self.entries.take_unchecked(pos)
+        };
+        (k, value)
+    }
+}
+
+impl<K, V, S, A: Allocator> Debug for IndexedOccupiedEntry<'_, K, V, S, A>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("IndexedOccupiedEntry")
+            .field("index", &self.index)
+            .field("key", self.key())
+            .field("value", self.get())
+            .finish()
+    }
+}
@@ -0,0 +1,32 @@
+use {crate::StableMap, alloc::format};
+
+#[test]
+fn entry_by_index() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.insert(3, 33);
+    map.remove(&2);
+    assert!(map.entry_by_index(1).is_none());
+    assert!(map.entry_by_index(3).is_none());
+    let mut entry = map.entry_by_index(2).unwrap();
+    assert_eq!(entry.index(), 2);
+    assert_eq!(entry.key(), &3);
+    assert_eq!(entry.get(), &33);
+    assert_eq!(entry.insert(34), 33);
+    *entry.get_mut() += 1;
+    assert_eq!(
+        format!("{entry:?}"),
+        "IndexedOccupiedEntry { index: 2, key: 3, value: 35 }"
+    );
+    assert_eq!(entry.into_mut(), &mut 35);
+    assert_eq!(map.get(&3), Some(&35));
+    assert_eq!(map.entry_by_index(0).unwrap().remove_entry(), (1, 11));
+    assert_eq!(map.get(&1), None);
+    assert!(!map.contains_index(0));
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.entry_by_index(2).unwrap().remove(), 35);
+    assert!(map.is_empty());
+    map.insert(4, 44);
+    assert_eq!(map.get_index(&4), Some(0));
+}
@@ -6,11 +6,12 @@ use {
         linear_storage::LinearStorage,
         pos_vec::pos::{InUse, Pos},
     },
+    allocator_api2::alloc::{Allocator, Global},
     core::{
         fmt::{Debug, Formatter},
         iter::FusedIterator,
     },
-    hashbrown::hash_map,
+    hashbrown::hash_table,
 };
 
 /// An owning iterator over the entries of a `StableMap` in arbitrary order.
@@ -43,12 +44,12 @@ use {
 /// assert_eq!(iter.next(), None);
 /// assert_eq!(iter.next(), None);
 /// ```
-pub struct IntoIter<K, V> {
-    pub(crate) iter: hash_map::IntoIter<K, Pos<InUse>>,
-    pub(crate) storage: LinearStorage<V>,
+pub struct IntoIter<K, V, A: Allocator = Global> {
+    pub(crate) iter: hash_table::IntoIter<(K, Pos<InUse>), A>,
+    pub(crate) storage: LinearStorage<V, A>,
 }
 
-impl<K, V> Iterator for IntoIter<K, V> {
+impl<K, V, A: Allocator> Iterator for IntoIter<K, V, A> {
     type Item = (K, V);
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -66,15 +67,15 @@ impl<K, V> Iterator for IntoIter<K, V> {
     }
 }
 
-impl<K, V> Debug for IntoIter<K, V> {
+impl<K, V, A: Allocator> Debug for IntoIter<K, V, A> {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("IntoIter").finish_non_exhaustive()
     }
 }
 
-impl<K, V> FusedIterator for IntoIter<K, V> {}
+impl<K, V, A: Allocator> FusedIterator for IntoIter<K, V, A> {}
 
-impl<K, V> ExactSizeIterator for IntoIter<K, V> {
+impl<K, V, A: Allocator> ExactSizeIterator for IntoIter<K, V, A> {
     fn len(&self) -> usize {
         self.iter.len()
     }
@@ -92,19 +93,21 @@ impl<K, V> Default for IntoIter<K, V> {
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V> Send for IntoIter<K, V>
+unsafe impl<K, V, A: Allocator> Send for IntoIter<K, V, A>
 where
     K: Send,
     V: Send,
+    A: Send,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V> Sync for IntoIter<K, V>
+unsafe impl<K, V, A: Allocator> Sync for IntoIter<K, V, A>
 where
     K: Sync,
     V: Sync,
+    A: Sync,
 {
 }
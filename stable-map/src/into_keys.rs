@@ -3,11 +3,12 @@ mod tests;
 
 use {
     crate::pos_vec::pos::{InUse, Pos},
+    allocator_api2::alloc::{Allocator, Global},
     core::{
         fmt::{Debug, Formatter},
         iter::FusedIterator,
     },
-    hashbrown::hash_map,
+    hashbrown::hash_table,
 };
 
 /// An owning iterator over the keys of a `StableMap` in arbitrary order.
@@ -39,15 +40,15 @@ use {
 /// assert_eq!(keys.next(), None);
 /// assert_eq!(keys.next(), None);
 /// ```
-pub struct IntoKeys<K> {
-    pub(crate) iter: hash_map::IntoKeys<K, Pos<InUse>>,
+pub struct IntoKeys<K, A: Allocator = Global> {
+    pub(crate) iter: hash_table::IntoIter<(K, Pos<InUse>), A>,
 }
 
-impl<K> Iterator for IntoKeys<K> {
+impl<K, A: Allocator> Iterator for IntoKeys<K, A> {
     type Item = K;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next()
+        self.iter.next().map(|(k, _)| k)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
@@ -55,21 +56,21 @@ impl<K> Iterator for IntoKeys<K> {
     }
 }
 
-impl<K> Debug for IntoKeys<K> {
+impl<K, A: Allocator> Debug for IntoKeys<K, A> {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("IntoKeys").finish_non_exhaustive()
     }
 }
 
-impl<K> FusedIterator for IntoKeys<K> {}
+impl<K, A: Allocator> FusedIterator for IntoKeys<K, A> {}
 
-impl<K> ExactSizeIterator for IntoKeys<K> {
+impl<K, A: Allocator> ExactSizeIterator for IntoKeys<K, A> {
     fn len(&self) -> usize {
         self.iter.len()
     }
 }
 
-impl<K> Default for IntoKeys<K> {
+impl<K, A: Allocator> Default for IntoKeys<K, A> {
     fn default() -> Self {
         Self {
             iter: Default::default(),
@@ -80,9 +81,19 @@ impl<K> Default for IntoKeys<K> {
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K> Send for IntoKeys<K> where K: Send {}
+unsafe impl<K, A: Allocator> Send for IntoKeys<K, A>
+where
+    K: Send,
+    A: Send,
+{
+}
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K> Sync for IntoKeys<K> where K: Sync {}
+unsafe impl<K, A: Allocator> Sync for IntoKeys<K, A>
+where
+    K: Sync,
+    A: Sync,
+{
+}
@@ -6,11 +6,12 @@ use {
         linear_storage::LinearStorage,
         pos_vec::pos::{InUse, Pos},
     },
+    allocator_api2::alloc::{Allocator, Global},
     core::{
         fmt::{Debug, Formatter},
         iter::FusedIterator,
     },
-    hashbrown::hash_map,
+    hashbrown::hash_table,
 };
 
 /// An owning iterator over the values of a `StableMap` in arbitrary order.
@@ -41,16 +42,16 @@ use {
 /// assert_eq!(values.next(), None);
 /// assert_eq!(values.next(), None);
 /// ```
-pub struct IntoValues<K, V> {
-    pub(crate) iter: hash_map::IntoValues<K, Pos<InUse>>,
-    pub(crate) storage: LinearStorage<V>,
+pub struct IntoValues<K, V, A: Allocator = Global> {
+    pub(crate) iter: hash_table::IntoIter<(K, Pos<InUse>), A>,
+    pub(crate) storage: LinearStorage<V, A>,
 }
 
-impl<K, V> Iterator for IntoValues<K, V> {
+impl<K, V, A: Allocator> Iterator for IntoValues<K, V, A> {
     type Item = V;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let pos = self.iter.next()?;
+        let (_, pos) = self.iter.next()?;
         let value = unsafe { self.storage.take_unchecked(pos) };
         Some(value)
     }
@@ -60,15 +61,15 @@ impl<K, V> Iterator for IntoValues<K, V> {
     }
 }
 
-impl<K, V> Debug for IntoValues<K, V> {
+impl<K, V, A: Allocator> Debug for IntoValues<K, V, A> {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("IntoValues").finish_non_exhaustive()
     }
 }
 
-impl<K, V> FusedIterator for IntoValues<K, V> {}
+impl<K, V, A: Allocator> FusedIterator for IntoValues<K, V, A> {}
 
-impl<K, V> ExactSizeIterator for IntoValues<K, V> {
+impl<K, V, A: Allocator> ExactSizeIterator for IntoValues<K, V, A> {
     fn len(&self) -> usize {
         self.iter.len()
     }
@@ -86,19 +87,21 @@ impl<K, V> Default for IntoValues<K, V> {
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V> Send for IntoValues<K, V>
+unsafe impl<K, V, A: Allocator> Send for IntoValues<K, V, A>
 where
     K: Send,
     V: Send,
+    A: Send,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V> Sync for IntoValues<K, V>
+unsafe impl<K, V, A: Allocator> Sync for IntoValues<K, V, A>
 where
     K: Sync,
     V: Sync,
+    A: Sync,
 {
 }
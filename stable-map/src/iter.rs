@@ -7,11 +7,12 @@ use {
         map::StableMap,
         pos_vec::pos::{InUse, Pos},
     },
+    allocator_api2::alloc::{Allocator, Global},
     core::{
         fmt::{Debug, Formatter},
         iter::FusedIterator,
     },
-    hashbrown::hash_map,
+    hashbrown::hash_table,
 };
 
 /// An iterator over the entries of a `StableMap` in arbitrary order.
@@ -42,12 +43,12 @@ use {
 /// assert_eq!(iter.next(), None);
 /// assert_eq!(iter.next(), None);
 /// ```
-pub struct Iter<'a, K, V> {
-    pub(crate) iter: hash_map::Iter<'a, K, Pos<InUse>>,
-    pub(crate) entries: &'a LinearStorage<V>,
+pub struct Iter<'a, K, V, A: Allocator = Global> {
+    pub(crate) iter: hash_table::Iter<'a, (K, Pos<InUse>)>,
+    pub(crate) entries: &'a LinearStorage<V, A>,
 }
 
-impl<'a, K, V> Iterator for Iter<'a, K, V> {
+impl<'a, K, V, A: Allocator> Iterator for Iter<'a, K, V, A> {
     type Item = (&'a K, &'a V);
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -61,16 +62,16 @@ impl<'a, K, V> Iterator for Iter<'a, K, V> {
     }
 }
 
-impl<'a, K, V, S> IntoIterator for &'a StableMap<K, V, S> {
+impl<'a, K, V, S, A: Allocator> IntoIterator for &'a StableMap<K, V, S, A> {
     type Item = (&'a K, &'a V);
-    type IntoIter = Iter<'a, K, V>;
+    type IntoIter = Iter<'a, K, V, A>;
 
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
 }
 
-impl<K, V> Clone for Iter<'_, K, V> {
+impl<K, V, A: Allocator> Clone for Iter<'_, K, V, A> {
     fn clone(&self) -> Self {
         Self {
             iter: self.iter.clone(),
@@ -79,7 +80,7 @@ impl<K, V> Clone for Iter<'_, K, V> {
     }
 }
 
-impl<K, V> Debug for Iter<'_, K, V>
+impl<K, V, A: Allocator> Debug for Iter<'_, K, V, A>
 where
     K: Debug,
     V: Debug,
@@ -89,9 +90,9 @@ where
     }
 }
 
-impl<K, V> FusedIterator for Iter<'_, K, V> {}
+impl<K, V, A: Allocator> FusedIterator for Iter<'_, K, V, A> {}
 
-impl<K, V> ExactSizeIterator for Iter<'_, K, V> {
+impl<K, V, A: Allocator> ExactSizeIterator for Iter<'_, K, V, A> {
     fn len(&self) -> usize {
         self.iter.len()
     }
@@ -100,19 +101,21 @@ impl<K, V> ExactSizeIterator for Iter<'_, K, V> {
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V> Send for Iter<'_, K, V>
+unsafe impl<K, V, A: Allocator> Send for Iter<'_, K, V, A>
 where
     K: Send,
     V: Send,
+    A: Sync,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V> Sync for Iter<'_, K, V>
+unsafe impl<K, V, A: Allocator> Sync for Iter<'_, K, V, A>
 where
     K: Sync,
     V: Sync,
+    A: Sync,
 {
 }
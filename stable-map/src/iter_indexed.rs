@@ -0,0 +1,95 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::{
+        linear_storage::LinearStorage,
+        pos_vec::pos::{InUse, Pos},
+    },
+    allocator_api2::alloc::{Allocator, Global},
+    core::{
+        fmt::{Debug, Formatter},
+        iter::FusedIterator,
+    },
+    hashbrown::hash_table,
+};
+
+/// An iterator over the entries of a `StableMap` and their indices in arbitrary order.
+/// The iterator element type is `(usize, &'a K, &'a V)`.
+///
+/// This `struct` is created by the [`iter_indexed`] method on [`StableMap`]. See its
+/// documentation for more.
+///
+/// [`iter_indexed`]: crate::StableMap::iter_indexed
+/// [`StableMap`]: crate::StableMap
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMap;
+///
+/// let map: StableMap<_, _> = [(1, "a"), (2, "b"), (3, "c")].into();
+///
+/// let mut iter = map.iter_indexed();
+/// let mut vec = vec![iter.next(), iter.next(), iter.next()];
+///
+/// // The `IterIndexed` iterator produces items in arbitrary order, so the
+/// // items must be sorted to test them against a sorted array.
+/// vec.sort_unstable();
+/// assert_eq!(vec, [Some((0, &1, &"a")), Some((1, &2, &"b")), Some((2, &3, &"c"))]);
+///
+/// // It is fused iterator
+/// assert_eq!(iter.next(), None);
+/// assert_eq!(iter.next(), None);
+/// ```
+pub struct IterIndexed<'a, K, V, A: Allocator = Global> {
+    pub(crate) iter: hash_table::Iter<'a, (K, Pos<InUse>)>,
+    pub(crate) entries: &'a LinearStorage<V, A>,
+}
+
+impl<'a, K, V, A: Allocator> Iterator for IterIndexed<'a, K, V, A> {
+    type Item = (usize, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (k, pos) = self.iter.next()?;
+        let (index, v) = unsafe {
+            // SAFETY: By the invariants, pos is valid.
+            (
+                self.entries.index_unchecked(pos),
+                self.entries.get_unchecked(pos),
+            )
+        };
+        Some((index, k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V, A: Allocator> Clone for IterIndexed<'_, K, V, A> {
+    fn clone(&self) -> Self {
+        Self {
+            iter: self.iter.clone(),
+            entries: self.entries,
+        }
+    }
+}
+
+impl<K, V, A: Allocator> Debug for IterIndexed<'_, K, V, A>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<K, V, A: Allocator> FusedIterator for IterIndexed<'_, K, V, A> {}
+
+impl<K, V, A: Allocator> ExactSizeIterator for IterIndexed<'_, K, V, A> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
@@ -0,0 +1,28 @@
+use {
+    crate::{IterIndexed, StableMap},
+    alloc::vec::Vec,
+    core::cell::Cell,
+    static_assertions::{assert_impl_all, assert_not_impl_any},
+};
+
+assert_impl_all!(IterIndexed<'static, i32, i32>: Send, Sync);
+assert_not_impl_any!(IterIndexed<'static, i32, Cell<i32>>: Send, Sync);
+assert_not_impl_any!(IterIndexed<'static, Cell<i32>, i32>: Send, Sync);
+
+#[test]
+fn test() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.insert(3, 33);
+    map.remove(&2);
+    let iter = map.iter_indexed();
+    assert_eq!(iter.len(), 2);
+    let mut linear = iter.collect::<Vec<_>>();
+    linear.sort();
+    assert_eq!(linear, [(0, &1, &11), (2, &3, &33)]);
+    for (index, k, v) in map.iter_indexed() {
+        assert_eq!(map.get_index(k), Some(index));
+        assert_eq!(map.get_by_index(index), Some(v));
+    }
+}
@@ -9,11 +9,12 @@ use {
             PosVecRawAccess,
         },
     },
+    allocator_api2::alloc::Allocator,
     core::{
         fmt::{Debug, Formatter},
         iter::FusedIterator,
     },
-    hashbrown::hash_map,
+    hashbrown::hash_table,
 };
 
 /// A mutable iterator over the entries of a `StableMap` in arbitrary order.
@@ -44,7 +45,7 @@ use {
 /// assert_eq!(map.get(&2).unwrap(), &"Two Mississippi".to_owned());
 /// ```
 pub struct IterMut<'a, K, V> {
-    pub(crate) iter: hash_map::IterMut<'a, K, Pos<InUse>>,
+    pub(crate) iter: hash_table::IterMut<'a, (K, Pos<InUse>)>,
     pub(crate) entries: PosVecRawAccess<'a, V>,
 }
 
@@ -62,7 +63,7 @@ impl<'a, K, V> Iterator for IterMut<'a, K, V> {
     }
 }
 
-impl<'a, K, V, S> IntoIterator for &'a mut StableMap<K, V, S> {
+impl<'a, K, V, S, A: Allocator> IntoIterator for &'a mut StableMap<K, V, S, A> {
     type Item = (&'a K, &'a mut V);
     type IntoIter = IterMut<'a, K, V>;
 
@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::pos_vec::{
+        pos::{InUse, Pos},
+        PosVecRawAccess,
+    },
+    core::{
+        fmt::{Debug, Formatter},
+        iter::FusedIterator,
+    },
+    hashbrown::hash_table,
+};
+
+/// A mutable iterator over the entries of a `StableMap` and their indices in arbitrary
+/// order. The iterator element type is `(usize, &'a K, &'a mut V)`.
+///
+/// This `struct` is created by the [`iter_mut_indexed`] method on [`StableMap`]. See its
+/// documentation for more.
+///
+/// [`iter_mut_indexed`]: crate::StableMap::iter_mut_indexed
+/// [`StableMap`]: crate::StableMap
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMap;
+///
+/// let mut map: StableMap<_, _> = [(1, "One".to_owned()), (2, "Two".into())].into();
+///
+/// let mut indices = vec![];
+/// let mut iter = map.iter_mut_indexed();
+/// iter.next().map(|(i, _, v)| { indices.push(i); v.push_str(" Mississippi") });
+/// iter.next().map(|(i, _, v)| { indices.push(i); v.push_str(" Mississippi") });
+///
+/// // It is fused iterator
+/// assert_eq!(iter.next(), None);
+/// assert_eq!(iter.next(), None);
+///
+/// indices.sort_unstable();
+/// assert_eq!(indices, [0, 1]);
+/// assert_eq!(map.get_by_index(0).unwrap(), &"One Mississippi".to_owned());
+/// assert_eq!(map.get_by_index(1).unwrap(), &"Two Mississippi".to_owned());
+/// ```
+pub struct IterMutIndexed<'a, K, V> {
+    pub(crate) iter: hash_table::IterMut<'a, (K, Pos<InUse>)>,
+    pub(crate) entries: PosVecRawAccess<'a, V>,
+}
+
+impl<'a, K, V> Iterator for IterMutIndexed<'a, K, V> {
+    type Item = (usize, &'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (k, pos) = self.iter.next()?;
+        let (index, value) = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid.
+            // - Each pos is yielded by the hash map at most once.
+            (
+                self.entries.index_unchecked(pos),
+                self.entries.get_unchecked_mut(pos),
+            )
+        };
+        Some((index, k, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<K, V> Debug for IterMutIndexed<'_, K, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("IterMutIndexed").finish_non_exhaustive()
+    }
+}
+
+impl<K, V> FusedIterator for IterMutIndexed<'_, K, V> {}
+
+impl<K, V> ExactSizeIterator for IterMutIndexed<'_, K, V> {
+    fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+// SAFETY:
+// - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
+//   but this API prevents this.
+unsafe impl<K, V> Send for IterMutIndexed<'_, K, V>
+where
+    K: Send,
+    V: Send,
+{
+}
+
+// SAFETY:
+// - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
+//   but this API prevents this.
+unsafe impl<K, V> Sync for IterMutIndexed<'_, K, V>
+where
+    K: Sync,
+    V: Sync,
+{
+}
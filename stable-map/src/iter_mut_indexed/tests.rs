@@ -0,0 +1,20 @@
+use {crate::StableMap, alloc::vec::Vec};
+
+#[test]
+fn test() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.insert(3, 33);
+    map.remove(&1);
+    let iter = map.iter_mut_indexed();
+    assert_eq!(iter.len(), 2);
+    let mut linear = iter.collect::<Vec<_>>();
+    linear.sort();
+    assert_eq!(linear, [(1, &2, &mut 22), (2, &3, &mut 33)]);
+    for (index, _, v) in map.iter_mut_indexed() {
+        *v += index;
+    }
+    assert_eq!(map.get_by_index(1), Some(&23));
+    assert_eq!(map.get_by_index(2), Some(&35));
+}
@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::pos_vec::SparseSlots,
+    alloc::vec,
+    core::{
+        fmt::{Debug, Formatter},
+        iter::FusedIterator,
+    },
+};
+
+/// An iterator over the indices of a `StableMap` in ascending order.
+/// The iterator element type is `Option<(&'a K, &'a V)>`.
+///
+/// The iterator yields one element for each index below
+/// [`index_len`](crate::StableMap::index_len). The element is `None` if the index is
+/// unused.
+///
+/// This `struct` is created by the [`iter_sparse`] method on [`StableMap`]. See its
+/// documentation for more.
+///
+/// [`iter_sparse`]: crate::StableMap::iter_sparse
+/// [`StableMap`]: crate::StableMap
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMap;
+///
+/// let mut map: StableMap<_, _> = [(1, "a"), (2, "b"), (3, "c")].into();
+/// map.remove(&2);
+///
+/// let mut iter = map.iter_sparse();
+/// assert_eq!(iter.len(), 3);
+/// assert_eq!(iter.next(), Some(Some((&1, &"a"))));
+/// assert_eq!(iter.next(), Some(None));
+/// assert_eq!(iter.next(), Some(Some((&3, &"c"))));
+///
+/// // It is fused iterator
+/// assert_eq!(iter.next(), None);
+/// assert_eq!(iter.next(), None);
+/// ```
+pub struct IterSparse<'a, K, V> {
+    pub(crate) keys: vec::IntoIter<Option<&'a K>>,
+    pub(crate) values: SparseSlots<'a, V>,
+}
+
+#[cfg_attr(feature = "inline-more", inline)]
+fn zip<'a, K, V>(key: Option<&'a K>, value: Option<&'a V>) -> Option<(&'a K, &'a V)> {
+    debug_assert_eq!(key.is_some(), value.is_some());
+    Some((key?, value?))
+}
+
+impl<'a, K, V> Iterator for IterSparse<'a, K, V> {
+    type Item = Option<(&'a K, &'a V)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = self.keys.next()?;
+        let value = self.values.next()?;
+        Some(zip(key, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.keys.size_hint()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IterSparse<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let key = self.keys.next_back()?;
+        let value = self.values.next_back()?;
+        Some(zip(key, value))
+    }
+}
+
+impl<K, V> Clone for IterSparse<'_, K, V> {
+    fn clone(&self) -> Self {
+        Self {
+            keys: self.keys.clone(),
+            values: self.values.clone(),
+        }
+    }
+}
+
+impl<K, V> Debug for IterSparse<'_, K, V>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<K, V> FusedIterator for IterSparse<'_, K, V> {}
+
+impl<K, V> ExactSizeIterator for IterSparse<'_, K, V> {}
@@ -0,0 +1,33 @@
+use {
+    crate::StableMap,
+    alloc::{format, vec::Vec},
+};
+
+#[test]
+fn iter_sparse() {
+    let mut map = StableMap::new();
+    assert_eq!(map.iter_sparse().len(), 0);
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.insert(3, 33);
+    map.remove(&2);
+    let iter = map.iter_sparse();
+    assert_eq!(iter.len(), map.index_len());
+    assert_eq!(
+        iter.collect::<Vec<_>>(),
+        [Some((&1, &11)), None, Some((&3, &33))]
+    );
+    assert_eq!(
+        map.iter_sparse().rev().collect::<Vec<_>>(),
+        [Some((&3, &33)), None, Some((&1, &11))]
+    );
+    assert_eq!(
+        format!("{:?}", map.iter_sparse()),
+        "[Some((1, 11)), None, Some((3, 33))]"
+    );
+    map.force_compact();
+    assert_eq!(
+        map.iter_sparse().collect::<Vec<_>>(),
+        [Some((&1, &11)), Some((&3, &33))]
+    );
+}
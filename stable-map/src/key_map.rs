@@ -0,0 +1,428 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::{
+        linear_storage::LinearStorage,
+        pos_vec::pos::{InUse, Pos},
+    },
+    allocator_api2::alloc::{Allocator, Global},
+    core::hash::{BuildHasher, Hash},
+    hashbrown::{hash_table, Equivalent, HashTable, TryReserveError},
+};
+
+/// The map from keys to the positions of their values.
+///
+/// This is a `HashTable` together with its hasher. It provides the subset of the
+/// `HashMap` API used by the stable map. Unlike `HashMap`, it also gives access to the
+/// entries of the table. This allows an entry to insert a key that is only created
+/// after the entry has been found to be vacant.
+pub(crate) struct KeyMap<K, S, A: Allocator = Global> {
+    table: HashTable<(K, Pos<InUse>), A>,
+    hash_builder: S,
+}
+
+impl<K, S> KeyMap<K, S> {
+    /// Creates a new map with the requested capacity.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            table: HashTable::with_capacity(capacity),
+            hash_builder,
+        }
+    }
+}
+
+impl<K, S, A: Allocator> KeyMap<K, S, A> {
+    /// Creates a new map with the requested capacity in the allocator.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn with_capacity_and_hasher_in(capacity: usize, hash_builder: S, alloc: A) -> Self {
+        Self {
+            table: HashTable::with_capacity_in(capacity, alloc),
+            hash_builder,
+        }
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn hasher(&self) -> &S {
+        &self.hash_builder
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn allocator(&self) -> &A {
+        self.table.allocator()
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn capacity(&self) -> usize {
+        self.table.capacity()
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn clear(&mut self) {
+        self.table.clear();
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn iter(&self) -> hash_table::Iter<'_, (K, Pos<InUse>)> {
+        self.table.iter()
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn iter_mut(&mut self) -> hash_table::IterMut<'_, (K, Pos<InUse>)> {
+        self.table.iter_mut()
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn drain(&mut self) -> hash_table::Drain<'_, (K, Pos<InUse>), A> {
+        self.table.drain()
+    }
+
+    /// Removes and yields the elements for which `f` returns `true`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn extract_if<F>(
+        &mut self,
+        mut f: F,
+    ) -> impl Iterator<Item = (K, Pos<InUse>)> + use<'_, K, S, A, F>
+    where
+        F: FnMut(&K, &mut Pos<InUse>) -> bool,
+    {
+        self.table.extract_if(move |(k, pos)| f(k, pos))
+    }
+
+    /// Returns the element with the hash for which `is_match` returns `true`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn find<F>(&self, hash: u64, mut is_match: F) -> Option<(&K, &Pos<InUse>)>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.table
+            .find(hash, |(k, _)| is_match(k))
+            .map(|(k, pos)| (k, pos))
+    }
+
+    /// Returns the element with the hash whose position satisfies `is_match`.
+    #[cfg(feature = "cached-hash")]
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn find_pos<F>(&self, hash: u64, mut is_match: F) -> Option<(&K, &Pos<InUse>)>
+    where
+        F: FnMut(&Pos<InUse>) -> bool,
+    {
+        self.table
+            .find(hash, |(_, pos)| is_match(pos))
+            .map(|(k, pos)| (k, pos))
+    }
+
+    /// Returns the entry with the hash whose position satisfies `is_match`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn find_pos_entry<F>(
+        &mut self,
+        hash: u64,
+        mut is_match: F,
+    ) -> Option<hash_table::OccupiedEntry<'_, (K, Pos<InUse>), A>>
+    where
+        F: FnMut(&Pos<InUse>) -> bool,
+    {
+        self.table.find_entry(hash, |(_, pos)| is_match(pos)).ok()
+    }
+
+    /// Returns the table and the hasher.
+    #[cfg(feature = "raw-entry")]
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn table_mut(&mut self) -> (&mut HashTable<(K, Pos<InUse>), A>, &S) {
+        (&mut self.table, &self.hash_builder)
+    }
+}
+
+impl<K, S, A: Allocator> KeyMap<K, S, A>
+where
+    K: Hash,
+    S: BuildHasher,
+{
+    /// Computes the hash of a key.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn hash<Q>(&self, key: &Q) -> u64
+    where
+        Q: Hash + ?Sized,
+    {
+        self.hash_builder.hash_one(key)
+    }
+
+    /// Computes the hash of a key that is contained in the map.
+    ///
+    /// `storage` must be the storage of the values. With the `cached-hash` feature, the
+    /// hash stored with the value is returned instead of hashing the key again.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn stored_hash<V, B: Allocator>(
+        &self,
+        storage: &LinearStorage<V, B>,
+        key: &K,
+        pos: &Pos<InUse>,
+    ) -> u64 {
+        rehash(&self.hash_builder, storage, key, pos)
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn reserve<V, B: Allocator>(
+        &mut self,
+        additional: usize,
+        storage: &LinearStorage<V, B>,
+    ) {
+        let hash_builder = &self.hash_builder;
+        self.table
+            .reserve(additional, |(k, pos)| rehash(hash_builder, storage, k, pos));
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn try_reserve<V, B: Allocator>(
+        &mut self,
+        additional: usize,
+        storage: &LinearStorage<V, B>,
+    ) -> Result<(), TryReserveError> {
+        let hash_builder = &self.hash_builder;
+        self.table
+            .try_reserve(additional, |(k, pos)| rehash(hash_builder, storage, k, pos))
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn shrink_to_fit<V, B: Allocator>(&mut self, storage: &LinearStorage<V, B>) {
+        let hash_builder = &self.hash_builder;
+        self.table
+            .shrink_to_fit(|(k, pos)| rehash(hash_builder, storage, k, pos));
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn get<Q>(&self, key: &Q) -> Option<&Pos<InUse>>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.get_key_value(key).map(|(_, pos)| pos)
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn get_mut<Q>(&mut self, key: &Q) -> Option<&mut Pos<InUse>>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let hash = self.hash(key);
+        self.table
+            .find_mut(hash, |(k, _)| key.equivalent(k))
+            .map(|(_, pos)| pos)
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn get_key_value<Q>(&self, key: &Q) -> Option<(&K, &Pos<InUse>)>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let hash = self.hash(key);
+        self.table
+            .find(hash, |(k, _)| key.equivalent(k))
+            .map(|(k, pos)| (k, pos))
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.get_key_value(key).is_some()
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn remove<Q>(&mut self, key: &Q) -> Option<Pos<InUse>>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.remove_entry(key).map(|(_, pos)| pos)
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, Pos<InUse>)>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.find_entry(key).map(|entry| entry.remove().0)
+    }
+
+    /// Returns the entry of the key if the key is contained in the map.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn find_entry<Q>(
+        &mut self,
+        key: &Q,
+    ) -> Option<hash_table::OccupiedEntry<'_, (K, Pos<InUse>), A>>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let hash = self.hash(key);
+        self.table.find_entry(hash, |(k, _)| key.equivalent(k)).ok()
+    }
+
+    /// Returns the entry of the key.
+    ///
+    /// `hash` must be the hash of the key and `storage` must be the storage of the values.
+    /// If the entry is vacant, the table has capacity for at least one more element.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn entry<Q, V, B: Allocator>(
+        &mut self,
+        hash: u64,
+        key: &Q,
+        storage: &LinearStorage<V, B>,
+    ) -> hash_table::Entry<'_, (K, Pos<InUse>), A>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let hash_builder = &self.hash_builder;
+        self.table.entry(
+            hash,
+            |(k, _)| key.equivalent(k),
+            |(k, pos)| rehash(hash_builder, storage, k, pos),
+        )
+    }
+
+    /// Inserts a key that is not yet contained in the map.
+    ///
+    /// `hash` must be the hash of the key and `storage` must be the storage of the values.
+    /// If the key is already contained in the map, the map is left in an unspecified but
+    /// memory-safe state.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn insert_unique<V, B: Allocator>(
+        &mut self,
+        hash: u64,
+        key: K,
+        pos: Pos<InUse>,
+        storage: &LinearStorage<V, B>,
+    ) -> (&K, &mut Pos<InUse>) {
+        let hash_builder = &self.hash_builder;
+        let (k, pos) = self
+            .table
+            .insert_unique(hash, (key, pos), |(k, pos)| {
+                rehash(hash_builder, storage, k, pos)
+            })
+            .into_mut();
+        (k, pos)
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn get_many_key_value_mut<Q, const N: usize>(
+        &mut self,
+        ks: [&Q; N],
+    ) -> [Option<(&K, &mut Pos<InUse>)>; N]
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let hashes = ks.map(|k| self.hash(k));
+        self.table
+            .get_many_mut(hashes, |i, (k, _)| ks[i].equivalent(k))
+            .map(|e| e.map(|(k, pos)| (&*k, pos)))
+    }
+
+    /// # Safety
+    ///
+    /// The keys must refer to distinct elements.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) unsafe fn get_many_key_value_unchecked_mut<Q, const N: usize>(
+        &mut self,
+        ks: [&Q; N],
+    ) -> [Option<(&K, &mut Pos<InUse>)>; N]
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let hashes = ks.map(|k| self.hash(k));
+        let entries = unsafe {
+            // SAFETY: The requirements are forwarded to the caller.
+            self.table
+                .get_many_unchecked_mut(hashes, |i, (k, _)| ks[i].equivalent(k))
+        };
+        entries.map(|e| e.map(|(k, pos)| (&*k, pos)))
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn get_many_mut<Q, const N: usize>(
+        &mut self,
+        ks: [&Q; N],
+    ) -> [Option<&mut Pos<InUse>>; N]
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.get_many_key_value_mut(ks)
+            .map(|e| e.map(|(_, pos)| pos))
+    }
+
+    /// # Safety
+    ///
+    /// The keys must refer to distinct elements.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) unsafe fn get_many_unchecked_mut<Q, const N: usize>(
+        &mut self,
+        ks: [&Q; N],
+    ) -> [Option<&mut Pos<InUse>>; N]
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let entries = unsafe {
+            // SAFETY: The requirements are forwarded to the caller.
+            self.get_many_key_value_unchecked_mut(ks)
+        };
+        entries.map(|e| e.map(|(_, pos)| pos))
+    }
+}
+
+/// Computes the hash of an element of the table when the table is resized.
+///
+/// `storage` must be the storage of the values. With the `cached-hash` feature, the hash
+/// stored with the value is used instead of hashing the key again. Otherwise the key is
+/// hashed with `hash_builder`.
+#[cfg_attr(feature = "inline-more", inline)]
+pub(crate) fn rehash<K, V, S, A>(
+    hash_builder: &S,
+    storage: &LinearStorage<V, A>,
+    key: &K,
+    pos: &Pos<InUse>,
+) -> u64
+where
+    K: Hash,
+    S: BuildHasher,
+    A: Allocator,
+{
+    #[cfg(feature = "cached-hash")]
+    {
+        let _ = (hash_builder, key);
+        storage.hash_of(pos)
+    }
+    #[cfg(not(feature = "cached-hash"))]
+    {
+        let _ = (storage, pos);
+        hash_builder.hash_one(key)
+    }
+}
+
+impl<K, S, A: Allocator> IntoIterator for KeyMap<K, S, A> {
+    type Item = (K, Pos<InUse>);
+    type IntoIter = hash_table::IntoIter<(K, Pos<InUse>), A>;
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn into_iter(self) -> Self::IntoIter {
+        self.table.into_iter()
+    }
+}
+
+impl<'a, K, S, A: Allocator> IntoIterator for &'a KeyMap<K, S, A> {
+    type Item = &'a (K, Pos<InUse>);
+    type IntoIter = hash_table::Iter<'a, (K, Pos<InUse>)>;
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn into_iter(self) -> Self::IntoIter {
+        self.table.iter()
+    }
+}
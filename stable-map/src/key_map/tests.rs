@@ -0,0 +1,121 @@
+use {
+    crate::{key_map::KeyMap, linear_storage::LinearStorage},
+    hashbrown::{hash_table, DefaultHashBuilder},
+};
+
+fn map() -> (KeyMap<i32, DefaultHashBuilder>, LinearStorage<i32>) {
+    let mut map = KeyMap::with_capacity_and_hasher(0, DefaultHashBuilder::default());
+    let mut storage = LinearStorage::with_capacity(0);
+    for i in 0..4 {
+        let hash = map.hash(&i);
+        map.insert_unique(hash, i, storage.insert_hashed(hash, i * 10), &storage);
+    }
+    (map, storage)
+}
+
+#[test]
+fn get() {
+    let (map, storage) = map();
+    assert_eq!(map.len(), 4);
+    for i in 0..4 {
+        let (k, pos) = map.get_key_value(&i).unwrap();
+        assert_eq!(*k, i);
+        assert_eq!(unsafe { *storage.get_unchecked(pos) }, i * 10);
+    }
+    assert!(map.get(&4).is_none());
+    assert!(!map.contains_key(&4));
+}
+
+#[cfg(feature = "raw-entry")]
+#[test]
+fn find() {
+    let (map, _) = map();
+    let hash = map.hash(&2);
+    let (k, _) = map.find(hash, |k| *k == 2).unwrap();
+    assert_eq!(*k, 2);
+    assert!(map.find(hash, |_| false).is_none());
+}
+
+#[test]
+fn entry() {
+    let (mut map, mut storage) = map();
+    match map.entry(map.hash(&1), &1, &storage) {
+        hash_table::Entry::Occupied(o) => assert_eq!(o.get().0, 1),
+        hash_table::Entry::Vacant(_) => unreachable!(),
+    }
+    match map.entry(map.hash(&5), &5, &storage) {
+        hash_table::Entry::Occupied(_) => unreachable!(),
+        hash_table::Entry::Vacant(v) => {
+            v.insert((5, storage.insert(50)));
+        }
+    }
+    assert_eq!(map.len(), 5);
+    assert!(map.contains_key(&5));
+}
+
+#[cfg(feature = "cached-hash")]
+#[test]
+fn find_pos() {
+    let (map, storage) = map();
+    let hash = map.hash(&2);
+    let is_match = |pos: &_| unsafe { storage.index_unchecked(pos) } == 2;
+    let (k, _) = map.find_pos(hash, is_match).unwrap();
+    assert_eq!(*k, 2);
+    assert!(map.find_pos(hash, |_| false).is_none());
+}
+
+#[test]
+fn find_pos_entry() {
+    let (mut map, storage) = map();
+    let hash = map.hash(&2);
+    let is_match = |pos: &_| unsafe { storage.index_unchecked(pos) } == 2;
+    let (k, _) = map.find_pos_entry(hash, is_match).unwrap().remove().0;
+    assert_eq!(k, 2);
+    assert!(map.find_pos_entry(hash, is_match).is_none());
+    assert_eq!(map.len(), 3);
+}
+
+#[test]
+fn stored_hash() {
+    let (mut map, storage) = map();
+    for (k, pos) in map.iter() {
+        assert_eq!(map.stored_hash(&storage, k, pos), map.hash(k));
+    }
+    map.reserve(100, &storage);
+    map.shrink_to_fit(&storage);
+    for i in 0..4 {
+        assert!(map.contains_key(&i));
+    }
+}
+
+#[test]
+fn remove() {
+    let (mut map, mut storage) = map();
+    let (k, pos) = map.remove_entry(&1).unwrap();
+    assert_eq!(k, 1);
+    assert_eq!(unsafe { storage.take_unchecked(pos) }, 10);
+    assert!(map.remove(&1).is_none());
+    assert_eq!(map.len(), 3);
+}
+
+#[test]
+fn get_many_mut() {
+    let (mut map, _) = map();
+    let [a, b, c] = map.get_many_key_value_mut([&0, &3, &7]);
+    assert_eq!(a.map(|(k, _)| *k), Some(0));
+    assert_eq!(b.map(|(k, _)| *k), Some(3));
+    assert!(c.is_none());
+}
+
+#[test]
+fn extract_if() {
+    let (mut map, mut storage) = map();
+    for (_, pos) in map.extract_if(|k, _| k % 2 == 0) {
+        unsafe {
+            storage.take_unchecked(pos);
+        }
+    }
+    assert_eq!(map.len(), 2);
+    assert!(map.contains_key(&1));
+    assert!(map.contains_key(&3));
+}
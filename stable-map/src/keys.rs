@@ -7,7 +7,7 @@ use {
         fmt::{Debug, Formatter},
         iter::FusedIterator,
     },
-    hashbrown::hash_map,
+    hashbrown::hash_table,
 };
 
 /// An iterator over the keys of a `StableMap` in arbitrary order.
@@ -39,14 +39,14 @@ use {
 /// assert_eq!(keys.next(), None);
 /// ```
 pub struct Keys<'a, K> {
-    pub(crate) iter: hash_map::Keys<'a, K, Pos<InUse>>,
+    pub(crate) iter: hash_table::Iter<'a, (K, Pos<InUse>)>,
 }
 
 impl<'a, K> Iterator for Keys<'a, K> {
     type Item = &'a K;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.iter.next()
+        self.iter.next().map(|(k, _)| k)
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
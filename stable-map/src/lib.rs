@@ -3,47 +3,124 @@
 //! This crate provides a hash map where each key is associated with an index. This index
 //! remains stable unless the user explicitly compacts the map. This allows for concurrent
 //! iteration over and modification of the map.
+//!
+//! [`StableMap`] is backed by a hash map. [`StableBTreeMap`] is backed by a B-tree and
+//! keeps its keys sorted. [`StableVec`] provides the stable indices without keys.
 
 #![no_std]
 extern crate alloc;
 
+mod apply_diff_error;
+mod btree_map;
+mod capacity_error;
+#[cfg(feature = "minicbor")]
+mod cbor;
 mod clone;
 mod debug;
 mod default;
+mod diff;
+mod diff_index_error;
 mod drain;
+mod drain_indexed;
 mod entry;
 mod eq;
 mod extend;
+mod filtered_view;
 mod from;
 mod from_iterator;
+mod handle;
+mod hash;
+#[cfg(any(feature = "rustc-hash", feature = "ahash"))]
+mod hashers;
 mod index;
+#[cfg(feature = "futures")]
+mod index_stream;
+mod index_type;
+mod indexed_occupied_entry;
 mod into_iter;
 mod into_keys;
 mod into_values;
 mod iter;
+mod iter_indexed;
 mod iter_mut;
+mod iter_mut_indexed;
+mod iter_sparse;
+mod key_map;
 mod keys;
 mod linear_storage;
 mod map;
+mod map_op;
+mod multi_map;
 mod occupied_error;
+mod overlap_error;
+#[cfg(feature = "rayon")]
+mod par_iter;
 mod pos_vec;
+mod prefetch;
+#[cfg(feature = "raw-entry")]
+mod raw_entry;
+mod reuse_policy;
 mod send_sync;
 #[cfg(feature = "serde")]
 mod serialize;
+#[cfg(feature = "serde_with")]
+mod serialize_as;
+mod stable_vec;
+mod try_reserve_error;
+mod typed_map;
 mod values;
+mod values_from_index;
 mod values_mut;
+mod values_mut_from_index;
+mod values_sparse;
 
 pub use {
+    apply_diff_error::ApplyDiffError,
+    btree_map::StableBTreeMap,
+    capacity_error::CapacityError,
+    diff::{DiffEntry, MapDiff},
+    diff_index_error::DiffIndexError,
     drain::Drain,
+    drain_indexed::DrainIndexed,
     entry::{Entry, EntryRef, OccupiedEntry, VacantEntry, VacantEntryRef},
+    filtered_view::FilteredView,
+    handle::Handle,
+    index_type::IndexType,
+    indexed_occupied_entry::IndexedOccupiedEntry,
     into_iter::IntoIter,
     into_keys::IntoKeys,
     into_values::IntoValues,
     iter::Iter,
+    iter_indexed::IterIndexed,
     iter_mut::IterMut,
+    iter_mut_indexed::IterMutIndexed,
+    iter_sparse::IterSparse,
     keys::Keys,
     map::StableMap,
+    map_op::MapOp,
+    multi_map::StableMultiMap,
     occupied_error::OccupiedError,
+    overlap_error::OverlapError,
+    reuse_policy::ReusePolicy,
+    stable_vec::StableVec,
+    try_reserve_error::TryReserveError,
+    typed_map::TypedStableMap,
     values::Values,
+    values_from_index::ValuesFromIndex,
     values_mut::ValuesMut,
+    values_mut_from_index::ValuesMutFromIndex,
+    values_sparse::ValuesSparse,
+};
+
+#[cfg(feature = "ahash")]
+pub use hashers::AHashStableMap;
+#[cfg(feature = "rustc-hash")]
+pub use hashers::FxStableMap;
+#[cfg(feature = "futures")]
+pub use index_stream::IndexStream;
+#[cfg(feature = "rayon")]
+pub use par_iter::ParIterByIndex;
+#[cfg(feature = "raw-entry")]
+pub use raw_entry::{
+    RawEntryBuilder, RawEntryBuilderMut, RawEntryMut, RawOccupiedEntryMut, RawVacantEntryMut,
 };
@@ -2,10 +2,16 @@
 pub mod tests;
 
 use {
-    crate::pos_vec::{
-        pos::{Free, InUse, Pos},
-        PosVec, PosVecRawAccess,
+    crate::{
+        pos_vec::{
+            pos::{Free, InUse, Pos, MAX_LEN},
+            PosVec, PosVecRawAccess, Slots, SlotsMut, SparseSlots, SparseSlotsMut,
+        },
+        try_reserve_error::TryReserveError,
+        ReusePolicy,
     },
+    allocator_api2::alloc::{Allocator, Global},
+    core::{cmp::Ordering, mem},
     min_max_heap::MinMaxHeap,
 };
 
@@ -22,21 +28,78 @@ use {
 //
 // - All valid Pos<InUse> are also valid for the underlying PosVec.
 // - The free_list contains only valid Pos<Free> returned by the PosVec.
+// - The free_list contains the Pos<Free> of every unused slot of the PosVec.
+// - If the reuse policy is LowestFirst, the key of each free slot is its index.
 //
 // SAFETY: Each mutating function must document how it upholds these invariants.
 #[derive(Debug)]
-pub struct LinearStorage<V> {
-    values: PosVec<V>,
-    free_list: MinMaxHeap<Pos<Free>>,
+pub struct LinearStorage<V, A: Allocator = Global> {
+    values: PosVec<V, A>,
+    free_list: MinMaxHeap<FreeSlot>,
+    amortized_compaction: bool,
+    auto_shrink: bool,
+    max_vacant: usize,
+    max_len: usize,
+    reuse_policy: ReusePolicy,
+    next_key: usize,
+    high_water_mark: usize,
+}
+
+/// An entry in the free list.
+///
+/// Free slots are ordered by their key. Depending on the reuse policy, the key is
+/// either the index of the slot or a counter that increases whenever a slot is freed.
+#[derive(Debug)]
+struct FreeSlot {
+    key: usize,
+    pos: Pos<Free>,
+}
+
+impl PartialEq for FreeSlot {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl Eq for FreeSlot {}
+
+impl PartialOrd for FreeSlot {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FreeSlot {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key.cmp(&other.key)
+    }
 }
 
 impl<V> LinearStorage<V> {
     /// Creates a new vector with the requested capacity.
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<V, A: Allocator> LinearStorage<V, A> {
+    /// Creates a new vector with the requested capacity in the provided allocator.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self
+    where
+        A: Clone,
+    {
         Self {
-            values: PosVec::with_capacity(capacity),
+            values: PosVec::with_capacity_in(capacity, alloc),
             free_list: Default::default(),
+            amortized_compaction: false,
+            auto_shrink: false,
+            max_vacant: usize::MAX,
+            max_len: usize::MAX,
+            reuse_policy: ReusePolicy::LowestFirst,
+            next_key: 0,
+            high_water_mark: 0,
         }
     }
 
@@ -52,25 +115,393 @@ impl<V> LinearStorage<V> {
         self.values.capacity()
     }
 
-    /// Stores a value.
+    /// Returns the number of values that can be inserted without reallocating the vector.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn spare_capacity(&self) -> usize {
+        self.free_list.len() + (self.values.capacity() - self.values.len())
+    }
+
+    /// Returns the number of unused slots.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn vacant_len(&self) -> usize {
+        self.free_list.len()
+    }
+
+    /// Returns the smallest index of an unused slot.
+    ///
+    /// Unless the reuse policy is `LowestFirst`, this function takes time linear in the
+    /// number of unused slots.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn lowest_vacant(&self) -> Option<usize> {
+        match self.reuse_policy {
+            ReusePolicy::LowestFirst => self.free_list.peek_min().map(|slot| slot.pos.get()),
+            ReusePolicy::Lifo | ReusePolicy::Fifo => {
+                self.free_list.iter().map(|slot| slot.pos.get()).min()
+            }
+        }
+    }
+
+    /// Returns the largest length the vector has had since it was created or since the
+    /// last call to `reset_high_water_mark`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark
+    }
+
+    /// Resets the high-water mark to the current length of the vector.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn reset_high_water_mark(&mut self) {
+        self.high_water_mark = self.values.len();
+    }
+
+    /// Returns whether amortized compaction is enabled.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn amortized_compaction(&self) -> bool {
+        self.amortized_compaction
+    }
+
+    /// Enables or disables amortized compaction.
+    ///
+    /// If amortized compaction is enabled, each call to `insert` additionally moves at
+    /// most one value from the end of the vector into an unused slot.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn set_amortized_compaction(&mut self, enabled: bool) {
+        self.amortized_compaction = enabled;
+    }
+
+    /// Returns whether the vector is shrunk after compaction.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn auto_shrink(&self) -> bool {
+        self.auto_shrink
+    }
+
+    /// Enables or disables shrinking after compaction.
+    ///
+    /// If this is enabled and, after `force_compact`, less than a quarter of the capacity
+    /// of the vector is in use, the capacity is shrunk to fit the length.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn set_auto_shrink(&mut self, enabled: bool) {
+        self.auto_shrink = enabled;
+    }
+
+    /// Returns the maximum number of unused slots retained by this object.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn max_vacant(&self) -> usize {
+        self.max_vacant
+    }
+
+    /// Sets the maximum number of unused slots retained by this object.
+    ///
+    /// Whenever the number of unused slots exceeds this number, values are moved from
+    /// the end of the vector into unused slots until it no longer does.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn set_max_vacant(&mut self, max_vacant: usize) {
+        self.max_vacant = max_vacant;
+        self.reclaim();
+    }
+
+    /// Returns the maximum number of values stored in this object.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Sets the maximum number of values stored in this object.
+    ///
+    /// Values that are already stored are not removed.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.max_len = max_len;
+    }
+
+    /// Returns whether storing another value would exceed `max_len`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn is_full(&self) -> bool {
+        self.values.len() - self.free_list.len() >= self.max_len
+    }
+
+    /// Panics if storing another value would exceed `max_len`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn check_len(&self) {
+        assert!(
+            !self.is_full(),
+            "stable-map: the number of elements exceeds the maximum of {}",
+            self.max_len,
+        );
+    }
+
+    /// Returns the order in which unused slots are reused.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn reuse_policy(&self) -> ReusePolicy {
+        self.reuse_policy
+    }
+
+    /// Sets the order in which unused slots are reused.
+    ///
+    /// This function takes time linear in the number of unused slots. Unused slots that
+    /// already exist are treated as if they had been freed in the order of their indices.
+    pub fn set_reuse_policy(&mut self, reuse_policy: ReusePolicy) {
+        self.reuse_policy = reuse_policy;
+        let mut free = mem::take(&mut self.free_list).into_vec();
+        free.sort_unstable_by_key(|slot| slot.pos.get());
+        for slot in free {
+            self.push_free(slot.pos);
+        }
+        // SAFETY(invariants):
+        // - All Pos<Free> pushed onto the free list were taken from the free list.
+        // - push_free computes the keys according to the new reuse policy.
+    }
+
+    /// Pushes a `Pos<Free>` onto the free list.
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn push_free(&mut self, pos: Pos<Free>) {
+        let key = match self.reuse_policy {
+            ReusePolicy::LowestFirst => pos.get(),
+            ReusePolicy::Lifo | ReusePolicy::Fifo => {
+                self.next_key += 1;
+                self.next_key
+            }
+        };
+        self.free_list.push(FreeSlot { key, pos });
+    }
+
+    /// Returns the `Pos<Free>` from the free list that would be popped by `pop_free`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn peek_free(&self) -> Option<&Pos<Free>> {
+        let slot = match self.reuse_policy {
+            ReusePolicy::LowestFirst | ReusePolicy::Fifo => self.free_list.peek_min(),
+            ReusePolicy::Lifo => self.free_list.peek_max(),
+        };
+        slot.map(|slot| &slot.pos)
+    }
+
+    /// Pops the `Pos<Free>` from the free list that should be reused next.
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn pop_free(&mut self) -> Option<Pos<Free>> {
+        let slot = match self.reuse_policy {
+            ReusePolicy::LowestFirst | ReusePolicy::Fifo => self.free_list.pop_min(),
+            ReusePolicy::Lifo => self.free_list.pop_max(),
+        };
+        slot.map(|slot| slot.pos)
+    }
+
+    /// Appends an unused slot to the vector and returns its `Pos<Free>`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn create_pos(&mut self) -> Pos<Free> {
+        let pos = self.values.create_pos();
+        self.high_water_mark = self.high_water_mark.max(self.values.len());
+        pos
+    }
+
+    /// Stores a value with a hash of `0`.
+    ///
+    /// Panics if this would exceed `max_len`.
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn insert(&mut self, value: V) -> Pos<InUse> {
-        let pos = match self.free_list.pop_min() {
+        self.insert_hashed(0, value)
+    }
+
+    /// Stores a value and its hash.
+    ///
+    /// The hash is discarded unless the `cached-hash` feature is enabled.
+    ///
+    /// Panics if this would exceed `max_len`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert_hashed(&mut self, hash: u64, value: V) -> Pos<InUse> {
+        self.check_len();
+        let pos = match self.pop_free() {
             Some(pos) => pos,
-            _ => self.values.create_pos(),
+            _ => self.create_pos(),
         };
-        unsafe {
+        let pos = unsafe {
+            // SAFETY:
+            // - If the pos was popped from the free list, then, by the invariants, it
+            //   is still valid for self.values.
+            // - Otherwise, create_pos, returns a new, valid Pos<Free>.
+            self.values.store(pos, hash, value)
+        };
+        if self.amortized_compaction {
+            self.compact_step();
+        }
+        pos
+        // SAFETY(invariants):
+        // - The returned Pos<InUse> was just returned PosVec::store and is therefore still valid.
+        //   compact_step does not invalidate any Pos<InUse>.
+        // - All Pos<Free> used by this function have been consumed by the PosVec.
+    }
+
+    /// Stores a value that is created from the index of its slot, and its hash.
+    ///
+    /// `f` is called before any slot is taken. If `f` panics, the vector is unchanged.
+    /// The compaction step is skipped if it would move the new value.
+    ///
+    /// Panics if this would exceed `max_len`.
+    pub fn insert_with(&mut self, hash: u64, f: impl FnOnce(usize) -> V) -> Pos<InUse> {
+        self.check_len();
+        let index = match self.peek_free() {
+            Some(pos) => pos.get(),
+            _ => self.values.len(),
+        };
+        let value = f(index);
+        let pos = match self.pop_free() {
+            Some(pos) => pos,
+            _ => self.create_pos(),
+        };
+        debug_assert_eq!(pos.get(), index);
+        let pos = unsafe {
             // SAFETY:
             // - If the pos was popped from the free list, then, by the invariants, it
             //   is still valid for self.values.
             // - Otherwise, create_pos, returns a new, valid Pos<Free>.
-            self.values.store(pos, value)
+            self.values.store(pos, hash, value)
+        };
+        // compact_step moves the last value. Skip it if that is the new value.
+        if self.amortized_compaction && self.values.last_occupied() != Some(index) {
+            self.compact_step();
         }
+        pos
         // SAFETY(invariants):
         // - The returned Pos<InUse> was just returned PosVec::store and is therefore still valid.
+        //   compact_step does not invalidate any Pos<InUse>.
         // - All Pos<Free> used by this function have been consumed by the PosVec.
     }
 
+    /// Reduces the number of unused slots to at most `max_vacant`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn reclaim(&mut self) {
+        while self.free_list.len() > self.max_vacant {
+            self.compact_step();
+        }
+    }
+
+    /// Moves at most one value from the end of the vector into the smallest unused slot.
+    ///
+    /// Unless the reuse policy is `LowestFirst`, this function takes time linear in the
+    /// number of unused slots.
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn compact_step(&mut self) {
+        if self.reuse_policy != ReusePolicy::LowestFirst {
+            let mut free = mem::take(&mut self.free_list).into_vec();
+            let smallest = free
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, slot)| slot.pos.get())
+                .map(|(idx, _)| idx);
+            let mut smallest = smallest.map(|idx| free.swap_remove(idx).pos);
+            unsafe {
+                // SAFETY:
+                // - By the invariants, the free list contains only valid Pos<Free>
+                //   returned by self.values.
+                self.values.compact(|| smallest.take());
+            }
+            free.retain(|slot| slot.pos.get() < self.values.len());
+            self.free_list = free.into();
+            return;
+        }
+        let mut free = self.free_list.pop_min().map(|slot| slot.pos);
+        unsafe {
+            // SAFETY:
+            // - By the invariants, the free list contains only valid Pos<Free> returned
+            //   by self.values.
+            self.values.compact(|| free.take());
+        }
+        while let Some(slot) = self.free_list.peek_max() {
+            if slot.pos.get() < self.values.len() {
+                break;
+            }
+            self.free_list.pop_max();
+        }
+        // SAFETY(invariants):
+        // - This function has no effect on returned Pos<InUse>.
+        // - The Pos<Free> passed to PosVec::compact has been consumed.
+        // - We've removed all Pos<Free> from the free list that were invalidated by
+        //   PosVec::compact. All other Pos<Free> remain valid.
+        // - PosVec::compact only removes unused slots from the end of the vector and
+        //   fills the slot of the consumed Pos<Free>. Therefore the free list still
+        //   contains the Pos<Free> of every unused slot.
+    }
+
+    /// Stores a value and its hash at a specific index.
+    ///
+    /// If the index is greater than or equal to the length of the vector, the vector is
+    /// extended and the new slots below the index become unused slots. If the index is
+    /// already in use, if it is not representable as a position, if extending the vector
+    /// would leave more than `max_vacant` unused slots, or if the memory for the new slots
+    /// cannot be allocated, the value is returned.
+    ///
+    /// If the index is less than the length of the vector, this function takes time
+    /// linear in the number of unused slots.
+    ///
+    /// Panics if this would exceed `max_len`.
+    pub fn insert_at(&mut self, index: usize, hash: u64, value: V) -> Result<Pos<InUse>, V> {
+        self.check_len();
+        let pos = if index >= self.values.len() {
+            let new_vacant = index - self.values.len();
+            // The vector can hold at most MAX_LEN slots, so index must be less than
+            // MAX_LEN.
+            if new_vacant >= MAX_LEN - self.values.len() {
+                return Err(value);
+            }
+            if new_vacant > self.max_vacant.saturating_sub(self.free_list.len()) {
+                return Err(value);
+            }
+            if self.values.try_reserve(new_vacant + 1).is_err() {
+                return Err(value);
+            }
+            self.free_list.reserve(new_vacant);
+            loop {
+                let pos = self.create_pos();
+                if pos.get() == index {
+                    break pos;
+                }
+                self.push_free(pos);
+            }
+        } else {
+            if self.values.get(index).is_some() {
+                return Err(value);
+            }
+            let mut free = mem::take(&mut self.free_list).into_vec();
+            let idx = free.iter().position(|slot| slot.pos.get() == index);
+            let pos = idx.map(|idx| free.swap_remove(idx).pos);
+            self.free_list = free.into();
+            match pos {
+                Some(pos) => pos,
+                // By the invariants of PosVec, every unused slot has a Pos<Free> and,
+                // by the invariants of this type, all of those are in the free list.
+                None => unreachable!(),
+            }
+        };
+        let pos = unsafe {
+            // SAFETY:
+            // - If the pos was taken from the free list, then, by the invariants, it
+            //   is still valid for self.values.
+            // - Otherwise, create_pos, returns a new, valid Pos<Free>.
+            self.values.store(pos, hash, value)
+        };
+        Ok(pos)
+        // SAFETY(invariants):
+        // - The returned Pos<InUse> was just returned PosVec::store and is therefore still valid.
+        // - All Pos<Free> pushed onto the free list were just returned by create_pos.
+        // - The Pos<Free> used by this function has been consumed by the PosVec.
+    }
+
+    /// Appends up to `n` unused slots to the vector and returns the number of slots that
+    /// were appended.
+    ///
+    /// At most as many slots are appended as `max_vacant` allows.
+    pub fn extend(&mut self, n: usize) -> usize {
+        let n = n.min(self.max_vacant.saturating_sub(self.free_list.len()));
+        self.values.reserve(n);
+        self.free_list.reserve(n);
+        for _ in 0..n {
+            let pos = self.create_pos();
+            self.push_free(pos);
+        }
+        n
+        // SAFETY(invariants):
+        // - All Pos<Free> pushed onto the free list were just returned by create_pos.
+    }
+
     /// Clears the vector.
     ///
     /// This function invalidates all `Pos<InUse>` previously returned by this object.
@@ -83,6 +514,34 @@ impl<V> LinearStorage<V> {
         // - We've cleared self.free_list.
     }
 
+    /// Returns whether a value is stored at a specific index in the vector.
+    ///
+    /// Note that, unlike the functions taking `Pos<InUse>`, the result is affected by
+    /// calls to `compact`.
+    #[inline]
+    pub fn is_occupied(&self, pos: usize) -> bool {
+        self.values.is_occupied(pos)
+    }
+
+    /// Returns the smallest index greater than or equal to `start` at which a value is
+    /// stored.
+    ///
+    /// Note that, unlike the functions taking `Pos<InUse>`, the result is affected by
+    /// calls to `compact`.
+    #[inline]
+    pub fn next_occupied(&self, start: usize) -> Option<usize> {
+        self.values.next_occupied(start)
+    }
+
+    /// Returns the largest index at which a value is stored.
+    ///
+    /// Note that, unlike the functions taking `Pos<InUse>`, the result is affected by
+    /// calls to `compact`.
+    #[inline]
+    pub fn last_occupied(&self) -> Option<usize> {
+        self.values.last_occupied()
+    }
+
     /// Retrieves a reference to a value stored at a specific index in the vector.
     ///
     /// Note that, unlike the functions taking `Pos<InUse>`, which value is returned is
@@ -101,6 +560,79 @@ impl<V> LinearStorage<V> {
         self.values.get_mut(pos)
     }
 
+    /// Returns the generation of the value stored at a specific index in the vector.
+    ///
+    /// The generation changes whenever a different value is stored at the index or the
+    /// value is moved by compaction.
+    #[inline]
+    pub fn generation(&self, pos: usize) -> Option<u64> {
+        self.values.generation(pos)
+    }
+
+    /// Returns the hash of the value stored at a specific index in the vector.
+    ///
+    /// Note that, unlike the functions taking `Pos<InUse>`, which hash is returned is
+    /// affected by calls to `compact`.
+    #[cfg(feature = "cached-hash")]
+    #[inline]
+    pub fn hash(&self, pos: usize) -> Option<u64> {
+        self.values.hash(pos)
+    }
+
+    /// Returns an iterator over the values stored in the vector, in the order of their
+    /// indices.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn iter(&self) -> impl Iterator<Item = &V> {
+        self.values.iter()
+    }
+
+    /// Hints to the CPU that the slot at `index` will be accessed soon.
+    ///
+    /// `index` does not have to be in bounds.
+    #[cfg(feature = "futures")]
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn prefetch(&self, index: usize) {
+        self.values.prefetch(index);
+    }
+
+    /// Returns an iterator over the slots of the vector.
+    ///
+    /// If the vector has no unused slots, the iterator yields exactly [`len`](Self::len)
+    /// values.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn slots(&self) -> Slots<'_, V> {
+        self.values.slots()
+    }
+
+    /// Returns an iterator that yields one `Option<&V>` per slot of the vector.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn sparse_slots(&self) -> SparseSlots<'_, V> {
+        self.values.sparse_slots()
+    }
+
+    /// Returns an iterator that yields one `Option<&V>` per slot of the vector, starting
+    /// at index `start`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn sparse_slots_from(&self, start: usize) -> SparseSlots<'_, V> {
+        self.values.sparse_slots_from(start)
+    }
+
+    /// Returns an iterator that yields one `Option<&mut V>` per slot of the vector,
+    /// starting at index `start`.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn sparse_slots_mut_from(&mut self, start: usize) -> SparseSlotsMut<'_, V> {
+        self.values.sparse_slots_mut_from(start)
+    }
+
+    /// Returns a mutable iterator over the slots of the vector.
+    ///
+    /// If the vector has no unused slots, the iterator yields exactly [`len`](Self::len)
+    /// values.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn slots_mut(&mut self) -> SlotsMut<'_, V> {
+        self.values.slots_mut()
+    }
+
     /// Reserves space for `additional` additional elements.
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn reserve(&mut self, additional: usize) {
@@ -108,6 +640,21 @@ impl<V> LinearStorage<V> {
             .reserve(additional.saturating_sub(self.free_list.len()));
     }
 
+    /// Tries to reserve space for `additional` additional elements.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        self.values
+            .try_reserve(additional.saturating_sub(self.free_list.len()))
+    }
+
+    /// Reserves space for `additional` additional slots at the end of the vector.
+    ///
+    /// Unlike `reserve`, this function does not take unused slots into account.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn reserve_slots(&mut self, additional: usize) {
+        self.values.reserve(additional);
+    }
+
     /// Reduces the capacity of the vector to its length.
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn shrink_to_fit(&mut self) {
@@ -131,15 +678,33 @@ impl<V> LinearStorage<V> {
     /// Compacts the storage unconditionally.
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn force_compact(&mut self) {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!(
+            "compact",
+            index_len = self.values.len(),
+            vacant = self.free_list.len(),
+        )
+        .entered();
+        let mut free = mem::take(&mut self.free_list).into_vec();
+        free.sort_unstable_by_key(|slot| slot.pos.get());
+        let mut free = free.into_iter().map(|slot| slot.pos);
         unsafe {
             // SAFETY:
             // - By the invariants, free_list contains only valid Pos<Free> returned by self.values.
-            self.values.compact(|| self.free_list.pop_min());
+            self.values.compact(|| free.next());
         }
-        self.free_list.clear();
+        drop(free);
+        #[cfg(any(test, feature = "checked-pos"))]
+        assert_eq!(self.values.iter().count(), self.values.len());
+        if self.auto_shrink && self.values.len() < self.values.capacity() / 4 {
+            self.values.shrink_to_fit();
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(index_len = self.values.len(), "compacted");
         // SAFETY(invariants):
         // - This function has no effect on returned Pos<InUse>
-        // - We've cleared self.free_list.
+        // - We've taken all Pos<Free> out of self.free_list.
+        // - After compaction, the PosVec contains no unused slots.
     }
 
     /// Retrieves a reference to the value referenced by a usize.
@@ -180,6 +745,24 @@ impl<V> LinearStorage<V> {
         // - This function has no effect on the invariants.
     }
 
+    /// Returns the index referenced by a `Pos<InUse>`.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` must be valid and must have been returned by this object.
+    #[inline]
+    pub unsafe fn index_unchecked(&self, pos: &Pos<InUse>) -> usize {
+        unsafe {
+            // SAFETY:
+            // - The requirements are forwarded to the caller.
+            // - By the invariants, any Pos<InUse> valid for this object is also valid for
+            //   self.values.
+            self.values.index_unchecked(pos)
+        }
+        // SAFETY(invariants):
+        // - This function has no effect on the invariants.
+    }
+
     /// Retrieves a reference to the value referenced by a `Pos<InUse>`.
     ///
     /// # Safety
@@ -216,6 +799,52 @@ impl<V> LinearStorage<V> {
         // - This function has no effect on the invariants.
     }
 
+    /// Returns the hash of the value referenced by a `Pos<InUse>`.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` must be valid and must have been returned by this object.
+    #[cfg(feature = "cached-hash")]
+    #[inline]
+    pub unsafe fn hash_unchecked(&self, pos: &Pos<InUse>) -> u64 {
+        unsafe {
+            // SAFETY:
+            // - The requirements are forwarded to the caller.
+            // - By the invariants, any Pos<InUse> valid for this object is also valid for
+            //   self.values.
+            self.values.hash_unchecked(pos)
+        }
+    }
+
+    /// Returns the hash of the value referenced by a `Pos<InUse>`.
+    ///
+    /// If the `Pos<InUse>` was not returned by this object, the returned hash is
+    /// unspecified.
+    #[cfg(feature = "cached-hash")]
+    #[inline]
+    pub fn hash_of(&self, pos: &Pos<InUse>) -> u64 {
+        self.values.hash_of(pos)
+    }
+
+    /// Replaces the hash of the value referenced by a `Pos<InUse>`.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` must be valid and must have been returned by this object.
+    #[cfg(feature = "cached-hash")]
+    #[inline]
+    pub unsafe fn set_hash_unchecked(&mut self, pos: &Pos<InUse>, hash: u64) {
+        unsafe {
+            // SAFETY:
+            // - The requirements are forwarded to the caller.
+            // - By the invariants, any Pos<InUse> valid for this object is also valid for
+            //   self.values.
+            self.values.set_hash_unchecked(pos, hash)
+        }
+        // SAFETY(invariants):
+        // - This function has no effect on the invariants.
+    }
+
     /// Retrieves mutable references to value referenced by `Pos<InUse>`.
     ///
     /// # Safety
@@ -252,11 +881,81 @@ impl<V> LinearStorage<V> {
             //   self.values.
             self.values.take_unchecked(pos)
         };
-        self.free_list.push(pos);
+        self.push_free(pos);
+        self.reclaim();
         value
         // SAFETY(invariants):
         // - The Pos<Free> returned by self.values is valid and therefore pushing it onte
         //   self.free_list is valid.
+        // - reclaim does not invalidate any Pos<InUse>.
+    }
+
+    /// Like `take_unchecked` but never moves any other value, even if the number of
+    /// unused slots exceeds `max_vacant`.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` must be valid and must have been returned by this object.
+    #[inline]
+    pub unsafe fn take_unchecked_no_reclaim(&mut self, pos: Pos<InUse>) -> V {
+        let (value, pos) = unsafe {
+            // SAFETY:
+            // - The requirements are forwarded to the caller.
+            // - By the invariants, any Pos<InUse> valid for this object is also valid for
+            //   self.values.
+            self.values.take_unchecked(pos)
+        };
+        self.push_free(pos);
+        value
+        // SAFETY(invariants):
+        // - The Pos<Free> returned by self.values is valid and therefore pushing it onto
+        //   self.free_list is valid.
+    }
+
+    /// Like `take_unchecked` but for a value that has already been moved out with
+    /// `ptr::read`. The value is neither read nor dropped.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` must be valid and must have been returned by this object.
+    #[inline]
+    pub unsafe fn remove_moved_unchecked(&mut self, pos: Pos<InUse>) {
+        let pos = unsafe {
+            // SAFETY:
+            // - The requirements are forwarded to the caller.
+            // - By the invariants, any Pos<InUse> valid for this object is also valid for
+            //   self.values.
+            self.values.remove_moved_unchecked(pos)
+        };
+        self.push_free(pos);
+        self.reclaim();
+        // SAFETY(invariants):
+        // - The Pos<Free> returned by self.values is valid and therefore pushing it onto
+        //   self.free_list is valid.
+        // - reclaim does not invalidate any Pos<InUse>.
+    }
+
+    /// Removes the value stored at a specific index.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` referring to this index, if any, becomes invalid. The caller must
+    /// not use it after this function returns.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub unsafe fn take(&mut self, idx: usize) -> Option<V> {
+        let (value, pos) = unsafe {
+            // SAFETY:
+            // - The requirement is forwarded to the caller.
+            self.values.take(idx)?
+        };
+        self.push_free(pos);
+        self.reclaim();
+        Some(value)
+        // SAFETY(invariants):
+        // - The invalidation of the Pos<InUse> is forwarded to the caller.
+        // - The Pos<Free> returned by self.values is valid and therefore pushing it onto
+        //   self.free_list is valid.
+        // - reclaim does not invalidate any Pos<InUse>.
     }
 
     /// Creates pointer-based access API for the vector.
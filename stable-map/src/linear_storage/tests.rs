@@ -1,4 +1,7 @@
-use {crate::linear_storage::LinearStorage, core::array};
+use {
+    crate::{linear_storage::LinearStorage, ReusePolicy},
+    core::array,
+};
 
 #[test]
 fn with_capacity() {
@@ -41,8 +44,8 @@ fn insert() {
     let p1 = v.insert(0);
     let p2 = v.insert(1);
     unsafe {
-        assert_eq!(p1.get_unchecked(), 0);
-        assert_eq!(p2.get_unchecked(), 1);
+        assert_eq!(v.index_unchecked(&p1), 0);
+        assert_eq!(v.index_unchecked(&p2), 1);
         assert_eq!(v.get_unchecked(&p1), &0);
         assert_eq!(v.get_unchecked(&p2), &1);
     }
@@ -81,8 +84,8 @@ fn compact() {
     assert_eq!(v.get(2), Some(&5));
     assert_eq!(v.get(3), Some(&4));
     unsafe {
-        assert_eq!(p5.get_unchecked(), 2);
-        assert_eq!(p4.get_unchecked(), 3);
+        assert_eq!(v.index_unchecked(&p5), 2);
+        assert_eq!(v.index_unchecked(&p4), 3);
         assert_eq!(v.get_unchecked(&p0), &0);
         assert_eq!(v.get_unchecked(&p1), &1);
         assert_eq!(v.get_unchecked(&p4), &4);
@@ -111,6 +114,18 @@ fn get() {
     assert_eq!(v.get_mut(1), Some(&mut 1));
 }
 
+#[test]
+fn is_occupied() {
+    let mut v = LinearStorage::with_capacity(0);
+    let p1 = v.insert(0);
+    v.insert(1);
+    assert!(v.is_occupied(0));
+    assert!(v.is_occupied(1));
+    assert!(!v.is_occupied(2));
+    unsafe { v.take_unchecked(p1) };
+    assert!(!v.is_occupied(0));
+}
+
 #[test]
 fn get_unchecked() {
     let mut v = LinearStorage::with_capacity(0);
@@ -121,10 +136,10 @@ fn get_unchecked() {
         assert_eq!(v.get_unchecked(&p2), &1);
         assert_eq!(v.get_unchecked_mut(&p1), &mut 0);
         assert_eq!(v.get_unchecked_mut(&p2), &mut 1);
-        assert_eq!(v.get_unchecked_raw(p1.get_unchecked()), &0);
-        assert_eq!(v.get_unchecked_raw(p2.get_unchecked()), &1);
-        assert_eq!(v.get_unchecked_raw_mut(p1.get_unchecked()), &mut 0);
-        assert_eq!(v.get_unchecked_raw_mut(p2.get_unchecked()), &mut 1);
+        assert_eq!(v.get_unchecked_raw(v.index_unchecked(&p1)), &0);
+        assert_eq!(v.get_unchecked_raw(v.index_unchecked(&p2)), &1);
+        assert_eq!(v.get_unchecked_raw_mut(v.index_unchecked(&p1)), &mut 0);
+        assert_eq!(v.get_unchecked_raw_mut(v.index_unchecked(&p2)), &mut 1);
     }
 }
 
@@ -159,8 +174,8 @@ fn take_unchecked() {
     let p3 = v.insert(2);
     assert_eq!(v.len(), 2);
     unsafe {
-        assert_eq!(p3.get_unchecked(), 0);
-        assert_eq!(p2.get_unchecked(), 1);
+        assert_eq!(v.index_unchecked(&p3), 0);
+        assert_eq!(v.index_unchecked(&p2), 1);
         assert_eq!(v.get_unchecked(&p3), &2);
         assert_eq!(v.get_unchecked(&p2), &1);
     }
@@ -202,3 +217,213 @@ fn reuse() {
         assert_eq!(v.get_unchecked(&p6), &6);
     }
 }
+
+#[test]
+fn insert_at() {
+    let mut v = LinearStorage::with_capacity(0);
+    let p0 = v.insert(0);
+    let p3 = v.insert_at(3, 0, 3).unwrap();
+    assert_eq!(v.len(), 4);
+    assert_eq!(v.get(1), None);
+    assert_eq!(v.get(2), None);
+    assert_eq!(v.insert_at(0, 0, 4).unwrap_err(), 4);
+    assert_eq!(v.insert_at(3, 0, 4).unwrap_err(), 4);
+    assert_eq!(v.insert_at(usize::MAX, 0, 4).unwrap_err(), 4);
+    assert_eq!(v.len(), 4);
+    let p2 = v.insert_at(2, 0, 2).unwrap();
+    let p1 = v.insert(1);
+    assert_eq!(v.len(), 4);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p0), 0);
+        assert_eq!(v.index_unchecked(&p1), 1);
+        assert_eq!(v.index_unchecked(&p2), 2);
+        assert_eq!(v.index_unchecked(&p3), 3);
+        assert_eq!(v.get_unchecked(&p0), &0);
+        assert_eq!(v.get_unchecked(&p1), &1);
+        assert_eq!(v.get_unchecked(&p2), &2);
+        assert_eq!(v.get_unchecked(&p3), &3);
+    }
+}
+
+#[cfg(feature = "cached-hash")]
+#[test]
+fn hash() {
+    let mut v = LinearStorage::with_capacity(0);
+    let p0 = v.insert_hashed(10, 0);
+    let p1 = v.insert_with(11, |idx| idx);
+    v.insert_at(3, 13, 3).unwrap();
+    assert_eq!(v.hash(0), Some(10));
+    assert_eq!(v.hash(1), Some(11));
+    assert_eq!(v.hash(2), None);
+    assert_eq!(v.hash(3), Some(13));
+    unsafe {
+        v.take_unchecked(p0);
+        v.set_hash_unchecked(&p1, 21);
+    }
+    v.force_compact();
+    assert_eq!(v.hash(0), Some(13));
+    assert_eq!(v.hash(1), Some(21));
+}
+
+#[test]
+fn amortized_compaction() {
+    let mut v = LinearStorage::with_capacity(0);
+    v.set_amortized_compaction(true);
+    let [p0, p1, p2, p3, p4, p5] = array::from_fn(|n| v.insert(n));
+    unsafe {
+        v.take_unchecked(p0);
+        v.take_unchecked(p1);
+        v.take_unchecked(p4);
+    }
+    assert_eq!(v.len(), 6);
+    let p6 = v.insert(6);
+    assert_eq!(v.len(), 5);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p6), 0);
+        assert_eq!(v.index_unchecked(&p5), 1);
+        assert_eq!(v.get_unchecked(&p5), &5);
+    }
+    assert_eq!(v.get(4), None);
+    let p7 = v.insert(7);
+    assert_eq!(v.len(), 5);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p7), 4);
+        assert_eq!(v.get_unchecked(&p2), &2);
+        assert_eq!(v.get_unchecked(&p3), &3);
+        assert_eq!(v.get_unchecked(&p7), &7);
+    }
+}
+
+#[test]
+fn insert_with() {
+    let mut v = LinearStorage::with_capacity(0);
+    v.set_amortized_compaction(true);
+    v.set_reuse_policy(ReusePolicy::Lifo);
+    let [p0, p1, _p2, p3] = array::from_fn(|n| v.insert(n));
+    unsafe {
+        v.take_unchecked(p0);
+        v.take_unchecked(p1);
+        v.take_unchecked(p3);
+    }
+    let p4 = v.insert_with(0, |idx| idx);
+    unsafe {
+        let idx = v.index_unchecked(&p4);
+        assert_eq!(v.get_unchecked(&p4), &idx);
+    }
+    let p5 = v.insert_with(0, |idx| idx);
+    unsafe {
+        let idx = v.index_unchecked(&p5);
+        assert_eq!(v.get_unchecked(&p5), &idx);
+        assert_eq!(v.get_unchecked(&p4), &3);
+    }
+    assert_eq!(v.len(), 3);
+}
+
+#[test]
+fn take_unchecked_no_reclaim() {
+    let mut v = LinearStorage::with_capacity(0);
+    let [p0, p1, p2] = array::from_fn(|n| v.insert(n));
+    v.set_max_vacant(0);
+    assert_eq!(unsafe { v.take_unchecked_no_reclaim(p0) }, 0);
+    assert_eq!(v.len(), 3);
+    assert_eq!(v.vacant_len(), 1);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p1), 1);
+        assert_eq!(v.index_unchecked(&p2), 2);
+    }
+    let p3 = v.insert(3);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p3), 0);
+    }
+}
+
+#[test]
+fn max_vacant() {
+    let mut v = LinearStorage::with_capacity(0);
+    let [p0, p1, p2, p3, p4, p5] = array::from_fn(|n| v.insert(n));
+    unsafe {
+        v.take_unchecked(p1);
+        v.take_unchecked(p5);
+        v.take_unchecked(p3);
+    }
+    assert_eq!(v.len(), 6);
+    v.set_max_vacant(1);
+    assert_eq!(v.max_vacant(), 1);
+    assert_eq!(v.len(), 4);
+    assert_eq!(v.get(3), None);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p4), 1);
+        assert_eq!(v.get_unchecked(&p0), &0);
+        assert_eq!(v.get_unchecked(&p2), &2);
+        assert_eq!(v.get_unchecked(&p4), &4);
+    }
+    unsafe {
+        v.take_unchecked(p0);
+    }
+    assert_eq!(v.len(), 2);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p2), 0);
+        assert_eq!(v.index_unchecked(&p4), 1);
+    }
+    assert_eq!(v.get(0), Some(&2));
+}
+
+#[test]
+fn take() {
+    let mut v = LinearStorage::with_capacity(0);
+    let [_, _, p2] = array::from_fn(|n| v.insert(n));
+    assert_eq!(unsafe { v.take(0) }, Some(0));
+    assert_eq!(unsafe { v.take(0) }, None);
+    assert_eq!(unsafe { v.take(3) }, None);
+    assert_eq!(v.len(), 3);
+    let p3 = v.insert(3);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p3), 0);
+        assert_eq!(v.get_unchecked(&p2), &2);
+    }
+}
+
+#[test]
+fn reuse_policy() {
+    let mut v = LinearStorage::with_capacity(0);
+    v.set_reuse_policy(ReusePolicy::Fifo);
+    let [p0, p1, p2, p3, p4, p5] = array::from_fn(|n| v.insert(n));
+    unsafe {
+        v.take_unchecked(p1);
+        v.take_unchecked(p5);
+        v.take_unchecked(p3);
+    }
+    v.set_max_vacant(1);
+    assert_eq!(v.len(), 4);
+    assert_eq!(v.get(3), None);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p4), 1);
+        assert_eq!(v.get_unchecked(&p0), &0);
+        assert_eq!(v.get_unchecked(&p2), &2);
+        assert_eq!(v.get_unchecked(&p4), &4);
+    }
+    v.set_max_vacant(usize::MAX);
+    unsafe {
+        v.take_unchecked(p0);
+    }
+    let p6 = v.insert(6);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p6), 3);
+    }
+    v.set_reuse_policy(ReusePolicy::Lifo);
+    unsafe {
+        v.take_unchecked(p2);
+    }
+    let p7 = v.insert(7);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p7), 2);
+    }
+    v.force_compact();
+    assert_eq!(v.len(), 3);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p6), 0);
+        assert_eq!(v.get_unchecked(&p4), &4);
+        assert_eq!(v.get_unchecked(&p6), &6);
+        assert_eq!(v.get_unchecked(&p7), &7);
+    }
+}
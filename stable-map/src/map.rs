@@ -1,30 +1,64 @@
+mod instrument;
 #[cfg(test)]
 mod tests;
 
+#[cfg(feature = "futures")]
+use crate::index_stream::IndexStream;
+#[cfg(feature = "rayon")]
+use crate::par_iter::ParIterByIndex;
+#[cfg(feature = "raw-entry")]
+use crate::raw_entry::{RawEntryBuilder, RawEntryBuilderMut, RawEntryMut};
 use {
     crate::{
+        apply_diff_error::ApplyDiffError,
+        capacity_error::CapacityError,
+        diff::{DiffEntry, MapDiff},
+        diff_index_error::DiffIndexError,
         drain::Drain,
+        drain_indexed::DrainIndexed,
         entry::{Entry, EntryRef, OccupiedEntry, VacantEntry, VacantEntryRef},
+        filtered_view::FilteredView,
+        handle::Handle,
+        indexed_occupied_entry::IndexedOccupiedEntry,
         into_iter::IntoIter,
         into_keys::IntoKeys,
         into_values::IntoValues,
         iter::Iter,
+        iter_indexed::IterIndexed,
         iter_mut::IterMut,
+        iter_mut_indexed::IterMutIndexed,
+        iter_sparse::IterSparse,
+        key_map::KeyMap,
         keys::Keys,
         linear_storage::LinearStorage,
+        map_op::MapOp,
         occupied_error::OccupiedError,
+        overlap_error::OverlapError,
         pos_vec::pos::{InUse, Pos},
+        reuse_policy::ReusePolicy,
+        try_reserve_error::TryReserveError,
         values::Values,
+        values_from_index::ValuesFromIndex,
         values_mut::ValuesMut,
+        values_mut_from_index::ValuesMutFromIndex,
+        values_sparse::ValuesSparse,
     },
+    alloc::{vec, vec::Vec},
+    allocator_api2::alloc::{Allocator, Global},
     core::{
+        cell::Cell,
         cmp::min,
         hash::{BuildHasher, Hash},
-        iter::FusedIterator,
+        iter::{self, FusedIterator},
         marker::PhantomData,
         mem::{self},
+        ops::{DerefMut, Range},
+        ptr,
+    },
+    hashbrown::{
+        hash_table::{self, HashTable},
+        DefaultHashBuilder, Equivalent, HashSet,
     },
-    hashbrown::{hash_map, DefaultHashBuilder, Equivalent, HashMap},
 };
 
 /// A hash map with temporarily-stable indices.
@@ -35,7 +69,10 @@ use {
 /// - `Vec<V>`
 ///
 /// The index of for each key stays the same unless the key is removed from the map or the
-/// map is explicitly compacted.
+/// map is explicitly compacted. If [amortized
+/// compaction](StableMap::set_amortized_compaction) is enabled, inserting a key can also
+/// change the index of one other key. If a [limit](StableMap::set_max_vacant) on unused
+/// indices is configured, removing a key can also change the indices of other keys.
 ///
 /// # Example
 ///
@@ -93,6 +130,43 @@ use {
 ///     }
 /// }
 /// ```
+///
+/// # Zero-sized values
+///
+/// The hash table maps each key to a slot and the storage maps each slot to the current
+/// index of the entry. This indirection is what allows the map to update indices during
+/// compaction, and it is required even if `V` is a zero-sized type. A `StableMap<K, ()>`
+/// therefore uses a slot, a generation counter, and an index per entry in addition to
+/// the `HashMap<K, _>`. If stable indices are not needed, a `HashSet<K>` is cheaper.
+///
+/// # Narrow indices
+///
+/// By default, slots and indices are stored as `usize`. With the `narrow-index` feature,
+/// they are stored as `u32` instead, which shrinks both the hash table payload and the
+/// per-entry bookkeeping of the storage. [index_len](Self::index_len) is then limited to
+/// `u32::MAX` and operations that would create an index beyond that limit panic.
+///
+/// # Cached hashes
+///
+/// With the `cached-hash` feature, the hash of each key is stored next to its value.
+/// This costs 8 bytes per index. In return, the keys do not have to be hashed again when
+/// the hash table grows or shrinks or when the map is cloned, and functions that look up
+/// the key of an index, such as [get_key_by_index](Self::get_key_by_index) and
+/// [entry_by_index](Self::entry_by_index), take constant instead of linear time.
+/// [map_hasher](Self::map_hasher) and [clone_with_hasher](Self::clone_with_hasher) still
+/// hash every key since they change the hasher.
+///
+/// # Custom allocators
+///
+/// Like [HashMap], this type has an allocator parameter `A` that defaults to the global
+/// allocator. The allocator trait is the one from the
+/// [allocator-api2](https://docs.rs/allocator-api2) crate, which re-exports the unstable
+/// `core::alloc::Allocator` trait if its `nightly` feature is enabled. Maps using a
+/// custom allocator are created with [new_in](Self::new_in),
+/// [with_capacity_in](Self::with_capacity_in), [with_hasher_in](Self::with_hasher_in),
+/// or [with_capacity_and_hasher_in](Self::with_capacity_and_hasher_in). The hash table
+/// and the storage of the values are allocated with the allocator. The list of unused
+/// indices is always allocated with the global allocator.
 //
 // This type upholds the following invariants:
 //
@@ -101,9 +175,9 @@ use {
 // SAFETY:
 // - LinearStorage::clear invalidates existing Pos<InUse> without consuming them.
 // - Code calling LinearStorage::clear must explain how it upholds the invariant.
-pub struct StableMap<K, V, S = DefaultHashBuilder> {
-    key_to_pos: HashMap<K, Pos<InUse>, S>,
-    storage: LinearStorage<V>,
+pub struct StableMap<K, V, S = DefaultHashBuilder, A: Allocator = Global> {
+    key_to_pos: KeyMap<K, S, A>,
+    storage: LinearStorage<V, A>,
 }
 
 #[cfg(feature = "default-hasher")]
@@ -124,7 +198,7 @@ impl<K, V> StableMap<K, V, DefaultHashBuilder> {
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn new() -> Self {
         Self {
-            key_to_pos: HashMap::new(),
+            key_to_pos: KeyMap::with_capacity_and_hasher(0, DefaultHashBuilder::default()),
             storage: LinearStorage::with_capacity(0),
         }
     }
@@ -145,13 +219,306 @@ impl<K, V> StableMap<K, V, DefaultHashBuilder> {
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn with_capacity(capacity: usize) -> Self {
         Self {
-            key_to_pos: HashMap::with_capacity(capacity),
+            key_to_pos: KeyMap::with_capacity_and_hasher(capacity, DefaultHashBuilder::default()),
             storage: LinearStorage::with_capacity(capacity),
         }
     }
+
+    /// Creates an empty `StableMap` that holds at most `max_len` elements.
+    ///
+    /// See [set_max_len](Self::set_max_len).
+    ///
+    /// The map is initially created with a capacity of 0, so it will not allocate until it
+    /// is first inserted into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    /// let mut map: StableMap<&str, i32> = StableMap::with_max_len(10);
+    /// assert_eq!(map.max_len(), 10);
+    /// assert_eq!(map.capacity(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_max_len(max_len: usize) -> Self {
+        let mut map = Self::new();
+        map.set_max_len(max_len);
+        map
+    }
 }
 
-impl<K, V, S> StableMap<K, V, S> {
+#[cfg(feature = "default-hasher")]
+impl<K, V, A: Allocator + Clone> StableMap<K, V, DefaultHashBuilder, A> {
+    /// Creates an empty `StableMap` using the given allocator.
+    ///
+    /// The map is initially created with a capacity of 0, so it will not allocate until it
+    /// is first inserted into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use allocator_api2::alloc::Global;
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, i32, _, _> = StableMap::new_in(Global);
+    /// assert_eq!(map.len(), 0);
+    /// assert_eq!(map.capacity(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_capacity_and_hasher_in(0, DefaultHashBuilder::default(), alloc)
+    }
+
+    /// Creates an empty `StableMap` with the specified capacity using the given
+    /// allocator.
+    ///
+    /// The map will be able to hold at least `capacity` elements without
+    /// reallocating. If `capacity` is 0, the map will not allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use allocator_api2::alloc::Global;
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, i32, _, _> = StableMap::with_capacity_in(10, Global);
+    /// assert_eq!(map.len(), 0);
+    /// assert!(map.capacity() >= 10);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self::with_capacity_and_hasher_in(capacity, DefaultHashBuilder::default(), alloc)
+    }
+}
+
+impl<K, V, S, A: Allocator> StableMap<K, V, S, A> {
+    /// Applies a change set produced by [diff](Self::diff).
+    ///
+    /// This function
+    ///
+    /// - removes the keys in `diff.removed`,
+    /// - updates the values of keys in `diff.changed` and `diff.added` that are already
+    ///   contained in the map, and
+    /// - inserts the remaining entries of `diff.changed` and `diff.added`.
+    ///
+    /// Entries that are already contained in the map keep their index. New entries are
+    /// inserted at the index recorded in the change set if that index is not in use.
+    /// Otherwise they are inserted at an arbitrary index. If a recorded index is larger
+    /// than or equal to [index_len](Self::index_len), the index space is extended up to
+    /// that index unless this would leave more than [max_vacant](Self::max_vacant)
+    /// unused indices.
+    ///
+    /// Since the change set might come from an untrusted source, the recorded indices of
+    /// `diff.changed` and `diff.added` must be less than `index_len()` plus the number of
+    /// entries in `diff.changed` and `diff.added`. Furthermore, applying the change set
+    /// must not increase the number of elements beyond [max_len](Self::max_len).
+    /// Otherwise the map is left unchanged and an error is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let a: StableMap<_, _> = [(1, "a"), (2, "b"), (3, "c")].into();
+    /// let b: StableMap<_, _> = [(4, "d"), (2, "e"), (3, "c")].into();
+    ///
+    /// let mut c: StableMap<_, _> = [(1, "a"), (2, "b"), (3, "c")].into();
+    /// c.apply_diff(a.diff(&b)).unwrap();
+    /// assert_eq!(c, b);
+    /// for key in [2, 3, 4] {
+    ///     assert_eq!(c.get_index(&key), b.get_index(&key));
+    /// }
+    /// ```
+    pub fn apply_diff(&mut self, diff: MapDiff<K, V>) -> Result<(), ApplyDiffError>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let num_entries = diff.changed.len().saturating_add(diff.added.len());
+        let bound = self.index_len().saturating_add(num_entries);
+        let mut entries = diff.changed.iter().chain(&diff.added);
+        if let Some(entry) = entries.find(|e| e.index >= bound) {
+            return Err(DiffIndexError {
+                index: entry.index,
+                bound,
+            }
+            .into());
+        }
+        let max_len = self.max_len();
+        if self.len().saturating_add(num_entries) > max_len {
+            let (retained, inserted) = self.count_diff_len(&diff);
+            let len = retained.saturating_add(inserted);
+            if inserted > 0 && len > max_len {
+                return Err(ApplyDiffError::MaxLen { len, max_len });
+            }
+        }
+        for (_, key) in diff.removed {
+            self.remove(&key);
+        }
+        for entry in diff.changed.into_iter().chain(diff.added) {
+            let hash = self.key_to_pos.hash(&entry.key);
+            match self.key_to_pos.entry(hash, &entry.key, &self.storage) {
+                hash_table::Entry::Occupied(occupied) => {
+                    let prev = unsafe {
+                        // SAFETY:
+                        // - By the invariants, occupied.get() is valid
+                        self.storage.get_unchecked_mut(&occupied.get().1)
+                    };
+                    *prev = entry.value;
+                }
+                hash_table::Entry::Vacant(vacant) => {
+                    let pos = match self.storage.insert_at(entry.index, hash, entry.value) {
+                        Ok(pos) => pos,
+                        Err(value) => self.storage.insert_hashed(hash, value),
+                    };
+                    vacant.insert((entry.key, pos));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the number of elements that remain in the map after removing the keys in
+    /// `diff.removed` and the number of keys that applying `diff` would then insert.
+    fn count_diff_len(&self, diff: &MapDiff<K, V>) -> (usize, usize)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let hasher = |key: &&K| self.key_to_pos.hash(*key);
+        let mut retained = self.len();
+        let mut removed = HashTable::with_capacity(diff.removed.len());
+        for (_, key) in &diff.removed {
+            let hash = self.key_to_pos.hash(key);
+            if let hash_table::Entry::Vacant(v) = removed.entry(hash, |k| *k == key, hasher) {
+                v.insert(key);
+                if self.contains_key(key) {
+                    retained -= 1;
+                }
+            }
+        }
+        let mut inserted = HashTable::new();
+        for entry in diff.changed.iter().chain(&diff.added) {
+            let key = &entry.key;
+            let hash = self.key_to_pos.hash(key);
+            if self.contains_key(key) && removed.find(hash, |k| *k == key).is_none() {
+                continue;
+            }
+            if let hash_table::Entry::Vacant(v) = inserted.entry(hash, |k| *k == key, hasher) {
+                v.insert(key);
+            }
+        }
+        (retained, inserted.len())
+    }
+
+    /// Applies a batch of operations to the map.
+    ///
+    /// Before the first operation is applied, this function reserves space for as many
+    /// elements as the lower bound of the size hint of the iterator.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an insertion would increase the number of elements beyond
+    /// [max_len](Self::max_len). The operations before that insertion have already been
+    /// applied. Use [try_apply_bounded](Self::try_apply_bounded) to handle this case.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{MapOp, StableMap};
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.apply([
+    ///     MapOp::Insert("c", 3),
+    ///     MapOp::Remove("a"),
+    ///     MapOp::Update("b", 4),
+    ///     MapOp::Update("d", 5),
+    /// ]);
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map["b"], 4);
+    /// assert_eq!(map["c"], 3);
+    /// ```
+    pub fn apply<I>(&mut self, ops: I)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+        I: IntoIterator<Item = MapOp<K, V>>,
+    {
+        let ops = ops.into_iter();
+        self.reserve(ops.size_hint().0);
+        for op in ops {
+            match op {
+                MapOp::Insert(key, value) => {
+                    self.insert_no_return(key, value);
+                }
+                MapOp::Remove(key) => {
+                    self.remove(&key);
+                }
+                MapOp::Update(key, value) => {
+                    if let Some(prev) = self.get_mut(&key) {
+                        *prev = value;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Applies a batch of operations to the map unless an insertion would increase the
+    /// number of elements beyond [max_len](Self::max_len).
+    ///
+    /// This function behaves like [apply](Self::apply) except that it stops at the first
+    /// insertion of a new key into a map that already contains `max_len` elements. The
+    /// key-value pair of that insertion is returned in the error. The operations before
+    /// it have already been applied and the operations after it are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{CapacityError, MapOp, StableMap};
+    ///
+    /// let mut map = StableMap::with_max_len(2);
+    /// map.insert("a", 1);
+    /// let res = map.try_apply_bounded([
+    ///     MapOp::Insert("b", 2),
+    ///     MapOp::Insert("c", 3),
+    ///     MapOp::Remove("a"),
+    /// ]);
+    /// assert_eq!(res, Err(CapacityError { key: "c", value: 3 }));
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map["a"], 1);
+    /// assert_eq!(map["b"], 2);
+    /// ```
+    pub fn try_apply_bounded<I>(&mut self, ops: I) -> Result<(), CapacityError<K, V>>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+        I: IntoIterator<Item = MapOp<K, V>>,
+    {
+        let ops = ops.into_iter();
+        self.reserve(min(
+            ops.size_hint().0,
+            self.max_len().saturating_sub(self.len()),
+        ));
+        for op in ops {
+            match op {
+                MapOp::Insert(key, value) => {
+                    self.try_insert_bounded(key, value)?;
+                }
+                MapOp::Remove(key) => {
+                    self.remove(&key);
+                }
+                MapOp::Update(key, value) => {
+                    if let Some(prev) = self.get_mut(&key) {
+                        *prev = value;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Returns the number of elements the map can hold without reallocating.
     ///
     /// This number is a lower bound; the `StableMap<K, V>` might be able to hold
@@ -197,6 +564,116 @@ impl<K, V, S> StableMap<K, V, S> {
         // - We have cleared key_to_pos.
     }
 
+    /// Clones the map into a map that uses `hash_builder` to hash the keys.
+    ///
+    /// Unlike [`Clone::clone`], each entry retains its index in the returned map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hashbrown::DefaultHashBuilder;
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// map.remove(&1);
+    ///
+    /// let clone = map.clone_with_hasher(DefaultHashBuilder::default());
+    /// assert_eq!(clone[&2], "b");
+    /// assert_eq!(clone.get_index(&2), Some(1));
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    pub fn clone_with_hasher<S2>(&self, hash_builder: S2) -> StableMap<K, V, S2, A>
+    where
+        K: Eq + Hash + Clone,
+        V: Clone,
+        S2: BuildHasher,
+        A: Clone,
+    {
+        let mut entries = Vec::with_capacity(self.len());
+        for (key, pos) in &self.key_to_pos {
+            let index = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.index_unchecked(pos)
+            };
+            let value = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.get_unchecked(pos)
+            };
+            entries.push((index, key, value));
+        }
+        entries.sort_unstable_by_key(|(index, _, _)| *index);
+        let mut map = StableMap::with_capacity_and_hasher_in(
+            self.len(),
+            hash_builder,
+            self.allocator().clone(),
+        );
+        map.set_amortized_compaction(self.amortized_compaction());
+        map.set_auto_shrink(self.auto_shrink());
+        map.set_max_vacant(self.max_vacant());
+        map.set_reuse_policy(self.reuse_policy());
+        map.reserve_index_capacity(self.index_len());
+        for (index, key, value) in entries {
+            // The keys are distinct and the indices are distinct, ascending, and valid in
+            // self. The unused indices below each index are unused in self as well, so
+            // they do not exceed max_vacant, and the memory for them has been reserved
+            // above. max_len is only set below.
+            map.insert_at_index(index, key.clone(), value.clone())
+                .unwrap_or_else(|_| unreachable!("the entries of a map can be inserted"));
+        }
+        map.extend_index_space(self.index_len() - map.index_len());
+        // The map might contain more than max_len elements.
+        map.set_max_len(self.max_len());
+        map
+    }
+
+    /// Replaces the value corresponding to the key if it is equal to `expected`.
+    ///
+    /// If the key is contained in the map and its value is equal to `expected`, the value
+    /// is replaced by `new` and the previous value is returned in `Ok`. Otherwise the
+    /// map is not modified and `Err` is returned. It contains the current value if the
+    /// key is contained in the map and `None` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("state", "idle");
+    ///
+    /// assert_eq!(map.compare_exchange("state", &"idle", "running"), Ok("idle"));
+    /// assert_eq!(
+    ///     map.compare_exchange("state", &"idle", "running"),
+    ///     Err(Some(&"running")),
+    /// );
+    /// assert_eq!(map.compare_exchange("other", &"idle", "running"), Err(None));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn compare_exchange<Q>(&mut self, key: &Q, expected: &V, new: V) -> Result<V, Option<&V>>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+        V: PartialEq,
+    {
+        let Some(pos) = self.key_to_pos.get(key) else {
+            return Err(None);
+        };
+        let value = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.get_unchecked_mut(pos)
+        };
+        if *value != *expected {
+            return Err(Some(value));
+        }
+        Ok(mem::replace(value, new))
+    }
+
     /// Returns `true` if the map contains a value for the specified key.
     ///
     /// The key may be any borrowed form of the map's key type, but
@@ -226,6 +703,77 @@ impl<K, V, S> StableMap<K, V, S> {
         self.key_to_pos.contains_key(key)
     }
 
+    /// Computes the changes required to turn this map into `other`.
+    ///
+    /// The returned [`MapDiff`] contains
+    ///
+    /// - the entries of `other` whose keys are not contained in this map,
+    /// - the keys of this map that are not contained in `other`, and
+    /// - the entries of `other` whose values differ from the values in this map.
+    ///
+    /// Each element is recorded together with its index in the map it was taken from.
+    /// The elements are listed in arbitrary order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut a = StableMap::new();
+    /// a.insert(1, "a");
+    /// a.insert(2, "b");
+    /// let mut b = StableMap::new();
+    /// b.insert(2, "c");
+    /// b.insert(3, "d");
+    ///
+    /// let diff = a.diff(&b);
+    /// assert_eq!(diff.added.len(), 1);
+    /// assert_eq!(diff.added[0].key, 3);
+    /// assert_eq!(diff.removed, [(0, 1)]);
+    /// assert_eq!(diff.changed.len(), 1);
+    /// assert_eq!(diff.changed[0].value, "c");
+    /// ```
+    pub fn diff(&self, other: &Self) -> MapDiff<K, V>
+    where
+        K: Eq + Hash + Clone,
+        V: PartialEq + Clone,
+        S: BuildHasher,
+    {
+        let mut diff = MapDiff::default();
+        for (k, pos) in &self.key_to_pos {
+            if !other.key_to_pos.contains_key(k) {
+                let index = unsafe {
+                    // SAFETY:
+                    // - By the invariants, pos is valid
+                    self.storage.index_unchecked(pos)
+                };
+                diff.removed.push((index, k.clone()));
+            }
+        }
+        for (k, pos) in &other.key_to_pos {
+            let value = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                other.storage.get_unchecked(pos)
+            };
+            let entry = || DiffEntry {
+                index: unsafe {
+                    // SAFETY:
+                    // - By the invariants, pos is valid
+                    other.storage.index_unchecked(pos)
+                },
+                key: k.clone(),
+                value: value.clone(),
+            };
+            match self.get(k) {
+                None => diff.added.push(entry()),
+                Some(old) if old != value => diff.changed.push(entry()),
+                Some(_) => {}
+            }
+        }
+        diff
+    }
+
     /// Clears the map, returning all key-value pairs as an iterator. Keeps the
     /// allocated memory for reuse.
     ///
@@ -265,15 +813,85 @@ impl<K, V, S> StableMap<K, V, S> {
     /// assert!(a.is_empty());
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn drain(&mut self) -> Drain<'_, K, V> {
+    pub fn drain(&mut self) -> Drain<'_, K, V, A> {
         Drain {
             drain: self.key_to_pos.drain(),
             entries: &mut self.storage,
         }
     }
 
+    /// Clears the map, returning all key-value pairs and their indices as an iterator.
+    /// Keeps the allocated memory for reuse.
+    ///
+    /// Each yielded index is the index that the key had when this function was called.
+    /// Unlike [drain](Self::drain), no values are moved while the iterator is consumed,
+    /// even if a [limit](Self::set_max_vacant) on unused indices is configured.
+    ///
+    /// If the returned iterator is dropped before being fully consumed, it drops the
+    /// remaining key-value pairs. The returned iterator keeps a mutable borrow on the
+    /// map to optimize its implementation.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// map.remove("b");
+    /// let capacity_before_drain = map.capacity();
+    ///
+    /// let mut drained: Vec<_> = map.drain_indexed().collect();
+    /// drained.sort_unstable();
+    /// assert_eq!(drained, [(0, "a", 1), (2, "c", 3)]);
+    ///
+    /// assert!(map.is_empty());
+    /// assert_eq!(map.capacity(), capacity_before_drain);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn drain_indexed(&mut self) -> DrainIndexed<'_, K, V, A> {
+        DrainIndexed {
+            drain: self.key_to_pos.drain(),
+            entries: &mut self.storage,
+        }
+    }
+
+    /// Clears the map, appending all key-value pairs to `buf`.
+    ///
+    /// The pairs are appended in arbitrary order. Keeps the allocated memory of the map
+    /// and of `buf` for reuse.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// let mut buf = Vec::new();
+    /// map.insert(1, "a");
+    /// map.drain_into(&mut buf);
+    /// map.insert(2, "b");
+    /// map.drain_into(&mut buf);
+    ///
+    /// assert!(map.is_empty());
+    /// assert_eq!(buf, [(1, "a"), (2, "b")]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn drain_into(&mut self, buf: &mut Vec<(K, V)>) {
+        buf.reserve(self.len());
+        buf.extend(self.drain());
+    }
+
     /// Gets the given key's corresponding entry in the map for in-place manipulation.
     ///
+    /// # Panics
+    ///
+    /// Inserting a value into the returned entry panics if the map already contains
+    /// [max_len](Self::max_len) elements. Use [`VacantEntry::try_insert_bounded`] to
+    /// handle this case.
+    ///
     /// # Examples
     ///
     /// ```
@@ -292,25 +910,36 @@ impl<K, V, S> StableMap<K, V, S> {
     /// assert_eq!(letters.get(&'y'), None);
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S>
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S, A>
     where
         K: Eq + Hash,
         S: BuildHasher,
     {
-        match self.key_to_pos.entry(key) {
-            hash_map::Entry::Occupied(v) => Entry::Occupied(OccupiedEntry {
+        let hash = self.key_to_pos.hash(&key);
+        match self.key_to_pos.entry(hash, &key, &self.storage) {
+            hash_table::Entry::Occupied(v) => Entry::Occupied(OccupiedEntry {
                 entry: v,
                 entries: &mut self.storage,
+                _phantom: PhantomData,
             }),
-            hash_map::Entry::Vacant(v) => Entry::Vacant(VacantEntry {
+            hash_table::Entry::Vacant(v) => Entry::Vacant(VacantEntry {
                 entry: v,
+                hash,
+                key,
                 entries: &mut self.storage,
+                _phantom: PhantomData,
             }),
         }
     }
 
     /// Gets the given key's corresponding entry by reference in the map for in-place manipulation.
     ///
+    /// # Panics
+    ///
+    /// Inserting a value into the returned entry panics if the map already contains
+    /// [max_len](Self::max_len) elements. Use [is_full](Self::is_full) to check for this
+    /// case beforehand.
+    ///
     /// # Examples
     ///
     /// ```
@@ -327,28 +956,176 @@ impl<K, V, S> StableMap<K, V, S> {
     /// assert_eq!(words["horseyland"], 1);
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn entry_ref<'b, Q>(&mut self, key: &'b Q) -> EntryRef<'_, 'b, K, Q, V, S>
+    pub fn entry_ref<'b, Q>(&mut self, key: &'b Q) -> EntryRef<'_, 'b, K, Q, V, S, A>
     where
         K: Eq + Hash,
         Q: Hash + Equivalent<K> + ?Sized,
         S: BuildHasher,
     {
-        match self.key_to_pos.entry_ref(key) {
-            hash_map::EntryRef::Occupied(v) => EntryRef::Occupied(OccupiedEntry {
+        let hash = self.key_to_pos.hash(key);
+        match self.key_to_pos.entry(hash, key, &self.storage) {
+            hash_table::Entry::Occupied(v) => EntryRef::Occupied(OccupiedEntry {
                 entry: v,
                 entries: &mut self.storage,
+                _phantom: PhantomData,
             }),
-            hash_map::EntryRef::Vacant(v) => EntryRef::Vacant(VacantEntryRef {
+            hash_table::Entry::Vacant(v) => EntryRef::Vacant(VacantEntryRef {
                 entry: v,
+                hash,
+                key,
                 entries: &mut self.storage,
+                _phantom: PhantomData,
             }),
         }
     }
 
-    /// Drains elements which are true under the given predicate,
-    /// and returns an iterator over the removed items.
+    /// Creates a raw immutable entry builder for the map.
     ///
-    /// In other words, move all pairs `(k, v)` such that `f(&k, &mut v)` returns `true` out
+    /// Raw entries provide the lowest level of control for searching and manipulating a
+    /// map. They must be manually initialized with a hash and then manually searched.
+    ///
+    /// This is useful for looking up keys by a precomputed hash or with a custom notion
+    /// of equality.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {core::hash::BuildHasher, stable_map::StableMap};
+    ///
+    /// let mut map = StableMap::new();
+    /// map.extend([("a", 100), ("b", 200), ("c", 300)]);
+    ///
+    /// for k in ["a", "b", "c", "d"] {
+    ///     let hash = map.hasher().hash_one(k);
+    ///     let v = map.get(&k).cloned();
+    ///     let kv = v.as_ref().map(|v| (&k, v));
+    ///
+    ///     assert_eq!(map.raw_entry().from_key(&k), kv);
+    ///     assert_eq!(map.raw_entry().from_hash(hash, |q| *q == k), kv);
+    ///     assert_eq!(map.raw_entry().from_key_hashed_nocheck(hash, &k), kv);
+    /// }
+    /// ```
+    #[cfg(feature = "raw-entry")]
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn raw_entry(&self) -> RawEntryBuilder<'_, K, V, S, A> {
+        RawEntryBuilder {
+            map: &self.key_to_pos,
+            entries: &self.storage,
+        }
+    }
+
+    /// Creates a raw entry builder for the map.
+    ///
+    /// Raw entries provide the lowest level of control for searching and manipulating a
+    /// map. They must be manually initialized with a hash and then manually searched.
+    /// After this, insertions into a vacant entry still require an owned key to be
+    /// provided.
+    ///
+    /// This is useful for avoiding the construction of owned keys for lookups and for
+    /// reusing hashes across multiple operations.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {
+    ///     core::hash::BuildHasher,
+    ///     stable_map::{RawEntryMut, StableMap},
+    /// };
+    ///
+    /// let mut map = StableMap::new();
+    /// map.extend([("a", 100), ("b", 200), ("c", 300)]);
+    ///
+    /// let hash = map.hasher().hash_one("a");
+    /// match map.raw_entry_mut().from_key_hashed_nocheck(hash, &"a") {
+    ///     RawEntryMut::Vacant(_) => unreachable!(),
+    ///     RawEntryMut::Occupied(mut o) => *o.get_mut() += 1,
+    /// }
+    /// assert_eq!(map[&"a"], 101);
+    ///
+    /// let hash = map.hasher().hash_one("d");
+    /// match map.raw_entry_mut().from_hash(hash, |q| *q == "d") {
+    ///     RawEntryMut::Occupied(_) => unreachable!(),
+    ///     RawEntryMut::Vacant(v) => {
+    ///         v.insert_hashed_nocheck(hash, "d", 400);
+    ///     }
+    /// }
+    /// assert_eq!(map[&"d"], 400);
+    /// ```
+    #[cfg(feature = "raw-entry")]
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn raw_entry_mut(&mut self) -> RawEntryBuilderMut<'_, K, V, S, A> {
+        RawEntryBuilderMut {
+            map: &mut self.key_to_pos,
+            entries: &mut self.storage,
+        }
+    }
+
+    /// Gets the entry whose key has the given hash and satisfies `is_match` for in-place
+    /// manipulation.
+    ///
+    /// This is a shorthand for `self.raw_entry_mut().from_hash(hash, is_match)`. The hash
+    /// must have been computed with the [hasher](Self::hasher) of the map. This is not
+    /// checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {core::hash::BuildHasher, stable_map::StableMap};
+    ///
+    /// let mut map = StableMap::new();
+    /// for key in [(1, "a"), (2, "b"), (1, "a")] {
+    ///     let hash = map.hasher().hash_one(key);
+    ///     let (_, counter) = map
+    ///         .entry_with_hash(hash, |k| *k == key)
+    ///         .or_insert_with(|| (key, 0));
+    ///     *counter += 1;
+    /// }
+    /// assert_eq!(map[&(1, "a")], 2);
+    /// assert_eq!(map[&(2, "b")], 1);
+    /// ```
+    #[cfg(feature = "raw-entry")]
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn entry_with_hash<F>(&mut self, hash: u64, is_match: F) -> RawEntryMut<'_, K, V, S, A>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        self.raw_entry_mut().from_hash(hash, is_match)
+    }
+
+    /// Extends the map with the key-value pairs of an iterator without overwriting the
+    /// values of keys that are already contained in the map.
+    ///
+    /// If a key occurs multiple times, the first occurrence wins.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.extend_keep_existing([("a", 2), ("b", 3), ("b", 4)]);
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map["a"], 1);
+    /// assert_eq!(map["b"], 3);
+    /// ```
+    pub fn extend_keep_existing<I>(&mut self, iter: I)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        for (key, value) in iter {
+            if let Entry::Vacant(vacant) = self.entry(key) {
+                vacant.insert(value);
+            }
+        }
+    }
+
+    /// Drains elements which are true under the given predicate,
+    /// and returns an iterator over the removed items.
+    ///
+    /// In other words, move all pairs `(k, v)` such that `f(&k, &mut v)` returns `true` out
     /// into another iterator.
     ///
     /// Note that `extract_if` lets you mutate every value in the filter closure, regardless of
@@ -392,7 +1169,7 @@ impl<K, V, S> StableMap<K, V, S> {
     pub fn extract_if<F>(
         &mut self,
         mut f: F,
-    ) -> impl FusedIterator<Item = (K, V)> + use<'_, K, V, F, S>
+    ) -> impl FusedIterator<Item = (K, V)> + use<'_, K, V, F, S, A>
     where
         F: FnMut(&K, &mut V) -> bool,
     {
@@ -422,13 +1199,13 @@ impl<K, V, S> StableMap<K, V, S> {
             };
             f(k, v)
         });
-        struct Iter<'a, K, V, I> {
+        struct Iter<'a, K, V, A: Allocator, I> {
             iter: I,
-            storage: *mut LinearStorage<V>,
+            storage: *mut LinearStorage<V, A>,
             _phantom1: PhantomData<fn() -> K>,
-            _phantom2: PhantomData<&'a mut LinearStorage<V>>,
+            _phantom2: PhantomData<&'a mut LinearStorage<V, A>>,
         }
-        impl<K, V, I> Iterator for Iter<'_, K, V, I>
+        impl<K, V, A: Allocator, I> Iterator for Iter<'_, K, V, A, I>
         where
             I: Iterator<Item = (K, Pos<InUse>)>,
         {
@@ -447,15 +1224,115 @@ impl<K, V, S> StableMap<K, V, S> {
                 Some((k, value))
             }
         }
-        impl<K, V, I> FusedIterator for Iter<'_, K, V, I> where I: FusedIterator<Item = (K, Pos<InUse>)> {}
-        Iter::<'_, K, V, _> {
-            iter,
+        impl<K, V, A: Allocator, I> FusedIterator for Iter<'_, K, V, A, I> where
+            I: FusedIterator<Item = (K, Pos<InUse>)>
+        {
+        }
+        Iter::<'_, K, V, A, _> {
+            // hashbrown only implements FusedIterator for ExtractIf if the allocator is
+            // Global.
+            iter: iter.fuse(),
             storage,
             _phantom1: PhantomData,
             _phantom2: PhantomData,
         }
     }
 
+    /// Returns a read-only view of the entries that satisfy the predicate.
+    ///
+    /// The predicate is evaluated lazily whenever the view accesses an entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    ///
+    /// let view = map.filtered_view(|_, v| *v > 1);
+    /// assert_eq!(view.get("a"), None);
+    /// assert_eq!(view.get("b"), Some(&2));
+    /// assert_eq!(view.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn filtered_view<F>(&self, pred: F) -> FilteredView<'_, K, V, S, F, A>
+    where
+        F: Fn(&K, &V) -> bool,
+    {
+        FilteredView { map: self, pred }
+    }
+
+    /// Returns an iterator over the keys and indices of all entries whose values satisfy
+    /// the predicate, in arbitrary order.
+    ///
+    /// This function performs a linear scan over the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    ///
+    /// let mut keys: Vec<_> = map.find_all(|v| v % 2 == 1).map(|(k, _)| *k).collect();
+    /// keys.sort_unstable();
+    /// assert_eq!(keys, ["a", "c"]);
+    /// ```
+    pub fn find_all<F>(
+        &self,
+        mut pred: F,
+    ) -> impl Iterator<Item = (&K, usize)> + use<'_, K, V, S, A, F>
+    where
+        F: FnMut(&V) -> bool,
+    {
+        self.key_to_pos.iter().filter_map(move |(k, pos)| {
+            let v = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.get_unchecked(pos)
+            };
+            if !pred(v) {
+                return None;
+            }
+            let index = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.index_unchecked(pos)
+            };
+            Some((k, index))
+        })
+    }
+
+    /// Returns the key and index of an entry whose value satisfies the predicate.
+    ///
+    /// If multiple values satisfy the predicate, it is unspecified which entry is
+    /// returned. This function performs a linear scan over the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    ///
+    /// assert_eq!(map.find_key(|v| *v == 2), Some((&"b", 1)));
+    /// assert_eq!(map.find_key(|v| *v == 3), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn find_key<F>(&self, pred: F) -> Option<(&K, usize)>
+    where
+        F: FnMut(&V) -> bool,
+    {
+        self.find_all(pred).next()
+    }
+
     /// Returns a reference to the value corresponding to the key.
     ///
     /// The key may be any borrowed form of the map's key type, but
@@ -491,6 +1368,101 @@ impl<K, V, S> StableMap<K, V, S> {
         Some(v)
     }
 
+    /// Returns a reference to the value whose key has the given hash and satisfies
+    /// `is_match`.
+    ///
+    /// This function does not hash any key. The hash must have been computed with the
+    /// [hasher](Self::hasher) of the map. This is not checked. If it was computed
+    /// differently, the key will most likely not be found.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {core::hash::BuildHasher, stable_map::StableMap};
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert((1, "a"), 10);
+    /// let hash = map.hasher().hash_one((1, "a"));
+    /// assert_eq!(map.get_with_hash(hash, |k| k.0 == 1), Some(&10));
+    /// assert_eq!(map.get_with_hash(hash, |k| k.0 == 2), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_with_hash<F>(&self, hash: u64, is_match: F) -> Option<&V>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let (_, pos) = self.key_to_pos.find(hash, is_match)?;
+        let v = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.get_unchecked(pos)
+        };
+        Some(v)
+    }
+
+    /// Returns an iterator over the values corresponding to the keys.
+    ///
+    /// The iterator yields the same values that would be returned by calling
+    /// [get](Self::get) with each of the keys, in the order of the keys.
+    ///
+    /// The keys may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: https://doc.rust-lang.org/std/cmp/trait.Eq.html
+    /// [`Hash`]: https://doc.rust-lang.org/std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// let values: Vec<_> = map.get_all(&[2, 3, 1]).collect();
+    /// assert_eq!(values, [Some(&"b"), None, Some(&"a")]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_all<'a, 'b, Q, I>(
+        &'a self,
+        keys: I,
+    ) -> impl Iterator<Item = Option<&'a V>> + use<'a, 'b, K, V, S, A, Q, I>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized + 'b,
+        S: BuildHasher,
+        I: IntoIterator<Item = &'b Q>,
+    {
+        keys.into_iter().map(move |key| self.get(key))
+    }
+
+    /// Returns a reference to the key stored in the map that is equivalent to the supplied
+    /// key.
+    ///
+    /// This is useful to deduplicate keys, e.g. for interning.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(String::from("a"), 1);
+    /// let key: &String = map.get_key("a").unwrap();
+    /// assert_eq!(key, "a");
+    /// assert_eq!(map.get_key("b"), None);
+    /// ```
+    #[inline]
+    pub fn get_key<Q>(&self, key: &Q) -> Option<&K>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        self.key_to_pos.get_key_value(key).map(|(k, _)| k)
+    }
+
     /// Returns the key-value pair corresponding to the supplied key.
     ///
     /// The supplied key may be any borrowed form of the map's key type, but
@@ -561,6 +1533,87 @@ impl<K, V, S> StableMap<K, V, S> {
         Some((k, value))
     }
 
+    /// Returns the index, key, and value corresponding to the supplied key.
+    ///
+    /// This performs a single lookup and returns the same values that would be returned
+    /// by calling [get_index](Self::get_index) and [get_key_value](Self::get_key_value).
+    ///
+    /// The supplied key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: https://doc.rust-lang.org/std/cmp/trait.Eq.html
+    /// [`Hash`]: https://doc.rust-lang.org/std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// assert_eq!(map.get_full(&2), Some((1, &2, &"b")));
+    /// assert_eq!(map.get_full(&3), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_full<Q>(&self, key: &Q) -> Option<(usize, &K, &V)>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        let (k, pos) = self.key_to_pos.get_key_value(key)?;
+        let (index, v) = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            (
+                self.storage.index_unchecked(pos),
+                self.storage.get_unchecked(pos),
+            )
+        };
+        Some((index, k, v))
+    }
+
+    /// Returns the index, key, and a mutable reference to the value corresponding to the
+    /// supplied key.
+    ///
+    /// This is the mutable counterpart of [get_full](Self::get_full).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// if let Some((index, _, v)) = map.get_full_mut(&2) {
+    ///     assert_eq!(index, 1);
+    ///     *v = "c";
+    /// }
+    /// assert_eq!(map[&2], "c");
+    /// assert_eq!(map.get_full_mut(&3), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_full_mut<Q>(&mut self, key: &Q) -> Option<(usize, &K, &mut V)>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        let (k, pos) = self.key_to_pos.get_key_value(key)?;
+        let (index, value) = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            (
+                self.storage.index_unchecked(pos),
+                self.storage.get_unchecked_mut(pos),
+            )
+        };
+        Some((index, k, value))
+    }
+
     /// Attempts to get mutable references to `N` values in the map at once, with immutable
     /// references to the corresponding keys.
     ///
@@ -783,30 +1836,83 @@ impl<K, V, S> StableMap<K, V, S> {
         }
     }
 
-    /// Attempts to get mutable references to `N` values in the map at once, without validating that
-    /// the values are unique.
-    ///
-    /// Returns an array of length `N` with the results of each query. `None` will be used if
-    /// the key is missing.
-    ///
-    /// For a safe alternative see [`get_many_mut`](`StableMap::get_many_mut`).
-    ///
-    /// # Safety
+    /// Attempts to get mutable references to `N` values in the map at once.
     ///
-    /// Calling this method with overlapping keys is *[undefined behavior]* even if the resulting
-    /// references are not used.
+    /// Returns an array of length `N` with the results of each query. `None` will be used
+    /// if the key is missing.
     ///
-    /// [undefined behavior]: https://doc.rust-lang.org/reference/behavior-considered-undefined.html
+    /// Unlike [`get_many_mut`](Self::get_many_mut), this function does not panic if any
+    /// keys are overlapping. Instead, the positions of the first two overlapping keys are
+    /// returned in the error.
     ///
     /// # Examples
     ///
     /// ```
-    /// use stable_map::StableMap;
+    /// use stable_map::{OverlapError, StableMap};
     ///
     /// let mut libraries = StableMap::new();
     /// libraries.insert("Bodleian Library".to_string(), 1602);
     /// libraries.insert("Athenæum".to_string(), 1807);
-    /// libraries.insert("Herzogin-Anna-Amalia-Bibliothek".to_string(), 1691);
+    ///
+    /// let got = libraries.get_many_mut_checked(["Athenæum", "Bodleian Library"]);
+    /// assert_eq!(got, Ok([Some(&mut 1807), Some(&mut 1602)]));
+    ///
+    /// let got = libraries.get_many_mut_checked(["Athenæum", "Athenæum"]);
+    /// assert_eq!(got, Err(OverlapError { first: 0, second: 1 }));
+    /// ```
+    pub fn get_many_mut_checked<Q, const N: usize>(
+        &mut self,
+        ks: [&Q; N],
+    ) -> Result<[Option<&mut V>; N], OverlapError>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        let indices = ks.map(|k| self.get_index(k));
+        for (second, index) in indices.iter().enumerate() {
+            if index.is_none() {
+                continue;
+            }
+            if let Some(first) = indices[..second].iter().position(|i| i == index) {
+                return Err(OverlapError { first, second });
+            }
+        }
+        let mut values = self.storage.raw_access();
+        let values = indices.map(|idx| unsafe {
+            // SAFETY:
+            // - Each key was resolved to its index exactly once, so the indices checked
+            //   above are the indices used here.
+            // - We've checked above that these indices are distinct.
+            values.get_mut(idx?)
+        });
+        Ok(values)
+    }
+
+    /// Attempts to get mutable references to `N` values in the map at once, without validating that
+    /// the values are unique.
+    ///
+    /// Returns an array of length `N` with the results of each query. `None` will be used if
+    /// the key is missing.
+    ///
+    /// For a safe alternative see [`get_many_mut`](`StableMap::get_many_mut`).
+    ///
+    /// # Safety
+    ///
+    /// Calling this method with overlapping keys is *[undefined behavior]* even if the resulting
+    /// references are not used.
+    ///
+    /// [undefined behavior]: https://doc.rust-lang.org/reference/behavior-considered-undefined.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut libraries = StableMap::new();
+    /// libraries.insert("Bodleian Library".to_string(), 1602);
+    /// libraries.insert("Athenæum".to_string(), 1807);
+    /// libraries.insert("Herzogin-Anna-Amalia-Bibliothek".to_string(), 1691);
     /// libraries.insert("Library of Congress".to_string(), 1800);
     ///
     /// // SAFETY: The keys do not overlap.
@@ -895,6 +2001,40 @@ impl<K, V, S> StableMap<K, V, S> {
         Some(value)
     }
 
+    /// Returns a mutable reference to the value whose key has the given hash and
+    /// satisfies `is_match`.
+    ///
+    /// This function does not hash any key. The hash must have been computed with the
+    /// [hasher](Self::hasher) of the map. This is not checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {core::hash::BuildHasher, stable_map::StableMap};
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert((1, "a"), 10);
+    /// let hash = map.hasher().hash_one((1, "a"));
+    /// if let Some(v) = map.get_mut_with_hash(hash, |k| k.0 == 1) {
+    ///     *v = 20;
+    /// }
+    /// assert_eq!(map[&(1, "a")], 20);
+    /// assert_eq!(map.get_mut_with_hash(hash, |k| k.0 == 2), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_mut_with_hash<F>(&mut self, hash: u64, is_match: F) -> Option<&mut V>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let (_, pos) = self.key_to_pos.find(hash, is_match)?;
+        let value = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.get_unchecked_mut(pos)
+        };
+        Some(value)
+    }
+
     /// Returns a reference to the map's [`BuildHasher`].
     ///
     /// [`BuildHasher`]: https://doc.rust-lang.org/std/hash/trait.BuildHasher.html
@@ -927,6 +2067,12 @@ impl<K, V, S> StableMap<K, V, S> {
     /// [`std::collections`]: https://doc.rust-lang.org/std/collections/index.html
     /// [module-level documentation]: https://doc.rust-lang.org/std/collections/index.html#insert-and-complex-keys
     ///
+    /// # Panics
+    ///
+    /// Panics if the map did not have this key present and already contains
+    /// [max_len](Self::max_len) elements. Use
+    /// [try_insert_bounded](Self::try_insert_bounded) to handle this case.
+    ///
     /// # Examples
     ///
     /// ```
@@ -946,23 +2092,307 @@ impl<K, V, S> StableMap<K, V, S> {
         K: Eq + Hash,
         S: BuildHasher,
     {
-        match self.key_to_pos.entry(key) {
-            hash_map::Entry::Occupied(occupied) => {
+        let _span = self.rehash_span(1);
+        let hash = self.key_to_pos.hash(&key);
+        match self.key_to_pos.entry(hash, &key, &self.storage) {
+            hash_table::Entry::Occupied(occupied) => {
                 let prev = unsafe {
                     // SAFETY:
                     // - By the invariants, occupied.get() is valid
-                    self.storage.get_unchecked_mut(occupied.get())
+                    self.storage.get_unchecked_mut(&occupied.get().1)
                 };
                 Some(mem::replace(prev, value))
             }
-            hash_map::Entry::Vacant(vacant) => {
-                let pos = self.storage.insert(value);
-                vacant.insert(pos);
+            hash_table::Entry::Vacant(vacant) => {
+                let pos = self.storage.insert_hashed(hash, value);
+                vacant.insert((key, pos));
                 None
             }
         }
     }
 
+    /// Inserts a key-value pair into the map where the value is created from the index of
+    /// the entry.
+    ///
+    /// If the map did not have this key present, `f` is called with the index that the
+    /// new entry will occupy. If the map did have this key present, `f` is called with
+    /// the index of the existing entry and the value is replaced. The key is not updated,
+    /// though.
+    ///
+    /// Returns a mutable reference to the inserted value.
+    ///
+    /// If `f` panics, no entry is inserted or modified, but the hash table might have
+    /// reserved space for the key.
+    ///
+    /// If [amortized compaction](Self::set_amortized_compaction) is enabled, the
+    /// compaction step is skipped if it would move the new value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 0);
+    /// assert_eq!(map.insert_with("b", |index| index * 10), &mut 10);
+    /// assert_eq!(map.insert_with("a", |index| index + 5), &mut 5);
+    /// assert_eq!(map.get_index("b"), Some(1));
+    /// assert_eq!(map["b"], 10);
+    /// ```
+    pub fn insert_with(&mut self, key: K, f: impl FnOnce(usize) -> V) -> &mut V
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let _span = self.rehash_span(1);
+        let hash = self.key_to_pos.hash(&key);
+        let index = match self.key_to_pos.entry(hash, &key, &self.storage) {
+            hash_table::Entry::Occupied(occupied) => {
+                let index = unsafe {
+                    // SAFETY:
+                    // - By the invariants, occupied.get() is valid
+                    self.storage.index_unchecked(&occupied.get().1)
+                };
+                let value = f(index);
+                unsafe {
+                    // SAFETY:
+                    // - By the invariants, occupied.get() is valid
+                    *self.storage.get_unchecked_mut(&occupied.get().1) = value;
+                }
+                index
+            }
+            hash_table::Entry::Vacant(vacant) => {
+                let pos = self.storage.insert_with(hash, f);
+                let index = unsafe {
+                    // SAFETY:
+                    // - pos was just returned by self.storage
+                    self.storage.index_unchecked(&pos)
+                };
+                vacant.insert((key, pos));
+                index
+            }
+        };
+        unsafe {
+            // SAFETY:
+            // - The key we've just inserted or updated has a Pos<InUse> with this index.
+            self.storage.get_unchecked_raw_mut(index)
+        }
+    }
+
+    /// Inserts a key-value pair into the map, replacing both the key and the value if an
+    /// equivalent key is already present.
+    ///
+    /// If the map did not have this key present, [`None`] is returned.
+    ///
+    /// If the map did have this key present, the stored key and value are replaced by the
+    /// new ones and the old pair is returned. Unlike [`insert`](Self::insert), this stores
+    /// the new key, which matters for types that can be `==` without being identical.
+    ///
+    /// The index of the entry does not change.
+    ///
+    /// [`None`]: https://doc.rust-lang.org/std/option/enum.Option.html#variant.None
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// let a1 = String::from("a");
+    /// let a2 = String::from("a");
+    /// let ptr = a2.as_ptr();
+    ///
+    /// assert_eq!(map.insert_full_replace(a1, 1), None);
+    /// assert_eq!(map.insert_full_replace(a2, 2), Some(("a".to_string(), 1)));
+    /// assert_eq!(map.get_key("a").unwrap().as_ptr(), ptr);
+    /// assert_eq!(map["a"], 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert_full_replace(&mut self, key: K, value: V) -> Option<(K, V)>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let Some((prev_key, pos)) = self.key_to_pos.remove_entry(&key) else {
+            self.insert_no_return(key, value);
+            return None;
+        };
+        let hash = self.key_to_pos.stored_hash(&self.storage, &prev_key, &pos);
+        let prev_value = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            mem::replace(self.storage.get_unchecked_mut(&pos), value)
+        };
+        // We've just removed the only key equivalent to `key` from the map. Equivalent
+        // keys have the same hash.
+        self.key_to_pos.insert_unique(hash, key, pos, &self.storage);
+        Some((prev_key, prev_value))
+    }
+
+    /// Inserts a new key-value pair into the map at a specific index.
+    ///
+    /// If `index` is greater than or equal to [index_len](Self::index_len), the index
+    /// space is extended up to that index and the indices in between become unused
+    /// indices.
+    ///
+    /// If the map already contains the key or [max_len](Self::max_len) elements, if the
+    /// index is already in use, if the index is larger than the largest index the map can
+    /// represent, if extending the index space would leave more than
+    /// [max_vacant](Self::max_vacant) unused indices, or if the memory for the new indices
+    /// cannot be allocated, the map is left unchanged and the key-value pair is returned
+    /// in the error.
+    ///
+    /// If `index` is less than `index_len()`, this function takes time linear in the
+    /// number of unused indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// assert_eq!(map.insert_at_index(2, "a", 1), Ok(&mut 1));
+    /// assert_eq!(map.index_len(), 3);
+    /// assert_eq!(map.insert_at_index(0, "a", 2), Err(("a", 2)));
+    /// assert_eq!(map.insert_at_index(2, "b", 2), Err(("b", 2)));
+    /// assert_eq!(map.insert_at_index(0, "b", 2), Ok(&mut 2));
+    /// assert_eq!(map.get_index("a"), Some(2));
+    /// assert_eq!(map.get_index("b"), Some(0));
+    /// ```
+    pub fn insert_at_index(&mut self, index: usize, key: K, value: V) -> Result<&mut V, (K, V)>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        if self.storage.is_full() || self.key_to_pos.contains_key(&key) {
+            return Err((key, value));
+        }
+        let _span = self.rehash_span(1);
+        let hash = self.key_to_pos.hash(&key);
+        let pos = match self.storage.insert_at(index, hash, value) {
+            Ok(pos) => pos,
+            Err(value) => return Err((key, value)),
+        };
+        // We've checked above that the key is not contained in the map.
+        self.key_to_pos.insert_unique(hash, key, pos, &self.storage);
+        let value = unsafe {
+            // SAFETY:
+            // - We've just inserted a Pos<InUse> with this index.
+            self.storage.get_unchecked_raw_mut(index)
+        };
+        Ok(value)
+    }
+
+    /// Inserts a key-value pair into the map without allocating.
+    ///
+    /// If the map did have this key present, the value is updated, and the index of the
+    /// entry and the old value are returned. The key is not updated, though.
+    ///
+    /// If the map did not have this key present, the key-value pair is inserted if this
+    /// is possible without allocating, and the index of the new entry is returned.
+    /// Otherwise the key-value pair is returned in the error.
+    ///
+    /// Inserting a new key is possible without allocating if the map has
+    /// [spare capacity](Self::spare_capacity). To use this function on a thread that
+    /// must not allocate, first [reserve](Self::reserve) capacity. The key-value pair is
+    /// also returned in the error if the map already contains [max_len](Self::max_len)
+    /// elements.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// assert_eq!(map.insert_within_capacity(1, 1), Err((1, 1)));
+    ///
+    /// map.reserve(4);
+    /// let spare = map.spare_capacity();
+    /// for i in 0..spare {
+    ///     assert_eq!(map.insert_within_capacity(i, i), Ok((i, None)));
+    /// }
+    /// assert_eq!(map.insert_within_capacity(spare, 0), Err((spare, 0)));
+    /// assert_eq!(map.insert_within_capacity(1, 2), Ok((1, Some(1))));
+    /// ```
+    pub fn insert_within_capacity(&mut self, key: K, value: V) -> Result<(usize, Option<V>), (K, V)>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let full = self.spare_capacity() == 0 || self.storage.is_full();
+        let hash = self.key_to_pos.hash(&key);
+        match self.key_to_pos.entry(hash, &key, &self.storage) {
+            hash_table::Entry::Occupied(occupied) => {
+                let index = unsafe {
+                    // SAFETY:
+                    // - By the invariants, occupied.get() is valid
+                    self.storage.index_unchecked(&occupied.get().1)
+                };
+                let prev = unsafe {
+                    // SAFETY:
+                    // - By the invariants, occupied.get() is valid
+                    self.storage.get_unchecked_mut(&occupied.get().1)
+                };
+                Ok((index, Some(mem::replace(prev, value))))
+            }
+            hash_table::Entry::Vacant(_) if full => Err((key, value)),
+            hash_table::Entry::Vacant(vacant) => {
+                let pos = self.storage.insert_hashed(hash, value);
+                let index = unsafe {
+                    // SAFETY:
+                    // - We just retrieved this position.
+                    self.storage.index_unchecked(&pos)
+                };
+                vacant.insert((key, pos));
+                Ok((index, None))
+            }
+        }
+    }
+
+    /// Inserts a key-value pair into the map without returning the old value.
+    ///
+    /// Returns `true` if the map already contained this key. In this case the old value is
+    /// dropped in place and replaced by the new value. The key is not updated.
+    ///
+    /// Unlike [insert](Self::insert), this function does not move the old value out of
+    /// the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// assert_eq!(map.insert_no_return(37, "a"), false);
+    /// assert_eq!(map.insert_no_return(37, "b"), true);
+    /// assert_eq!(map[&37], "b");
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert_no_return(&mut self, key: K, value: V) -> bool
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let _span = self.rehash_span(1);
+        let hash = self.key_to_pos.hash(&key);
+        match self.key_to_pos.entry(hash, &key, &self.storage) {
+            hash_table::Entry::Occupied(occupied) => {
+                let prev = unsafe {
+                    // SAFETY:
+                    // - By the invariants, occupied.get() is valid
+                    self.storage.get_unchecked_mut(&occupied.get().1)
+                };
+                *prev = value;
+                true
+            }
+            hash_table::Entry::Vacant(vacant) => {
+                let pos = self.storage.insert_hashed(hash, value);
+                vacant.insert((key, pos));
+                false
+            }
+        }
+    }
+
     /// Insert a key-value pair into the map without checking
     /// if the key already exists in the map.
     ///
@@ -1026,12 +2456,37 @@ impl<K, V, S> StableMap<K, V, S> {
         K: Eq + Hash,
         S: BuildHasher,
     {
-        let pos = self.storage.insert(value);
-        let (key, pos) = unsafe {
+        let hash = self.key_to_pos.hash(&key);
+        unsafe {
             // SAFETY:
-            // - The requirement is forwarded to the caller.
-            self.key_to_pos.insert_unique_unchecked(key, pos)
-        };
+            // - The requirements are forwarded to the caller.
+            self.insert_unique_hashed_unchecked(hash, key, value)
+        }
+    }
+
+    /// Like [insert_unique_unchecked](Self::insert_unique_unchecked) but uses a hash that
+    /// has already been computed.
+    ///
+    /// # Safety
+    ///
+    /// The key must not already exist in the map.
+    ///
+    /// The hash must be the hash of the key. Otherwise, the map is left in an unspecified
+    /// but memory-safe state.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) unsafe fn insert_unique_hashed_unchecked(
+        &mut self,
+        hash: u64,
+        key: K,
+        value: V,
+    ) -> (&K, &mut V)
+    where
+        K: Hash,
+        S: BuildHasher,
+    {
+        let _span = self.rehash_span(1);
+        let pos = self.storage.insert_hashed(hash, value);
+        let (key, pos) = self.key_to_pos.insert_unique(hash, key, pos, &self.storage);
         let value = unsafe {
             // SAFETY:
             // - We just retrieved this position.
@@ -1040,6 +2495,76 @@ impl<K, V, S> StableMap<K, V, S> {
         (key, value)
     }
 
+    /// Returns an iterator over the entries of the map together with the hashes of their
+    /// keys.
+    ///
+    /// With the `cached-hash` feature, the hashes are not recomputed.
+    pub(crate) fn iter_hashed(&self) -> impl Iterator<Item = (u64, &K, &V)>
+    where
+        K: Hash,
+        S: BuildHasher,
+    {
+        self.key_to_pos.iter().map(|(k, pos)| {
+            let hash = self.key_to_pos.stored_hash(&self.storage, k, pos);
+            let value = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.get_unchecked(pos)
+            };
+            (hash, k, value)
+        })
+    }
+
+    /// Insert a key-value pair into the map without checking
+    /// if the key already exists in the map if debug assertions are disabled.
+    ///
+    /// This is a safe version of
+    /// [insert_unique_unchecked](Self::insert_unique_unchecked). If debug assertions
+    /// are enabled, this function checks that the key does not exist in the map.
+    /// Otherwise it compiles to the same code as `insert_unique_unchecked`.
+    ///
+    /// Returns a reference to the key and value just inserted.
+    ///
+    /// # Panics
+    ///
+    /// Panics if debug assertions are enabled and the key already exists in the map.
+    ///
+    /// If debug assertions are disabled and the key already exists in the map, the
+    /// behavior is unspecified as described in the documentation of
+    /// `insert_unique_unchecked`. Memory safety is not violated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// for i in 0..3 {
+    ///     map.insert_unique_debug_checked(i, i * 10);
+    /// }
+    ///
+    /// assert_eq!(map[&1], 10);
+    /// assert_eq!(map.len(), 3);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert_unique_debug_checked(&mut self, key: K, value: V) -> (&K, &mut V)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        debug_assert!(
+            !self.key_to_pos.contains_key(&key),
+            "the key already exists in the map",
+        );
+        unsafe {
+            // SAFETY:
+            // - Duplicate keys do not violate any of the invariants of this type. As
+            //   documented by insert_unique_unchecked, they only cause unspecified
+            //   behavior that is not memory unsafe.
+            self.insert_unique_unchecked(key, value)
+        }
+    }
+
     /// Creates a consuming iterator visiting all the keys in arbitrary order.
     /// The map cannot be used after calling this.
     /// The iterator element type is `K`.
@@ -1062,9 +2587,9 @@ impl<K, V, S> StableMap<K, V, S> {
     /// assert_eq!(vec, ["a", "b", "c"]);
     /// ```
     #[inline]
-    pub fn into_keys(self) -> IntoKeys<K> {
+    pub fn into_keys(self) -> IntoKeys<K, A> {
         IntoKeys {
-            iter: self.key_to_pos.into_keys(),
+            iter: self.key_to_pos.into_iter(),
         }
     }
 
@@ -1090,14 +2615,133 @@ impl<K, V, S> StableMap<K, V, S> {
     /// assert_eq!(vec, [1, 2, 3]);
     /// ```
     #[inline]
-    pub fn into_values(self) -> IntoValues<K, V> {
+    pub fn into_values(self) -> IntoValues<K, V, A> {
         IntoValues {
-            iter: self.key_to_pos.into_values(),
+            iter: self.key_to_pos.into_iter(),
             storage: self.storage,
         }
     }
 
-    /// Returns `true` if the map contains no elements.
+    /// Consumes the map and returns a map from the values to the keys.
+    ///
+    /// If multiple keys map to the same value, the key with the lowest index is retained
+    /// and the other keys are dropped. The indices of the returned map are unrelated to
+    /// the indices of this map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 1);
+    ///
+    /// let inverted: StableMap<i32, &str> = map.invert();
+    /// assert_eq!(inverted.len(), 2);
+    /// assert_eq!(inverted[&1], "a");
+    /// assert_eq!(inverted[&2], "b");
+    /// ```
+    pub fn invert<S2>(self) -> StableMap<V, K, S2>
+    where
+        V: Eq + Hash,
+        S2: BuildHasher + Default,
+    {
+        let Self {
+            key_to_pos,
+            mut storage,
+        } = self;
+        // Taking values must not move the remaining values since we compare their
+        // indices below.
+        storage.set_max_vacant(usize::MAX);
+        let mut map = StableMap::with_capacity_and_hasher(key_to_pos.len(), S2::default());
+        // The index in self of the key stored at each index of map. Since we never remove
+        // elements from map, its indices are contiguous.
+        let mut indices = Vec::with_capacity(key_to_pos.len());
+        for (key, pos) in key_to_pos {
+            let index = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                storage.index_unchecked(&pos)
+            };
+            let value = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                storage.take_unchecked(pos)
+            };
+            let hash = map.key_to_pos.hash(&value);
+            match map.key_to_pos.entry(hash, &value, &map.storage) {
+                hash_table::Entry::Occupied(occupied) => {
+                    let prev_index = unsafe {
+                        // SAFETY:
+                        // - By the invariants, occupied.get() is valid
+                        &mut indices[map.storage.index_unchecked(&occupied.get().1)]
+                    };
+                    if index < *prev_index {
+                        *prev_index = index;
+                        let prev = unsafe {
+                            // SAFETY:
+                            // - By the invariants, occupied.get() is valid
+                            map.storage.get_unchecked_mut(&occupied.get().1)
+                        };
+                        *prev = key;
+                    }
+                }
+                hash_table::Entry::Vacant(vacant) => {
+                    indices.push(index);
+                    vacant.insert((value, map.storage.insert_hashed(hash, key)));
+                }
+            }
+        }
+        map
+    }
+
+    /// Consumes the map and returns its entries together with their indices.
+    ///
+    /// The entries are sorted by their indices. The map can be reconstructed with the
+    /// same indices via [from_indexed_iter](Self::from_indexed_iter).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// map.remove("b");
+    ///
+    /// let vec = map.into_indexed_vec();
+    /// assert_eq!(vec, [(0, "a", 1), (2, "c", 3)]);
+    /// ```
+    pub fn into_indexed_vec(self) -> Vec<(usize, K, V)> {
+        let Self {
+            key_to_pos,
+            mut storage,
+        } = self;
+        // Taking values must not move the remaining values.
+        storage.set_max_vacant(usize::MAX);
+        let mut vec = Vec::with_capacity(key_to_pos.len());
+        for (key, pos) in key_to_pos {
+            let index = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                storage.index_unchecked(&pos)
+            };
+            let value = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                storage.take_unchecked(pos)
+            };
+            vec.push((index, key, value));
+        }
+        vec.sort_unstable_by_key(|(index, _, _)| *index);
+        vec
+    }
+
+    /// Returns `true` if the map contains no elements.
     ///
     /// # Examples
     ///
@@ -1130,6 +2774,52 @@ impl<K, V, S> StableMap<K, V, S> {
         !self.is_empty()
     }
 
+    /// Returns `true` if every key of this map is also contained in `other`.
+    ///
+    /// The values are ignored. This function does not allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let a: StableMap<_, _> = [(1, "a"), (2, "b")].into_iter().collect();
+    /// let b: StableMap<_, _> = [(1, 10), (2, 20), (3, 30)].into_iter().collect();
+    /// assert!(a.is_subset_keys(&b));
+    /// assert!(!b.is_subset_keys(&a));
+    /// ```
+    pub fn is_subset_keys<V2, S2, A2>(&self, other: &StableMap<K, V2, S2, A2>) -> bool
+    where
+        K: Eq + Hash,
+        S2: BuildHasher,
+        A2: Allocator,
+    {
+        self.len() <= other.len() && self.keys().all(|k| other.contains_key(k))
+    }
+
+    /// Returns `true` if every key of `other` is also contained in this map.
+    ///
+    /// The values are ignored. This function does not allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let a: StableMap<_, _> = [(1, "a"), (2, "b")].into_iter().collect();
+    /// let b: StableMap<_, _> = [(1, 10), (2, 20), (3, 30)].into_iter().collect();
+    /// assert!(b.is_superset_keys(&a));
+    /// assert!(!a.is_superset_keys(&b));
+    /// ```
+    pub fn is_superset_keys<V2, S2, A2>(&self, other: &StableMap<K, V2, S2, A2>) -> bool
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+        A2: Allocator,
+    {
+        other.is_subset_keys(self)
+    }
+
     /// An iterator visiting all key-value pairs in arbitrary order.
     /// The iterator element type is `(&'a K, &'a V)`.
     ///
@@ -1158,13 +2848,145 @@ impl<K, V, S> StableMap<K, V, S> {
     /// assert_eq!(map.len(), 3);
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn iter(&self) -> Iter<'_, K, V> {
+    pub fn iter(&self) -> Iter<'_, K, V, A> {
         Iter {
             iter: self.key_to_pos.iter(),
             entries: &self.storage,
         }
     }
 
+    /// An iterator visiting all key-value pairs and their indices in arbitrary order.
+    /// The iterator element type is `(usize, &'a K, &'a V)`.
+    ///
+    /// Each index is the same value that would be returned by calling
+    /// [get_index](Self::get_index) with the key, but no additional lookup is performed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// map.remove("b");
+    ///
+    /// let mut vec: Vec<_> = map.iter_indexed().map(|(i, &k, &v)| (i, k, v)).collect();
+    ///
+    /// // The `IterIndexed` iterator produces items in arbitrary order, so the
+    /// // items must be sorted to test them against a sorted array.
+    /// vec.sort_unstable();
+    /// assert_eq!(vec, [(0, "a", 1), (2, "c", 3)]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn iter_indexed(&self) -> IterIndexed<'_, K, V, A> {
+        IterIndexed {
+            iter: self.key_to_pos.iter(),
+            entries: &self.storage,
+        }
+    }
+
+    /// An iterator visiting all indices below [index_len](Self::index_len) in ascending
+    /// order. The iterator element type is `Option<(&'a K, &'a V)>`.
+    ///
+    /// For each index, the iterator yields the key that the index belongs to and the
+    /// value that would be returned by [get_by_index](Self::get_by_index), or `None` if
+    /// the index is unused.
+    ///
+    /// Since the map does not store the key of an index, this function visits all keys
+    /// once and allocates a vector of length `index_len` to order them by index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// map.remove("b");
+    ///
+    /// let entries: Vec<_> = map.iter_sparse().collect();
+    /// assert_eq!(entries, [Some((&"a", &1)), None, Some((&"c", &3))]);
+    /// ```
+    pub fn iter_sparse(&self) -> IterSparse<'_, K, V> {
+        let mut keys = vec![None; self.storage.len()];
+        for (k, pos) in &self.key_to_pos {
+            let index = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.index_unchecked(pos)
+            };
+            keys[index] = Some(k);
+        }
+        IterSparse {
+            keys: keys.into_iter(),
+            values: self.storage.sparse_slots(),
+        }
+    }
+
+    /// An iterator visiting the values with indices greater than or equal to `start` in
+    /// ascending order of their indices. The iterator element type is `(usize, &'a V)`.
+    ///
+    /// Unused indices are skipped. This can be used to resume an iteration over the map
+    /// by passing the index after the last visited index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// for i in 0..5 {
+    ///     map.insert(i, i * 10);
+    /// }
+    /// map.remove(&3);
+    ///
+    /// let first: Vec<_> = map.values_from_index(0).take(2).collect();
+    /// assert_eq!(first, [(0, &0), (1, &10)]);
+    /// let rest: Vec<_> = map.values_from_index(2).collect();
+    /// assert_eq!(rest, [(2, &20), (4, &40)]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn values_from_index(&self, start: usize) -> ValuesFromIndex<'_, V> {
+        ValuesFromIndex {
+            index: start,
+            slots: self.storage.sparse_slots_from(start),
+        }
+    }
+
+    /// A mutable iterator visiting the values with indices greater than or equal to
+    /// `start` in ascending order of their indices. The iterator element type is
+    /// `(usize, &'a mut V)`.
+    ///
+    /// This is the mutable counterpart of [values_from_index](Self::values_from_index).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// for i in 0..5 {
+    ///     map.insert(i, i * 10);
+    /// }
+    ///
+    /// for (_, v) in map.values_mut_from_index(3) {
+    ///     *v += 1;
+    /// }
+    /// let values: Vec<_> = map.values_from_index(0).map(|(_, v)| *v).collect();
+    /// assert_eq!(values, [0, 10, 20, 31, 41]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn values_mut_from_index(&mut self, start: usize) -> ValuesMutFromIndex<'_, V> {
+        ValuesMutFromIndex {
+            index: start,
+            slots: self.storage.sparse_slots_mut_from(start),
+        }
+    }
+
     /// An iterator visiting all key-value pairs in arbitrary order,
     /// with mutable references to the values.
     /// The iterator element type is `(&'a K, &'a mut V)`.
@@ -1207,8 +3029,12 @@ impl<K, V, S> StableMap<K, V, S> {
         }
     }
 
-    /// An iterator visiting all keys in arbitrary order.
-    /// The iterator element type is `&'a K`.
+    /// An iterator visiting all key-value pairs and their indices in arbitrary order,
+    /// with mutable references to the values.
+    /// The iterator element type is `(usize, &'a K, &'a mut V)`.
+    ///
+    /// Each index is the same value that would be returned by calling
+    /// [get_index](Self::get_index) with the key, but no additional lookup is performed.
     ///
     /// # Examples
     ///
@@ -1219,585 +3045,2587 @@ impl<K, V, S> StableMap<K, V, S> {
     /// map.insert("a", 1);
     /// map.insert("b", 2);
     /// map.insert("c", 3);
-    /// assert_eq!(map.len(), 3);
-    /// let mut vec: Vec<&str> = Vec::new();
     ///
-    /// for key in map.keys() {
-    ///     println!("{}", key);
-    ///     vec.push(*key);
+    /// // Update some values and remember their indices
+    /// let mut updated = Vec::new();
+    /// for (index, _, val) in map.iter_mut_indexed() {
+    ///     if *val % 2 == 1 {
+    ///         *val *= 10;
+    ///         updated.push(index);
+    ///     }
     /// }
     ///
-    /// // The `Keys` iterator produces keys in arbitrary order, so the
-    /// // keys must be sorted to test them against a sorted array.
-    /// vec.sort_unstable();
-    /// assert_eq!(vec, ["a", "b", "c"]);
-    ///
-    /// assert_eq!(map.len(), 3);
+    /// updated.sort_unstable();
+    /// assert_eq!(updated, [0, 2]);
+    /// assert_eq!(map.get_by_index(0), Some(&10));
+    /// assert_eq!(map.get_by_index(2), Some(&30));
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn keys(&self) -> Keys<'_, K> {
-        Keys {
-            iter: self.key_to_pos.keys(),
+    pub fn iter_mut_indexed(&mut self) -> IterMutIndexed<'_, K, V> {
+        IterMutIndexed {
+            iter: self.key_to_pos.iter_mut(),
+            entries: self.storage.raw_access(),
         }
     }
 
-    /// Returns the number of elements in the map.
+    /// An iterator visiting the entries whose keys are contained in both this map and
+    /// `other`, in arbitrary order.
+    ///
+    /// The iterator element type is `(&'a K, &'a V, &'a V2)`.
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut a = StableMap::new();
-    /// assert_eq!(a.len(), 0);
-    /// a.insert(1, "a");
-    /// assert_eq!(a.len(), 1);
+    /// let names: StableMap<_, _> = [(1, "a"), (2, "b")].into();
+    /// let ages: StableMap<_, _> = [(2, 20), (3, 30)].into();
+    ///
+    /// let joined: Vec<_> = names.join(&ages).collect();
+    /// assert_eq!(joined, [(&2, &"b", &20)]);
     /// ```
-    #[cfg_attr(feature = "inline-more", inline)]
-    pub fn len(&self) -> usize {
-        self.key_to_pos.len()
+    pub fn join<'a, V2, S2, A2>(
+        &'a self,
+        other: &'a StableMap<K, V2, S2, A2>,
+    ) -> impl Iterator<Item = (&'a K, &'a V, &'a V2)> + use<'a, K, V, V2, S, A, S2, A2>
+    where
+        K: Eq + Hash,
+        S2: BuildHasher,
+        A2: Allocator,
+    {
+        self.iter()
+            .filter_map(|(k, v)| other.get(k).map(|v2| (k, v, v2)))
     }
 
-    /// Removes a key from the map, returning the value at the key if the key
-    /// was previously in the map. Keeps the allocated memory for reuse.
-    ///
-    /// The key may be any borrowed form of the map's key type, but
-    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
-    /// the key type.
+    /// An iterator visiting the entries of this map together with the value of the same
+    /// key in `other`, if any, in arbitrary order.
     ///
-    /// [`Eq`]: https://doc.rust-lang.org/std/cmp/trait.Eq.html
-    /// [`Hash`]: https://doc.rust-lang.org/std/hash/trait.Hash.html
+    /// The iterator element type is `(&'a K, &'a V, Option<&'a V2>)`.
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut map = StableMap::new();
-    /// // The map is empty
-    /// assert!(map.is_empty() && map.capacity() == 0);
-    ///
-    /// map.insert(1, "a");
-    ///
-    /// assert_eq!(map.remove(&1), Some("a"));
-    /// assert_eq!(map.remove(&1), None);
+    /// let names: StableMap<_, _> = [(1, "a"), (2, "b")].into();
+    /// let ages: StableMap<_, _> = [(2, 20), (3, 30)].into();
     ///
-    /// // Now map holds none elements
-    /// assert!(map.is_empty());
+    /// let mut joined: Vec<_> = names.left_join(&ages).collect();
+    /// joined.sort_unstable();
+    /// assert_eq!(joined, [(&1, &"a", None), (&2, &"b", Some(&20))]);
     /// ```
-    #[cfg_attr(feature = "inline-more", inline)]
-    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
+    pub fn left_join<'a, V2, S2, A2>(
+        &'a self,
+        other: &'a StableMap<K, V2, S2, A2>,
+    ) -> impl Iterator<Item = (&'a K, &'a V, Option<&'a V2>)> + use<'a, K, V, V2, S, A, S2, A2>
     where
         K: Eq + Hash,
-        Q: Hash + Equivalent<K> + ?Sized,
-        S: BuildHasher,
+        S2: BuildHasher,
+        A2: Allocator,
     {
-        let pos = self.key_to_pos.remove(key)?;
-        let value = unsafe {
-            // SAFETY:
-            // - By the invariants, pos is valid
-            self.storage.take_unchecked(pos)
-        };
-        Some(value)
+        self.iter().map(|(k, v)| (k, v, other.get(k)))
     }
 
-    /// Removes a key from the map, returning the stored key and value if the
-    /// key was previously in the map. Keeps the allocated memory for reuse.
+    /// An iterator visiting the entries whose keys are contained in this map or in
+    /// `other`, in arbitrary order.
     ///
-    /// The key may be any borrowed form of the map's key type, but
-    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
-    /// the key type.
+    /// The entries of this map are visited first, followed by the entries of `other`
+    /// whose keys are not contained in this map.
     ///
-    /// [`Eq`]: https://doc.rust-lang.org/std/cmp/trait.Eq.html
-    /// [`Hash`]: https://doc.rust-lang.org/std/hash/trait.Hash.html
+    /// The iterator element type is `(&'a K, Option<&'a V>, Option<&'a V2>)`. At least
+    /// one of the values is `Some`.
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut map = StableMap::new();
-    /// // The map is empty
-    /// assert!(map.is_empty() && map.capacity() == 0);
-    ///
-    /// map.insert(1, "a");
-    ///
-    /// assert_eq!(map.remove_entry(&1), Some((1, "a")));
-    /// assert_eq!(map.remove(&1), None);
+    /// let names: StableMap<_, _> = [(1, "a"), (2, "b")].into();
+    /// let ages: StableMap<_, _> = [(2, 20), (3, 30)].into();
     ///
-    /// // Now map hold none elements
-    /// assert!(map.is_empty());
+    /// let mut joined: Vec<_> = names.outer_join(&ages).collect();
+    /// joined.sort_unstable();
+    /// assert_eq!(
+    ///     joined,
+    ///     [
+    ///         (&1, Some(&"a"), None),
+    ///         (&2, Some(&"b"), Some(&20)),
+    ///         (&3, None, Some(&30)),
+    ///     ],
+    /// );
     /// ```
-    #[cfg_attr(feature = "inline-more", inline)]
-    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    pub fn outer_join<'a, V2, S2, A2>(
+        &'a self,
+        other: &'a StableMap<K, V2, S2, A2>,
+    ) -> impl Iterator<Item = (&'a K, Option<&'a V>, Option<&'a V2>)> + use<'a, K, V, V2, S, A, S2, A2>
     where
         K: Eq + Hash,
-        Q: Hash + Equivalent<K> + ?Sized,
         S: BuildHasher,
+        S2: BuildHasher,
+        A2: Allocator,
     {
-        let (k, pos) = self.key_to_pos.remove_entry(key)?;
-        let value = unsafe {
-            // SAFETY:
-            // - By the invariants, pos is valid
-            self.storage.take_unchecked(pos)
-        };
-        Some((k, value))
+        let left = self.iter().map(|(k, v)| (k, Some(v), other.get(k)));
+        let right = other
+            .iter()
+            .filter(|(k, _)| !self.contains_key(*k))
+            .map(|(k, v2)| (k, None, Some(v2)));
+        left.chain(right)
     }
 
-    /// Reserves capacity for at least `additional` more elements to be inserted
-    /// in the `StableMap`. The collection may reserve more space to avoid
-    /// frequent reallocations.
-    ///
-    /// # Panics
-    ///
-    /// Panics if the new capacity exceeds [`isize::MAX`] bytes and [`abort`] the program
-    /// in case of allocation error.
-    ///
-    /// [`isize::MAX`]: https://doc.rust-lang.org/std/primitive.isize.html
-    /// [`abort`]: https://doc.rust-lang.org/alloc/alloc/fn.handle_alloc_error.html
+    /// An iterator visiting all keys in arbitrary order.
+    /// The iterator element type is `&'a K`.
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
-    /// let mut map: StableMap<&str, i32> = StableMap::new();
-    /// // Map is empty and doesn't allocate memory
-    /// assert_eq!(map.capacity(), 0);
     ///
-    /// map.reserve(10);
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// assert_eq!(map.len(), 3);
+    /// let mut vec: Vec<&str> = Vec::new();
     ///
-    /// // And now map can hold at least 10 elements
-    /// assert!(map.capacity() >= 10);
+    /// for key in map.keys() {
+    ///     println!("{}", key);
+    ///     vec.push(*key);
+    /// }
+    ///
+    /// // The `Keys` iterator produces keys in arbitrary order, so the
+    /// // keys must be sorted to test them against a sorted array.
+    /// vec.sort_unstable();
+    /// assert_eq!(vec, ["a", "b", "c"]);
+    ///
+    /// assert_eq!(map.len(), 3);
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn reserve(&mut self, additional: usize)
+    pub fn keys(&self) -> Keys<'_, K> {
+        Keys {
+            iter: self.key_to_pos.iter(),
+        }
+    }
+
+    /// Returns a set containing clones of all keys of the map.
+    ///
+    /// The set uses a clone of the hasher of the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    ///
+    /// let set = map.key_set();
+    /// assert_eq!(set.len(), 2);
+    /// assert!(set.contains("a"));
+    /// assert!(set.contains("b"));
+    /// ```
+    pub fn key_set(&self) -> HashSet<K, S>
     where
-        K: Eq + Hash,
-        S: BuildHasher,
+        K: Eq + Hash + Clone,
+        S: BuildHasher + Clone,
     {
-        self.key_to_pos.reserve(additional);
-        self.storage.reserve(additional);
+        let mut set = HashSet::with_capacity_and_hasher(self.len(), self.hasher().clone());
+        for (key, _) in &self.key_to_pos {
+            unsafe {
+                // SAFETY:
+                // - All keys are part of the same hash map so they must be distinct.
+                set.insert_unique_unchecked(key.clone());
+            }
+        }
+        set
     }
 
-    /// Retains only the elements specified by the predicate. Keeps the
-    /// allocated memory for reuse.
-    ///
-    /// In other words, remove all pairs `(k, v)` such that `f(&k, &mut v)` returns `false`.
-    /// The elements are visited in unsorted (and unspecified) order.
+    /// Returns the number of elements in the map.
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut map: StableMap<i32, i32> = (0..8).map(|x|(x, x*10)).collect();
-    /// assert_eq!(map.len(), 8);
+    /// let mut a = StableMap::new();
+    /// assert_eq!(a.len(), 0);
+    /// a.insert(1, "a");
+    /// assert_eq!(a.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn len(&self) -> usize {
+        self.key_to_pos.len()
+    }
+
+    /// Consumes the map and returns a map that uses `hash_builder` to hash the keys.
     ///
-    /// map.retain(|&k, _| k % 2 == 0);
+    /// All keys are rehashed with the new hash builder. The values are not moved and
+    /// each key retains its index.
     ///
-    /// // We can see, that the number of elements inside map is changed.
-    /// assert_eq!(map.len(), 4);
+    /// # Examples
     ///
-    /// let mut vec: Vec<(i32, i32)> = map.iter().map(|(&k, &v)| (k, v)).collect();
-    /// vec.sort_unstable();
-    /// assert_eq!(vec, [(0, 0), (2, 20), (4, 40), (6, 60)]);
     /// ```
-    pub fn retain<F>(&mut self, mut f: F)
+    /// use hashbrown::DefaultHashBuilder;
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// map.remove(&1);
+    ///
+    /// let map = map.map_hasher(DefaultHashBuilder::default());
+    /// assert_eq!(map[&2], "b");
+    /// assert_eq!(map.get_index(&2), Some(1));
+    /// ```
+    pub fn map_hasher<S2>(self, hash_builder: S2) -> StableMap<K, V, S2, A>
     where
-        F: FnMut(&K, &mut V) -> bool,
+        K: Eq + Hash,
+        S2: BuildHasher,
+        A: Clone,
     {
-        let storage = &raw mut self.storage;
-        let iter = self.key_to_pos.extract_if(move |k, pos| {
-            let storage = unsafe {
-                // SAFETY: See the documentation in extract_if
-                &mut *storage
-            };
-            let value = unsafe {
-                // SAFETY: By the invariants, pos is valid
-                storage.get_unchecked_mut(pos)
-            };
-            let retain = f(k, value);
-            !retain
-        });
-        for (_, pos) in iter {
-            let storage = unsafe {
-                // SAFETY: See the documentation in extract_if
-                &mut *storage
-            };
+        let mut key_to_pos = KeyMap::with_capacity_and_hasher_in(
+            self.len(),
+            hash_builder,
+            self.key_to_pos.allocator().clone(),
+        );
+        #[cfg_attr(not(feature = "cached-hash"), allow(unused_mut))]
+        let mut storage = self.storage;
+        for (k, pos) in self.key_to_pos {
+            let hash = key_to_pos.hash(&k);
+            #[cfg(feature = "cached-hash")]
             unsafe {
-                // SAFETY: By the invariants, pos is valid
-                storage.take_unchecked(pos);
+                // SAFETY:
+                // - By the invariants, pos is valid
+                storage.set_hash_unchecked(&pos, hash);
             }
+            // All k are part of the same hash map so they must be distinct.
+            key_to_pos.insert_unique(hash, k, pos, &storage);
+        }
+        StableMap {
+            key_to_pos,
+            storage,
         }
+        // SAFETY(invariants):
+        // - All Pos<InUse> have been moved from self.key_to_pos to key_to_pos and only
+        //   the hashes in the storage have changed.
     }
 
-    /// Shrinks the capacity of the map as much as possible. It will drop
-    /// down as much as possible while maintaining the internal rules
-    /// and possibly leaving some space in accordance with the resize policy.
+    /// Gets the occupied entry for the given key, if the key is contained in the map.
+    ///
+    /// Unlike [entry](Self::entry), this function does not require an owned key. The
+    /// returned entry can be used to inspect, replace, or remove the value.
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut map: StableMap<i32, i32> = StableMap::with_capacity(100);
-    /// map.insert(1, 2);
-    /// map.insert(3, 4);
-    /// assert!(map.capacity() >= 100);
-    /// map.shrink_to_fit();
-    /// assert!(map.capacity() >= 2);
+    /// let mut map: StableMap<String, u32> = StableMap::new();
+    /// map.insert("a".to_string(), 1);
+    ///
+    /// assert!(map.occupied_entry("b").is_none());
+    /// let entry = map.occupied_entry("a").unwrap();
+    /// assert_eq!(entry.remove(), 1);
+    /// assert!(map.is_empty());
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn shrink_to_fit(&mut self)
+    pub fn occupied_entry<Q>(&mut self, key: &Q) -> Option<OccupiedEntry<'_, K, V, S, A>>
     where
         K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
         S: BuildHasher,
     {
-        self.key_to_pos.shrink_to_fit();
-        self.storage.shrink_to_fit();
+        let entry = self.key_to_pos.find_entry(key)?;
+        Some(OccupiedEntry {
+            entry,
+            entries: &mut self.storage,
+            _phantom: PhantomData,
+        })
     }
 
-    /// Tries to insert a key-value pair into the map, and returns
-    /// a mutable reference to the value in the entry.
+    /// Gets the occupied entry for the given index, if the index is in use.
     ///
-    /// # Errors
+    /// The returned entry can be used to inspect, replace, or remove the value without
+    /// knowing its key. Removing the entry also removes the key from the map.
     ///
-    /// If the map already had this key present, nothing is updated, and
-    /// an error containing the occupied entry and the value is returned.
+    /// With the `cached-hash` feature, this function takes constant expected time.
+    /// Otherwise, if the index is in use, it takes time linear in the size of the map
+    /// since the key of the index has to be found by scanning the map.
     ///
     /// # Examples
     ///
-    /// Basic usage:
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
     ///
+    /// assert!(map.entry_by_index(2).is_none());
+    /// let entry = map.entry_by_index(0).unwrap();
+    /// assert_eq!(entry.key(), &"a");
+    /// assert_eq!(entry.remove(), 1);
+    /// assert_eq!(map.get(&"a"), None);
     /// ```
-    /// use stable_map::{OccupiedError, StableMap};
+    pub fn entry_by_index(&mut self, index: usize) -> Option<IndexedOccupiedEntry<'_, K, V, S, A>>
+    where
+        K: Hash,
+        S: BuildHasher,
+    {
+        #[cfg(feature = "cached-hash")]
+        let hash = self.storage.hash(index)?;
+        #[cfg(not(feature = "cached-hash"))]
+        let hash = self
+            .find_by_index(index)
+            .map(|(k, _)| self.key_to_pos.hash(k))?;
+        let storage = &self.storage;
+        let entry = self.key_to_pos.find_pos_entry(hash, |pos| {
+            let i = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                storage.index_unchecked(pos)
+            };
+            i == index
+        })?;
+        Some(IndexedOccupiedEntry {
+            entry,
+            entries: &mut self.storage,
+            index,
+            _phantom: PhantomData,
+        })
+    }
+
+    /// Removes a key from the map, returning the value at the key if the key
+    /// was previously in the map. Keeps the allocated memory for reuse.
+    ///
+    /// The key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: https://doc.rust-lang.org/std/cmp/trait.Eq.html
+    /// [`Hash`]: https://doc.rust-lang.org/std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
     ///
     /// let mut map = StableMap::new();
-    /// assert_eq!(map.try_insert(37, "a").unwrap(), &"a");
+    /// // The map is empty
+    /// assert!(map.is_empty() && map.capacity() == 0);
     ///
-    /// match map.try_insert(37, "b") {
-    ///     Err(OccupiedError { entry, value }) => {
-    ///         assert_eq!(entry.key(), &37);
-    ///         assert_eq!(entry.get(), &"a");
-    ///         assert_eq!(value, "b");
-    ///     }
-    ///     _ => panic!()
-    /// }
+    /// map.insert(1, "a");
+    ///
+    /// assert_eq!(map.remove(&1), Some("a"));
+    /// assert_eq!(map.remove(&1), None);
+    ///
+    /// // Now map holds none elements
+    /// assert!(map.is_empty());
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn try_insert(&mut self, key: K, value: V) -> Result<&mut V, OccupiedError<'_, K, V, S>>
+    pub fn remove<Q>(&mut self, key: &Q) -> Option<V>
     where
         K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
         S: BuildHasher,
     {
-        match self.entry(key) {
-            Entry::Occupied(o) => Err(OccupiedError { entry: o, value }),
-            Entry::Vacant(v) => Ok(v.insert(value)),
+        let pos = self.key_to_pos.remove(key)?;
+        let value = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.take_unchecked(pos)
+        };
+        Some(value)
+    }
+
+    /// Removes a key from the map, returning the stored key and value if the
+    /// key was previously in the map. Keeps the allocated memory for reuse.
+    ///
+    /// The key may be any borrowed form of the map's key type, but
+    /// [`Hash`] and [`Eq`] on the borrowed form *must* match those for
+    /// the key type.
+    ///
+    /// [`Eq`]: https://doc.rust-lang.org/std/cmp/trait.Eq.html
+    /// [`Hash`]: https://doc.rust-lang.org/std/hash/trait.Hash.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// // The map is empty
+    /// assert!(map.is_empty() && map.capacity() == 0);
+    ///
+    /// map.insert(1, "a");
+    ///
+    /// assert_eq!(map.remove_entry(&1), Some((1, "a")));
+    /// assert_eq!(map.remove(&1), None);
+    ///
+    /// // Now map hold none elements
+    /// assert!(map.is_empty());
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn remove_entry<Q>(&mut self, key: &Q) -> Option<(K, V)>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        let (k, pos) = self.key_to_pos.remove_entry(key)?;
+        let value = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.take_unchecked(pos)
+        };
+        Some((k, value))
+    }
+
+    /// Removes a key from the map if the predicate returns `true` for the stored key and
+    /// value, returning the value.
+    ///
+    /// The key is only hashed once.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, 10);
+    /// map.insert(2, 20);
+    ///
+    /// assert_eq!(map.remove_if(&1, |_, v| *v > 15), None);
+    /// assert_eq!(map.remove_if(&2, |_, v| *v > 15), Some(20));
+    /// assert_eq!(map.remove_if(&3, |_, _| true), None);
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn remove_if<Q, F>(&mut self, key: &Q, pred: F) -> Option<V>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+        F: FnOnce(&K, &V) -> bool,
+    {
+        let occupied = self.key_to_pos.find_entry(key)?;
+        let value = unsafe {
+            // SAFETY:
+            // - By the invariants, occupied.get() is valid
+            self.storage.get_unchecked(&occupied.get().1)
+        };
+        if !pred(&occupied.get().0, value) {
+            return None;
         }
+        let ((_, pos), _) = occupied.remove();
+        let value = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.take_unchecked(pos)
+        };
+        Some(value)
     }
 
-    /// An iterator visiting all values in arbitrary order.
-    /// The iterator element type is `&'a V`.
+    /// Reserves capacity for at least `additional` more elements to be inserted
+    /// in the `StableMap`. The collection may reserve more space to avoid
+    /// frequent reallocations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity exceeds [`isize::MAX`] bytes and [`abort`] the program
+    /// in case of allocation error.
+    ///
+    /// [`isize::MAX`]: https://doc.rust-lang.org/std/primitive.isize.html
+    /// [`abort`]: https://doc.rust-lang.org/alloc/alloc/fn.handle_alloc_error.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    /// let mut map: StableMap<&str, i32> = StableMap::new();
+    /// // Map is empty and doesn't allocate memory
+    /// assert_eq!(map.capacity(), 0);
+    ///
+    /// map.reserve(10);
+    ///
+    /// // And now map can hold at least 10 elements
+    /// assert!(map.capacity() >= 10);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn reserve(&mut self, additional: usize)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let _span = self.rehash_span(additional);
+        self.key_to_pos.reserve(additional, &self.storage);
+        self.storage.reserve(additional);
+    }
+
+    /// Tries to reserve capacity for at least `additional` more elements to be inserted
+    /// in the `StableMap`. The collection may reserve more space to avoid
+    /// frequent reallocations.
+    ///
+    /// Both the hash table and the storage of the values are grown. If either
+    /// allocation fails, the error is returned and the map remains usable. The hash
+    /// table might have been grown even if an error is returned.
+    ///
+    /// # Errors
+    ///
+    /// If the capacity overflows, or the allocator reports a failure, then an error
+    /// is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{StableMap, TryReserveError};
+    ///
+    /// let mut map: StableMap<&str, isize> = StableMap::new();
+    /// // Map is empty and doesn't allocate memory
+    /// assert_eq!(map.capacity(), 0);
+    ///
+    /// map.try_reserve(10).expect("why is the test harness OOMing on 10 bytes?");
+    ///
+    /// // And now map can hold at least 10 elements
+    /// assert!(map.capacity() >= 10);
+    ///
+    /// assert_eq!(map.try_reserve(usize::MAX), Err(TryReserveError::CapacityOverflow));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let _span = self.rehash_span(additional);
+        self.key_to_pos.try_reserve(additional, &self.storage)?;
+        self.storage.try_reserve(additional)?;
+        Ok(())
+    }
+
+    /// Removes all keys contained in `keys` from the map. Keeps the allocated memory
+    /// for reuse.
+    ///
+    /// This function iterates over the smaller of the two collections.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hashbrown::HashSet;
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<i32, i32> = (0..8).map(|x| (x, x * 10)).collect();
+    /// let keys: HashSet<i32> = [1, 3, 9].into_iter().collect();
+    ///
+    /// map.remove_keys_in(&keys);
+    /// assert_eq!(map.len(), 6);
+    /// assert!(!map.contains_key(&1));
+    /// assert!(!map.contains_key(&3));
+    /// ```
+    pub fn remove_keys_in<S2>(&mut self, keys: &HashSet<K, S2>)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+        S2: BuildHasher,
+    {
+        if keys.len() < self.len() {
+            for key in keys {
+                self.remove(key);
+            }
+        } else {
+            self.retain(|key, _| !keys.contains(key));
+        }
+    }
+
+    /// Retains only the keys contained in `keys`. Keeps the allocated memory for
+    /// reuse.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hashbrown::HashSet;
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<i32, i32> = (0..8).map(|x| (x, x * 10)).collect();
+    /// let keys: HashSet<i32> = [1, 3, 9].into_iter().collect();
+    ///
+    /// map.retain_keys_in(&keys);
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map[&1], 10);
+    /// assert_eq!(map[&3], 30);
+    /// ```
+    pub fn retain_keys_in<S2>(&mut self, keys: &HashSet<K, S2>)
+    where
+        K: Eq + Hash,
+        S2: BuildHasher,
+    {
+        if keys.is_empty() {
+            self.clear();
+        } else {
+            self.retain(|key, _| keys.contains(key));
+        }
+    }
+
+    /// Retains only the elements specified by the predicate. Keeps the
+    /// allocated memory for reuse.
+    ///
+    /// In other words, remove all pairs `(k, v)` such that `f(&k, &mut v)` returns `false`.
+    /// The elements are visited in unsorted (and unspecified) order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<i32, i32> = (0..8).map(|x|(x, x*10)).collect();
+    /// assert_eq!(map.len(), 8);
+    ///
+    /// map.retain(|&k, _| k % 2 == 0);
+    ///
+    /// // We can see, that the number of elements inside map is changed.
+    /// assert_eq!(map.len(), 4);
+    ///
+    /// let mut vec: Vec<(i32, i32)> = map.iter().map(|(&k, &v)| (k, v)).collect();
+    /// vec.sort_unstable();
+    /// assert_eq!(vec, [(0, 0), (2, 20), (4, 40), (6, 60)]);
+    /// ```
+    pub fn retain<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, &mut V) -> bool,
+    {
+        let storage = &raw mut self.storage;
+        let iter = self.key_to_pos.extract_if(move |k, pos| {
+            let storage = unsafe {
+                // SAFETY: See the documentation in extract_if
+                &mut *storage
+            };
+            let value = unsafe {
+                // SAFETY: By the invariants, pos is valid
+                storage.get_unchecked_mut(pos)
+            };
+            let retain = f(k, value);
+            !retain
+        });
+        for (_, pos) in iter {
+            let storage = unsafe {
+                // SAFETY: See the documentation in extract_if
+                &mut *storage
+            };
+            unsafe {
+                // SAFETY: By the invariants, pos is valid
+                storage.take_unchecked(pos);
+            }
+        }
+    }
+
+    /// Retains only the elements for which the function returns `Some` and replaces
+    /// their values by the returned values. Keeps the allocated memory for reuse.
+    ///
+    /// In other words, remove all pairs `(k, v)` such that `f(&k, v)` returns `None`.
+    /// The elements are visited in unsorted (and unspecified) order. Retained elements
+    /// keep their index.
+    ///
+    /// # Panics
+    ///
+    /// If `f` panics, the element whose value was passed to `f` is removed from the map.
+    /// Elements that have already been visited keep their new values and all other
+    /// elements are unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<i32, String> = (0..8).map(|x| (x, x.to_string())).collect();
+    /// let index = map.get_index(&2);
+    ///
+    /// map.retain_map(|&k, v| (k % 2 == 0).then(|| v + "!"));
+    /// assert_eq!(map.len(), 4);
+    /// assert_eq!(map[&2], "2!");
+    /// assert_eq!(map.get_index(&2), index);
+    /// ```
+    pub fn retain_map<F>(&mut self, mut f: F)
+    where
+        F: FnMut(&K, V) -> Option<V>,
+    {
+        let moved = Cell::new(None);
+        let map = &raw mut *self;
+        let _guard = RetainMapGuard { map, moved: &moved };
+        let storage = unsafe {
+            // SAFETY: map was just created from a mutable reference.
+            &raw mut (*map).storage
+        };
+        let key_to_pos = unsafe {
+            // SAFETY: map was just created from a mutable reference.
+            &mut (*map).key_to_pos
+        };
+        let moved = &moved;
+        let iter = key_to_pos.extract_if(move |k, pos| {
+            let storage = unsafe {
+                // SAFETY: See the documentation in extract_if
+                &mut *storage
+            };
+            let index = unsafe {
+                // SAFETY: By the invariants, pos is valid
+                storage.index_unchecked(pos)
+            };
+            let slot = unsafe {
+                // SAFETY: By the invariants, pos is valid
+                storage.get_unchecked_mut(pos)
+            };
+            let value = unsafe {
+                // SAFETY:
+                // - slot is a reference.
+                // - The value is either overwritten below or forgotten after it has been
+                //   taken from the storage. If f panics, the guard removes the element
+                //   and forgets the value.
+                ptr::read(slot)
+            };
+            moved.set(Some(index));
+            let value = f(k, value);
+            moved.set(None);
+            match value {
+                Some(value) => {
+                    unsafe {
+                        // SAFETY: slot is a reference.
+                        ptr::write(slot, value);
+                    }
+                    false
+                }
+                None => true,
+            }
+        });
+        for (_, pos) in iter {
+            let storage = unsafe {
+                // SAFETY: See the documentation in extract_if
+                &mut *storage
+            };
+            unsafe {
+                // SAFETY: By the invariants, pos is valid
+                // NOTE: The value has already been moved into f above.
+                storage.remove_moved_unchecked(pos);
+            }
+        }
+    }
+
+    /// Shrinks the capacity of the map as much as possible. It will drop
+    /// down as much as possible while maintaining the internal rules
+    /// and possibly leaving some space in accordance with the resize policy.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<i32, i32> = StableMap::with_capacity(100);
+    /// map.insert(1, 2);
+    /// map.insert(3, 4);
+    /// assert!(map.capacity() >= 100);
+    /// map.shrink_to_fit();
+    /// assert!(map.capacity() >= 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn shrink_to_fit(&mut self)
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        self.key_to_pos.shrink_to_fit(&self.storage);
+        self.storage.shrink_to_fit();
+    }
+
+    /// Returns the indices that are currently in use, sorted in ascending order.
+    ///
+    /// The returned vector does not borrow the map. This is useful if a lock protecting
+    /// the map has to be released while processing each index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// map.remove("b");
+    ///
+    /// assert_eq!(map.indices_snapshot(), [0, 2]);
+    /// ```
+    pub fn indices_snapshot(&self) -> Vec<usize> {
+        let mut vec = Vec::with_capacity(self.len());
+        for index in 0..self.storage.len() {
+            if self.storage.get(index).is_some() {
+                vec.push(index);
+            }
+        }
+        vec
+    }
+
+    /// Returns clones of all values together with their indices, sorted by index.
+    ///
+    /// This function visits each index once. It is useful to take a snapshot while
+    /// holding a lock and to process the values after releasing it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// map.remove("b");
+    ///
+    /// assert_eq!(map.snapshot_indexed_values(), [(0, 1), (2, 3)]);
+    /// ```
+    pub fn snapshot_indexed_values(&self) -> Vec<(usize, V)>
+    where
+        V: Clone,
+    {
+        let mut vec = Vec::with_capacity(self.len());
+        for index in 0..self.storage.len() {
+            if let Some(value) = self.storage.get(index) {
+                vec.push((index, value.clone()));
+            }
+        }
+        vec
+    }
+
+    /// Returns the number of new keys that can be inserted without reallocating.
+    ///
+    /// Inserting up to this many new keys reallocates neither the underlying hash table
+    /// nor the storage of the values. Insertions that replace the value of an existing
+    /// key never reallocate. Use [reserve](Self::reserve) to increase this number ahead
+    /// of time, for example before handing the map to a real-time thread.
+    ///
+    /// Indices freed by removals are reused without allocating. The slot table that
+    /// tracks the index of each entry is reserved together with the storage of the
+    /// values, so insertions that create a new index, that is, that increase
+    /// [index_len](Self::index_len), do not allocate either.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.reserve(10);
+    /// let spare = map.spare_capacity();
+    /// assert!(spare >= 10);
+    ///
+    /// let capacity = map.capacity();
+    /// for i in 0..spare {
+    ///     map.insert(i, i);
+    /// }
+    /// assert_eq!(map.capacity(), capacity);
+    /// assert_eq!(map.spare_capacity(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn spare_capacity(&self) -> usize {
+        min(
+            self.key_to_pos.capacity() - self.key_to_pos.len(),
+            self.storage.spare_capacity(),
+        )
+    }
+
+    /// Tries to insert a key-value pair into the map, and returns
+    /// a mutable reference to the value in the entry.
+    ///
+    /// # Errors
+    ///
+    /// If the map already had this key present, nothing is updated, and
+    /// an error containing the occupied entry and the value is returned.
+    ///
+    /// # Examples
+    ///
+    /// Basic usage:
+    ///
+    /// ```
+    /// use stable_map::{OccupiedError, StableMap};
+    ///
+    /// let mut map = StableMap::new();
+    /// assert_eq!(map.try_insert(37, "a").unwrap(), &"a");
+    ///
+    /// match map.try_insert(37, "b") {
+    ///     Err(OccupiedError { entry, value }) => {
+    ///         assert_eq!(entry.key(), &37);
+    ///         assert_eq!(entry.get(), &"a");
+    ///         assert_eq!(value, "b");
+    ///     }
+    ///     _ => panic!()
+    /// }
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn try_insert(&mut self, key: K, value: V) -> Result<&mut V, OccupiedError<'_, K, V, S, A>>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        match self.entry(key) {
+            Entry::Occupied(o) => Err(OccupiedError { entry: o, value }),
+            Entry::Vacant(v) => Ok(v.insert(value)),
+        }
+    }
+
+    /// Inserts a key-value pair into the map unless this would increase the number of
+    /// elements beyond [max_len](Self::max_len).
+    ///
+    /// If the map did not have this key present, and the map contains fewer than
+    /// `max_len` elements, `Ok(None)` is returned. If the map did have this key present,
+    /// the value is updated, and the old value is returned as `Ok(Some(old))`. The key is
+    /// not updated, though.
+    ///
+    /// Otherwise, the map is left unchanged and the key-value pair is returned in the
+    /// error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{CapacityError, StableMap};
+    ///
+    /// let mut map = StableMap::with_max_len(1);
+    /// assert_eq!(map.try_insert_bounded(37, "a"), Ok(None));
+    /// assert_eq!(map.try_insert_bounded(37, "b"), Ok(Some("a")));
+    /// assert_eq!(
+    ///     map.try_insert_bounded(38, "c"),
+    ///     Err(CapacityError { key: 38, value: "c" }),
+    /// );
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn try_insert_bounded(&mut self, key: K, value: V) -> Result<Option<V>, CapacityError<K, V>>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let full = self.storage.is_full();
+        match self.entry(key) {
+            Entry::Occupied(mut o) => Ok(Some(o.insert(value))),
+            Entry::Vacant(v) if full => Err(CapacityError {
+                key: v.into_key(),
+                value,
+            }),
+            Entry::Vacant(v) => {
+                v.insert(value);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Inserts the key-value pairs of an iterator into the map unless this would increase
+    /// the number of elements beyond [max_len](Self::max_len).
+    ///
+    /// This function behaves like [extend](Extend::extend) except that it stops at the
+    /// first key-value pair that [try_insert_bounded](Self::try_insert_bounded) rejects.
+    /// That pair is returned in the error. The pairs before it have already been inserted
+    /// and the remaining pairs are dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{CapacityError, StableMap};
+    ///
+    /// let mut map = StableMap::with_max_len(2);
+    /// assert_eq!(
+    ///     map.try_extend_bounded([(1, "a"), (1, "b"), (2, "c"), (3, "d"), (4, "e")]),
+    ///     Err(CapacityError { key: 3, value: "d" }),
+    /// );
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map[&1], "b");
+    /// assert_eq!(map[&2], "c");
+    /// ```
+    pub fn try_extend_bounded<I>(&mut self, iter: I) -> Result<(), CapacityError<K, V>>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+        I: IntoIterator<Item = (K, V)>,
+    {
+        for (k, v) in iter {
+            self.try_insert_bounded(k, v)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts the value returned by `insert` if the key is not contained in the map.
+    /// Otherwise calls `update` with the existing value.
+    ///
+    /// Returns a mutable reference to the value in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut counts = StableMap::new();
+    /// for word in ["a", "b", "a"] {
+    ///     counts.upsert_with(word, || 1, |count| *count += 1);
+    /// }
+    /// assert_eq!(counts["a"], 2);
+    /// assert_eq!(counts["b"], 1);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn upsert_with<I, U>(&mut self, key: K, insert: I, update: U) -> &mut V
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+        I: FnOnce() -> V,
+        U: FnOnce(&mut V),
+    {
+        match self.entry(key) {
+            Entry::Occupied(occupied) => {
+                let value = occupied.into_mut();
+                update(value);
+                value
+            }
+            Entry::Vacant(vacant) => vacant.insert(insert()),
+        }
+    }
+
+    /// An iterator visiting all values in arbitrary order.
+    /// The iterator element type is `&'a V`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// assert_eq!(map.len(), 3);
+    /// let mut vec: Vec<i32> = Vec::new();
+    ///
+    /// for val in map.values() {
+    ///     println!("{}", val);
+    ///     vec.push(*val);
+    /// }
+    ///
+    /// // The `Values` iterator produces values in arbitrary order, so the
+    /// // values must be sorted to test them against a sorted array.
+    /// vec.sort_unstable();
+    /// assert_eq!(vec, [1, 2, 3]);
+    ///
+    /// assert_eq!(map.len(), 3);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn values(&self) -> Values<'_, K, V, A> {
+        Values::new(self.key_to_pos.iter(), &self.storage)
+    }
+
+    /// An iterator visiting all indices below [index_len](Self::index_len) in ascending
+    /// order. The iterator element type is `Option<&'a V>`.
+    ///
+    /// For each index, the iterator yields the same value that would be returned by
+    /// [get_by_index](Self::get_by_index). The iterator walks the storage directly and
+    /// does not hash any keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// map.remove("b");
+    ///
+    /// let values: Vec<_> = map.values_sparse().collect();
+    /// assert_eq!(values, [Some(&1), None, Some(&3)]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn values_sparse(&self) -> ValuesSparse<'_, V> {
+        ValuesSparse {
+            slots: self.storage.sparse_slots(),
+        }
+    }
+
+    /// An iterator visiting all values mutably in arbitrary order.
+    /// The iterator element type is `&'a mut V`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    ///
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    ///
+    /// for val in map.values_mut() {
+    ///     *val = *val + 10;
+    /// }
+    ///
+    /// assert_eq!(map.len(), 3);
+    /// let mut vec: Vec<i32> = Vec::new();
+    ///
+    /// for val in map.values() {
+    ///     println!("{}", val);
+    ///     vec.push(*val);
+    /// }
+    ///
+    /// // The `Values` iterator produces values in arbitrary order, so the
+    /// // values must be sorted to test them against a sorted array.
+    /// vec.sort_unstable();
+    /// assert_eq!(vec, [11, 12, 13]);
+    ///
+    /// assert_eq!(map.len(), 3);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
+        ValuesMut::new(self.key_to_pos.iter_mut(), &mut self.storage)
+    }
+}
+
+impl<K, V, S> StableMap<K, V, S> {
+    /// Creates an empty `StableMap` with the specified capacity, using `hash_builder`
+    /// to hash the keys.
+    ///
+    /// The hash map will be able to hold at least `capacity` elements without
+    /// reallocating. If `capacity` is 0, the hash map will not allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hashbrown::DefaultHashBuilder;
+    /// use stable_map::StableMap;
+    ///
+    /// let s = DefaultHashBuilder::default();
+    /// let mut map = StableMap::with_capacity_and_hasher(10, s);
+    /// assert_eq!(map.len(), 0);
+    /// assert!(map.capacity() >= 10);
+    ///
+    /// map.insert(1, 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
+        Self {
+            key_to_pos: KeyMap::with_capacity_and_hasher(capacity, hash_builder),
+            storage: LinearStorage::with_capacity(capacity),
+        }
+    }
+
+    /// Creates an empty `StableMap` which will use the given hash builder to hash
+    /// keys.
+    ///
+    /// The hash map is initially created with a capacity of 0, so it will not
+    /// allocate until it is first inserted into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hashbrown::DefaultHashBuilder;
+    /// use stable_map::StableMap;
+    ///
+    /// let s = DefaultHashBuilder::default();
+    /// let mut map = StableMap::with_hasher(s);
+    /// assert_eq!(map.len(), 0);
+    /// assert_eq!(map.capacity(), 0);
+    ///
+    /// map.insert(1, 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            key_to_pos: KeyMap::with_capacity_and_hasher(0, hash_builder),
+            storage: LinearStorage::with_capacity(0),
+        }
+    }
+}
+
+impl<K, V, S, A: Allocator + Clone> StableMap<K, V, S, A> {
+    /// Creates an empty `StableMap` with the specified capacity, using `hash_builder`
+    /// to hash the keys and `alloc` to allocate memory.
+    ///
+    /// The hash table and the storage of the values are allocated with `alloc`.
+    ///
+    /// Allocating from shared memory does not by itself make the map usable from several
+    /// processes: the hash table and the vectors refer to their buffers by absolute
+    /// address, `hash_builder` must produce the same hashes in every process, and all
+    /// accesses must be synchronized externally.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use allocator_api2::alloc::Global;
+    /// use hashbrown::DefaultHashBuilder;
+    /// use stable_map::StableMap;
+    ///
+    /// let s = DefaultHashBuilder::default();
+    /// let mut map = StableMap::with_capacity_and_hasher_in(10, s, Global);
+    /// assert_eq!(map.len(), 0);
+    /// assert!(map.capacity() >= 10);
+    ///
+    /// map.insert(1, 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_capacity_and_hasher_in(capacity: usize, hash_builder: S, alloc: A) -> Self {
+        Self {
+            key_to_pos: KeyMap::with_capacity_and_hasher_in(capacity, hash_builder, alloc.clone()),
+            storage: LinearStorage::with_capacity_in(capacity, alloc),
+        }
+    }
+
+    /// Creates an empty `StableMap` which will use the given hash builder to hash
+    /// keys and the given allocator to allocate memory.
+    ///
+    /// The hash map is initially created with a capacity of 0, so it will not
+    /// allocate until it is first inserted into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use allocator_api2::alloc::Global;
+    /// use hashbrown::DefaultHashBuilder;
+    /// use stable_map::StableMap;
+    ///
+    /// let s = DefaultHashBuilder::default();
+    /// let mut map = StableMap::with_hasher_in(s, Global);
+    /// assert_eq!(map.len(), 0);
+    /// assert_eq!(map.capacity(), 0);
+    ///
+    /// map.insert(1, 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_hasher_in(hash_builder: S, alloc: A) -> Self {
+        Self::with_capacity_and_hasher_in(0, hash_builder, alloc)
+    }
+}
+
+impl<K, V, S, A: Allocator> StableMap<K, V, S, A> {
+    /// Returns a reference to the underlying allocator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let map: StableMap<i32, i32> = StableMap::new();
+    /// let _alloc = map.allocator();
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn allocator(&self) -> &A {
+        self.key_to_pos.allocator()
+    }
+
+    /// Returns one more than the highest possible index of this map.
+    ///
+    /// Using [get_by_index](Self::get_by_index) with higher indices will always return
+    /// `None`.
+    ///
+    /// With the `narrow-index` feature, this value never exceeds `u32::MAX`. See
+    /// [Narrow indices](Self#narrow-indices).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut a = StableMap::new();
+    /// assert_eq!(a.index_len(), 0);
+    /// a.insert(1, "a");
+    /// a.insert(2, "b");
+    /// a.remove(&2);
+    /// assert_eq!(a.len(), 1);
+    /// assert_eq!(a.index_len(), 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn index_len(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Appends up to `n` unused indices to the index space of this map and returns the
+    /// number of indices that were appended.
+    ///
+    /// After this function returns, [index_len](Self::index_len) has increased by the
+    /// returned number. New entries are assigned unused indices before the index space grows any
+    /// further. Therefore, until more than `index_len()` entries have been inserted,
+    /// all indices are guaranteed to be less than `index_len()`.
+    ///
+    /// If the map has a [max_vacant](Self::set_max_vacant) limit, at most as many
+    /// indices are appended as keep the number of unused indices within that limit. The
+    /// returned number is then less than `n`.
+    ///
+    /// The unused indices can be removed again by compacting the map. If the map uses
+    /// [amortized compaction](Self::set_amortized_compaction) or max_vacant, they might
+    /// be removed by other operations as well.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, 1);
+    /// assert_eq!(map.extend_index_space(3), 3);
+    /// assert_eq!(map.index_len(), 4);
+    /// for i in 2..5 {
+    ///     map.insert(i, i);
+    /// }
+    /// assert_eq!(map.index_len(), 4);
+    /// map.insert(5, 5);
+    /// assert_eq!(map.get_index(&5), Some(4));
+    ///
+    /// map.set_max_vacant(2);
+    /// assert_eq!(map.extend_index_space(3), 2);
+    /// assert_eq!(map.index_len(), 7);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn extend_index_space(&mut self, n: usize) -> usize {
+        self.storage.extend(n)
+    }
+
+    /// Returns the largest [index_len](Self::index_len) this map has had.
+    ///
+    /// This is the largest value since the map was created or since the last call to
+    /// [reset_index_high_water_mark](Self::reset_index_high_water_mark). Clearing or
+    /// compacting the map does not reset this value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// for i in 0..4 {
+    ///     map.insert(i, i);
+    /// }
+    /// map.clear();
+    /// map.insert(0, 0);
+    /// assert_eq!(map.index_len(), 1);
+    /// assert_eq!(map.index_high_water_mark(), 4);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn index_high_water_mark(&self) -> usize {
+        self.storage.high_water_mark()
+    }
+
+    /// Resets the [index_high_water_mark](Self::index_high_water_mark) to the current
+    /// [index_len](Self::index_len).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// for i in 0..4 {
+    ///     map.insert(i, i);
+    /// }
+    /// map.clear();
+    /// map.reset_index_high_water_mark();
+    /// assert_eq!(map.index_high_water_mark(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn reset_index_high_water_mark(&mut self) {
+        self.storage.reset_high_water_mark();
+    }
+
+    /// Returns the number of indices the map can hold without reallocating the storage
+    /// of the values.
+    ///
+    /// [index_len](Self::index_len) can grow up to this number without reallocating.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::<i32, i32>::new();
+    /// assert_eq!(map.index_capacity(), 0);
+    /// map.reserve_index_capacity(10);
+    /// assert!(map.index_capacity() >= 10);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn index_capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Reserves capacity for at least `additional` more indices beyond
+    /// [index_len](Self::index_len).
+    ///
+    /// Unlike [reserve](Self::reserve), this function only grows the storage of the
+    /// values and leaves the hash table untouched. This is useful if entries are inserted
+    /// and removed constantly such that `len()` stays small while `index_len()` is large.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the new capacity exceeds [`isize::MAX`] bytes and [`abort`] the program
+    /// in case of allocation error.
+    ///
+    /// [`isize::MAX`]: https://doc.rust-lang.org/std/primitive.isize.html
+    /// [`abort`]: https://doc.rust-lang.org/alloc/alloc/fn.handle_alloc_error.html
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::<i32, i32>::new();
+    /// map.reserve_index_capacity(100);
+    /// assert!(map.index_capacity() >= 100);
+    /// assert_eq!(map.capacity(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn reserve_index_capacity(&mut self, additional: usize) {
+        self.storage.reserve_slots(additional);
+    }
+}
+
+impl<K, V, S> StableMap<K, V, S> {
+    /// Creates a map from an iterator of `(index, key, value)` triples.
+    ///
+    /// Each entry is assigned exactly the given index. Indices that do not occur in the
+    /// iterator and are less than the largest index become unused indices.
+    ///
+    /// If a key or an index occurs more than once, or if an index is out of range, the
+    /// offending triple is returned in the error. An index is out of range if it is larger
+    /// than the largest index the map can represent or if the memory for the indices below
+    /// it cannot be allocated.
+    ///
+    /// If the indices are yielded in ascending order, this function takes time linear in
+    /// the number of entries.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let map: StableMap<_, _> =
+    ///     StableMap::from_indexed_iter([(3, "a", 1), (1, "b", 2)]).unwrap();
+    /// assert_eq!(map.index_len(), 4);
+    /// assert_eq!(map.get_index("a"), Some(3));
+    /// assert_eq!(map.get_index("b"), Some(1));
+    ///
+    /// let res: Result<StableMap<_, _>, _> =
+    ///     StableMap::from_indexed_iter([(0, "a", 1), (0, "b", 2)]);
+    /// assert_eq!(res.unwrap_err(), (0, "b", 2));
+    ///
+    /// let res: Result<StableMap<_, _>, _> =
+    ///     StableMap::from_indexed_iter([(0, "a", 1), (usize::MAX, "b", 2)]);
+    /// assert_eq!(res.unwrap_err(), (usize::MAX, "b", 2));
+    /// ```
+    pub fn from_indexed_iter<I>(iter: I) -> Result<Self, (usize, K, V)>
+    where
+        K: Eq + Hash,
+        S: BuildHasher + Default,
+        I: IntoIterator<Item = (usize, K, V)>,
+    {
+        let iter = iter.into_iter();
+        let mut map = Self::with_capacity_and_hasher(iter.size_hint().0, S::default());
+        for (index, key, value) in iter {
+            map.insert_at_index(index, key, value)
+                .map_err(|(key, value)| (index, key, value))?;
+        }
+        Ok(map)
+    }
+}
+
+impl<K, V, S, A: Allocator> StableMap<K, V, S, A> {
+    /// Returns the index that the key maps to.
+    ///
+    /// This function returns `Some` if and only if the key is contained in the map.
+    ///
+    /// As long as the key is not removed from the map, and unless
+    /// [compact](Self::compact) or [force_compact](Self::force_compact) is called, this
+    /// function will always return the same value.
+    ///
+    /// The returned value can be used to retrieve the value by using
+    /// [get_by_index](Self::get_by_index) or [get_by_index_mut](Self::get_by_index_mut).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut a = StableMap::new();
+    /// assert_eq!(a.index_len(), 0);
+    /// a.insert(1, "a");
+    /// assert_eq!(a.get_by_index(a.get_index(&1).unwrap()).unwrap(), &"a");
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_index<Q>(&self, q: &Q) -> Option<usize>
+    where
+        S: BuildHasher,
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.key_to_pos.get(q).map(|v| unsafe {
+            // SAFETY:
+            // - By the invariants, v is valid
+            self.storage.index_unchecked(v)
+        })
+    }
+
+    /// Returns `true` if the index currently belongs to the key.
+    ///
+    /// This is equivalent to `self.get_index(q) == Some(index)`. It can be used to check
+    /// that a cached key-index pair is still valid, for example after the map might have
+    /// been compacted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// let index = map.get_index(&2).unwrap();
+    /// assert!(map.check_index(&2, index));
+    /// assert!(!map.check_index(&1, index));
+    ///
+    /// map.remove(&1);
+    /// map.force_compact();
+    /// assert!(!map.check_index(&2, index));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn check_index<Q>(&self, q: &Q, index: usize) -> bool
+    where
+        S: BuildHasher,
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.get_index(q) == Some(index)
+    }
+
+    /// Returns the indices that the keys map to.
+    ///
+    /// The returned array contains the same values that would be returned by calling
+    /// [get_index](Self::get_index) with each of the keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// assert_eq!(map.get_index_many(["b", "c", "a"]), [Some(1), None, Some(0)]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_index_many<Q, const N: usize>(&self, ks: [&Q; N]) -> [Option<usize>; N]
+    where
+        S: BuildHasher,
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        ks.map(|k| self.get_index(k))
+    }
+
+    /// Returns `true` if the index is in use.
+    ///
+    /// This function returns `true` if and only if there is a key, `key`, for which
+    /// [get_index](Self::get_index) returns this index. It is equivalent to
+    /// `self.get_by_index(index).is_some()` but does not construct a reference to the
+    /// value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// map.remove(&1);
+    /// let occupied: Vec<_> = (0..map.index_len()).map(|i| map.contains_index(i)).collect();
+    /// assert_eq!(occupied, [false, true]);
+    /// assert!(!map.contains_index(2));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn contains_index(&self, index: usize) -> bool {
+        self.storage.is_occupied(index)
+    }
+
+    /// Returns the smallest index that is in use.
+    ///
+    /// This is the index of the first element yielded by
+    /// [values_from_index(0)](Self::values_from_index).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// assert_eq!(map.first_index(), None);
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// map.remove("a");
+    /// assert_eq!(map.first_index(), Some(1));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn first_index(&self) -> Option<usize> {
+        self.storage.next_occupied(0)
+    }
+
+    /// Returns the largest index that is in use.
+    ///
+    /// All indices that are in use are smaller than or equal to this index. Unlike
+    /// [index_len](Self::index_len), this does not account for unused indices at the end
+    /// of the index space.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// assert_eq!(map.last_index(), None);
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// map.remove("c");
+    /// assert_eq!(map.last_index(), Some(1));
+    /// assert_eq!(map.index_len(), 3);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn last_index(&self) -> Option<usize> {
+        self.storage.last_occupied()
+    }
+
+    /// Returns the smallest index greater than `after` that is in use.
+    ///
+    /// This is equivalent to `(after + 1..self.index_len()).find(|&i| self.contains_index(i))`
+    /// but scans the storage directly. The map does not track runs of unused indices, so
+    /// this function still visits every unused index it skips and takes time linear in
+    /// their number.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// for i in 0..6 {
+    ///     map.insert(i, i);
+    /// }
+    /// for i in 1..5 {
+    ///     map.remove(&i);
+    /// }
+    /// assert_eq!(map.next_occupied_index(0), Some(5));
+    /// assert_eq!(map.next_occupied_index(5), None);
+    ///
+    /// map.insert(6, 6);
+    /// let mut visited = vec![0];
+    /// while let Some(i) = map.next_occupied_index(*visited.last().unwrap()) {
+    ///     visited.push(i);
+    /// }
+    /// // The new entry reused the lowest unused index.
+    /// assert_eq!(visited, [0, 1, 5]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn next_occupied_index(&self, after: usize) -> Option<usize> {
+        self.storage.next_occupied(after.checked_add(1)?)
+    }
+
+    /// Returns a reference to the value corresponding to the index.
+    ///
+    /// This function returns `Some` if and only if there is a key, `key`, for which
+    /// [get_index](Self::get_index) returns this index. In this case, it returns the same
+    /// value that would be returned by calling [get](Self::get).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut a = StableMap::new();
+    /// assert_eq!(a.index_len(), 0);
+    /// a.insert(1, "a");
+    /// assert_eq!(a.get_by_index(a.get_index(&1).unwrap()).unwrap(), &"a");
+    /// ```
+    #[inline]
+    pub fn get_by_index(&self, index: usize) -> Option<&V> {
+        self.storage.get(index)
+    }
+
+    /// Returns a mutable reference to the value corresponding to the index.
+    ///
+    /// This function returns `Some` if and only if there is a key, `key`, for which
+    /// [get_index](Self::get_index) returns this index. In this case, it returns the same
+    /// value that would be returned by calling [get_mut](Self::get_mut).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut a = StableMap::new();
+    /// assert_eq!(a.index_len(), 0);
+    /// a.insert(1, "a");
+    /// assert_eq!(a.get_by_index_mut(a.get_index(&1).unwrap()).unwrap(), &"a");
+    /// ```
+    #[inline]
+    pub fn get_by_index_mut(&mut self, index: usize) -> Option<&mut V> {
+        self.storage.get_mut(index)
+    }
+
+    /// Returns a reference to the key corresponding to the index.
+    ///
+    /// This function returns `Some` if and only if there is a key, `key`, for which
+    /// [get_index](Self::get_index) returns this index. In this case, it returns that key.
+    ///
+    /// With the `cached-hash` feature, this function takes constant expected time.
+    /// Otherwise, if the index is in use, it takes time linear in the size of the map
+    /// since the key of the index has to be found by scanning the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// let index = map.get_index(&"b").unwrap();
+    /// assert_eq!(map.get_key_by_index(index), Some(&"b"));
+    /// map.remove(&"b");
+    /// assert_eq!(map.get_key_by_index(index), None);
+    /// ```
+    pub fn get_key_by_index(&self, index: usize) -> Option<&K> {
+        self.find_by_index(index).map(|(k, _)| k)
+    }
+
+    /// Removes the key of the entry with the given index from key_to_pos and returns it
+    /// together with its position.
+    ///
+    /// The value is left in the storage.
+    #[cfg(feature = "cached-hash")]
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn remove_key_by_index(&mut self, index: usize) -> Option<(K, Pos<InUse>)> {
+        let hash = self.storage.hash(index)?;
+        let storage = &self.storage;
+        let entry = self.key_to_pos.find_pos_entry(hash, |pos| {
+            let i = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                storage.index_unchecked(pos)
+            };
+            i == index
+        })?;
+        Some(entry.remove().0)
+    }
+
+    /// Removes the key of the entry with the given index from key_to_pos and returns it
+    /// together with its position.
+    ///
+    /// The value is left in the storage.
+    #[cfg(not(feature = "cached-hash"))]
+    fn remove_key_by_index(&mut self, index: usize) -> Option<(K, Pos<InUse>)> {
+        if !self.storage.is_occupied(index) {
+            return None;
+        }
+        let storage = &self.storage;
+        self.key_to_pos
+            .extract_if(|_, pos| {
+                let i = unsafe {
+                    // SAFETY:
+                    // - By the invariants, pos is valid
+                    storage.index_unchecked(pos)
+                };
+                i == index
+            })
+            .next()
+    }
+
+    /// Returns the key and position of the entry with the given index.
+    ///
+    /// The storage contains the hash of the key of each index, so the entry can be found
+    /// by comparing the positions of the keys with this hash.
+    #[cfg(feature = "cached-hash")]
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn find_by_index(&self, index: usize) -> Option<(&K, &Pos<InUse>)> {
+        let hash = self.storage.hash(index)?;
+        self.key_to_pos.find_pos(hash, |pos| {
+            let i = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.index_unchecked(pos)
+            };
+            i == index
+        })
+    }
+
+    /// Returns the key and position of the entry with the given index.
+    ///
+    /// The storage does not contain the hash of the key of an index, so this function
+    /// scans the map.
+    #[cfg(not(feature = "cached-hash"))]
+    fn find_by_index(&self, index: usize) -> Option<(&K, &Pos<InUse>)> {
+        if !self.storage.is_occupied(index) {
+            return None;
+        }
+        self.key_to_pos
+            .iter()
+            .find(|(_, pos)| {
+                let i = unsafe {
+                    // SAFETY:
+                    // - By the invariants, pos is valid
+                    self.storage.index_unchecked(pos)
+                };
+                i == index
+            })
+            .map(|(k, pos)| (k, pos))
+    }
+
+    /// Returns a handle to the entry of the key.
+    ///
+    /// Unlike the index returned by [get_index](Self::get_index), the handle becomes
+    /// stale once the entry is removed or moved to a different index, for example by
+    /// [compact](Self::compact). Stale handles are rejected by
+    /// [get_by_handle](Self::get_by_handle) even if the index has since been reused.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, "a");
+    /// let handle = map.get_handle(&1).unwrap();
+    /// assert_eq!(handle.index(), 0);
+    ///
+    /// map.remove(&1);
+    /// map.insert(2, "b");
+    /// assert_eq!(map.get_index(&2), Some(0));
+    /// assert_eq!(map.get_by_handle(handle), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_handle<Q>(&self, q: &Q) -> Option<Handle>
+    where
+        S: BuildHasher,
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let index = self.get_index(q)?;
+        let generation = self.storage.generation(index)?;
+        Some(Handle { index, generation })
+    }
+
+    /// Returns a reference to the value that the handle refers to.
+    ///
+    /// Returns `None` if the handle has become stale.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, "a");
+    /// let handle = map.get_handle(&1).unwrap();
+    /// assert_eq!(map.get_by_handle(handle), Some(&"a"));
+    /// map.remove(&1);
+    /// assert_eq!(map.get_by_handle(handle), None);
+    /// ```
+    #[inline]
+    pub fn get_by_handle(&self, handle: Handle) -> Option<&V> {
+        if self.storage.generation(handle.index)? != handle.generation {
+            return None;
+        }
+        self.storage.get(handle.index)
+    }
+
+    /// Returns a mutable reference to the value that the handle refers to.
+    ///
+    /// Returns `None` if the handle has become stale.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, 10);
+    /// let handle = map.get_handle(&1).unwrap();
+    /// *map.get_by_handle_mut(handle).unwrap() += 1;
+    /// assert_eq!(map[&1], 11);
+    /// ```
+    #[inline]
+    pub fn get_by_handle_mut(&mut self, handle: Handle) -> Option<&mut V> {
+        if self.storage.generation(handle.index)? != handle.generation {
+            return None;
+        }
+        self.storage.get_mut(handle.index)
+    }
+
+    /// Removes and returns the entry with the lowest index.
+    ///
+    /// This does not turn the map into a queue ordered by the time of insertion. Removing
+    /// the entry makes its index unused, and, with the default
+    /// [reuse policy](Self::set_reuse_policy), the next entry inserted into the map is
+    /// assigned this index and is therefore the next entry returned by this function.
+    ///
+    /// Finding the lowest index takes time linear in the number of unused indices below
+    /// it. With the `cached-hash` feature, the entry is then removed in constant expected
+    /// time. Otherwise, removing it takes time linear in the size of the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// assert_eq!(map.pop_lowest_index(), Some(("a", 1)));
+    /// map.insert("d", 4);
+    /// assert_eq!(map.pop_lowest_index(), Some(("d", 4)));
+    /// assert_eq!(map.pop_lowest_index(), Some(("b", 2)));
+    /// assert_eq!(map.pop_lowest_index(), Some(("c", 3)));
+    /// assert_eq!(map.pop_lowest_index(), None);
+    /// ```
+    pub fn pop_lowest_index(&mut self) -> Option<(K, V)> {
+        let index = self.first_index()?;
+        self.remove_by_index(index)
+    }
+
+    /// Removes and returns the entry with the highest index.
+    ///
+    /// With the default [reuse policy](Self::set_reuse_policy), new entries are assigned
+    /// the lowest unused index. As long as the map has no unused indices, interleaving
+    /// insertions with this function therefore uses the map as a stack of entries
+    /// ordered by the time of their insertion. Once entries are removed by other means,
+    /// new entries fill the unused indices and this order no longer holds.
+    ///
+    /// Finding the highest index takes time linear in the number of unused indices above
+    /// it. With the `cached-hash` feature, the entry is then removed in constant expected
+    /// time. Otherwise, removing it takes time linear in the size of the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// map.insert("b", 2);
+    /// map.insert("c", 3);
+    /// assert_eq!(map.pop_highest_index(), Some(("c", 3)));
+    /// map.insert("d", 4);
+    /// assert_eq!(map.pop_highest_index(), Some(("d", 4)));
+    /// assert_eq!(map.pop_highest_index(), Some(("b", 2)));
+    /// assert_eq!(map.pop_highest_index(), Some(("a", 1)));
+    /// assert_eq!(map.pop_highest_index(), None);
+    /// ```
+    pub fn pop_highest_index(&mut self) -> Option<(K, V)> {
+        let index = self.last_index()?;
+        self.remove_by_index(index)
+    }
+
+    /// Removes the entry with the given index.
+    fn remove_by_index(&mut self, index: usize) -> Option<(K, V)> {
+        let (key, pos) = self.remove_key_by_index(index)?;
+        let value = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.take_unchecked(pos)
+        };
+        Some((key, value))
+    }
+
+    /// Returns a reference to the value corresponding to the index, without
+    /// validating that the index is valid.
+    ///
+    /// This function returns the same value that would be returned by
+    /// [get_by_index](Self::get_by_index).
+    ///
+    /// # Safety
+    ///
+    /// There must be some `key` for which `self.get_index(k)` would return this index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut a = StableMap::new();
+    /// assert_eq!(a.index_len(), 0);
+    /// a.insert(1, "a");
+    /// unsafe {
+    ///     assert_eq!(a.get_by_index_unchecked(a.get_index(&1).unwrap()), &"a");
+    /// }
+    /// ```
+    #[inline]
+    pub unsafe fn get_by_index_unchecked(&self, index: usize) -> &V {
+        unsafe {
+            // SAFETY:
+            // - By the requirements of this function, there is an element of key_to_pos
+            //   with this index.
+            // - By the invariants, that element could be used to call get_unchecked.
+            self.storage.get_unchecked_raw(index)
+        }
+    }
+
+    /// Returns a mutable reference to the value corresponding to the index, without
+    /// validating that the index is valid.
+    ///
+    /// This function returns the same value that would be returned by
+    /// [get_by_index_mut](Self::get_by_index_mut).
+    ///
+    /// # Safety
+    ///
+    /// There must be some `key` for which `self.get_index(k)` would return this index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut a = StableMap::new();
+    /// assert_eq!(a.index_len(), 0);
+    /// a.insert(1, "a");
+    /// unsafe {
+    ///     assert_eq!(a.get_by_index_unchecked_mut(a.get_index(&1).unwrap()), &"a");
+    /// }
+    /// ```
+    #[inline]
+    pub unsafe fn get_by_index_unchecked_mut(&mut self, index: usize) -> &mut V {
+        unsafe {
+            // SAFETY:
+            // - By the requirements of this function, there is an element of key_to_pos
+            //   with this index.
+            // - By the invariants, that element could be used to call get_unchecked_mut.
+            self.storage.get_unchecked_raw_mut(index)
+        }
+    }
+
+    /// Splits the index space into ranges containing roughly the same number of entries.
+    ///
+    /// The returned ranges are non-empty, disjoint, sorted, and together cover every
+    /// index for which [get_by_index](Self::get_by_index) returns `Some`. At most `n`
+    /// ranges are returned. This can be used to distribute the entries of the map over
+    /// worker threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// for i in 0..10 {
+    ///     map.insert(i, i);
+    /// }
+    /// map.remove(&0);
+    /// map.remove(&1);
+    ///
+    /// let chunks: Vec<_> = map.index_chunks(4).collect();
+    /// assert_eq!(chunks, [0..4, 4..6, 6..8, 8..10]);
+    /// let mut sum = 0;
+    /// for chunk in chunks {
+    ///     for i in chunk {
+    ///         if let Some(value) = map.get_by_index(i) {
+    ///             sum += value;
+    ///         }
+    ///     }
+    /// }
+    /// assert_eq!(sum, (2..10).sum());
+    /// ```
+    pub fn index_chunks(
+        &self,
+        n: usize,
+    ) -> impl Iterator<Item = Range<usize>> + use<'_, K, V, S, A> {
+        assert_ne!(n, 0, "n must not be 0");
+        let per_chunk = self.len().div_ceil(n);
+        let index_len = self.index_len();
+        let mut remaining = self.len();
+        let mut start = 0;
+        iter::from_fn(move || {
+            if remaining == 0 {
+                return None;
+            }
+            let target = per_chunk.min(remaining);
+            let mut end = start;
+            let mut count = 0;
+            while count < target {
+                if self.storage.get(end).is_some() {
+                    count += 1;
+                }
+                end += 1;
+            }
+            remaining -= count;
+            if remaining == 0 {
+                end = index_len;
+            }
+            let range = start..end;
+            start = end;
+            Some(range)
+        })
+    }
+
+    /// Returns a parallel iterator over the values of the map that walks the index space.
+    ///
+    /// The iterator yields each value together with its index. The index space is split
+    /// into contiguous ranges that are processed by the rayon thread pool, which gives
+    /// better cache locality than iterating the hash table if `V` is large. Unused
+    /// indices are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {rayon::iter::ParallelIterator, stable_map::StableMap};
+    ///
+    /// let mut map = StableMap::new();
+    /// for i in 0..100 {
+    ///     map.insert(i, i);
+    /// }
+    /// map.remove(&0);
+    ///
+    /// let sum: i32 = map.par_iter_by_index().map(|(_, v)| *v).sum();
+    /// assert_eq!(sum, 4950);
+    /// ```
+    #[cfg(feature = "rayon")]
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn par_iter_by_index(&self) -> ParIterByIndex<'_, V, A>
+    where
+        V: Sync,
+    {
+        ParIterByIndex {
+            storage: &self.storage,
+        }
+    }
+
+    /// Maybe compacts the map, removing indices for which `get_by_index` would return
+    /// `None`.
+    ///
+    /// This function does nothing if there are no more than 8 indices for which
+    /// [get_by_index](Self::get_by_index) returns `None` or if at least half of the
+    /// indices are in use.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// for i in 0..32 {
+    ///     map.insert(i, i);
+    /// }
+    /// for i in 0..16 {
+    ///     map.remove(&i);
+    /// }
+    /// assert_eq!(map.index_len(), 32);
+    /// map.compact();
+    /// assert_eq!(map.index_len(), 32);
+    /// map.remove(&16);
+    /// map.compact();
+    /// assert_eq!(map.index_len(), 15);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn compact(&mut self) {
+        self.storage.compact();
+    }
+
+    /// Compacts the map, removing indices for which `get_by_index` would return `None`.
+    ///
+    /// After this function returns, [index_len](Self::index_len) will be the same as
+    /// [len](Self::len).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert(1, 1);
+    /// map.remove(&1);
+    /// assert_eq!(map.index_len(), 1);
+    /// map.force_compact();
+    /// assert_eq!(map.index_len(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn force_compact(&mut self) {
+        self.storage.force_compact();
+    }
+
+    /// Calls a closure on a clone of each value while the map is unlocked.
+    ///
+    /// This function implements the pattern from the [type-level
+    /// documentation](StableMap#example): For each index, the value is cloned while the
+    /// lock is held, then `map` is dropped, `f` is called, and the map is re-locked by
+    /// calling `lock`. Since `f` is called without holding the lock, it can itself lock
+    /// the map and insert or remove entries. After all indices have been visited, the map
+    /// is [compacted](Self::compact) and the guard is returned.
+    ///
+    /// `map` can be any guard that dereferences to the map, for example a `MutexGuard`
+    /// or a `RefMut`.
+    ///
+    /// Entries inserted by `f` at indices that have not yet been visited are also
+    /// visited. If [amortized compaction](Self::set_amortized_compaction) or a [limit on
+    /// unused indices](Self::set_max_vacant) is configured, entries can move while the
+    /// map is unlocked and might therefore be skipped or visited twice.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {parking_lot::Mutex, stable_map::StableMap};
+    ///
+    /// let map = Mutex::new(StableMap::new());
+    /// map.lock().insert(1, 1);
+    /// map.lock().insert(2, 2);
+    ///
+    /// let mut sum = 0;
+    /// StableMap::for_each_unlocked(map.lock(), || map.lock(), |v| {
+    ///     sum += v;
+    ///     // The map is not locked while this closure runs.
+    ///     map.lock().remove(&2);
+    /// });
+    /// assert_eq!(sum, 1);
+    /// ```
+    pub fn for_each_unlocked<G, L, F>(mut map: G, mut lock: L, mut f: F) -> G
+    where
+        G: DerefMut<Target = Self>,
+        L: FnMut() -> G,
+        F: FnMut(V),
+        V: Clone,
+    {
+        let mut i = 0;
+        while i < map.index_len() {
+            if let Some(value) = map.get_by_index(i).cloned() {
+                drop(map);
+                f(value);
+                map = lock();
+            }
+            i += 1;
+        }
+        map.compact();
+        map
+    }
+
+    /// Returns a stream over the values of the map in the order of their indices.
+    ///
+    /// The stream yields each value together with its index. After visiting
+    /// `yield_every` indices, including unused indices, the stream wakes the task and
+    /// returns `Poll::Pending` once so that other tasks can make progress.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `yield_every` is 0.
     ///
     /// # Examples
     ///
     /// ```
-    /// use stable_map::StableMap;
+    /// use {
+    ///     futures_core::Stream,
+    ///     stable_map::StableMap,
+    ///     std::{
+    ///         pin::pin,
+    ///         task::{Context, Poll, Waker},
+    ///     },
+    /// };
     ///
     /// let mut map = StableMap::new();
-    /// map.insert("a", 1);
-    /// map.insert("b", 2);
-    /// map.insert("c", 3);
-    /// assert_eq!(map.len(), 3);
-    /// let mut vec: Vec<i32> = Vec::new();
-    ///
-    /// for val in map.values() {
-    ///     println!("{}", val);
-    ///     vec.push(*val);
-    /// }
-    ///
-    /// // The `Values` iterator produces values in arbitrary order, so the
-    /// // values must be sorted to test them against a sorted array.
-    /// vec.sort_unstable();
-    /// assert_eq!(vec, [1, 2, 3]);
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// map.remove(&1);
     ///
-    /// assert_eq!(map.len(), 3);
+    /// let mut stream = pin!(map.index_stream(64));
+    /// let mut cx = Context::from_waker(Waker::noop());
+    /// assert_eq!(stream.as_mut().poll_next(&mut cx), Poll::Ready(Some((1, &"b"))));
+    /// assert_eq!(stream.as_mut().poll_next(&mut cx), Poll::Ready(None));
     /// ```
+    #[cfg(feature = "futures")]
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn values(&self) -> Values<'_, K, V> {
-        Values {
-            iter: self.key_to_pos.values(),
+    pub fn index_stream(&self, yield_every: usize) -> IndexStream<'_, V, A> {
+        assert_ne!(yield_every, 0, "yield_every must not be 0");
+        IndexStream {
             storage: &self.storage,
+            index: 0,
+            yield_every,
+            budget: yield_every,
         }
     }
 
-    /// An iterator visiting all values mutably in arbitrary order.
-    /// The iterator element type is `&'a mut V`.
+    /// Returns whether amortized compaction is enabled.
+    ///
+    /// See [set_amortized_compaction](Self::set_amortized_compaction).
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut map = StableMap::new();
+    /// let mut map = StableMap::<i32, i32>::new();
+    /// assert!(!map.amortized_compaction());
+    /// map.set_amortized_compaction(true);
+    /// assert!(map.amortized_compaction());
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn amortized_compaction(&self) -> bool {
+        self.storage.amortized_compaction()
+    }
+
+    /// Enables or disables amortized compaction.
     ///
-    /// map.insert("a", 1);
-    /// map.insert("b", 2);
-    /// map.insert("c", 3);
+    /// If amortized compaction is enabled, each insertion of a new key additionally moves
+    /// at most one entry from the highest index into the lowest unused index. This
+    /// spreads the cost of [force_compact](Self::force_compact) over many insertions and
+    /// avoids long pauses in workloads with heavy churn.
     ///
-    /// for val in map.values_mut() {
-    ///     *val = *val + 10;
-    /// }
+    /// While this is enabled, inserting a key can change the index of one other key.
+    /// Indices are only stable until the next insertion of a new key.
     ///
-    /// assert_eq!(map.len(), 3);
-    /// let mut vec: Vec<i32> = Vec::new();
+    /// Amortized compaction is disabled by default.
     ///
-    /// for val in map.values() {
-    ///     println!("{}", val);
-    ///     vec.push(*val);
-    /// }
+    /// # Examples
     ///
-    /// // The `Values` iterator produces values in arbitrary order, so the
-    /// // values must be sorted to test them against a sorted array.
-    /// vec.sort_unstable();
-    /// assert_eq!(vec, [11, 12, 13]);
+    /// ```
+    /// use stable_map::StableMap;
     ///
-    /// assert_eq!(map.len(), 3);
+    /// let mut map = StableMap::new();
+    /// map.set_amortized_compaction(true);
+    /// for i in 0..4 {
+    ///     map.insert(i, i);
+    /// }
+    /// map.remove(&0);
+    /// map.remove(&1);
+    /// assert_eq!(map.index_len(), 4);
+    /// map.insert(4, 4);
+    /// assert_eq!(map.get_index(&4), Some(0));
+    /// assert_eq!(map.get_index(&3), Some(1));
+    /// assert_eq!(map.index_len(), 3);
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn values_mut(&mut self) -> ValuesMut<'_, K, V> {
-        ValuesMut {
-            iter: self.key_to_pos.values_mut(),
-            storage: self.storage.raw_access(),
-        }
+    pub fn set_amortized_compaction(&mut self, enabled: bool) {
+        self.storage.set_amortized_compaction(enabled);
     }
 
-    /// Creates an empty `StableMap` with the specified capacity, using `hash_builder`
-    /// to hash the keys.
+    /// Returns whether the index space is shrunk after compaction.
     ///
-    /// The hash map will be able to hold at least `capacity` elements without
-    /// reallocating. If `capacity` is 0, the hash map will not allocate.
+    /// See [set_auto_shrink](Self::set_auto_shrink).
     ///
     /// # Examples
     ///
     /// ```
-    /// use hashbrown::DefaultHashBuilder;
     /// use stable_map::StableMap;
     ///
-    /// let s = DefaultHashBuilder::default();
-    /// let mut map = StableMap::with_capacity_and_hasher(10, s);
-    /// assert_eq!(map.len(), 0);
-    /// assert!(map.capacity() >= 10);
-    ///
-    /// map.insert(1, 2);
+    /// let mut map = StableMap::<i32, i32>::new();
+    /// assert!(!map.auto_shrink());
+    /// map.set_auto_shrink(true);
+    /// assert!(map.auto_shrink());
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn with_capacity_and_hasher(capacity: usize, hash_builder: S) -> Self {
-        Self {
-            key_to_pos: HashMap::with_capacity_and_hasher(capacity, hash_builder),
-            storage: LinearStorage::with_capacity(capacity),
-        }
+    pub fn auto_shrink(&self) -> bool {
+        self.storage.auto_shrink()
     }
 
-    /// Creates an empty `StableMap` which will use the given hash builder to hash
-    /// keys.
+    /// Enables or disables shrinking of the index space after compaction.
     ///
-    /// The hash map is initially created with a capacity of 0, so it will not
-    /// allocate until it is first inserted into.
+    /// If this is enabled and, after [force_compact](Self::force_compact) (or
+    /// [compact](Self::compact)) has compacted the map, less than a quarter of the
+    /// [index_capacity](Self::index_capacity) is in use, the index capacity is shrunk to
+    /// fit [len](Self::len). This prevents long-lived maps from retaining the memory of
+    /// their peak size.
+    ///
+    /// The capacity of the hash table is not affected. Use
+    /// [shrink_to_fit](Self::shrink_to_fit) to shrink it.
+    ///
+    /// Auto-shrinking is disabled by default.
     ///
     /// # Examples
     ///
     /// ```
-    /// use hashbrown::DefaultHashBuilder;
     /// use stable_map::StableMap;
     ///
-    /// let s = DefaultHashBuilder::default();
-    /// let mut map = StableMap::with_hasher(s);
-    /// assert_eq!(map.len(), 0);
-    /// assert_eq!(map.capacity(), 0);
-    ///
-    /// map.insert(1, 2);
+    /// let mut map = StableMap::new();
+    /// map.set_auto_shrink(true);
+    /// for i in 0..100 {
+    ///     map.insert(i, i);
+    /// }
+    /// for i in 1..100 {
+    ///     map.remove(&i);
+    /// }
+    /// assert!(map.index_capacity() >= 100);
+    /// map.force_compact();
+    /// assert!(map.index_capacity() < 100);
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn with_hasher(hash_builder: S) -> Self {
-        Self {
-            key_to_pos: HashMap::with_hasher(hash_builder),
-            storage: LinearStorage::with_capacity(0),
-        }
+    pub fn set_auto_shrink(&mut self, enabled: bool) {
+        self.storage.set_auto_shrink(enabled);
     }
 
-    /// Returns one more than the highest possible index of this map.
+    /// Returns the maximum number of unused indices retained by the map.
     ///
-    /// Using [get_by_index](Self::get_by_index) with higher indices will always return
-    /// `None`.
+    /// See [set_max_vacant](Self::set_max_vacant).
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut a = StableMap::new();
-    /// assert_eq!(a.index_len(), 0);
-    /// a.insert(1, "a");
-    /// a.insert(2, "b");
-    /// a.remove(&2);
-    /// assert_eq!(a.len(), 1);
-    /// assert_eq!(a.index_len(), 2);
+    /// let mut map = StableMap::<i32, i32>::new();
+    /// assert_eq!(map.max_vacant(), usize::MAX);
+    /// map.set_max_vacant(1024);
+    /// assert_eq!(map.max_vacant(), 1024);
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn index_len(&self) -> usize {
-        self.storage.len()
+    pub fn max_vacant(&self) -> usize {
+        self.storage.max_vacant()
     }
 
-    /// Returns the index that the key maps to.
+    /// Sets the maximum number of unused indices retained by the map.
     ///
-    /// This function returns `Some` if and only if the key is contained in the map.
+    /// Whenever a removal causes the number of indices for which
+    /// [get_by_index](Self::get_by_index) returns `None` to exceed this number, entries
+    /// are moved from the highest indices into the lowest unused indices until it no
+    /// longer does. This bounds the memory retained by maps with bursty churn without
+    /// having to call [compact](Self::compact) periodically.
     ///
-    /// As long as the key is not removed from the map, and unless
-    /// [compact](Self::compact) or [force_compact](Self::force_compact) is called, this
-    /// function will always return the same value.
+    /// While this limit is exceeded, removing a key can change the indices of other keys.
+    /// If the map already retains more unused indices than `max_vacant`, this function
+    /// reclaims them immediately.
     ///
-    /// The returned value can be used to retrieve the value by using
-    /// [get_by_index](Self::get_by_index) or [get_by_index_mut](Self::get_by_index_mut).
+    /// By default, there is no limit.
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut a = StableMap::new();
-    /// assert_eq!(a.index_len(), 0);
-    /// a.insert(1, "a");
-    /// assert_eq!(a.get_by_index(a.get_index(&1).unwrap()).unwrap(), &"a");
+    /// let mut map = StableMap::new();
+    /// map.set_max_vacant(1);
+    /// for i in 0..4 {
+    ///     map.insert(i, i);
+    /// }
+    /// map.remove(&0);
+    /// assert_eq!(map.index_len(), 4);
+    /// map.remove(&1);
+    /// assert_eq!(map.index_len(), 3);
+    /// assert_eq!(map.get_index(&3), Some(0));
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn get_index<Q>(&self, q: &Q) -> Option<usize>
-    where
-        S: BuildHasher,
-        K: Eq + Hash,
-        Q: Hash + Equivalent<K> + ?Sized,
-    {
-        self.key_to_pos.get(q).map(|v| unsafe {
-            // SAFETY:
-            // - By the invariants, v is valid
-            v.get_unchecked()
-        })
+    pub fn set_max_vacant(&mut self, max_vacant: usize) {
+        self.storage.set_max_vacant(max_vacant);
     }
 
-    /// Returns a reference to the value corresponding to the index.
+    /// Returns the maximum number of elements in the map.
     ///
-    /// This function returns `Some` if and only if there is a key, `key`, for which
-    /// [get_index](Self::get_index) returns this index. In this case, it returns the same
-    /// value that would be returned by calling [get](Self::get).
+    /// See [set_max_len](Self::set_max_len).
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut a = StableMap::new();
-    /// assert_eq!(a.index_len(), 0);
-    /// a.insert(1, "a");
-    /// assert_eq!(a.get_by_index(a.get_index(&1).unwrap()).unwrap(), &"a");
+    /// let mut map = StableMap::<i32, i32>::new();
+    /// assert_eq!(map.max_len(), usize::MAX);
+    /// map.set_max_len(10);
+    /// assert_eq!(map.max_len(), 10);
     /// ```
-    #[inline]
-    pub fn get_by_index(&self, index: usize) -> Option<&V> {
-        self.storage.get(index)
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn max_len(&self) -> usize {
+        self.storage.max_len()
     }
 
-    /// Returns a mutable reference to the value corresponding to the index.
+    /// Returns whether the map contains at least [max_len](Self::max_len) elements.
     ///
-    /// This function returns `Some` if and only if there is a key, `key`, for which
-    /// [get_index](Self::get_index) returns this index. In this case, it returns the same
-    /// value that would be returned by calling [get_mut](Self::get_mut).
+    /// If this function returns `true`, inserting a new key panics and the bounded
+    /// functions, such as [try_insert_bounded](Self::try_insert_bounded), return an
+    /// error.
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut a = StableMap::new();
-    /// assert_eq!(a.index_len(), 0);
-    /// a.insert(1, "a");
-    /// assert_eq!(a.get_by_index_mut(a.get_index(&1).unwrap()).unwrap(), &"a");
+    /// let mut map = StableMap::with_max_len(1);
+    /// assert!(!map.is_full());
+    /// map.insert(1, 1);
+    /// assert!(map.is_full());
     /// ```
-    #[inline]
-    pub fn get_by_index_mut(&mut self, index: usize) -> Option<&mut V> {
-        self.storage.get_mut(index)
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn is_full(&self) -> bool {
+        self.storage.is_full()
     }
 
-    /// Returns a reference to the value corresponding to the index, without
-    /// validating that the index is valid.
+    /// Sets the maximum number of elements in the map.
     ///
-    /// This function returns the same value that would be returned by
-    /// [get_by_index](Self::get_by_index).
+    /// [try_insert_bounded](Self::try_insert_bounded),
+    /// [try_extend_bounded](Self::try_extend_bounded),
+    /// [try_apply_bounded](Self::try_apply_bounded),
+    /// [`VacantEntry::try_insert_bounded`], and [apply_diff](Self::apply_diff) return an
+    /// error instead of inserting a new key into a map that already contains `max_len`
+    /// elements. All other functions that insert new keys, such as
+    /// [insert](Self::insert), the entry API, or [extend](Extend::extend), panic instead.
+    /// [is_full](Self::is_full) can be used to check for this case beforehand. Updating
+    /// the value of an existing key is always possible.
     ///
-    /// # Safety
+    /// If the map already contains more elements, no elements are removed, but no new
+    /// keys can be inserted until enough elements have been removed.
     ///
-    /// There must be some `key` for which `self.get_index(k)` would return this index.
+    /// By default, there is no limit.
     ///
     /// # Examples
     ///
     /// ```
     /// use stable_map::StableMap;
     ///
-    /// let mut a = StableMap::new();
-    /// assert_eq!(a.index_len(), 0);
-    /// a.insert(1, "a");
-    /// unsafe {
-    ///     assert_eq!(a.get_by_index_unchecked(a.get_index(&1).unwrap()), &"a");
-    /// }
+    /// let mut map = StableMap::new();
+    /// map.insert(1, 1);
+    /// map.insert(2, 2);
+    /// map.set_max_len(1);
+    /// assert!(map.try_insert_bounded(3, 3).is_err());
+    /// assert_eq!(map.insert(2, 3), Some(2));
+    /// map.remove(&1);
+    /// map.remove(&2);
+    /// assert!(map.try_insert_bounded(3, 3).is_ok());
     /// ```
-    #[inline]
-    pub unsafe fn get_by_index_unchecked(&self, index: usize) -> &V {
-        unsafe {
-            // SAFETY:
-            // - By the requirements of this function, there is an element of key_to_pos
-            //   with this index.
-            // - By the invariants, that element could be used to call get_unchecked.
-            self.storage.get_unchecked_raw(index)
-        }
-    }
-
-    /// Returns a mutable reference to the value corresponding to the index, without
-    /// validating that the index is valid.
     ///
-    /// This function returns the same value that would be returned by
-    /// [get_by_index_mut](Self::get_by_index_mut).
+    /// ```should_panic
+    /// use stable_map::StableMap;
     ///
-    /// # Safety
+    /// let mut map = StableMap::with_max_len(1);
+    /// map.insert(1, 1);
+    /// map.insert(2, 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn set_max_len(&mut self, max_len: usize) {
+        self.storage.set_max_len(max_len);
+    }
+
+    /// Returns the order in which the indices of removed entries are reused.
     ///
-    /// There must be some `key` for which `self.get_index(k)` would return this index.
+    /// See [set_reuse_policy](Self::set_reuse_policy).
     ///
     /// # Examples
     ///
     /// ```
-    /// use stable_map::StableMap;
+    /// use stable_map::{ReusePolicy, StableMap};
     ///
-    /// let mut a = StableMap::new();
-    /// assert_eq!(a.index_len(), 0);
-    /// a.insert(1, "a");
-    /// unsafe {
-    ///     assert_eq!(a.get_by_index_unchecked_mut(a.get_index(&1).unwrap()), &"a");
-    /// }
+    /// let mut map = StableMap::<i32, i32>::new();
+    /// assert_eq!(map.reuse_policy(), ReusePolicy::LowestFirst);
+    /// map.set_reuse_policy(ReusePolicy::Lifo);
+    /// assert_eq!(map.reuse_policy(), ReusePolicy::Lifo);
     /// ```
-    #[inline]
-    pub unsafe fn get_by_index_unchecked_mut(&mut self, index: usize) -> &mut V {
-        unsafe {
-            // SAFETY:
-            // - By the requirements of this function, there is an element of key_to_pos
-            //   with this index.
-            // - By the invariants, that element could be used to call get_unchecked_mut.
-            self.storage.get_unchecked_raw_mut(index)
-        }
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn reuse_policy(&self) -> ReusePolicy {
+        self.storage.reuse_policy()
     }
 
-    /// Maybe compacts the map, removing indices for which `get_by_index` would return
-    /// `None`.
+    /// Returns the smallest index that is not currently in use and smaller than
+    /// [index_len](Self::index_len).
     ///
-    /// This function does nothing if there are no more than 8 indices for which
-    /// [get_by_index](Self::get_by_index) returns `None` or if at least half of the
-    /// indices are in use.
+    /// If the [reuse policy](Self::set_reuse_policy) is [`ReusePolicy::LowestFirst`],
+    /// which is the default, the next entry inserted into the map will be assigned this
+    /// index. If this function returns `None`, the next entry will be assigned the index
+    /// `index_len()` instead.
+    ///
+    /// Unless the reuse policy is [`ReusePolicy::LowestFirst`], this function takes time
+    /// linear in the number of unused indices.
     ///
     /// # Examples
     ///
@@ -1805,50 +5633,87 @@ impl<K, V, S> StableMap<K, V, S> {
     /// use stable_map::StableMap;
     ///
     /// let mut map = StableMap::new();
-    /// for i in 0..32 {
+    /// for i in 0..4 {
     ///     map.insert(i, i);
     /// }
-    /// for i in 0..16 {
-    ///     map.remove(&i);
-    /// }
-    /// assert_eq!(map.index_len(), 32);
-    /// map.compact();
-    /// assert_eq!(map.index_len(), 32);
-    /// map.remove(&16);
-    /// map.compact();
-    /// assert_eq!(map.index_len(), 15);
+    /// assert_eq!(map.lowest_vacant_index(), None);
+    /// map.remove(&2);
+    /// map.remove(&1);
+    /// assert_eq!(map.lowest_vacant_index(), Some(1));
+    /// map.insert(4, 4);
+    /// assert_eq!(map.get_index(&4), Some(1));
+    /// assert_eq!(map.lowest_vacant_index(), Some(2));
     /// ```
     #[cfg_attr(feature = "inline-more", inline)]
-    pub fn compact(&mut self) {
-        self.storage.compact();
+    pub fn lowest_vacant_index(&self) -> Option<usize> {
+        self.storage.lowest_vacant()
     }
 
-    /// Compacts the map, removing indices for which `get_by_index` would return `None`.
+    /// Sets the order in which the indices of removed entries are reused.
     ///
-    /// After this function returns, [index_len](Self::index_len) will be the same as
-    /// [len](Self::len).
+    /// By default, the smallest unused index is reused first. This policy is usually
+    /// configured right after the map has been created. If the map already contains
+    /// unused indices, they are treated as if they had been freed in ascending order.
+    ///
+    /// This function takes time linear in the number of unused indices.
+    ///
+    /// Unless the policy is [`ReusePolicy::LowestFirst`], each step of
+    /// [amortized compaction](Self::set_amortized_compaction) and each index reclaimed due
+    /// to [max_vacant](Self::set_max_vacant) also takes time linear in the number of unused
+    /// indices.
     ///
     /// # Examples
     ///
     /// ```
-    /// use stable_map::StableMap;
+    /// use stable_map::{ReusePolicy, StableMap};
     ///
     /// let mut map = StableMap::new();
-    /// map.insert(1, 1);
-    /// map.remove(&1);
-    /// assert_eq!(map.index_len(), 1);
-    /// map.force_compact();
-    /// assert_eq!(map.index_len(), 0);
+    /// map.set_reuse_policy(ReusePolicy::Lifo);
+    /// for i in 0..4 {
+    ///     map.insert(i, i);
+    /// }
+    /// map.remove(&0);
+    /// map.remove(&2);
+    /// map.insert(4, 4);
+    /// assert_eq!(map.get_index(&4), Some(2));
     /// ```
-    #[cfg_attr(feature = "inline-more", inline)]
-    pub fn force_compact(&mut self) {
-        self.storage.force_compact();
+    pub fn set_reuse_policy(&mut self, reuse_policy: ReusePolicy) {
+        self.storage.set_reuse_policy(reuse_policy);
+    }
+}
+
+/// Removes the element whose value was moved into the callback of `retain_map` if the
+/// callback panics.
+struct RetainMapGuard<'a, K, V, S, A: Allocator> {
+    map: *mut StableMap<K, V, S, A>,
+    /// The index of the value that is currently owned by the callback.
+    moved: &'a Cell<Option<usize>>,
+}
+
+impl<K, V, S, A: Allocator> Drop for RetainMapGuard<'_, K, V, S, A> {
+    fn drop(&mut self) {
+        let Some(index) = self.moved.get() else {
+            return;
+        };
+        let map = unsafe {
+            // SAFETY:
+            // - The callback panicked and the iterator borrowing the map has been
+            //   dropped, therefore this is the only reference to the map.
+            &mut *self.map
+        };
+        if let Some((_, pos)) = map.remove_key_by_index(index) {
+            unsafe {
+                // SAFETY: By the invariants, pos is valid
+                // NOTE: The value has already been moved into the callback.
+                map.storage.remove_moved_unchecked(pos);
+            }
+        }
     }
 }
 
-impl<K, V, S> IntoIterator for StableMap<K, V, S> {
+impl<K, V, S, A: Allocator> IntoIterator for StableMap<K, V, S, A> {
     type Item = (K, V);
-    type IntoIter = IntoIter<K, V>;
+    type IntoIter = IntoIter<K, V, A>;
 
     fn into_iter(self) -> Self::IntoIter {
         IntoIter {
@@ -0,0 +1,34 @@
+use {crate::StableMap, allocator_api2::alloc::Allocator};
+
+/// A guard that covers an operation that might rehash the map.
+///
+/// This type is zero-sized unless the `tracing` feature is enabled.
+pub(crate) struct RehashSpan {
+    #[cfg(feature = "tracing")]
+    _span: Option<tracing::span::EnteredSpan>,
+}
+
+impl<K, V, S, A: Allocator> StableMap<K, V, S, A> {
+    /// Enters a span if inserting `additional` elements will grow the hash table.
+    ///
+    /// This function does nothing unless the `tracing` feature is enabled.
+    #[cfg(not(feature = "tracing"))]
+    #[inline(always)]
+    pub(crate) fn rehash_span(&self, additional: usize) -> RehashSpan {
+        let _ = additional;
+        RehashSpan {}
+    }
+
+    /// Enters a span if inserting `additional` elements will grow the hash table.
+    ///
+    /// This function does nothing unless the `tracing` feature is enabled.
+    #[cfg(feature = "tracing")]
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub(crate) fn rehash_span(&self, additional: usize) -> RehashSpan {
+        let len = self.key_to_pos.len();
+        let capacity = self.key_to_pos.capacity();
+        let span = (additional > capacity - len)
+            .then(|| tracing::debug_span!("rehash", len, capacity, additional).entered());
+        RehashSpan { _span: span }
+    }
+}
@@ -1,4 +1,17 @@
-use {crate::StableMap, alloc::vec::Vec};
+use {
+    crate::{
+        ApplyDiffError, CapacityError, DiffEntry, DiffIndexError, Entry, MapDiff, MapOp,
+        OverlapError, ReusePolicy, StableMap, TryReserveError,
+    },
+    alloc::{boxed::Box, vec::Vec},
+    allocator_api2::alloc::{AllocError, Allocator, Global, Layout},
+    core::{
+        cell::{Cell, RefCell},
+        hash::BuildHasher,
+        ptr::NonNull,
+    },
+    hashbrown::{DefaultHashBuilder, HashSet},
+};
 
 #[test]
 fn capacity() {
@@ -135,6 +148,19 @@ fn get_mut() {
     assert_eq!(map.get_mut(&3), None);
 }
 
+#[test]
+fn get_with_hash() {
+    let mut map = StableMap::new();
+    map.insert((1, 2), 12);
+    map.insert((3, 4), 34);
+    let hash = map.hasher().hash_one((3, 4));
+    assert_eq!(map.get_with_hash(hash, |k| k.0 == 3), Some(&34));
+    assert_eq!(map.get_with_hash(hash, |k| k.0 == 5), None);
+    *map.get_mut_with_hash(hash, |k| k.1 == 4).unwrap() += 1;
+    assert_eq!(map.get(&(3, 4)), Some(&35));
+    assert_eq!(map.get_mut_with_hash(hash, |_| false), None);
+}
+
 #[test]
 fn insert() {
     let mut map = StableMap::new();
@@ -472,3 +498,1391 @@ fn compact() {
         assert_eq!(map.get_index(&31), Some(0));
     }
 }
+
+#[test]
+fn apply_diff() {
+    let mut map1 = StableMap::new();
+    map1.insert(1, 11);
+    map1.insert(2, 22);
+    map1.insert(3, 33);
+    let mut map2 = StableMap::new();
+    map2.insert(4, 44);
+    map2.insert(3, 34);
+    map2.insert(2, 22);
+    map2.insert(5, 55);
+    let mut map3 = StableMap::new();
+    map3.insert(1, 11);
+    map3.insert(2, 22);
+    map3.insert(3, 33);
+    map3.apply_diff(map1.diff(&map2)).unwrap();
+    assert_eq!(map3, map2);
+    assert_eq!(map3.get_index(&2), Some(1));
+    assert_eq!(map3.get_index(&3), Some(2));
+    assert_eq!(map3.get_index(&4), Some(0));
+    assert_eq!(map3.get_index(&5), Some(3));
+    assert_eq!(map3.index_len(), 4);
+}
+
+#[test]
+fn apply_diff_large_index() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    let diff = MapDiff {
+        added: alloc::vec![
+            DiffEntry {
+                index: 2,
+                key: 2,
+                value: 22,
+            },
+            DiffEntry {
+                index: 4_000_000_000,
+                key: 3,
+                value: 33,
+            },
+        ],
+        removed: alloc::vec![(0, 1)],
+        changed: alloc::vec![],
+    };
+    assert_eq!(
+        map.apply_diff(diff),
+        Err(ApplyDiffError::Index(DiffIndexError {
+            index: 4_000_000_000,
+            bound: 3,
+        })),
+    );
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.index_len(), 1);
+    map.set_max_vacant(0);
+    let diff = MapDiff {
+        added: alloc::vec![
+            DiffEntry {
+                index: 2,
+                key: 2,
+                value: 22,
+            },
+            DiffEntry {
+                index: 1,
+                key: 3,
+                value: 33,
+            },
+        ],
+        removed: alloc::vec![],
+        changed: alloc::vec![],
+    };
+    map.apply_diff(diff).unwrap();
+    assert_eq!(map.get_index(&2), Some(1));
+    assert_eq!(map.get_index(&3), Some(2));
+    assert_eq!(map.index_len(), map.len());
+}
+
+#[test]
+fn apply_diff_max_len() {
+    let mut map = StableMap::with_max_len(2);
+    map.insert(1, 11);
+    map.insert(2, 22);
+    let entry = |key| DiffEntry {
+        index: 0,
+        key,
+        value: key * 11,
+    };
+    let diff = MapDiff {
+        added: alloc::vec![entry(3)],
+        removed: alloc::vec![(1, 2), (1, 2), (2, 4)],
+        changed: alloc::vec![entry(1), entry(4)],
+    };
+    assert_eq!(
+        map.apply_diff(diff),
+        Err(ApplyDiffError::MaxLen { len: 3, max_len: 2 }),
+    );
+    assert_eq!(map.len(), 2);
+    assert_eq!(map[&1], 11);
+    assert_eq!(map[&2], 22);
+    let diff = MapDiff {
+        added: alloc::vec![entry(3), entry(3)],
+        removed: alloc::vec![(1, 2), (1, 2), (0, 1)],
+        changed: alloc::vec![entry(1)],
+    };
+    map.apply_diff(diff).unwrap();
+    assert_eq!(map.len(), 2);
+    assert_eq!(map[&1], 11);
+    assert_eq!(map[&3], 33);
+    map.set_max_len(1);
+    let diff = MapDiff {
+        added: alloc::vec![],
+        removed: alloc::vec![(0, 1)],
+        changed: alloc::vec![entry(3)],
+    };
+    map.apply_diff(diff).unwrap();
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+fn try_extend_bounded() {
+    let mut map = StableMap::with_max_len(2);
+    map.insert(1, 1);
+    assert_eq!(
+        map.try_extend_bounded([(1, 2), (2, 2), (3, 3), (4, 4)]),
+        Err(CapacityError { key: 3, value: 3 }),
+    );
+    assert_eq!(map.len(), 2);
+    assert_eq!(map[&1], 2);
+    assert_eq!(map[&2], 2);
+    assert!(map.is_full());
+    map.remove(&1);
+    assert!(!map.is_full());
+    assert_eq!(map.try_extend_bounded([(3, 3)]), Ok(()));
+}
+
+#[test]
+fn try_apply_bounded() {
+    let mut map = StableMap::with_max_len(1);
+    assert_eq!(
+        map.try_apply_bounded([
+            MapOp::Insert(1, 1),
+            MapOp::Update(1, 2),
+            MapOp::Insert(2, 2),
+            MapOp::Remove(1),
+        ]),
+        Err(CapacityError { key: 2, value: 2 }),
+    );
+    assert_eq!(map.len(), 1);
+    assert_eq!(map[&1], 2);
+    assert_eq!(
+        map.try_apply_bounded([MapOp::Remove(1), MapOp::Insert(2, 2)]),
+        Ok(()),
+    );
+    assert_eq!(map[&2], 2);
+}
+
+#[test]
+fn try_insert_bounded_entry() {
+    let mut map = StableMap::with_max_len(1);
+    map.insert(1, 1);
+    match map.entry(2) {
+        Entry::Vacant(v) => {
+            assert_eq!(
+                v.try_insert_bounded(2),
+                Err(CapacityError { key: 2, value: 2 }),
+            );
+        }
+        Entry::Occupied(_) => unreachable!(),
+    }
+    assert_eq!(map.len(), 1);
+    map.remove(&1);
+    match map.entry(2) {
+        Entry::Vacant(v) => assert_eq!(v.try_insert_bounded(2), Ok(&mut 2)),
+        Entry::Occupied(_) => unreachable!(),
+    }
+    assert_eq!(map[&2], 2);
+}
+
+#[test]
+fn invert() {
+    let mut map = StableMap::new();
+    for i in 0..16 {
+        map.insert(i, i % 4);
+    }
+    map.remove(&0);
+    let inverted: StableMap<_, _> = map.invert();
+    assert_eq!(inverted.len(), 4);
+    assert_eq!(inverted[&0], 4);
+    assert_eq!(inverted[&1], 1);
+    assert_eq!(inverted[&2], 2);
+    assert_eq!(inverted[&3], 3);
+    let mut map = StableMap::new();
+    map.set_max_vacant(0);
+    for i in 0..16 {
+        map.insert(i, i % 4);
+    }
+    let inverted: StableMap<_, _> = map.invert();
+    for i in 0..4 {
+        assert_eq!(inverted[&i], i);
+    }
+}
+
+#[test]
+fn get_all() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.insert(3, 33);
+    map.remove(&2);
+    assert_eq!(
+        map.get_all([&3, &2, &1, &3]).collect::<Vec<_>>(),
+        [Some(&33), None, Some(&11), Some(&33)],
+    );
+}
+
+#[test]
+fn insert_unique_debug_checked() {
+    let mut map = StableMap::new();
+    assert_eq!(map.insert_unique_debug_checked(1, 11), (&1, &mut 11));
+    assert_eq!(map.insert_unique_debug_checked(2, 22), (&2, &mut 22));
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&1), Some(&11));
+    assert_eq!(map.get(&2), Some(&22));
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic]
+fn insert_unique_debug_checked_duplicate() {
+    let mut map = StableMap::new();
+    map.insert_unique_debug_checked(1, 11);
+    map.insert_unique_debug_checked(1, 22);
+}
+
+#[test]
+fn insert_no_return() {
+    let mut map = StableMap::new();
+    assert!(!map.insert_no_return(1, 11));
+    assert!(!map.insert_no_return(2, 22));
+    assert!(map.insert_no_return(1, 33));
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.get(&1), Some(&33));
+    assert_eq!(map.get(&2), Some(&22));
+    assert_eq!(map.get_index(&1), Some(0));
+}
+
+#[test]
+fn map_hasher() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.insert(3, 33);
+    map.remove(&2);
+    let map = map.map_hasher(DefaultHashBuilder::default());
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.index_len(), 3);
+    assert_eq!(map.get(&1), Some(&11));
+    assert_eq!(map.get(&3), Some(&33));
+    assert_eq!(map.get_index(&1), Some(0));
+    assert_eq!(map.get_index(&3), Some(2));
+}
+
+#[test]
+fn amortized_compaction() {
+    let mut map = StableMap::new();
+    map.set_amortized_compaction(true);
+    for i in 0..16 {
+        map.insert(i, i);
+    }
+    for i in 0..8 {
+        map.remove(&i);
+    }
+    assert_eq!(map.index_len(), 16);
+    for i in 16..20 {
+        map.insert(i, i);
+    }
+    assert_eq!(map.len(), 12);
+    assert_eq!(map.index_len(), 12);
+    for i in 8..20 {
+        assert_eq!(map.get_by_index(map.get_index(&i).unwrap()), Some(&i));
+    }
+    assert!(map.clone().amortized_compaction());
+}
+
+#[test]
+fn max_vacant() {
+    let mut map = StableMap::new();
+    for i in 0..16 {
+        map.insert(i, i);
+    }
+    for i in 0..8 {
+        map.remove(&i);
+    }
+    assert_eq!(map.index_len(), 16);
+    map.set_max_vacant(2);
+    assert_eq!(map.index_len(), 10);
+    map.remove(&8);
+    map.remove(&9);
+    assert_eq!(map.index_len(), 6);
+    for i in 10..16 {
+        assert_eq!(map.get_by_index(map.get_index(&i).unwrap()), Some(&i));
+    }
+    assert_eq!(map.clone().max_vacant(), 2);
+}
+
+#[test]
+fn reuse_policy() {
+    let mut map = StableMap::new();
+    map.set_reuse_policy(ReusePolicy::Fifo);
+    for i in 0..6 {
+        map.insert(i, i);
+    }
+    map.remove(&3);
+    map.remove(&1);
+    map.remove(&5);
+    map.insert(6, 6);
+    map.insert(7, 7);
+    assert_eq!(map.get_index(&6), Some(3));
+    assert_eq!(map.get_index(&7), Some(1));
+    map.set_reuse_policy(ReusePolicy::Lifo);
+    map.remove(&0);
+    map.insert(8, 8);
+    map.insert(9, 9);
+    assert_eq!(map.get_index(&8), Some(0));
+    assert_eq!(map.get_index(&9), Some(5));
+    assert_eq!(map.clone().reuse_policy(), ReusePolicy::Lifo);
+}
+
+#[test]
+fn lowest_vacant_index() {
+    let mut map = StableMap::new();
+    assert_eq!(map.lowest_vacant_index(), None);
+    for i in 0..4 {
+        map.insert(i, i);
+    }
+    map.remove(&3);
+    map.remove(&1);
+    assert_eq!(map.lowest_vacant_index(), Some(1));
+    map.set_reuse_policy(ReusePolicy::Lifo);
+    assert_eq!(map.lowest_vacant_index(), Some(1));
+    map.insert(4, 4);
+    assert_eq!(map.get_index(&4), Some(3));
+    assert_eq!(map.lowest_vacant_index(), Some(1));
+    map.force_compact();
+    assert_eq!(map.lowest_vacant_index(), None);
+}
+
+#[test]
+fn try_insert_bounded() {
+    let mut map = StableMap::with_max_len(2);
+    assert_eq!(map.try_insert_bounded(1, 1), Ok(None));
+    assert_eq!(map.try_insert_bounded(2, 2), Ok(None));
+    assert_eq!(
+        map.try_insert_bounded(3, 3),
+        Err(CapacityError { key: 3, value: 3 }),
+    );
+    assert_eq!(map.try_insert_bounded(2, 4), Ok(Some(2)));
+    assert_eq!(map.len(), 2);
+    map.remove(&1);
+    assert_eq!(map.try_insert_bounded(3, 3), Ok(None));
+    assert_eq!(map.clone().max_len(), 2);
+    map.set_max_len(4);
+    map.insert(5, 5);
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.try_insert_bounded(6, 6), Ok(None));
+    assert!(map.try_insert_bounded(7, 7).is_err());
+    map.set_max_len(0);
+    assert_eq!(map.try_insert_bounded(6, 7), Ok(Some(6)));
+    assert_eq!(map.clone().len(), 4);
+}
+
+#[test]
+fn max_len() {
+    extern crate std;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut map = StableMap::with_max_len(2);
+    map.insert(1, 1);
+    map.insert(2, 2);
+    map.remove(&1);
+    map.insert(3, 3);
+    let inserts: [fn(&mut StableMap<i32, i32>); 6] = [
+        |map| {
+            map.insert(4, 4);
+        },
+        |map| {
+            map.entry(4).or_insert(4);
+        },
+        |map| {
+            map.entry_ref(&4).or_insert_owned(4);
+        },
+        |map| {
+            map.insert_with(4, |_| 4);
+        },
+        |map| map.extend([(4, 4)]),
+        |map| map.apply([MapOp::Insert(4, 4)]),
+    ];
+    for insert in inserts {
+        let res = catch_unwind(AssertUnwindSafe(|| insert(&mut map)));
+        assert!(res.is_err());
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.index_len(), 2);
+        assert_eq!(map.get(&4), None);
+        assert_eq!(map.get(&2), Some(&2));
+        assert_eq!(map.get(&3), Some(&3));
+    }
+    map.insert(2, 5);
+    map.entry(3).and_modify(|v| *v = 6).or_insert(0);
+    assert_eq!(map.get(&2), Some(&5));
+    assert_eq!(map.get(&3), Some(&6));
+}
+
+#[test]
+fn insert_within_capacity() {
+    let mut map = StableMap::with_capacity(16);
+    let spare = map.spare_capacity();
+    assert!(spare >= 16);
+    assert_eq!(map.insert_within_capacity(0, 0), Ok((0, None)));
+    for i in 1..spare {
+        map.insert(i, i);
+    }
+    let capacity = map.capacity();
+    assert_eq!(map.insert_within_capacity(spare, 0), Err((spare, 0)));
+    assert_eq!(map.insert_within_capacity(2, 5), Ok((2, Some(2))));
+    map.remove(&3);
+    map.remove(&1);
+    assert_eq!(map.insert_within_capacity(spare, 0), Ok((1, None)));
+    assert_eq!(map.insert_within_capacity(spare + 1, 1), Ok((3, None)));
+    assert_eq!(
+        map.insert_within_capacity(spare + 2, 2),
+        Err((spare + 2, 2))
+    );
+    assert_eq!(map.index_len(), spare);
+    assert_eq!(map.capacity(), capacity);
+    assert_eq!(map.get(&2), Some(&5));
+    assert_eq!(map.get(&spare), Some(&0));
+}
+
+#[test]
+fn insert_within_capacity_max_len() {
+    let mut map = StableMap::with_max_len(1);
+    map.insert(1, 1);
+    map.reserve(8);
+    assert_eq!(map.insert_within_capacity(2, 2), Err((2, 2)));
+    assert_eq!(map.insert_within_capacity(1, 2), Ok((0, Some(1))));
+    assert_eq!(map.len(), 1);
+}
+
+#[test]
+fn extend_index_space() {
+    let mut map = StableMap::new();
+    map.insert(0, 0);
+    assert_eq!(map.extend_index_space(3), 3);
+    assert_eq!(map.index_len(), 4);
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.lowest_vacant_index(), Some(1));
+    assert_eq!(map.insert_within_capacity(1, 1), Ok((1, None)));
+    map.set_max_vacant(1);
+    assert_eq!(map.index_len(), 2);
+    assert_eq!(map.extend_index_space(2), 1);
+    assert_eq!(map.index_len(), 3);
+    assert_eq!(map.lowest_vacant_index(), Some(2));
+    assert_eq!(map.extend_index_space(2), 0);
+    assert_eq!(map.index_len(), 3);
+    map.set_max_vacant(usize::MAX);
+    assert_eq!(map.extend_index_space(2), 2);
+    assert_eq!(map.index_len(), 5);
+}
+
+#[test]
+fn insert_at_index() {
+    let mut map = StableMap::new();
+    map.insert(0, 0);
+    assert_eq!(map.insert_at_index(3, 3, 3), Ok(&mut 3));
+    assert_eq!(map.index_len(), 4);
+    assert_eq!(map.insert_at_index(3, 4, 4), Err((4, 4)));
+    assert_eq!(map.insert_at_index(1, 3, 4), Err((3, 4)));
+    assert_eq!(map.insert_at_index(usize::MAX, 5, 5), Err((5, 5)));
+    assert_eq!(map.index_len(), 4);
+    assert_eq!(map.insert_at_index(2, 2, 2), Ok(&mut 2));
+    map.insert(1, 1);
+    for i in 0..4 {
+        assert_eq!(map.get_index(&i), Some(i));
+        assert_eq!(map.get_by_index(i), Some(&i));
+    }
+    map.set_max_vacant(1);
+    assert_eq!(map.insert_at_index(6, 6, 6), Err((6, 6)));
+    assert_eq!(map.index_len(), 4);
+    assert_eq!(map.insert_at_index(5, 5, 5), Ok(&mut 5));
+    assert_eq!(map.index_len(), 6);
+}
+
+#[test]
+fn insert_at_index_max_len() {
+    let mut map = StableMap::with_max_len(1);
+    assert_eq!(map.insert_at_index(1, 1, 1), Ok(&mut 1));
+    assert_eq!(map.insert_at_index(0, 2, 2), Err((2, 2)));
+    assert_eq!(map.insert_at_index(3, 2, 2), Err((2, 2)));
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.index_len(), 2);
+    map.remove(&1);
+    assert_eq!(map.insert_at_index(0, 2, 2), Ok(&mut 2));
+}
+
+#[test]
+fn from_indexed_iter() {
+    let map = StableMap::<_, _>::from_indexed_iter([(4, 4, 4), (0, 0, 0), (2, 2, 2)]).unwrap();
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.index_len(), 5);
+    for i in [0, 2, 4] {
+        assert_eq!(map.get_index(&i), Some(i));
+        assert_eq!(map.get_by_index(i), Some(&i));
+    }
+    let res = StableMap::<_, _>::from_indexed_iter([(0, 0, 0), (1, 0, 1)]);
+    assert_eq!(res.unwrap_err(), (1, 0, 1));
+    let res = StableMap::<_, _>::from_indexed_iter([(0, 0, 0), (0, 1, 1)]);
+    assert_eq!(res.unwrap_err(), (0, 1, 1));
+    for index in [usize::MAX, usize::MAX / 2] {
+        let res = StableMap::<_, _>::from_indexed_iter([(0, 0, 0), (index, 1, 1)]);
+        assert_eq!(res.unwrap_err(), (index, 1, 1));
+    }
+}
+
+#[test]
+fn into_indexed_vec() {
+    let mut map = StableMap::new();
+    for i in 0..8 {
+        map.insert(i, i * 10);
+    }
+    for i in [1, 4, 5] {
+        map.remove(&i);
+    }
+    let vec = map.into_indexed_vec();
+    assert_eq!(
+        vec,
+        [(0, 0, 0), (2, 2, 20), (3, 3, 30), (6, 6, 60), (7, 7, 70)],
+    );
+    let map = StableMap::<_, _>::from_indexed_iter(vec).unwrap();
+    assert_eq!(map.index_len(), 8);
+    for i in [0, 2, 3, 6, 7] {
+        assert_eq!(map.get_index(&i), Some(i));
+        assert_eq!(map.get(&i), Some(&(i * 10)));
+    }
+}
+
+#[test]
+fn retain_map() {
+    let mut map = StableMap::new();
+    for i in 0..8 {
+        map.insert(i, Box::new(i));
+    }
+    map.remove(&0);
+    map.retain_map(|&k, v| (k % 3 != 0).then(|| Box::new(*v * 10)));
+    assert_eq!(map.len(), 5);
+    assert_eq!(map.index_len(), 8);
+    for i in [1, 2, 4, 5, 7] {
+        assert_eq!(map.get_index(&i), Some(i));
+        assert_eq!(*map[&i], i * 10);
+    }
+    map.insert(8, Box::new(8));
+    assert_eq!(map.get_index(&8), Some(0));
+}
+
+#[test]
+fn retain_map_panic() {
+    extern crate std;
+    use {
+        alloc::rc::Rc,
+        std::panic::{catch_unwind, AssertUnwindSafe},
+    };
+
+    let value = Rc::new(());
+    let mut map = StableMap::new();
+    for i in 0..8 {
+        map.insert(i, value.clone());
+    }
+    let mut visited = 0;
+    let res = catch_unwind(AssertUnwindSafe(|| {
+        map.retain_map(|&k, v| {
+            visited += 1;
+            if visited == 4 {
+                panic!();
+            }
+            (k % 2 == 0).then_some(v)
+        });
+    }));
+    assert!(res.is_err());
+    assert!(map.len() < 8);
+    assert_eq!(Rc::strong_count(&value), map.len() + 1);
+    for (k, v) in &map {
+        assert!(Rc::ptr_eq(v, &value));
+        assert_eq!(map.get(k).map(|v| Rc::ptr_eq(v, &value)), Some(true));
+    }
+    map.insert(8, value.clone());
+    drop(map);
+    assert_eq!(Rc::strong_count(&value), 1);
+}
+
+#[test]
+fn apply() {
+    let mut map = StableMap::new();
+    for i in 0..4 {
+        map.insert(i, i);
+    }
+    map.apply([
+        MapOp::Remove(1),
+        MapOp::Insert(4, 4),
+        MapOp::Insert(2, 20),
+        MapOp::Update(3, 30),
+        MapOp::Update(5, 50),
+        MapOp::Remove(6),
+    ]);
+    assert_eq!(map.len(), 4);
+    assert_eq!(map.get_index(&4), Some(1));
+    assert_eq!(map[&2], 20);
+    assert_eq!(map[&3], 30);
+    assert_eq!(map.get(&5), None);
+}
+
+#[test]
+fn reserve_index_capacity() {
+    let mut map = StableMap::new();
+    map.insert(0, 0);
+    map.insert(1, 1);
+    map.remove(&0);
+    let capacity = map.key_to_pos.capacity();
+    map.reserve_index_capacity(10);
+    assert!(map.index_capacity() >= 12);
+    assert_eq!(map.key_to_pos.capacity(), capacity);
+}
+
+#[test]
+fn index_high_water_mark() {
+    let mut map = StableMap::new();
+    assert_eq!(map.index_high_water_mark(), 0);
+    for i in 0..4 {
+        map.insert(i, i);
+    }
+    map.remove(&0);
+    map.remove(&1);
+    map.force_compact();
+    assert_eq!(map.index_len(), 2);
+    assert_eq!(map.index_high_water_mark(), 4);
+    map.reset_index_high_water_mark();
+    assert_eq!(map.index_high_water_mark(), 2);
+    map.extend_index_space(3);
+    assert_eq!(map.index_high_water_mark(), 5);
+    map.insert_at_index(7, 7, 7).unwrap();
+    assert_eq!(map.index_high_water_mark(), 8);
+}
+
+#[test]
+fn key_set() {
+    let mut map = StableMap::new();
+    for i in 0..4 {
+        map.insert(i, i);
+    }
+    map.remove(&2);
+    let mut keys: Vec<_> = map.key_set().into_iter().collect();
+    keys.sort_unstable();
+    assert_eq!(keys, [0, 1, 3]);
+}
+
+#[test]
+fn remove_keys_in() {
+    let mut map: StableMap<_, _> = (0..8).map(|x| (x, x)).collect();
+    let keys: HashSet<_> = [1, 2].into_iter().collect();
+    map.remove_keys_in(&keys);
+    assert_eq!(map.len(), 6);
+    let keys: HashSet<_> = (0..16).filter(|x| x % 2 == 0).collect();
+    map.remove_keys_in(&keys);
+    let mut keys: Vec<_> = map.keys().copied().collect();
+    keys.sort_unstable();
+    assert_eq!(keys, [3, 5, 7]);
+}
+
+#[test]
+fn retain_keys_in() {
+    let mut map: StableMap<_, _> = (0..8).map(|x| (x, x)).collect();
+    let keys: HashSet<_> = [1, 2, 9].into_iter().collect();
+    map.retain_keys_in(&keys);
+    let mut keys: Vec<_> = map.keys().copied().collect();
+    keys.sort_unstable();
+    assert_eq!(keys, [1, 2]);
+    map.retain_keys_in(&HashSet::new());
+    assert!(map.is_empty());
+}
+
+#[test]
+fn extend_keep_existing() {
+    let mut map = StableMap::new();
+    map.insert(1, 1);
+    map.insert(2, 2);
+    map.remove(&1);
+    map.extend_keep_existing([(1, 10), (2, 20), (3, 30), (3, 31)]);
+    assert_eq!(map.len(), 3);
+    assert_eq!(map[&1], 10);
+    assert_eq!(map[&2], 2);
+    assert_eq!(map[&3], 30);
+    assert_eq!(map.get_index(&1), Some(0));
+}
+
+#[test]
+fn upsert_with() {
+    let mut map = StableMap::new();
+    assert_eq!(*map.upsert_with(1, || 10, |_| unreachable!()), 10);
+    assert_eq!(*map.upsert_with(1, || unreachable!(), |v| *v += 1), 11);
+    *map.upsert_with(2, || 20, |_| unreachable!()) += 1;
+    assert_eq!(map[&1], 11);
+    assert_eq!(map[&2], 21);
+}
+
+#[test]
+fn get_key() {
+    let mut map = StableMap::new();
+    let key = Box::new(1);
+    let addr: *const i32 = &*key;
+    map.insert(key, 1);
+    map.insert(Box::new(1), 2);
+    let stored: *const i32 = &**map.get_key(&1).unwrap();
+    assert_eq!(stored, addr);
+    assert_eq!(map.get_key(&2), None);
+}
+
+#[test]
+fn insert_full_replace() {
+    let mut map = StableMap::new();
+    map.insert(Box::new(0), 0);
+    assert_eq!(map.insert_full_replace(Box::new(1), 1), None);
+    let key = Box::new(1);
+    let addr: *const i32 = &*key;
+    assert_eq!(map.insert_full_replace(key, 2), Some((Box::new(1), 1)));
+    let stored: *const i32 = &**map.get_key(&1).unwrap();
+    assert_eq!(stored, addr);
+    assert_eq!(map[&1], 2);
+    assert_eq!(map.get_index(&1), Some(1));
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn clone_with_hasher() {
+    let mut map = StableMap::new();
+    for i in 0..6 {
+        map.insert(i, i * 10);
+    }
+    map.remove(&0);
+    map.remove(&3);
+    map.remove(&5);
+    map.set_reuse_policy(ReusePolicy::Lifo);
+    map.set_max_len(2);
+    let clone = map.clone_with_hasher(DefaultHashBuilder::default());
+    assert_eq!(clone.len(), 3);
+    assert_eq!(clone.index_len(), map.index_len());
+    assert_eq!(clone.reuse_policy(), ReusePolicy::Lifo);
+    assert_eq!(clone.max_len(), 2);
+    for (k, v) in &map {
+        assert_eq!(clone.get_index(k), map.get_index(k));
+        assert_eq!(clone[k], *v);
+    }
+}
+
+#[test]
+fn subset_keys() {
+    let mut a = StableMap::new();
+    let mut b = StableMap::new();
+    assert!(a.is_subset_keys(&b));
+    assert!(a.is_superset_keys(&b));
+    a.insert(1, ());
+    b.insert(1, 1);
+    b.insert(2, 2);
+    assert!(a.is_subset_keys(&b));
+    assert!(!a.is_superset_keys(&b));
+    assert!(b.is_superset_keys(&a));
+    a.insert(3, ());
+    assert!(!a.is_subset_keys(&b));
+    assert!(!b.is_superset_keys(&a));
+}
+
+#[test]
+fn auto_shrink() {
+    let mut map = StableMap::new();
+    for i in 0..64 {
+        map.insert(i, i);
+    }
+    for i in 16..64 {
+        map.remove(&i);
+    }
+    map.force_compact();
+    assert!(map.index_capacity() >= 64);
+    map.set_auto_shrink(true);
+    assert!(map.clone().auto_shrink());
+    for i in 0..16 {
+        map.insert(i + 100, i);
+    }
+    for i in 0..16 {
+        map.remove(&i);
+    }
+    for i in 100..108 {
+        map.remove(&i);
+    }
+    map.force_compact();
+    assert_eq!(map.len(), 8);
+    assert_eq!(map.index_len(), 8);
+    assert!(map.index_capacity() < 64);
+    for i in 108..116 {
+        assert_eq!(map[&i], i - 100);
+    }
+}
+
+#[test]
+fn find_key() {
+    let mut map = StableMap::new();
+    for i in 0..8 {
+        map.insert(i, i * 10);
+    }
+    map.remove(&2);
+    assert_eq!(map.find_key(|v| *v == 30), Some((&3, 3)));
+    assert_eq!(map.find_key(|v| *v == 20), None);
+    let mut found: Vec<_> = map.find_all(|v| *v >= 50).collect();
+    found.sort_unstable();
+    assert_eq!(found, [(&5, 5), (&6, 6), (&7, 7)]);
+}
+
+#[test]
+fn join() {
+    let mut a = StableMap::new();
+    let mut b = StableMap::new();
+    for i in 0..4 {
+        a.insert(i, i * 10);
+    }
+    for i in 2..6 {
+        b.insert(i, i * 100);
+    }
+    let mut joined: Vec<_> = a.join(&b).collect();
+    joined.sort_unstable();
+    assert_eq!(joined, [(&2, &20, &200), (&3, &30, &300)]);
+    let mut joined: Vec<_> = a.left_join(&b).collect();
+    joined.sort_unstable();
+    assert_eq!(
+        joined,
+        [
+            (&0, &0, None),
+            (&1, &10, None),
+            (&2, &20, Some(&200)),
+            (&3, &30, Some(&300)),
+        ],
+    );
+    let mut joined: Vec<_> = a.outer_join(&b).collect();
+    joined.sort_unstable();
+    assert_eq!(
+        joined,
+        [
+            (&0, Some(&0), None),
+            (&1, Some(&10), None),
+            (&2, Some(&20), Some(&200)),
+            (&3, Some(&30), Some(&300)),
+            (&4, None, Some(&400)),
+            (&5, None, Some(&500)),
+        ],
+    );
+}
+
+#[test]
+fn snapshot_indexed_values() {
+    let mut map = StableMap::new();
+    for i in 0..5 {
+        map.insert(i, Box::new(i * 10));
+    }
+    map.remove(&0);
+    map.remove(&3);
+    let snapshot = map.snapshot_indexed_values();
+    map.clear();
+    assert_eq!(
+        snapshot,
+        [(1, Box::new(10)), (2, Box::new(20)), (4, Box::new(40))],
+    );
+}
+
+#[test]
+fn indices_snapshot() {
+    let mut map = StableMap::new();
+    assert!(map.indices_snapshot().is_empty());
+    for i in 0..5 {
+        map.insert(i, i);
+    }
+    map.remove(&0);
+    map.remove(&3);
+    assert_eq!(map.indices_snapshot(), [1, 2, 4]);
+    map.force_compact();
+    assert_eq!(map.indices_snapshot(), [0, 1, 2]);
+}
+
+#[test]
+fn get_many_mut_checked() {
+    let mut map = StableMap::new();
+    for i in 0..4 {
+        map.insert(i, i * 10);
+    }
+    let [a, b, c] = map.get_many_mut_checked([&0, &5, &3]).unwrap();
+    assert_eq!(a, Some(&mut 0));
+    assert_eq!(b, None);
+    *c.unwrap() += 1;
+    assert_eq!(map[&3], 31);
+    assert_eq!(
+        map.get_many_mut_checked([&5, &1, &5, &2, &1]),
+        Err(OverlapError {
+            first: 1,
+            second: 4,
+        }),
+    );
+}
+
+#[test]
+fn get_many_mut_checked_unstable_hash() {
+    // Resolves to a different key every time it is hashed.
+    struct Flaky {
+        keys: [u32; 2],
+        calls: Cell<usize>,
+        current: Cell<u32>,
+    }
+
+    impl core::hash::Hash for Flaky {
+        fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+            let n = self.calls.get();
+            self.calls.set(n + 1);
+            let key = self.keys[n.min(1)];
+            self.current.set(key);
+            key.hash(state);
+        }
+    }
+
+    impl hashbrown::Equivalent<u32> for Flaky {
+        fn equivalent(&self, key: &u32) -> bool {
+            *key == self.current.get()
+        }
+    }
+
+    let flaky = |keys| Flaky {
+        keys,
+        calls: Cell::new(0),
+        current: Cell::new(0),
+    };
+    let mut map = StableMap::new();
+    map.insert(0u32, 0);
+    map.insert(1u32, 1);
+    let a = flaky([0, 0]);
+    let b = flaky([1, 0]);
+    let [x, y] = map.get_many_mut_checked([&a, &b]).unwrap();
+    let (x, y) = (x.unwrap(), y.unwrap());
+    assert!(!core::ptr::eq(x, y));
+    assert_eq!((*x, *y), (0, 1));
+}
+
+#[test]
+fn handle() {
+    let mut map = StableMap::new();
+    for i in 0..4 {
+        map.insert(i, i * 10);
+    }
+    let h0 = map.get_handle(&0).unwrap();
+    let h1 = map.get_handle(&1).unwrap();
+    let h3 = map.get_handle(&3).unwrap();
+    assert_eq!(map.get_handle(&4), None);
+    assert_eq!(map.get_by_handle(h1), Some(&10));
+    *map.get_by_handle_mut(h0).unwrap() += 1;
+    assert_eq!(map[&0], 1);
+    map.remove(&1);
+    assert_eq!(map.get_by_handle(h1), None);
+    map.insert(1, 11);
+    assert_eq!(map.get_index(&1), Some(1));
+    assert_eq!(map.get_by_handle(h1), None);
+    assert_eq!(map.get_by_handle_mut(h1), None);
+    map.remove(&1);
+    map.force_compact();
+    assert_eq!(map.get_index(&3), Some(1));
+    assert_eq!(map.get_by_handle(h3), None);
+    assert_eq!(map.get_by_handle(h0), Some(&1));
+    map.clear();
+    map.insert(0, 0);
+    assert_eq!(map.get_by_handle(h0), None);
+}
+
+#[test]
+fn pop_index() {
+    let mut map = StableMap::new();
+    for i in 0..6 {
+        map.insert(i, i);
+    }
+    map.remove(&0);
+    map.remove(&5);
+    assert_eq!(map.pop_lowest_index(), Some((1, 1)));
+    assert_eq!(map.pop_highest_index(), Some((4, 4)));
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.pop_lowest_index(), Some((2, 2)));
+    assert_eq!(map.pop_lowest_index(), Some((3, 3)));
+    assert_eq!(map.pop_lowest_index(), None);
+    assert_eq!(map.pop_highest_index(), None);
+    assert!(map.is_empty());
+}
+
+#[test]
+fn for_each_unlocked() {
+    let map = RefCell::new(StableMap::new());
+    for i in 0..4 {
+        map.borrow_mut().insert(i, i);
+    }
+    let mut visited = Vec::new();
+    let guard = StableMap::for_each_unlocked(
+        map.borrow_mut(),
+        || map.borrow_mut(),
+        |v| {
+            visited.push(v);
+            let mut map = map.borrow_mut();
+            if v == 0 {
+                map.remove(&1);
+                map.insert(4, 4);
+            }
+        },
+    );
+    assert_eq!(guard.len(), 4);
+    drop(guard);
+    assert_eq!(visited, [0, 4, 2, 3]);
+}
+
+#[test]
+fn spare_capacity() {
+    let mut map = StableMap::with_capacity(16);
+    for i in 0..8 {
+        map.insert(i, i);
+    }
+    for i in 0..4 {
+        map.remove(&i);
+    }
+    let spare = map.spare_capacity();
+    assert!(spare >= 8);
+    let key_capacity = map.key_to_pos.capacity();
+    let storage_capacity = map.storage.capacity();
+    for i in 8..8 + spare {
+        map.insert(i, i);
+    }
+    assert_eq!(map.key_to_pos.capacity(), key_capacity);
+    assert_eq!(map.storage.capacity(), storage_capacity);
+}
+
+#[test]
+fn index_chunks() {
+    let mut map = StableMap::new();
+    assert_eq!(map.index_chunks(3).count(), 0);
+    for i in 0..10 {
+        map.insert(i, i);
+    }
+    map.remove(&9);
+    map.remove(&8);
+    let chunks: Vec<_> = map.index_chunks(3).collect();
+    assert_eq!(chunks, [0..3, 3..6, 6..10]);
+    let chunks: Vec<_> = map.index_chunks(100).collect();
+    assert_eq!(chunks.len(), 8);
+    assert_eq!(chunks[7], 7..10);
+}
+
+#[test]
+fn check_index() {
+    let mut map = StableMap::new();
+    map.insert(1, 1);
+    map.insert(2, 2);
+    assert!(map.check_index(&1, 0));
+    assert!(map.check_index(&2, 1));
+    assert!(!map.check_index(&1, 1));
+    assert!(!map.check_index(&3, 0));
+    map.remove(&1);
+    assert!(!map.check_index(&1, 0));
+    map.force_compact();
+    assert!(map.check_index(&2, 0));
+}
+
+#[test]
+fn get_index_many() {
+    let mut map = StableMap::new();
+    map.insert(1, 1);
+    map.insert(2, 2);
+    map.insert(3, 3);
+    map.remove(&2);
+    assert_eq!(
+        map.get_index_many([&3, &2, &1, &3]),
+        [Some(2), None, Some(0), Some(2)]
+    );
+    assert_eq!(map.get_index_many::<i32, 0>([]), []);
+}
+
+#[test]
+fn remove_if() {
+    let mut map = StableMap::new();
+    map.insert(1, 10);
+    map.insert(2, 20);
+    assert_eq!(map.remove_if(&1, |k, v| *k == 1 && *v == 11), None);
+    assert_eq!(map.get(&1), Some(&10));
+    assert_eq!(map.remove_if(&1, |k, v| *k == 1 && *v == 10), Some(10));
+    assert_eq!(map.get(&1), None);
+    assert_eq!(map.remove_if(&1, |_, _| panic!()), None);
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.index_len(), 2);
+}
+
+#[test]
+fn occupied_entry() {
+    let mut map = StableMap::new();
+    map.insert(1, 10);
+    assert!(map.occupied_entry(&2).is_none());
+    let mut entry = map.occupied_entry(&1).unwrap();
+    assert_eq!(entry.key(), &1);
+    assert_eq!(entry.insert(11), 10);
+    assert_eq!(map[&1], 11);
+    assert_eq!(map.occupied_entry(&1).unwrap().remove_entry(), (1, 11));
+    assert!(map.is_empty());
+}
+
+#[test]
+fn compare_exchange() {
+    let mut map = StableMap::new();
+    map.insert(1, 10);
+    assert_eq!(map.compare_exchange(&1, &11, 12), Err(Some(&10)));
+    assert_eq!(map.compare_exchange(&2, &10, 12), Err(None));
+    assert_eq!(map[&1], 10);
+    assert_eq!(map.compare_exchange(&1, &10, 12), Ok(10));
+    assert_eq!(map[&1], 12);
+    assert_eq!(map.get_index(&1), Some(0));
+}
+
+#[test]
+fn allocator() {
+    #[derive(Clone, Copy)]
+    struct Counting<'a>(&'a Cell<isize>);
+
+    unsafe impl Allocator for Counting<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.set(self.0.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.set(self.0.get() - 1);
+            unsafe { Global.deallocate(ptr, layout) }
+        }
+    }
+
+    let live = Cell::new(0);
+    let mut map = StableMap::with_hasher_in(DefaultHashBuilder::default(), Counting(&live));
+    assert_eq!(live.get(), 0);
+    for i in 0..100 {
+        map.insert(i, i);
+    }
+    assert!(live.get() > 0);
+    assert!(core::ptr::eq(map.allocator().0, &live));
+    map.remove(&1);
+    map.force_compact();
+    let clone = map.clone();
+    assert_eq!(clone, map);
+    drop(clone);
+    assert_eq!(map.drain().count(), 99);
+    drop(map);
+    assert_eq!(live.get(), 0);
+}
+
+#[test]
+fn try_reserve() {
+    #[derive(Clone, Copy)]
+    struct Failing;
+
+    unsafe impl Allocator for Failing {
+        fn allocate(&self, _layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            Err(AllocError)
+        }
+
+        unsafe fn deallocate(&self, _ptr: NonNull<u8>, _layout: Layout) {
+            unreachable!();
+        }
+    }
+
+    let mut map = StableMap::<i32, i32>::new();
+    assert_eq!(
+        map.try_reserve(usize::MAX),
+        Err(TryReserveError::CapacityOverflow),
+    );
+    assert_eq!(map.try_reserve(10), Ok(()));
+    assert!(map.capacity() >= 10);
+    assert!(map.index_capacity() >= 10);
+
+    let mut map: StableMap<i32, i32, _, _> =
+        StableMap::with_hasher_in(DefaultHashBuilder::default(), Failing);
+    assert!(matches!(
+        map.try_reserve(1),
+        Err(TryReserveError::AllocError { .. }),
+    ));
+    assert_eq!(map.capacity(), 0);
+}
+
+#[test]
+fn get_full() {
+    let mut map = StableMap::new();
+    map.insert(1, 10);
+    map.insert(2, 20);
+    map.insert(3, 30);
+    map.remove(&1);
+    assert_eq!(map.get_full(&1), None);
+    assert_eq!(map.get_full(&3), Some((2, &3, &30)));
+    let (index, k, v) = map.get_full_mut(&2).unwrap();
+    assert_eq!((index, *k), (1, 2));
+    *v = 21;
+    assert_eq!(map.get_by_index(1), Some(&21));
+    assert_eq!(map.get_full_mut(&1), None);
+    map.force_compact();
+    assert_eq!(map.get_full(&3).map(|f| f.0), map.get_index(&3));
+}
+
+#[test]
+fn get_key_by_index() {
+    let mut map = StableMap::new();
+    map.insert(1, 10);
+    map.insert(2, 20);
+    map.insert(3, 30);
+    assert_eq!(map.get_key_by_index(1), Some(&2));
+    assert_eq!(map.get_key_by_index(3), None);
+    map.remove(&2);
+    assert_eq!(map.get_key_by_index(1), None);
+    map.force_compact();
+    assert_eq!(map.get_key_by_index(1), Some(&3));
+    assert_eq!(map.get_key_by_index(2), None);
+}
+
+#[test]
+fn get_key_by_index_after_rehash() {
+    let mut map = StableMap::new();
+    for i in 0..32 {
+        map.insert(i, i * 10);
+    }
+    map.entry(7).and_replace_entry_with(|_, v| Some(v + 1));
+    map.insert_full_replace(8, 81);
+    let map = map.map_hasher(DefaultHashBuilder::default());
+    for i in 0..32 {
+        let index = map.get_index(&i).unwrap();
+        assert_eq!(map.get_key_by_index(index), Some(&i));
+    }
+}
+
+#[test]
+fn contains_index() {
+    let mut map = StableMap::new();
+    map.insert(1, 10);
+    map.insert(2, 20);
+    assert!(map.contains_index(0));
+    assert!(map.contains_index(1));
+    assert!(!map.contains_index(2));
+    map.remove(&1);
+    assert!(!map.contains_index(0));
+    map.force_compact();
+    assert!(map.contains_index(0));
+    assert!(!map.contains_index(1));
+}
+
+#[test]
+fn next_occupied_index() {
+    let mut map = StableMap::new();
+    assert_eq!(map.next_occupied_index(0), None);
+    for i in 0..8 {
+        map.insert(i, i);
+    }
+    for i in [1, 2, 3, 6] {
+        map.remove(&i);
+    }
+    let mut visited = Vec::new();
+    let mut index = 0;
+    while let Some(i) = map.next_occupied_index(index) {
+        visited.push(i);
+        index = i;
+    }
+    assert_eq!(visited, [4, 5, 7]);
+    assert_eq!(map.next_occupied_index(usize::MAX), None);
+}
+
+#[test]
+fn first_last_index() {
+    let mut map = StableMap::new();
+    assert_eq!(map.first_index(), None);
+    assert_eq!(map.last_index(), None);
+    for i in 0..5 {
+        map.insert(i, i);
+    }
+    assert_eq!(map.first_index(), Some(0));
+    assert_eq!(map.last_index(), Some(4));
+    map.remove(&0);
+    map.remove(&4);
+    map.remove(&3);
+    assert_eq!(map.first_index(), Some(1));
+    assert_eq!(map.last_index(), Some(2));
+    assert_eq!(map.index_len(), 5);
+    map.clear();
+    assert_eq!(map.first_index(), None);
+    assert_eq!(map.last_index(), None);
+}
+
+#[test]
+fn insert_with() {
+    let mut map = StableMap::new();
+    for i in 0..4 {
+        assert_eq!(map.insert_with(i, |index| index), &mut (i as usize));
+    }
+    map.remove(&1);
+    map.remove(&2);
+    assert_eq!(map.insert_with(4, |index| index + 10), &mut 11);
+    assert_eq!(map.insert_with(0, |index| index + 20), &mut 20);
+    assert_eq!(map.len(), 3);
+    map.set_amortized_compaction(true);
+    let index = *map.insert_with(5, |index| index);
+    assert_eq!(map.get_index(&5), Some(index));
+    assert_eq!(map.get_by_index(index), Some(&index));
+
+    let mut map = StableMap::new();
+    map.set_reuse_policy(ReusePolicy::Fifo);
+    map.insert("a", 0);
+    map.insert("b", 0);
+    map.insert("c", 0);
+    map.remove("b");
+    map.remove("a");
+    map.remove("c");
+    map.set_amortized_compaction(true);
+    let index = *map.insert_with("d", |index| index);
+    assert_eq!(index, 1);
+    assert_eq!(map.get_index("d"), Some(index));
+}
+
+#[test]
+fn insert_with_panic() {
+    extern crate std;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+
+    let mut map = StableMap::new();
+    for i in 0..20 {
+        map.insert(i, i);
+    }
+    for i in 0..19 {
+        map.remove(&i);
+    }
+    map.set_amortized_compaction(true);
+    let res = catch_unwind(AssertUnwindSafe(|| {
+        map.insert_with(20, |_| panic!());
+    }));
+    assert!(res.is_err());
+    assert_eq!(map.index_len(), 20);
+    assert_eq!(map.get_index(&19), Some(19));
+    assert_eq!(map.get(&20), None);
+    let mut map = StableMap::new();
+    map.insert(0, 0);
+    let res = catch_unwind(AssertUnwindSafe(|| {
+        map.insert_with(1, |_| panic!());
+    }));
+    assert!(res.is_err());
+    assert_eq!(map.index_len(), 1);
+    assert_eq!(map.insert_with(1, |index| index), &mut 1);
+}
+
+#[cfg(feature = "cached-hash")]
+#[test]
+fn cached_hash() {
+    #[derive(PartialEq, Eq, Clone)]
+    struct Counted<'a>(u32, &'a Cell<usize>);
+
+    impl core::hash::Hash for Counted<'_> {
+        fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+            self.1.set(self.1.get() + 1);
+            self.0.hash(state);
+        }
+    }
+
+    let hashes = Cell::new(0);
+    let mut map = StableMap::new();
+    for i in 0..100 {
+        map.insert(Counted(i, &hashes), i);
+    }
+    assert_eq!(hashes.get(), 100);
+    map.reserve(1000);
+    map.shrink_to_fit();
+    let clone = map.clone();
+    map.remove(&Counted(0, &hashes));
+    assert_eq!(hashes.get(), 101);
+    assert_eq!(clone.len(), 100);
+    assert_eq!(clone[&Counted(1, &hashes)], 1);
+    assert_eq!(map.get_key_by_index(1).map(|k| k.0), Some(1));
+}
@@ -0,0 +1,17 @@
+/// An operation that can be applied to a `StableMap`.
+///
+/// Batches of operations are applied by the [`apply`] method on [`StableMap`]. See its
+/// documentation for more.
+///
+/// [`apply`]: crate::StableMap::apply
+/// [`StableMap`]: crate::StableMap
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MapOp<K, V> {
+    /// Inserts a key-value pair into the map, replacing the value if the key is already
+    /// contained in the map.
+    Insert(K, V),
+    /// Removes a key from the map if it is contained in the map.
+    Remove(K),
+    /// Replaces the value of a key if the key is contained in the map.
+    Update(K, V),
+}
@@ -0,0 +1,665 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::{
+        linear_storage::LinearStorage,
+        pos_vec::pos::{InUse, Pos},
+    },
+    alloc::vec::Vec,
+    allocator_api2::{
+        alloc::{Allocator, Global},
+        vec::Vec as AllocVec,
+    },
+    core::{
+        fmt::{Debug, Formatter},
+        hash::{BuildHasher, Hash},
+    },
+    hashbrown::{hash_map, DefaultHashBuilder, Equivalent, HashMap},
+};
+
+/// A multi-map with temporarily-stable indices.
+///
+/// Each key maps to one or more values. Each value has its own index which stays the
+/// same unless the value is removed from the map or the map is explicitly compacted.
+/// Compaction changes the indices of values but never the key a value belongs to.
+///
+/// Each value is stored together with a clone of its key so that it can be removed by
+/// its index.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMultiMap;
+///
+/// let mut map = StableMultiMap::new();
+/// let a = map.insert("x", 1);
+/// let b = map.insert("x", 2);
+/// map.insert("y", 3);
+///
+/// assert_eq!(map.get_all("x").collect::<Vec<_>>(), [(a, &1), (b, &2)]);
+/// assert_eq!(map.remove_value_by_index(a), Some(("x", 1)));
+/// assert_eq!(map.get_all("x").collect::<Vec<_>>(), [(b, &2)]);
+/// assert_eq!(map.len(), 2);
+/// ```
+//
+// This type upholds the following invariants:
+//
+// - key_to_pos contains only valid Pos<InUse> returned by storage.
+// - None of the vectors in key_to_pos is empty.
+// - The key stored next to each value is equal to the key under which its Pos<InUse>
+//   is stored in key_to_pos.
+//
+// SAFETY:
+// - LinearStorage::clear invalidates existing Pos<InUse> without consuming them.
+// - Code calling LinearStorage::clear must explain how it upholds the invariant.
+pub struct StableMultiMap<K, V, S = DefaultHashBuilder, A: Allocator = Global> {
+    key_to_pos: HashMap<K, AllocVec<Pos<InUse>, A>, S, A>,
+    storage: LinearStorage<(K, V), A>,
+}
+
+#[cfg(feature = "default-hasher")]
+impl<K, V> StableMultiMap<K, V, DefaultHashBuilder> {
+    /// Creates an empty `StableMultiMap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    /// let mut map: StableMultiMap<&str, i32> = StableMultiMap::new();
+    /// assert_eq!(map.len(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn new() -> Self {
+        Self::with_hasher(DefaultHashBuilder::default())
+    }
+}
+
+#[cfg(feature = "default-hasher")]
+impl<K, V, A: Allocator + Clone> StableMultiMap<K, V, DefaultHashBuilder, A> {
+    /// Creates an empty `StableMultiMap` using the given allocator.
+    ///
+    /// The map is initially created with a capacity of 0, so it will not allocate until it
+    /// is first inserted into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use allocator_api2::alloc::Global;
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map: StableMultiMap<&str, i32, _, _> = StableMultiMap::new_in(Global);
+    /// assert_eq!(map.len(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn new_in(alloc: A) -> Self {
+        Self::with_capacity_and_hasher_in(0, DefaultHashBuilder::default(), alloc)
+    }
+
+    /// Creates an empty `StableMultiMap` with the specified capacity using the given
+    /// allocator.
+    ///
+    /// The map will be able to hold at least `capacity` values without reallocating its
+    /// storage. If `capacity` is 0, the map will not allocate.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use allocator_api2::alloc::Global;
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map: StableMultiMap<&str, i32, _, _> = StableMultiMap::with_capacity_in(10, Global);
+    /// assert_eq!(map.len(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self::with_capacity_and_hasher_in(capacity, DefaultHashBuilder::default(), alloc)
+    }
+}
+
+impl<K, V, S> StableMultiMap<K, V, S> {
+    /// Creates an empty `StableMultiMap` which will use the given hash builder to hash
+    /// keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use hashbrown::DefaultHashBuilder;
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::with_hasher(DefaultHashBuilder::default());
+    /// map.insert(1, 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_hasher(hash_builder: S) -> Self {
+        Self {
+            key_to_pos: HashMap::with_hasher(hash_builder),
+            storage: LinearStorage::with_capacity(0),
+        }
+    }
+}
+
+impl<K, V, S, A: Allocator + Clone> StableMultiMap<K, V, S, A> {
+    /// Creates an empty `StableMultiMap` with the specified capacity, using
+    /// `hash_builder` to hash the keys and `alloc` to allocate memory.
+    ///
+    /// The hash map, the vectors of positions, and the storage of the values are
+    /// allocated with `alloc`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use allocator_api2::alloc::Global;
+    /// use hashbrown::DefaultHashBuilder;
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let s = DefaultHashBuilder::default();
+    /// let mut map = StableMultiMap::with_capacity_and_hasher_in(10, s, Global);
+    /// map.insert(1, 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_capacity_and_hasher_in(capacity: usize, hash_builder: S, alloc: A) -> Self {
+        Self {
+            key_to_pos: HashMap::with_capacity_and_hasher_in(capacity, hash_builder, alloc.clone()),
+            storage: LinearStorage::with_capacity_in(capacity, alloc),
+        }
+    }
+
+    /// Creates an empty `StableMultiMap` which will use the given hash builder to hash
+    /// keys and the given allocator to allocate memory.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use allocator_api2::alloc::Global;
+    /// use hashbrown::DefaultHashBuilder;
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let s = DefaultHashBuilder::default();
+    /// let mut map = StableMultiMap::with_hasher_in(s, Global);
+    /// map.insert(1, 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_hasher_in(hash_builder: S, alloc: A) -> Self {
+        Self::with_capacity_and_hasher_in(0, hash_builder, alloc)
+    }
+}
+
+impl<K, V, S, A: Allocator> StableMultiMap<K, V, S, A> {
+    /// Returns a reference to the underlying allocator.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let map: StableMultiMap<i32, i32> = StableMultiMap::new();
+    /// let _alloc = map.allocator();
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn allocator(&self) -> &A {
+        self.key_to_pos.allocator()
+    }
+
+    /// Clears the map, removing all values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.clear();
+    /// assert!(map.is_empty());
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn clear(&mut self) {
+        self.key_to_pos.clear();
+        self.storage.clear();
+        // SAFETY(invariants):
+        // - We have cleared key_to_pos.
+    }
+
+    /// Returns `true` if the map contains at least one value for the specified key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// map.insert(1, "a");
+    /// assert!(map.contains_key(&1));
+    /// assert!(!map.contains_key(&2));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn contains_key<Q>(&self, key: &Q) -> bool
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        self.key_to_pos.contains_key(key)
+    }
+
+    /// An iterator visiting the indices and values of the key in the order in which they
+    /// were inserted.
+    ///
+    /// The iterator is empty if the key is not contained in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// map.insert(1, "c");
+    ///
+    /// let values: Vec<_> = map.get_all(&1).map(|(_, v)| *v).collect();
+    /// assert_eq!(values, ["a", "c"]);
+    /// assert_eq!(map.get_all(&3).count(), 0);
+    /// ```
+    pub fn get_all<Q>(&self, key: &Q) -> impl Iterator<Item = (usize, &V)> + use<'_, K, V, S, A, Q>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        let positions = self
+            .key_to_pos
+            .get(key)
+            .map(AllocVec::as_slice)
+            .unwrap_or_default();
+        positions.iter().map(|pos| {
+            let index = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.index_unchecked(pos)
+            };
+            let (_, value) = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.get_unchecked(pos)
+            };
+            (index, value)
+        })
+    }
+
+    /// Returns the key and value stored at the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// let index = map.insert(1, "a");
+    /// assert_eq!(map.get_by_index(index), Some((&1, &"a")));
+    /// assert_eq!(map.get_by_index(index + 1), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_by_index(&self, index: usize) -> Option<(&K, &V)> {
+        self.storage.get(index).map(|(k, v)| (k, v))
+    }
+
+    /// Returns the key and a mutable reference to the value stored at the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// let index = map.insert(1, 10);
+    /// *map.get_by_index_mut(index).unwrap().1 += 1;
+    /// assert_eq!(map.get_by_index(index), Some((&1, &11)));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_by_index_mut(&mut self, index: usize) -> Option<(&K, &mut V)> {
+        self.storage.get_mut(index).map(|(k, v)| (&*k, v))
+    }
+
+    /// Inserts a value for the key and returns its index.
+    ///
+    /// Values that are already stored for the key are not affected.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// assert_eq!(map.insert(1, "a"), 0);
+    /// assert_eq!(map.insert(1, "b"), 1);
+    /// assert_eq!(map.len(), 2);
+    /// assert_eq!(map.key_len(), 1);
+    /// ```
+    pub fn insert(&mut self, key: K, value: V) -> usize
+    where
+        K: Eq + Hash + Clone,
+        S: BuildHasher,
+        A: Clone,
+    {
+        let alloc = self.key_to_pos.allocator().clone();
+        let (key, positions) = match self.key_to_pos.entry(key) {
+            hash_map::Entry::Occupied(o) => {
+                let key = o.key().clone();
+                (key, o.into_mut())
+            }
+            hash_map::Entry::Vacant(v) => {
+                let key = v.key().clone();
+                (key, v.insert(AllocVec::with_capacity_in(1, alloc)))
+            }
+        };
+        let pos = self.storage.insert((key, value));
+        let index = unsafe {
+            // SAFETY:
+            // - pos was just returned by storage and is therefore valid.
+            self.storage.index_unchecked(&pos)
+        };
+        positions.push(pos);
+        index
+        // SAFETY(invariants):
+        // - The Pos<InUse> pushed onto positions was just returned by storage.
+        // - positions contains at least this Pos<InUse>.
+        // - The stored key is a clone of the key in key_to_pos.
+    }
+
+    /// Returns `true` if the map contains no values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// assert!(map.is_empty());
+    /// map.insert(1, "a");
+    /// assert!(!map.is_empty());
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn is_empty(&self) -> bool {
+        self.key_to_pos.is_empty()
+    }
+
+    /// An iterator visiting all values together with their indices and keys, in the
+    /// order of their indices.
+    ///
+    /// This function takes time linear in [index_len](Self::index_len).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// map.insert(1, "c");
+    ///
+    /// let entries: Vec<_> = map.iter().collect();
+    /// assert_eq!(entries, [(0, &1, &"a"), (1, &2, &"b"), (2, &1, &"c")]);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &K, &V)> + use<'_, K, V, S, A> {
+        (0..self.storage.len())
+            .filter_map(|index| self.storage.get(index).map(|(k, v)| (index, k, v)))
+    }
+
+    /// Returns the number of distinct keys in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// map.insert(2, "c");
+    /// assert_eq!(map.key_len(), 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn key_len(&self) -> usize {
+        self.key_to_pos.len()
+    }
+
+    /// Returns the number of values in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(1, "b");
+    /// assert_eq!(map.len(), 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn len(&self) -> usize {
+        self.storage.len() - self.storage.vacant_len()
+    }
+
+    /// Removes all values of the key and returns them together with their indices, in
+    /// the order in which they were inserted.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// map.insert(1, "c");
+    ///
+    /// assert_eq!(map.remove_all(&1), [(0, "a"), (2, "c")]);
+    /// assert!(map.remove_all(&1).is_empty());
+    /// assert_eq!(map.len(), 1);
+    /// ```
+    pub fn remove_all<Q>(&mut self, key: &Q) -> Vec<(usize, V)>
+    where
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+        S: BuildHasher,
+    {
+        let Some(positions) = self.key_to_pos.remove(key) else {
+            return Vec::new();
+        };
+        let mut values = Vec::with_capacity(positions.len());
+        for pos in positions {
+            let index = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.index_unchecked(&pos)
+            };
+            let (_, value) = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.take_unchecked(pos)
+            };
+            values.push((index, value));
+        }
+        values
+        // SAFETY(invariants):
+        // - take_unchecked does not invalidate any other Pos<InUse>.
+    }
+
+    /// Removes the value stored at the index and returns it together with its key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// let a = map.insert(1, "a");
+    /// let b = map.insert(1, "b");
+    ///
+    /// assert_eq!(map.remove_value_by_index(a), Some((1, "a")));
+    /// assert_eq!(map.remove_value_by_index(a), None);
+    /// assert_eq!(map.get_all(&1).collect::<Vec<_>>(), [(b, &"b")]);
+    /// ```
+    pub fn remove_value_by_index(&mut self, index: usize) -> Option<(K, V)>
+    where
+        K: Eq + Hash,
+        S: BuildHasher,
+    {
+        let (key, _) = self.storage.get(index)?;
+        let positions = self.key_to_pos.get_mut(key);
+        debug_assert!(
+            positions.is_some(),
+            "the key of a stored value is not contained in key_to_pos",
+        );
+        // By the invariants, the key of each value is contained in key_to_pos.
+        let positions = positions?;
+        let idx = positions.iter().position(|pos| {
+            let i = unsafe {
+                // SAFETY:
+                // - By the invariants, pos is valid
+                self.storage.index_unchecked(pos)
+            };
+            i == index
+        });
+        debug_assert!(
+            idx.is_some(),
+            "the position of a stored value is not stored under its key",
+        );
+        // By the invariants, the Pos<InUse> of each value is stored under its key.
+        let pos = positions.remove(idx?);
+        let remove_key = positions.is_empty();
+        let (key, value) = unsafe {
+            // SAFETY:
+            // - By the invariants, pos is valid
+            self.storage.take_unchecked(pos)
+        };
+        if remove_key {
+            self.key_to_pos.remove(&key);
+        }
+        Some((key, value))
+        // SAFETY(invariants):
+        // - take_unchecked does not invalidate any other Pos<InUse>.
+        // - If the vector became empty, we've removed it from key_to_pos.
+    }
+
+    /// Returns the size of the index space.
+    ///
+    /// Using [get_by_index](Self::get_by_index) with higher indices will always return
+    /// `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// map.insert(1, "a");
+    /// let b = map.insert(1, "b");
+    /// map.remove_value_by_index(b);
+    /// assert_eq!(map.index_len(), 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn index_len(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Maybe compacts the map, removing indices for which `get_by_index` would return
+    /// `None`.
+    ///
+    /// This function does nothing if there are no more than 8 indices for which
+    /// [get_by_index](Self::get_by_index) returns `None` or if at least half of the
+    /// indices are in use.
+    ///
+    /// Compaction changes the indices of values but not the keys they belong to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// for i in 0..32 {
+    ///     map.insert(i % 2, i);
+    /// }
+    /// for i in 0..17 {
+    ///     map.remove_value_by_index(i);
+    /// }
+    /// map.compact();
+    /// assert_eq!(map.index_len(), 15);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn compact(&mut self) {
+        self.storage.compact();
+    }
+
+    /// Compacts the map, removing indices for which `get_by_index` would return `None`.
+    ///
+    /// After this function returns, [index_len](Self::index_len) will be the same as
+    /// [len](Self::len). Compaction changes the indices of values but not the keys they
+    /// belong to.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMultiMap;
+    ///
+    /// let mut map = StableMultiMap::new();
+    /// let a = map.insert(1, "a");
+    /// map.insert(2, "b");
+    /// map.insert(1, "c");
+    /// map.remove_value_by_index(a);
+    /// map.force_compact();
+    /// assert_eq!(map.index_len(), 2);
+    /// assert_eq!(map.get_all(&1).collect::<Vec<_>>(), [(0, &"c")]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn force_compact(&mut self) {
+        self.storage.force_compact();
+    }
+}
+
+impl<K, V, S, A: Allocator + Clone + Default> Default for StableMultiMap<K, V, S, A>
+where
+    S: Default,
+{
+    fn default() -> Self {
+        Self::with_hasher_in(S::default(), A::default())
+    }
+}
+
+impl<K, V, S, A: Allocator> Debug for StableMultiMap<K, V, S, A>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+// SAFETY:
+// - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
+//   but this API prevents this.
+unsafe impl<K, V, S, A: Allocator> Send for StableMultiMap<K, V, S, A>
+where
+    K: Send,
+    V: Send,
+    S: Send,
+    A: Send,
+{
+}
+
+// SAFETY:
+// - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
+//   but this API prevents this.
+unsafe impl<K, V, S, A: Allocator> Sync for StableMultiMap<K, V, S, A>
+where
+    K: Sync,
+    V: Sync,
+    S: Sync,
+    A: Sync,
+{
+}
@@ -0,0 +1,110 @@
+use {
+    crate::StableMultiMap,
+    alloc::{format, vec::Vec},
+    allocator_api2::alloc::{AllocError, Allocator, Global, Layout},
+    core::{cell::Cell, ptr::NonNull},
+};
+
+#[test]
+fn insert_remove() {
+    let mut map = StableMultiMap::new();
+    assert_eq!(map.insert(1, 10), 0);
+    assert_eq!(map.insert(2, 20), 1);
+    assert_eq!(map.insert(1, 11), 2);
+    assert_eq!(map.insert(1, 12), 3);
+    assert_eq!(map.len(), 4);
+    assert_eq!(map.key_len(), 2);
+    assert_eq!(
+        map.get_all(&1).collect::<Vec<_>>(),
+        [(0, &10), (2, &11), (3, &12)],
+    );
+    assert_eq!(map.remove_value_by_index(2), Some((1, 11)));
+    assert_eq!(map.remove_value_by_index(2), None);
+    assert_eq!(map.get_all(&1).collect::<Vec<_>>(), [(0, &10), (3, &12)]);
+    assert_eq!(map.remove_value_by_index(1), Some((2, 20)));
+    assert!(!map.contains_key(&2));
+    assert_eq!(map.key_len(), 1);
+    assert_eq!(map.insert(3, 30), 1);
+    assert_eq!(map.get_by_index(1), Some((&3, &30)));
+    assert_eq!(map.remove_all(&1), [(0, 10), (3, 12)]);
+    assert_eq!(map.len(), 1);
+    assert_eq!(map.key_len(), 1);
+}
+
+#[test]
+fn force_compact() {
+    let mut map = StableMultiMap::new();
+    for i in 0..8 {
+        map.insert(i % 3, i);
+    }
+    for i in [0, 2, 5] {
+        map.remove_value_by_index(i);
+    }
+    map.force_compact();
+    assert_eq!(map.len(), 5);
+    assert_eq!(map.index_len(), 5);
+    for key in 0..3 {
+        for (index, value) in map.get_all(&key) {
+            assert_eq!(value % 3, key);
+            assert_eq!(map.get_by_index(index), Some((&key, value)));
+        }
+    }
+    let mut values: Vec<_> = map.iter().map(|(_, _, v)| *v).collect();
+    values.sort_unstable();
+    assert_eq!(values, [1, 3, 4, 6, 7]);
+}
+
+#[test]
+fn clear() {
+    let mut map = StableMultiMap::new();
+    map.insert(1, 10);
+    map.insert(1, 11);
+    map.clear();
+    assert!(map.is_empty());
+    assert_eq!(map.len(), 0);
+    assert_eq!(map.index_len(), 0);
+    assert_eq!(map.get_all(&1).count(), 0);
+}
+
+#[test]
+fn debug() {
+    let mut map = StableMultiMap::new();
+    map.insert(1, 10);
+    map.insert(1, 11);
+    assert_eq!(format!("{:?}", map), "[(0, 1, 10), (1, 1, 11)]");
+}
+
+#[test]
+fn allocator() {
+    #[derive(Clone, Copy)]
+    struct Counting<'a>(&'a Cell<isize>);
+
+    unsafe impl Allocator for Counting<'_> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.set(self.0.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            self.0.set(self.0.get() - 1);
+            unsafe { Global.deallocate(ptr, layout) }
+        }
+    }
+
+    let live = Cell::new(0);
+    let mut map = StableMultiMap::with_capacity_in(100, Counting(&live));
+    assert!(core::ptr::eq(map.allocator().0, &live));
+    let tables = live.get();
+    for i in 0..10 {
+        map.insert(i, i);
+    }
+    // The vector of positions of each key is allocated with the map's allocator.
+    assert_eq!(live.get(), tables + 10);
+    for i in 10..100 {
+        map.insert(i % 10, i);
+    }
+    assert_eq!(map.remove_all(&1).len(), 10);
+    map.force_compact();
+    drop(map);
+    assert_eq!(live.get(), 0);
+}
@@ -1,5 +1,6 @@
 use {
     crate::entry::OccupiedEntry,
+    allocator_api2::alloc::{Allocator, Global},
     core::fmt::{Debug, Display, Formatter},
 };
 
@@ -27,14 +28,14 @@ use {
 /// }
 /// assert_eq!(map[&"a"], 100);
 /// ```
-pub struct OccupiedError<'a, K, V, S> {
+pub struct OccupiedError<'a, K, V, S, A: Allocator = Global> {
     /// The entry in the map that was already occupied.
-    pub entry: OccupiedEntry<'a, K, V, S>,
+    pub entry: OccupiedEntry<'a, K, V, S, A>,
     /// The value which was not inserted, because the entry was already occupied.
     pub value: V,
 }
 
-impl<K, V, S> Debug for OccupiedError<'_, K, V, S>
+impl<K, V, S, A: Allocator> Debug for OccupiedError<'_, K, V, S, A>
 where
     K: Debug,
     V: Debug,
@@ -48,7 +49,7 @@ where
     }
 }
 
-impl<K, V, S> Display for OccupiedError<'_, K, V, S>
+impl<K, V, S, A: Allocator> Display for OccupiedError<'_, K, V, S, A>
 where
     K: Debug,
     V: Debug,
@@ -0,0 +1,37 @@
+use core::fmt::{Display, Formatter};
+
+/// The error returned by
+/// [`get_many_mut_checked`](crate::StableMap::get_many_mut_checked) when two keys refer
+/// to the same entry.
+///
+/// Contains the positions of the two keys in the array of keys.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{OverlapError, StableMap};
+///
+/// let mut map = StableMap::new();
+/// map.insert("a", 1);
+/// map.insert("b", 2);
+///
+/// let res = map.get_many_mut_checked(["a", "b", "a"]);
+/// assert_eq!(res, Err(OverlapError { first: 0, second: 2 }));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OverlapError {
+    /// The position of the first key that refers to the entry.
+    pub first: usize,
+    /// The position of the second key that refers to the entry.
+    pub second: usize,
+}
+
+impl Display for OverlapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "the keys at positions {} and {} refer to the same entry",
+            self.first, self.second,
+        )
+    }
+}
@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::linear_storage::LinearStorage,
+    allocator_api2::alloc::{Allocator, Global},
+    core::fmt::{Debug, Formatter},
+    rayon::iter::{plumbing::UnindexedConsumer, IntoParallelIterator, ParallelIterator},
+};
+
+/// A parallel iterator over the values of a `StableMap` that walks the index space.
+/// The iterator element type is `(usize, &'a V)`.
+///
+/// Unlike iterating the hash table, this splits the work over contiguous ranges of the
+/// storage of the values, skipping unused indices.
+///
+/// This `struct` is created by the [`par_iter_by_index`] method on [`StableMap`]. See
+/// its documentation for more.
+///
+/// [`par_iter_by_index`]: crate::StableMap::par_iter_by_index
+/// [`StableMap`]: crate::StableMap
+pub struct ParIterByIndex<'a, V, A: Allocator = Global> {
+    pub(crate) storage: &'a LinearStorage<V, A>,
+}
+
+impl<'a, V, A: Allocator> ParIterByIndex<'a, V, A> {
+    fn get(&self, index: usize) -> Option<(usize, &'a V)> {
+        self.storage.get(index).map(|v| (index, v))
+    }
+}
+
+impl<V, A: Allocator> Clone for ParIterByIndex<'_, V, A> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<V, A: Allocator> Copy for ParIterByIndex<'_, V, A> {}
+
+impl<'a, V, A> ParallelIterator for ParIterByIndex<'a, V, A>
+where
+    V: Sync,
+    A: Allocator + Sync,
+{
+    type Item = (usize, &'a V);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+    where
+        C: UnindexedConsumer<Self::Item>,
+    {
+        (0..self.storage.len())
+            .into_par_iter()
+            .filter_map(move |index| self.get(index))
+            .drive_unindexed(consumer)
+    }
+}
+
+impl<V, A: Allocator> Debug for ParIterByIndex<'_, V, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ParIterByIndex")
+            .field("index_len", &self.storage.len())
+            .finish_non_exhaustive()
+    }
+}
+
+// SAFETY:
+// - This object only provides shared access to the LinearStorage. Shared access never
+//   modifies the Pos<Stored> contained in the storage and only exposes `&V`.
+unsafe impl<V, A: Allocator> Send for ParIterByIndex<'_, V, A>
+where
+    V: Sync,
+    A: Sync,
+{
+}
+
+// SAFETY:
+// - This object only provides shared access to the LinearStorage. Shared access never
+//   modifies the Pos<Stored> contained in the storage and only exposes `&V`.
+unsafe impl<V, A: Allocator> Sync for ParIterByIndex<'_, V, A>
+where
+    V: Sync,
+    A: Sync,
+{
+}
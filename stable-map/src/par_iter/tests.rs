@@ -0,0 +1,22 @@
+use {crate::StableMap, alloc::vec::Vec, rayon::iter::ParallelIterator};
+
+#[test]
+fn test() {
+    let mut map = StableMap::new();
+    for i in 0..1000 {
+        map.insert(i, i * 2);
+    }
+    for i in (0..1000).step_by(3) {
+        map.remove(&i);
+    }
+    let mut values: Vec<_> = map.par_iter_by_index().collect();
+    values.sort_unstable();
+    let expected: Vec<_> = (0..map.index_len())
+        .filter_map(|i| map.get_by_index(i).map(|v| (i, v)))
+        .collect();
+    assert_eq!(values, expected);
+    assert_eq!(
+        map.par_iter_by_index().map(|(_, v)| *v).sum::<i32>(),
+        map.values().sum::<i32>()
+    );
+}
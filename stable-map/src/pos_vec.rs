@@ -1,7 +1,14 @@
 use {
-    alloc::vec::Vec,
-    core::{marker::PhantomData, ptr},
-    pos::{Free, InUse, Pos, Stored},
+    crate::{
+        prefetch::{self, prefetch_read},
+        try_reserve_error::{try_reserve_vec, TryReserveError},
+    },
+    allocator_api2::{
+        alloc::{Allocator, Global},
+        vec::Vec,
+    },
+    core::{hint::unreachable_unchecked, marker::PhantomData, num::NonZeroU64, ptr, slice},
+    pos::{from_raw, to_raw, Free, InUse, Pos, RawIndex, Stored, MAX_LEN},
     tag::Tag,
 };
 
@@ -10,9 +17,18 @@ mod tag;
 #[cfg(test)]
 mod tests;
 
+// `None` is stored in the niche of `generation`, so `Option<PositionedValue<V>>` has the
+// same size as `PositionedValue<V>`. A separate occupancy bitmap would not make the
+// slots smaller.
 #[derive(Debug)]
 struct PositionedValue<V> {
     pos: Pos<Stored>,
+    generation: NonZeroU64,
+    /// An opaque hash stored alongside the value. Maps store the hash of the key here so
+    /// that the key of an index can be found without scanning the whole map, and so that
+    /// keys do not have to be rehashed when the hash table grows.
+    #[cfg(feature = "cached-hash")]
+    hash: u64,
     value: V,
 }
 
@@ -27,10 +43,15 @@ struct PositionedValue<V> {
 //
 // We maintain the following invariants:
 //
-// - The Pos<Stored> stored in each entry is set to its index in the vector.
+// - The slot of the Pos<Stored> stored in each entry is mapped to its index in the
+//   vector by self.slots.
+// - Each Pos<Stored> has a unique slot that is not contained in self.free_slots.
+// - self.free_slots contains every slot less than self.slots.len() that does not belong
+//   to a Pos<Stored>.
 // - Each returned, valid Pos<InUse> corresponds to a stored Pos<Stored>.
 // - Each returned, valid Pos<Free> corresponds to an entry containing None.
 // - Each returned, valid Pos has the same tag as self.tag.
+// - If self.values contains None, then self.slots.capacity() >= self.values.len().
 //
 // The following requirements are implicit:
 //
@@ -39,25 +60,72 @@ struct PositionedValue<V> {
 //
 // SAFETY: Each mutating function must document how it upholds these invariants.
 #[derive(Debug)]
-pub struct PosVec<V> {
+pub struct PosVec<V, A: Allocator = Global> {
     tag: Tag,
-    values: Vec<Option<PositionedValue<V>>>,
+    values: Vec<Option<PositionedValue<V>>, A>,
+    /// The current index of each slot.
+    slots: Vec<RawIndex, A>,
+    /// The slots that are not used by any `Pos<Stored>`.
+    free_slots: Vec<RawIndex, A>,
+    /// The generation assigned to the next value that is stored or moved.
+    next_generation: NonZeroU64,
+}
+
+/// An iterator over the slots of a `PosVec` that yields the values of the used slots in
+/// the order of their indices.
+pub struct Slots<'a, V> {
+    iter: slice::Iter<'a, Option<PositionedValue<V>>>,
+}
+
+/// An iterator over the slots of a `PosVec` that yields one `Option<&V>` per slot in the
+/// order of their indices.
+pub struct SparseSlots<'a, V> {
+    iter: slice::Iter<'a, Option<PositionedValue<V>>>,
+}
+
+/// A mutable iterator over the slots of a `PosVec` that yields one `Option<&mut V>` per
+/// slot in the order of their indices.
+pub struct SparseSlotsMut<'a, V> {
+    iter: slice::IterMut<'a, Option<PositionedValue<V>>>,
+}
+
+/// A mutable iterator over the slots of a `PosVec` that yields the values of the used
+/// slots in the order of their indices.
+pub struct SlotsMut<'a, V> {
+    iter: slice::IterMut<'a, Option<PositionedValue<V>>>,
 }
 
 pub struct PosVecRawAccess<'a, V> {
-    #[cfg(test)]
+    #[cfg(any(test, feature = "checked-pos"))]
     tag: Tag,
     values: *mut Option<PositionedValue<V>>,
+    len: usize,
+    slots: *const RawIndex,
     _phantom: PhantomData<&'a mut PosVec<V>>,
 }
 
+#[cfg(test)]
 impl<V> PosVec<V> {
     /// Creates a new vector with the requested capacity.
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::with_capacity_in(capacity, Global)
+    }
+}
+
+impl<V, A: Allocator> PosVec<V, A> {
+    /// Creates a new vector with the requested capacity in the provided allocator.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self
+    where
+        A: Clone,
+    {
         Self {
             tag: Tag::next(),
-            values: Vec::with_capacity(capacity),
+            values: Vec::with_capacity_in(capacity, alloc.clone()),
+            slots: Vec::with_capacity_in(capacity, alloc.clone()),
+            free_slots: Vec::new_in(alloc),
+            next_generation: NonZeroU64::MIN,
         }
     }
 
@@ -78,61 +146,139 @@ impl<V> PosVec<V> {
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn reserve(&mut self, additional: usize) {
         self.values.reserve(additional);
+        self.slots
+            .reserve(self.values.capacity().saturating_sub(self.slots.len()));
+    }
+
+    /// Tries to reserve space for `additional` additional elements in the vector.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn try_reserve(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        // create_pos ensures that the length never exceeds MAX_LEN.
+        if additional > MAX_LEN - self.values.len() {
+            return Err(TryReserveError::CapacityOverflow);
+        }
+        try_reserve_vec(&mut self.values, additional)?;
+        let additional_slots = self.values.capacity().saturating_sub(self.slots.len());
+        try_reserve_vec(&mut self.slots, additional_slots)
+        // SAFETY(invariants):
+        // - The lengths of self.values and self.slots are unchanged.
     }
 
     /// Reduces the capacity of the vector to its length.
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn shrink_to_fit(&mut self) {
-        self.values.shrink_to_fit()
+        self.values.shrink_to_fit();
+        self.slots.shrink_to(self.values.len());
+        self.free_slots.shrink_to_fit();
+        // SAFETY(invariants):
+        // - The capacity of self.slots remains at least self.values.len().
     }
 
     /// Creates a new `Pos<Free>`.
     #[cfg_attr(feature = "inline-more", inline)]
     pub(crate) fn create_pos(&mut self) -> Pos<Free> {
+        assert!(
+            self.values.len() < MAX_LEN,
+            "stable-map: the number of indices exceeds the maximum of {MAX_LEN}",
+        );
         let pos = unsafe {
             // SAFETY:
             // - Since the index we are using is the length of the vector (and therefore
             //   not valid), the invariants that hold at the start of this function
             //   guarantee that there is no returned, valid Pos with this index.
+            // - We've checked that the index is less than MAX_LEN.
             Pos::new(self.tag, self.values.len())
         };
         self.values.push(None);
+        if self.slots.capacity() < self.values.len() {
+            self.slots
+                .reserve(self.values.capacity() - self.slots.len());
+        }
         pos
         // SAFETY(invariants):
         // - The Pos<Free> corresponds to the last element in self.values
         //   and that value is None.
         // - The tag is self.tag.
+        // - We've reserved capacity for self.slots if necessary.
     }
 
-    /// Stores a value in a `Pos<Free>`.
+    /// Returns the generation for the next value that is stored or moved.
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn next_generation(&mut self) -> NonZeroU64 {
+        let generation = self.next_generation;
+        self.next_generation = generation.saturating_add(1);
+        generation
+    }
+
+    /// Stores a value and its hash in a `Pos<Free>`.
+    ///
+    /// The hash is discarded unless the `cached-hash` feature is enabled.
     ///
     /// # Safety
     ///
     /// - The `Pos<Free>` must have been returned by this object and must be valid.
     #[cfg_attr(feature = "inline-more", inline)]
-    pub(crate) unsafe fn store(&mut self, pos: Pos<Free>, value: V) -> Pos<InUse> {
-        #[cfg(test)]
+    pub(crate) unsafe fn store(&mut self, pos: Pos<Free>, hash: u64, value: V) -> Pos<InUse> {
+        #[cfg(any(test, feature = "checked-pos"))]
         assert_eq!(pos.tag(), self.tag);
         let idx = pos.get();
-        let (pos, stored) = pos.activate();
+        let slot = match self.free_slots.pop() {
+            Some(slot) => {
+                let slot = from_raw(slot);
+                unsafe {
+                    // SAFETY:
+                    // - By the invariants, free slots are less than self.slots.len().
+                    *self.slots.get_unchecked_mut(slot) = to_raw(idx);
+                }
+                slot
+            }
+            None => {
+                self.slots.push(to_raw(idx));
+                self.slots.len() - 1
+            }
+        };
+        let (pos, stored) = unsafe {
+            // SAFETY:
+            // - By the invariants, the slot was not used by any Pos<Stored>.
+            // - There is at most one slot per index, therefore the slot is less than
+            //   MAX_LEN.
+            pos.activate(slot)
+        };
+        let generation = self.next_generation();
+        #[cfg(not(feature = "cached-hash"))]
+        let _ = hash;
         let opt = unsafe {
             // SAFETY:
             // - By the invariants, the position points to a None in the vector.
             self.values.get_unchecked_mut(idx)
         };
-        #[cfg(test)]
+        #[cfg(any(test, feature = "checked-pos"))]
         assert!(opt.is_none());
         unsafe {
             // SAFETY:
             // - opt is a reference so this is always safe.
             // NOTE:
             // - we do this to avoid running the drop check for the old value.
-            ptr::write(opt, Some(PositionedValue { pos: stored, value }));
+            ptr::write(
+                opt,
+                Some(PositionedValue {
+                    pos: stored,
+                    generation,
+                    #[cfg(feature = "cached-hash")]
+                    hash,
+                    value,
+                }),
+            );
         }
         pos
         // SAFETY(invariants):
-        // - The Pos<Stored> refers to its index since the first unsafe block accesses
+        // - The slot of the Pos<Stored> is mapped to idx and we've written the value to
         //   that index.
+        // - The slot was either taken from self.free_slots or newly created.
+        // - If self.slots had to grow, then there was no free slot. Then all slots are
+        //   used by a Pos<Stored>, self.slots.len() is the number of Some entries and,
+        //   since this entry was None, self.slots.capacity() >= self.values.len() >
+        //   self.slots.len(). Therefore this function does not allocate memory.
         // - The Pos<InUse> refers to the same index and we just wrote `Some` to
         //   it.
         // - The tag of the Pos<InUse> is the tag of the input Pos<Free>. By the
@@ -142,13 +288,17 @@ impl<V> PosVec<V> {
 
     /// Removes unused slots in this PosVec.
     ///
-    /// `smallest_free` must return the smallest `Pos<Free>` returned by this object.
+    /// `smallest_free` should return the smallest `Pos<Free>` returned by this object that
+    /// has not yet been passed to this function. Each returned `Pos<Free>` is filled with
+    /// a value moved from the end of the vector. If `smallest_free` returns all unused
+    /// slots, the vector contains no unused slots after this function returns.
     ///
     /// # Safety
     ///
     /// - `smallest_free` must return valid `Pos<Free>` returned by this object.
     /// - Immediately after this function returns, all previously returned `Pos<Free>`
-    ///   become invalid. The caller must drop them before calling back into this object.
+    ///   whose index is not less than the length of the vector become invalid. The
+    ///   caller must drop them before calling back into this object.
     #[cfg_attr(feature = "inline-more", inline)]
     pub(crate) unsafe fn compact<F>(&mut self, mut smallest_free: F)
     where
@@ -158,35 +308,44 @@ impl<V> PosVec<V> {
         // - Note that the callback `smallest_free` cannot change self.tag since self.tag
         //   is only changed by self.clean which requires a `&mut` reference. Therefore
         //   we do not need to discuss that invariant.
-        // - After this function returns, all Pos<Free> become invalid. Therefore we do
-        //   not need to discuss that invariant.
+        // - After this function returns, all Pos<Free> whose index is not less than the
+        //   length of the vector become invalid. The Pos<Free> returned by
+        //   `smallest_free` are consumed. We only remove entries from the end of the
+        //   vector and only write to the indices of the consumed Pos<Free>. Therefore
+        //   all other Pos<Free> continue to correspond to entries containing None.
         // - We never drop any Pos<Stored>, therefore it is clear that the invariant that
-        //   Pos<InUse> corresponds to a Pos<Stored> continues to hold.
-        // - The invariant that Pos<Stored> refers to its index in the vector is discussed
-        //   below whenever we modify a position or the vector.
+        //   Pos<InUse> corresponds to a Pos<Stored> continues to hold. For the same
+        //   reason, the slots and free slots are unaffected.
+        // - The invariant that the slot of a Pos<Stored> is mapped to its index in the
+        //   vector is discussed below whenever we modify a slot or the vector.
+        // - Shrinking self.values does not affect the capacity invariant.
         'outer: while let Some(free) = smallest_free() {
-            #[cfg(test)]
+            #[cfg(any(test, feature = "checked-pos"))]
             assert_eq!(free.tag(), self.tag);
             // SAFETY(invariants):
             // - If this value is, None, it cannot be referred to by a Pos<InUse>.
             // - Otherwise we restore the Pos<Stored> invariant in the two branches below.
             while let Some(value) = self.values.pop() {
+                // Values are moved from the end of the vector.
+                self.prefetch(self.values.len().wrapping_sub(prefetch::DISTANCE));
                 if let Some(mut entry) = value {
                     if free.get() < self.values.len() {
-                        let idx = unsafe {
+                        #[cfg(any(test, feature = "checked-pos"))]
+                        assert_eq!(entry.pos.tag(), free.tag());
+                        let idx = free.get();
+                        unsafe {
                             // SAFETY:
-                            // - By the invariants, entry.pos has the tag self.tag.
-                            // - By the requirements of this method, free is a valid
-                            //   Pos<Free> returned by this object. By the invariants,
-                            //   that Pos<Free> has the tag self.tag.
-                            entry.pos.set(free)
-                        };
+                            // - By the invariants, the slot of entry.pos is less than
+                            //   self.slots.len().
+                            *self.slots.get_unchecked_mut(entry.pos.slot()) = to_raw(idx);
+                        }
+                        entry.generation = self.next_generation();
                         let opt = unsafe {
                             // SAFETY:
                             // - We just checked that free.get() < self.values.len().
                             self.values.get_unchecked_mut(idx)
                         };
-                        #[cfg(test)]
+                        #[cfg(any(test, feature = "checked-pos"))]
                         assert!(opt.is_none());
                         unsafe {
                             // SAFETY:
@@ -195,7 +354,7 @@ impl<V> PosVec<V> {
                             // - we do this to avoid running drop checks for *opt.
                             // SAFETY(invariants):
                             // - opt refers to the idx'th element of self.values and we
-                            //   just set entry.pos to that value.
+                            //   just mapped the slot of entry.pos to that index.
                             ptr::write(opt, Some(entry));
                         }
                         break;
@@ -209,10 +368,6 @@ impl<V> PosVec<V> {
                 }
             }
         }
-        #[cfg(test)]
-        for e in &self.values {
-            assert!(e.is_some());
-        }
     }
 
     /// Removes all objects from this vector.
@@ -223,11 +378,44 @@ impl<V> PosVec<V> {
     pub fn clear(&mut self) {
         self.tag = Tag::next();
         self.values.clear();
+        self.slots.clear();
+        self.free_slots.clear();
         // SAFETY(invariants):
         // - There no longer are any valid `Pos`, therefore all invariants are trivially
         //   satisfied.
     }
 
+    /// Returns whether a value is stored at a specific index in the vector.
+    ///
+    /// Note that, unlike the functions taking `Pos<InUse>`, the result is affected by
+    /// calls to `compact`.
+    #[inline]
+    pub fn is_occupied(&self, pos: usize) -> bool {
+        matches!(self.values.get(pos), Some(Some(_)))
+    }
+
+    /// Returns the smallest index greater than or equal to `start` at which a value is
+    /// stored.
+    ///
+    /// Note that, unlike the functions taking `Pos<InUse>`, the result is affected by
+    /// calls to `compact`.
+    #[inline]
+    pub fn next_occupied(&self, start: usize) -> Option<usize> {
+        // Occupancy is only stored in the slots themselves (see PositionedValue), so this
+        // has to look at each skipped slot.
+        let offset = self.values.get(start..)?.iter().position(Option::is_some)?;
+        Some(start + offset)
+    }
+
+    /// Returns the largest index at which a value is stored.
+    ///
+    /// Note that, unlike the functions taking `Pos<InUse>`, the result is affected by
+    /// calls to `compact`.
+    #[inline]
+    pub fn last_occupied(&self) -> Option<usize> {
+        self.values.iter().rposition(Option::is_some)
+    }
+
     /// Retrieves a reference to a value stored at a specific index in the vector.
     ///
     /// Note that, unlike the functions taking `Pos<InUse>`, which value is returned is
@@ -252,6 +440,96 @@ impl<V> PosVec<V> {
             .map(|v| &mut v.value)
     }
 
+    /// Returns the generation of the value stored at a specific index in the vector.
+    ///
+    /// Each value receives a new generation when it is stored and whenever it is moved by
+    /// `compact`. Generations are never reused, not even after `clear`.
+    #[inline]
+    pub fn generation(&self, pos: usize) -> Option<u64> {
+        self.values
+            .get(pos)
+            .and_then(|v| v.as_ref())
+            .map(|v| v.generation.get())
+    }
+
+    /// Returns the hash of the value stored at a specific index in the vector.
+    ///
+    /// Note that, unlike the functions taking `Pos<InUse>`, which hash is returned is
+    /// affected by calls to `compact`.
+    #[cfg(feature = "cached-hash")]
+    #[inline]
+    pub fn hash(&self, pos: usize) -> Option<u64> {
+        self.values
+            .get(pos)
+            .and_then(|v| v.as_ref())
+            .map(|v| v.hash)
+    }
+
+    /// Returns an iterator over the values stored in the vector, in the order of their
+    /// indices.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn iter(&self) -> impl Iterator<Item = &V> {
+        self.values
+            .iter()
+            .filter_map(|v| v.as_ref())
+            .map(|v| &v.value)
+    }
+
+    /// Hints to the CPU that the slot at `index` will be accessed soon.
+    ///
+    /// `index` does not have to be in bounds.
+    #[inline]
+    pub fn prefetch(&self, index: usize) {
+        prefetch_read(self.values.as_ptr().wrapping_add(index));
+    }
+
+    /// Returns an iterator over the slots of the vector.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn slots(&self) -> Slots<'_, V> {
+        Slots {
+            iter: self.values.iter(),
+        }
+    }
+
+    /// Returns an iterator over the slots of the vector that also yields the unused
+    /// slots.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn sparse_slots(&self) -> SparseSlots<'_, V> {
+        self.sparse_slots_from(0)
+    }
+
+    /// Returns an iterator over the slots of the vector, starting at index `start`, that
+    /// also yields the unused slots.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn sparse_slots_from(&self, start: usize) -> SparseSlots<'_, V> {
+        let start = start.min(self.values.len());
+        SparseSlots {
+            iter: self.values[start..].iter(),
+        }
+    }
+
+    /// Returns a mutable iterator over the slots of the vector, starting at index
+    /// `start`, that also yields the unused slots.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn sparse_slots_mut_from(&mut self, start: usize) -> SparseSlotsMut<'_, V> {
+        let start = start.min(self.values.len());
+        SparseSlotsMut {
+            iter: self.values[start..].iter_mut(),
+        }
+        // SAFETY(invariants):
+        // - SparseSlotsMut only exposes the `V`, which does not affect any invariants.
+    }
+
+    /// Returns a mutable iterator over the slots of the vector.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn slots_mut(&mut self) -> SlotsMut<'_, V> {
+        SlotsMut {
+            iter: self.values.iter_mut(),
+        }
+        // SAFETY(invariants):
+        // - SlotsMut only exposes the `V`, which does not affect any invariants.
+    }
+
     /// Retrieves a reference to the value referenced by a usize.
     ///
     /// # Safety
@@ -298,22 +576,34 @@ impl<V> PosVec<V> {
         // - exposing the `V` does not affect any invariants
     }
 
-    /// Retrieves a reference to the value referenced by a `Pos<InUse>`.
+    /// Returns the index referenced by a `Pos<InUse>`.
     ///
     /// # Safety
     ///
     /// The `Pos<InUse>` must be valid and must have been returned by this object.
     #[inline]
-    pub unsafe fn get_unchecked(&self, pos: &Pos<InUse>) -> &V {
-        #[cfg(test)]
+    pub unsafe fn index_unchecked(&self, pos: &Pos<InUse>) -> usize {
+        #[cfg(any(test, feature = "checked-pos"))]
+        assert_eq!(pos.tag(), self.tag);
         unsafe {
-            assert_eq!(pos.tag_unchecked(), self.tag);
+            // SAFETY:
+            // - Since the Pos<InUse> is valid, the invariants guarantee that it
+            //   corresponds to a Pos<Stored>. Therefore its slot is in bounds.
+            from_raw(*self.slots.get_unchecked(pos.slot()))
         }
+    }
+
+    /// Retrieves a reference to the value referenced by a `Pos<InUse>`.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` must be valid and must have been returned by this object.
+    #[inline]
+    pub unsafe fn get_unchecked(&self, pos: &Pos<InUse>) -> &V {
         let idx = unsafe {
             // SAFETY:
-            // - Since the Pos<InUse> is valid, the invariants guarantee that it
-            //   corresponds to a Pos<Stored>. Therefore the allocation is still valid.
-            pos.get_unchecked()
+            // - The requirements are forwarded to the caller.
+            self.index_unchecked(pos)
         };
         let value_opt = unsafe {
             // SAFETY:
@@ -334,15 +624,10 @@ impl<V> PosVec<V> {
     /// The `Pos<InUse>` must be valid and must have been returned by this object.
     #[inline]
     pub unsafe fn get_unchecked_mut(&mut self, pos: &Pos<InUse>) -> &mut V {
-        #[cfg(test)]
-        unsafe {
-            assert_eq!(pos.tag_unchecked(), self.tag);
-        }
         let idx = unsafe {
             // SAFETY:
-            // - Since the Pos<InUse> is valid, the invariants guarantee that it
-            //   corresponds to a Pos<Stored>. Therefore the allocation is still valid.
-            pos.get_unchecked()
+            // - The requirements are forwarded to the caller.
+            self.index_unchecked(pos)
         };
         let value_opt = unsafe {
             // SAFETY:
@@ -358,6 +643,73 @@ impl<V> PosVec<V> {
         // - exposing the `V` does not affect any invariants
     }
 
+    /// Returns the hash of the value referenced by a `Pos<InUse>`.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` must be valid and must have been returned by this object.
+    #[cfg(feature = "cached-hash")]
+    #[inline]
+    pub unsafe fn hash_unchecked(&self, pos: &Pos<InUse>) -> u64 {
+        let idx = unsafe {
+            // SAFETY:
+            // - The requirements are forwarded to the caller.
+            self.index_unchecked(pos)
+        };
+        let value_opt = unsafe {
+            // SAFETY:
+            // - By the invariants, pos points in-bounds.
+            self.values.get_unchecked(idx)
+        };
+        unsafe {
+            // SAFETY:
+            // - By the invariants, pos points to a Some value.
+            value_opt.as_ref().unwrap_unchecked().hash
+        }
+    }
+
+    /// Returns the hash of the value referenced by a `Pos<InUse>`.
+    ///
+    /// Unlike [`hash_unchecked`](Self::hash_unchecked), this function can be called with
+    /// any `Pos<InUse>`. If the `Pos<InUse>` was not returned by this object, the returned
+    /// hash is unspecified.
+    #[cfg(feature = "cached-hash")]
+    #[inline]
+    pub fn hash_of(&self, pos: &Pos<InUse>) -> u64 {
+        self.slots
+            .get(pos.slot())
+            .and_then(|&idx| self.values.get(from_raw(idx)))
+            .and_then(|v| v.as_ref())
+            .map_or(0, |v| v.hash)
+    }
+
+    /// Replaces the hash of the value referenced by a `Pos<InUse>`.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` must be valid and must have been returned by this object.
+    #[cfg(feature = "cached-hash")]
+    #[inline]
+    pub unsafe fn set_hash_unchecked(&mut self, pos: &Pos<InUse>, hash: u64) {
+        let idx = unsafe {
+            // SAFETY:
+            // - The requirements are forwarded to the caller.
+            self.index_unchecked(pos)
+        };
+        let value_opt = unsafe {
+            // SAFETY:
+            // - By the invariants, pos points in-bounds.
+            self.values.get_unchecked_mut(idx)
+        };
+        unsafe {
+            // SAFETY:
+            // - By the invariants, pos points to a Some value.
+            value_opt.as_mut().unwrap_unchecked().hash = hash;
+        }
+        // SAFETY(invariants):
+        // - the hash does not affect any invariants
+    }
+
     /// Retrieves mutable references to the value referenced by `Pos<InUse>`.
     ///
     /// # Safety
@@ -377,16 +729,10 @@ impl<V> PosVec<V> {
         pos.map(|pos| {
             pos.map(|mut t| {
                 let pos = f(&mut t);
-                #[cfg(test)]
-                unsafe {
-                    assert_eq!(pos.tag_unchecked(), self.tag);
-                }
                 let idx = unsafe {
                     // SAFETY:
-                    // - Since the Pos<InUse> is valid, the invariants guarantee that it
-                    //   corresponds to a Pos<Stored>. Therefore the allocation is still
-                    //   valid.
-                    pos.get_unchecked()
+                    // - The requirements are forwarded to the caller.
+                    self.index_unchecked(pos)
                 };
                 let value_opt = unsafe {
                     // SAFETY:
@@ -394,8 +740,8 @@ impl<V> PosVec<V> {
                     // - Due to the form of F, each `&mut Pos<InUse>` must be owned by its
                     //   array element.
                     // - Therefore they must all be distinct.
-                    // - Therefore, since their tags are all identical, the idx must all
-                    //   be distinct.
+                    // - Therefore, since their tags are all identical, their slots and
+                    //   therefore the idx must all be distinct.
                     // - Therefore, we only created references to distinct elements of
                     //   self.values.
                     &mut *values.add(idx)
@@ -412,6 +758,30 @@ impl<V> PosVec<V> {
         // - exposing the `V` does not affect any invariants
     }
 
+    /// Removes the value stored at a specific index.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` referring to this index, if any, becomes invalid. The caller must
+    /// not use it after this function returns.
+    #[inline]
+    pub(crate) unsafe fn take(&mut self, idx: usize) -> Option<(V, Pos<Free>)> {
+        let value = self.values.get_mut(idx)?.take()?;
+        self.free_slots.push(to_raw(value.pos.slot()));
+        let pos = unsafe {
+            // SAFETY:
+            // - The requirement is forwarded to the caller.
+            // - By the invariants, idx is the index of value.pos.
+            value.pos.into_free(idx)
+        };
+        Some((value.value, pos))
+        // SAFETY(invariants):
+        // - We called take(), therefore pos refers to a None value.
+        // - The invalidation of the Pos<InUse> is forwarded to the caller.
+        // - The slot of the dropped Pos<Stored> has been added to the free slots.
+        // - The tags are unaffected.
+    }
+
     /// Consumes a `Pos<InUse>` and returns the value referenced by it.
     ///
     /// # Safety
@@ -419,15 +789,10 @@ impl<V> PosVec<V> {
     /// The `Pos<InUse>` must be valid and must have been returned by this object.
     #[inline]
     pub(crate) unsafe fn take_unchecked(&mut self, pos: Pos<InUse>) -> (V, Pos<Free>) {
-        #[cfg(test)]
-        unsafe {
-            assert_eq!(pos.tag_unchecked(), self.tag);
-        }
         let idx = unsafe {
             // SAFETY:
-            // - Since the Pos<InUse> is valid, the invariants guarantee that it
-            //   corresponds to a Pos<Stored>. Therefore the allocation is still valid.
-            pos.get_unchecked()
+            // - The requirements are forwarded to the caller.
+            self.index_unchecked(&pos)
         };
         let value = unsafe {
             // SAFETY:
@@ -439,14 +804,68 @@ impl<V> PosVec<V> {
             // - By the invariants, pos points to a Some value.
             value.take().unwrap_unchecked()
         };
+        self.free_slots.push(to_raw(value.pos.slot()));
         let pos = unsafe {
             // SAFETY:
-            // - By the invariants, pos and value.pos are a pair.
-            pos.deactivate(value.pos)
+            // - By the invariants, pos and value.pos are a pair and idx is their index.
+            pos.deactivate(value.pos, idx)
         };
         (value.value, pos)
         // SAFETY(invariants):
         // - We called value.take(), therefore pos refers to a None value.
+        // - The slot of the consumed Pos<Stored> has been added to the free slots.
+        // - The tags are unaffected.
+    }
+
+    /// Consumes a `Pos<InUse>` whose value has already been moved out with `ptr::read`.
+    ///
+    /// Unlike `take_unchecked`, this function neither reads nor drops the value.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` must be valid and must have been returned by this object.
+    #[inline]
+    pub(crate) unsafe fn remove_moved_unchecked(&mut self, pos: Pos<InUse>) -> Pos<Free> {
+        let idx = unsafe {
+            // SAFETY:
+            // - The requirements are forwarded to the caller.
+            self.index_unchecked(&pos)
+        };
+        let value_opt = unsafe {
+            // SAFETY:
+            // - By the invariants, pos points in-bounds.
+            self.values.get_unchecked_mut(idx)
+        };
+        let stored = match value_opt {
+            Some(value) => unsafe {
+                // SAFETY:
+                // - value.pos is a reference and the original is overwritten below
+                //   without being dropped.
+                ptr::read(&value.pos)
+            },
+            None => unsafe {
+                // SAFETY:
+                // - By the invariants, pos points to a Some value.
+                unreachable_unchecked()
+            },
+        };
+        unsafe {
+            // SAFETY:
+            // - value_opt is a reference, therefore ptr::write is safe.
+            // NOTE:
+            // - The value might no longer be valid. Overwriting it with ptr::write
+            //   ensures that it is neither moved nor dropped.
+            ptr::write(value_opt, None);
+        }
+        self.free_slots.push(to_raw(stored.slot()));
+        unsafe {
+            // SAFETY:
+            // - By the invariants, pos and stored are a pair and idx is their index.
+            pos.deactivate(stored, idx)
+        }
+        // SAFETY(invariants):
+        // - We've written None, therefore the returned Pos<Free> refers to a None value.
+        // - The slot of the consumed Pos<Stored> has been added to the free slots.
         // - The tags are unaffected.
     }
 
@@ -454,15 +873,186 @@ impl<V> PosVec<V> {
     #[cfg_attr(feature = "inline-more", inline)]
     pub fn raw_access(&mut self) -> PosVecRawAccess<'_, V> {
         PosVecRawAccess {
-            #[cfg(test)]
+            #[cfg(any(test, feature = "checked-pos"))]
             tag: self.tag,
             values: self.values.as_mut_ptr(),
+            len: self.values.len(),
+            slots: self.slots.as_ptr(),
             _phantom: Default::default(),
         }
     }
 }
 
+impl<V> Slots<'_, V> {
+    /// Returns the number of remaining slots.
+    ///
+    /// This is an upper bound for the number of remaining values.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, V> Iterator for Slots<'a, V> {
+    type Item = &'a V;
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn next(&mut self) -> Option<Self::Item> {
+        prefetch_read(
+            self.iter
+                .as_slice()
+                .as_ptr()
+                .wrapping_add(prefetch::DISTANCE),
+        );
+        self.iter.find_map(|v| v.as_ref()).map(|v| &v.value)
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.iter.len()))
+    }
+}
+
+impl<V> Clone for Slots<'_, V> {
+    fn clone(&self) -> Self {
+        Self {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+impl<'a, V> Iterator for SparseSlots<'a, V> {
+    type Item = Option<&'a V>;
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn next(&mut self) -> Option<Self::Item> {
+        prefetch_read(
+            self.iter
+                .as_slice()
+                .as_ptr()
+                .wrapping_add(prefetch::DISTANCE),
+        );
+        self.iter.next().map(|v| v.as_ref().map(|v| &v.value))
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<V> DoubleEndedIterator for SparseSlots<'_, V> {
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter.next_back().map(|v| v.as_ref().map(|v| &v.value))
+    }
+}
+
+impl<V> ExactSizeIterator for SparseSlots<'_, V> {}
+
+impl<V> Clone for SparseSlots<'_, V> {
+    fn clone(&self) -> Self {
+        Self {
+            iter: self.iter.clone(),
+        }
+    }
+}
+
+impl<'a, V> Iterator for SparseSlotsMut<'a, V> {
+    type Item = Option<&'a mut V>;
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn next(&mut self) -> Option<Self::Item> {
+        prefetch_read(
+            self.iter
+                .as_slice()
+                .as_ptr()
+                .wrapping_add(prefetch::DISTANCE),
+        );
+        self.iter.next().map(|v| v.as_mut().map(|v| &mut v.value))
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+impl<V> ExactSizeIterator for SparseSlotsMut<'_, V> {}
+
+impl<V> SlotsMut<'_, V> {
+    /// Returns the number of remaining slots.
+    ///
+    /// This is an upper bound for the number of remaining values.
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn len(&self) -> usize {
+        self.iter.len()
+    }
+}
+
+impl<'a, V> Iterator for SlotsMut<'a, V> {
+    type Item = &'a mut V;
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn next(&mut self) -> Option<Self::Item> {
+        prefetch_read(
+            self.iter
+                .as_slice()
+                .as_ptr()
+                .wrapping_add(prefetch::DISTANCE),
+        );
+        self.iter.find_map(|v| v.as_mut()).map(|v| &mut v.value)
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.iter.len()))
+    }
+}
+
 impl<'a, V> PosVecRawAccess<'a, V> {
+    /// Retrieves a mutable reference to the value stored at a specific index in the
+    /// vector.
+    ///
+    /// # Safety
+    ///
+    /// This API must not be used to create multiple mutable references for the same
+    /// index.
+    #[inline]
+    pub unsafe fn get_mut(&mut self, idx: usize) -> Option<&'a mut V> {
+        if idx >= self.len {
+            return None;
+        }
+        let value_opt = unsafe {
+            // SAFETY:
+            // - We've checked that idx is in bounds.
+            // - By the requirements of this function, we do not create multiple mutable
+            //   references to the same index.
+            &mut *self.values.add(idx)
+        };
+        value_opt.as_mut().map(|v| &mut v.value)
+        // SAFETY(invariants):
+        // - exposing the `V` does not affect any invariants
+    }
+
+    /// Returns the index referenced by a `Pos<InUse>`.
+    ///
+    /// # Safety
+    ///
+    /// The `Pos<InUse>` must be valid and must have been returned by the PosVec<V> used
+    /// to create this object.
+    #[inline]
+    pub unsafe fn index_unchecked(&self, pos: &Pos<InUse>) -> usize {
+        #[cfg(any(test, feature = "checked-pos"))]
+        assert_eq!(pos.tag(), self.tag);
+        unsafe {
+            // SAFETY:
+            // - Since the Pos<InUse> is valid, the invariants guarantee that it
+            //   corresponds to a Pos<Stored>. Therefore its slot is in bounds.
+            from_raw(*self.slots.add(pos.slot()))
+        }
+    }
+
     /// Retrieves a mutable reference to the value referenced by a `Pos<InUse>`.
     ///
     /// # Safety
@@ -473,15 +1063,13 @@ impl<'a, V> PosVecRawAccess<'a, V> {
     ///   `Pos<InUse>`.
     #[inline]
     pub unsafe fn get_unchecked_mut(&mut self, pos: &Pos<InUse>) -> &'a mut V {
-        #[cfg(test)]
-        unsafe {
-            assert_eq!(pos.tag_unchecked(), self.tag);
-        }
+        #[cfg(any(test, feature = "checked-pos"))]
+        assert_eq!(pos.tag(), self.tag);
         let idx = unsafe {
             // SAFETY:
             // - Since the Pos<InUse> is valid, the invariants guarantee that it
-            //   corresponds to a Pos<Stored>. Therefore the allocation is still valid.
-            pos.get_unchecked()
+            //   corresponds to a Pos<Stored>. Therefore its slot is in bounds.
+            from_raw(*self.slots.add(pos.slot()))
         };
         let value_opt = unsafe {
             // SAFETY:
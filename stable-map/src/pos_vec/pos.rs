@@ -1,60 +1,66 @@
 use {
-    crate::pos_vec::{
-        pos::private::{AllocationView, Borrower, Data, Owner, TypeState},
-        tag::Tag,
-    },
-    alloc::boxed::Box,
-    core::{marker::PhantomData, mem::ManuallyDrop, ptr::NonNull},
+    crate::pos_vec::{pos::private::TypeState, tag::Tag},
+    core::marker::PhantomData,
 };
 
+/// The integer type used to store indices and slots.
+#[cfg(feature = "narrow-index")]
+pub(crate) type RawIndex = u32;
+
+/// The integer type used to store indices and slots.
+#[cfg(not(feature = "narrow-index"))]
+pub(crate) type RawIndex = usize;
+
+/// The maximum length of a vector.
+///
+/// Every index and slot is less than this value and can therefore be stored in a
+/// `RawIndex`.
+pub(crate) const MAX_LEN: usize = if RawIndex::BITS < usize::BITS {
+    from_raw(RawIndex::MAX)
+} else {
+    usize::MAX
+};
+
+/// Converts an index or slot to a `RawIndex`.
+///
+/// The value must be less than [MAX_LEN].
+#[inline(always)]
+#[allow(clippy::unnecessary_cast)]
+pub(crate) const fn to_raw(value: usize) -> RawIndex {
+    value as RawIndex
+}
+
+/// Converts a `RawIndex` back to an index or slot.
+#[inline(always)]
+#[allow(clippy::unnecessary_cast)]
+pub(crate) const fn from_raw(value: RawIndex) -> usize {
+    value as usize
+}
+
 /// A position in a vector.
 ///
 /// There are three versions of this:
 ///
 /// - `Pos<Free>`: An unoccupied position in a vector.
 /// - `Pos<InUse>` and `Pos<Stored>`: An occupied position in a vector, always occur as a
-///    pair.
+///   pair.
 ///
-/// Each `Pos` contains a pointer to an allocated `usize`. A `Pos<InUse>` and
-/// `Pos<Stored>` point to the same allocation. `Pos<Free>` and `Pos<Stored>` own the
-/// allocation, dropping them frees it. `Pos<InUse>` must not be used after the
-/// `Pos<Stored>` has been dropped since the pointer is dangling.
+/// A `Pos<Free>` contains the index of the position. A `Pos<InUse>` and `Pos<Stored>`
+/// instead contain the same slot. The vector maps each slot to the current index of the
+/// position. This allows the vector to move the value without invalidating the
+/// `Pos<InUse>`. `Pos<InUse>` must not be used after the `Pos<Stored>` has been dropped
+/// since the slot might have been reused.
 ///
 /// `Pos<Free>` can be converted to a `Pos<InUse>`, `Pos<Stored>` pair and vice versa.
 #[derive(Debug)]
 pub struct Pos<T: TypeState> {
-    data: NonNull<Data>,
+    tag: Tag,
+    value: RawIndex,
     _phantom: PhantomData<T>,
 }
 
 mod private {
-    use crate::pos_vec::tag::Tag;
-
-    pub struct Data {
-        #[cfg_attr(not(test), expect(dead_code))]
-        pub tag: Tag,
-        pub pos: usize,
-    }
-
-    pub trait TypeState {
-        type AllocationView: AllocationView;
-    }
-
-    pub struct Owner;
-
-    pub struct Borrower;
-
-    pub trait AllocationView {
-        const OWNER: bool;
-    }
-
-    impl AllocationView for Owner {
-        const OWNER: bool = true;
-    }
-
-    impl AllocationView for Borrower {
-        const OWNER: bool = false;
-    }
+    pub trait TypeState {}
 }
 
 #[derive(Debug)]
@@ -66,38 +72,38 @@ pub(crate) struct Free;
 #[derive(Debug)]
 pub(super) struct Stored;
 
-impl TypeState for InUse {
-    type AllocationView = Borrower;
-}
+impl TypeState for InUse {}
 
-impl TypeState for Free {
-    type AllocationView = Owner;
-}
+impl TypeState for Free {}
 
-impl TypeState for Stored {
-    type AllocationView = Owner;
-}
+impl TypeState for Stored {}
 
-impl<T: TypeState> Drop for Pos<T> {
-    fn drop(&mut self) {
-        if <T::AllocationView as AllocationView>::OWNER {
-            unsafe {
-                let _ = Box::from_raw(self.data.as_ptr());
-            }
-        }
+impl<T: TypeState> Pos<T> {
+    #[cfg(any(test, feature = "checked-pos"))]
+    pub(super) fn tag(&self) -> Tag {
+        self.tag
     }
 }
 
 impl Pos<InUse> {
+    /// Returns the slot of this object.
+    pub(super) fn slot(&self) -> usize {
+        from_raw(self.value)
+    }
+
+    /// Converts this object to a `Pos<Free>` with the index `idx`.
+    ///
     /// # Safety
     ///
-    /// `self` and `stored` must be a pair returned by [Pos<Free>::activate].
-    pub(super) unsafe fn deactivate(self, stored: Pos<Stored>) -> Pos<Free> {
-        #[cfg(test)]
-        assert_eq!(self.data, stored.data);
-        let _ = ManuallyDrop::new(stored);
+    /// - `self` and `stored` must be a pair returned by [Pos<Free>::activate].
+    /// - `idx` must be the index of the pair.
+    pub(super) unsafe fn deactivate(self, stored: Pos<Stored>, idx: usize) -> Pos<Free> {
+        #[cfg(any(test, feature = "checked-pos"))]
+        assert_eq!(self.value, stored.value);
+        let _ = stored;
         Pos {
-            data: self.data,
+            tag: self.tag,
+            value: to_raw(idx),
             _phantom: PhantomData,
         }
     }
@@ -106,92 +112,58 @@ impl Pos<InUse> {
 impl Pos<Free> {
     /// # Safety
     ///
-    /// For each `(tag, pos)` there must be at most one `Pos<Free>` or `Pos<Stored>`.
+    /// - For each `(tag, pos)` there must be at most one `Pos<Free>` or `Pos<Stored>`.
+    /// - `pos` must be less than [MAX_LEN].
     pub unsafe fn new(tag: Tag, pos: usize) -> Self {
         Self {
-            data: Box::leak(Box::new(Data { tag, pos })).into(),
+            tag,
+            value: to_raw(pos),
             _phantom: PhantomData,
         }
     }
 
-    /// Converts this object to a `Pos<InUse>`, `Pos<Stored>` pair.
-    pub(super) fn activate(self) -> (Pos<InUse>, Pos<Stored>) {
-        let slf = ManuallyDrop::new(self);
+    /// Converts this object to a `Pos<InUse>`, `Pos<Stored>` pair using `slot`.
+    ///
+    /// # Safety
+    ///
+    /// - For each `(tag, slot)` there must be at most one `Pos<Stored>`.
+    /// - `slot` must be less than [MAX_LEN].
+    pub(super) unsafe fn activate(self, slot: usize) -> (Pos<InUse>, Pos<Stored>) {
         let active = Pos {
-            data: slf.data,
+            tag: self.tag,
+            value: to_raw(slot),
             _phantom: PhantomData,
         };
         let borrow = Pos {
-            data: slf.data,
+            tag: self.tag,
+            value: to_raw(slot),
             _phantom: PhantomData,
         };
         (active, borrow)
     }
-}
 
-impl Pos<Stored> {
-    /// Changes the index of this object and the corresponding `Pos<InUse>` to the index
-    /// of `pos`.
-    ///
-    /// The invariant that `(tag, pos)` are unique is automatically enforced since the
-    /// `Pos<Free>` is consumed.
-    ///
-    /// Returns `pos.get()`.
-    ///
-    /// # Safety
-    ///
-    /// `self` and `pos` must have the same tag.
-    pub(crate) unsafe fn set(&mut self, pos: Pos<Free>) -> usize {
-        #[cfg(test)]
-        assert_eq!(self.tag(), pos.tag());
-        let idx = pos.get();
-        unsafe {
-            // SAFETY:
-            // - Pos<Stored> owns the allocation. Therefore the pointer is still valid.
-            self.data.as_mut().pos = idx;
-        }
-        idx
+    pub(crate) fn get(&self) -> usize {
+        from_raw(self.value)
     }
 }
 
-impl<T: TypeState<AllocationView = Owner>> Pos<T> {
-    #[cfg(test)]
-    pub(super) fn tag(&self) -> Tag {
-        unsafe {
-            // SAFETY: This Pos owns the allocation, so the pointer is valid.
-            self.data.as_ref().tag
-        }
-    }
-
-    pub(super) fn get(&self) -> usize {
-        unsafe {
-            // SAFETY: This Pos owns the allocation, so the pointer is valid.
-            self.data.as_ref().pos
-        }
+impl Pos<Stored> {
+    /// Returns the slot of this object.
+    pub(super) fn slot(&self) -> usize {
+        from_raw(self.value)
     }
-}
 
-impl<T: TypeState<AllocationView = Borrower>> Pos<T> {
-    /// # Safety
+    /// Converts this object to a `Pos<Free>` with the index `idx`.
     ///
-    /// The allocation pointed to by this Pos must still be valid.
-    pub(crate) unsafe fn get_unchecked(&self) -> usize {
-        unsafe {
-            // SAFETY:
-            // - The requirement is forwarded to the caller.
-            self.data.as_ref().pos
-        }
-    }
-
     /// # Safety
     ///
-    /// The allocation pointed to by this Pos must still be valid.
-    #[cfg(test)]
-    pub(super) unsafe fn tag_unchecked(&self) -> Tag {
-        unsafe {
-            // SAFETY:
-            // - The requirement is forwarded to the caller.
-            self.data.as_ref().tag
+    /// - The corresponding `Pos<InUse>` must not be used after this function returns.
+    /// - `idx` must be the index of this object.
+    pub(super) unsafe fn into_free(self, idx: usize) -> Pos<Free> {
+        Pos {
+            tag: self.tag,
+            value: to_raw(idx),
+            _phantom: PhantomData,
         }
     }
 }
@@ -1,24 +1,24 @@
-#[cfg(test)]
+#[cfg(any(test, feature = "checked-pos"))]
 type Data = usize;
 
-#[cfg(not(test))]
+#[cfg(not(any(test, feature = "checked-pos")))]
 type Data = ();
 
 /// A unique tag.
 #[derive(Copy, Clone, Debug)]
-#[cfg_attr(test, derive(Eq, PartialEq))]
+#[cfg_attr(any(test, feature = "checked-pos"), derive(Eq, PartialEq))]
 pub struct Tag(Data);
 
 impl Tag {
     /// Returns a new, unique tag.
     pub fn next() -> Self {
-        #[cfg(test)]
+        #[cfg(any(test, feature = "checked-pos"))]
         {
             use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
             static NEXT: AtomicUsize = AtomicUsize::new(0);
             Self(NEXT.fetch_add(1, Relaxed))
         }
-        #[cfg(not(test))]
+        #[cfg(not(any(test, feature = "checked-pos")))]
         {
             Self(())
         }
@@ -1,13 +1,39 @@
 use {
-    crate::pos_vec::{PosVec, PositionedValue},
-    alloc::vec,
+    crate::pos_vec::{
+        pos::{Pos, RawIndex, Stored, MAX_LEN},
+        PosVec, PositionedValue,
+    },
+    alloc::{vec, vec::Vec},
+    core::array,
     static_assertions::assert_eq_size,
 };
 
+#[cfg(feature = "cached-hash")]
 assert_eq_size! {
     PositionedValue<usize>,
     Option<PositionedValue<usize>>,
-    [usize; 2],
+    (Pos<Stored>, u64, u64, usize),
+}
+
+#[cfg(not(feature = "cached-hash"))]
+assert_eq_size! {
+    PositionedValue<usize>,
+    Option<PositionedValue<usize>>,
+    (Pos<Stored>, u64, usize),
+}
+
+#[cfg(feature = "narrow-index")]
+assert_eq_size!(RawIndex, u32);
+
+#[cfg(not(feature = "narrow-index"))]
+assert_eq_size!(RawIndex, usize);
+
+#[test]
+fn max_len() {
+    #[cfg(feature = "narrow-index")]
+    assert_eq!(MAX_LEN, u32::MAX as usize);
+    #[cfg(not(feature = "narrow-index"))]
+    assert_eq!(MAX_LEN, usize::MAX);
 }
 
 #[test]
@@ -65,13 +91,13 @@ fn create_pos() {
 fn store() {
     let mut v = PosVec::with_capacity(0);
     let p1 = v.create_pos();
-    let p1 = unsafe { v.store(p1, 1) };
+    let p1 = unsafe { v.store(p1, 0, 1) };
     unsafe {
         assert_eq!(*v.get_unchecked(&p1), 1);
     }
     assert_eq!(v.get(0), Some(&1));
     let p2 = v.create_pos();
-    let p2 = unsafe { v.store(p2, 2) };
+    let p2 = unsafe { v.store(p2, 0, 2) };
     unsafe {
         assert_eq!(*v.get_unchecked(&p2), 2);
     }
@@ -83,7 +109,7 @@ fn store() {
         assert_eq!(*v.get_unchecked(&p2), 2);
     }
     assert_eq!(v.get(1), Some(&2));
-    let p1 = unsafe { v.store(p1, 3) };
+    let p1 = unsafe { v.store(p1, 0, 3) };
     unsafe {
         assert_eq!(*v.get_unchecked(&p1), 3);
     }
@@ -99,10 +125,10 @@ fn compact() {
     let p4 = v.create_pos();
     let p5 = v.create_pos();
     let p6 = v.create_pos();
-    let p1 = unsafe { v.store(p1, 1) };
-    let p2 = unsafe { v.store(p2, 2) };
-    let p5 = unsafe { v.store(p5, 3) };
-    let p6 = unsafe { v.store(p6, 4) };
+    let p1 = unsafe { v.store(p1, 0, 1) };
+    let p2 = unsafe { v.store(p2, 0, 2) };
+    let p5 = unsafe { v.store(p5, 0, 3) };
+    let p6 = unsafe { v.store(p6, 0, 4) };
     assert_eq!(v.get(0), Some(&1));
     assert_eq!(v.get(1), Some(&2));
     assert_eq!(v.get(2), None);
@@ -124,8 +150,8 @@ fn compact() {
     assert_eq!(v.get(2), Some(&4));
     assert_eq!(v.get(3), Some(&3));
     unsafe {
-        assert_eq!(p6.get_unchecked(), 2);
-        assert_eq!(p5.get_unchecked(), 3);
+        assert_eq!(v.index_unchecked(&p6), 2);
+        assert_eq!(v.index_unchecked(&p5), 3);
         assert_eq!(v.get_unchecked(&p1), &1);
         assert_eq!(v.get_unchecked(&p2), &2);
         assert_eq!(v.get_unchecked(&p5), &3);
@@ -133,13 +159,43 @@ fn compact() {
     }
 }
 
+#[test]
+fn slots() {
+    let mut v = PosVec::with_capacity(8);
+    let [p0, p1, p2, p3] = array::from_fn(|_| v.create_pos());
+    let p0 = unsafe { v.store(p0, 0, 0) };
+    let p3 = unsafe { v.store(p3, 0, 3) };
+    assert_eq!(v.slots.len(), 2);
+    let mut free = vec![p2, p1];
+    unsafe {
+        v.compact(|| free.pop());
+    }
+    assert_eq!(v.len(), 2);
+    assert_eq!(v.slots.len(), 2);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p3), 1);
+        assert_eq!(v.get_unchecked(&p0), &0);
+        assert_eq!(v.get_unchecked(&p3), &3);
+    }
+    let (_, p0) = unsafe { v.take_unchecked(p0) };
+    assert_eq!(v.free_slots.len(), 1);
+    let p0 = unsafe { v.store(p0, 0, 4) };
+    assert_eq!(v.free_slots.len(), 0);
+    assert_eq!(v.slots.len(), 2);
+    unsafe {
+        assert_eq!(v.index_unchecked(&p0), 0);
+        assert_eq!(v.get_unchecked(&p0), &4);
+        assert_eq!(v.get_unchecked(&p3), &3);
+    }
+}
+
 #[test]
 fn clear() {
     let mut v = PosVec::with_capacity(0);
     let p1 = v.create_pos();
     let p2 = v.create_pos();
-    unsafe { v.store(p1, 1) };
-    unsafe { v.store(p2, 2) };
+    unsafe { v.store(p1, 0, 1) };
+    unsafe { v.store(p2, 0, 2) };
     assert_eq!(v.len(), 2);
     v.clear();
     assert_eq!(v.len(), 0);
@@ -150,30 +206,147 @@ fn get() {
     let mut v = PosVec::with_capacity(0);
     let p1 = v.create_pos();
     let p2 = v.create_pos();
-    unsafe { v.store(p1, 1) };
-    unsafe { v.store(p2, 2) };
+    unsafe { v.store(p1, 0, 1) };
+    unsafe { v.store(p2, 0, 2) };
     assert_eq!(v.get(0), Some(&1));
     assert_eq!(v.get(1), Some(&2));
     assert_eq!(v.get_mut(0), Some(&mut 1));
     assert_eq!(v.get_mut(1), Some(&mut 2));
 }
 
+#[test]
+fn sparse_slots() {
+    let mut v = PosVec::with_capacity(0);
+    let [p0, _p1, p2] = array::from_fn(|_| v.create_pos());
+    unsafe { v.store(p0, 0, 0) };
+    unsafe { v.store(p2, 0, 2) };
+    let slots = v.sparse_slots();
+    assert_eq!(slots.len(), 3);
+    assert_eq!(
+        slots.clone().collect::<Vec<_>>(),
+        [Some(&0), None, Some(&2)]
+    );
+    assert_eq!(slots.rev().collect::<Vec<_>>(), [Some(&2), None, Some(&0)]);
+}
+
+#[test]
+fn sparse_slots_from() {
+    let mut v = PosVec::with_capacity(0);
+    let [p0, _p1, p2] = array::from_fn(|_| v.create_pos());
+    unsafe { v.store(p0, 0, 0) };
+    unsafe { v.store(p2, 0, 2) };
+    assert_eq!(v.sparse_slots_from(1).collect::<Vec<_>>(), [None, Some(&2)]);
+    assert_eq!(v.sparse_slots_from(4).len(), 0);
+    for value in v.sparse_slots_mut_from(2).flatten() {
+        *value += 1;
+    }
+    assert_eq!(v.get(2), Some(&3));
+    assert_eq!(v.sparse_slots_mut_from(4).len(), 0);
+}
+
+#[test]
+fn next_occupied() {
+    let mut v = PosVec::with_capacity(0);
+    let [p0, _p1, _p2, p3] = array::from_fn(|_| v.create_pos());
+    unsafe { v.store(p0, 0, 0) };
+    unsafe { v.store(p3, 0, 3) };
+    assert_eq!(v.next_occupied(0), Some(0));
+    assert_eq!(v.next_occupied(1), Some(3));
+    assert_eq!(v.next_occupied(3), Some(3));
+    assert_eq!(v.next_occupied(4), None);
+    assert_eq!(v.next_occupied(usize::MAX), None);
+}
+
+#[test]
+fn last_occupied() {
+    let mut v = PosVec::with_capacity(0);
+    assert_eq!(v.last_occupied(), None);
+    let [p0, _p1, _p2] = array::from_fn(|_| v.create_pos());
+    assert_eq!(v.last_occupied(), None);
+    unsafe { v.store(p0, 0, 0) };
+    assert_eq!(v.last_occupied(), Some(0));
+}
+
+#[test]
+fn is_occupied() {
+    let mut v = PosVec::with_capacity(0);
+    let p1 = v.create_pos();
+    let _p2 = v.create_pos();
+    let p1 = unsafe { v.store(p1, 0, 1) };
+    assert!(v.is_occupied(0));
+    assert!(!v.is_occupied(1));
+    assert!(!v.is_occupied(2));
+    unsafe { v.take_unchecked(p1) };
+    assert!(!v.is_occupied(0));
+}
+
+#[test]
+fn generation() {
+    let mut v = PosVec::with_capacity(0);
+    let p1 = v.create_pos();
+    let p2 = v.create_pos();
+    let p3 = v.create_pos();
+    unsafe { v.store(p1, 0, 1) };
+    unsafe { v.store(p3, 0, 3) };
+    assert_eq!(v.generation(0), Some(1));
+    assert_eq!(v.generation(1), None);
+    assert_eq!(v.generation(2), Some(2));
+    let mut free = Some(p2);
+    unsafe {
+        v.compact(|| free.take());
+    }
+    assert_eq!(v.generation(0), Some(1));
+    assert_eq!(v.generation(1), Some(3));
+    assert_eq!(v.generation(2), None);
+}
+
+#[cfg(feature = "cached-hash")]
+#[test]
+fn hash() {
+    let mut v = PosVec::with_capacity(0);
+    let p1 = v.create_pos();
+    let p2 = v.create_pos();
+    let p3 = v.create_pos();
+    let p1 = unsafe { v.store(p1, 11, 1) };
+    let p3 = unsafe { v.store(p3, 33, 3) };
+    assert_eq!(v.hash(0), Some(11));
+    assert_eq!(v.hash(1), None);
+    assert_eq!(v.hash(2), Some(33));
+    let mut free = Some(p2);
+    unsafe {
+        v.compact(|| free.take());
+    }
+    assert_eq!(v.hash(1), Some(33));
+    assert_eq!(v.hash(2), None);
+    unsafe {
+        v.set_hash_unchecked(&p1, 12);
+        v.set_hash_unchecked(&p3, 34);
+    }
+    assert_eq!(v.hash(0), Some(12));
+    assert_eq!(v.hash(1), Some(34));
+    unsafe {
+        assert_eq!(v.hash_unchecked(&p3), 34);
+    }
+    assert_eq!(v.hash_of(&p1), 12);
+    assert_eq!(v.hash_of(&p3), 34);
+}
+
 #[test]
 fn get_unchecked() {
     let mut v = PosVec::with_capacity(0);
     let p1 = v.create_pos();
     let p2 = v.create_pos();
-    let p1 = unsafe { v.store(p1, 1) };
-    let p2 = unsafe { v.store(p2, 2) };
+    let p1 = unsafe { v.store(p1, 0, 1) };
+    let p2 = unsafe { v.store(p2, 0, 2) };
     unsafe {
         assert_eq!(v.get_unchecked(&p1), &1);
         assert_eq!(v.get_unchecked(&p2), &2);
         assert_eq!(v.get_unchecked_mut(&p1), &mut 1);
         assert_eq!(v.get_unchecked_mut(&p2), &mut 2);
-        assert_eq!(v.get_unchecked_raw(p1.get_unchecked()), &1);
-        assert_eq!(v.get_unchecked_raw(p2.get_unchecked()), &2);
-        assert_eq!(v.get_unchecked_raw_mut(p1.get_unchecked()), &mut 1);
-        assert_eq!(v.get_unchecked_raw_mut(p2.get_unchecked()), &mut 2);
+        assert_eq!(v.get_unchecked_raw(v.index_unchecked(&p1)), &1);
+        assert_eq!(v.get_unchecked_raw(v.index_unchecked(&p2)), &2);
+        assert_eq!(v.get_unchecked_raw_mut(v.index_unchecked(&p1)), &mut 1);
+        assert_eq!(v.get_unchecked_raw_mut(v.index_unchecked(&p2)), &mut 2);
     }
 }
 
@@ -182,8 +355,8 @@ fn get_many_unchecked_mut() {
     let mut v = PosVec::with_capacity(0);
     let p1 = v.create_pos();
     let p2 = v.create_pos();
-    let mut p1 = unsafe { v.store(p1, 1) };
-    let mut p2 = unsafe { v.store(p2, 2) };
+    let mut p1 = unsafe { v.store(p1, 0, 1) };
+    let mut p2 = unsafe { v.store(p2, 0, 2) };
     unsafe {
         assert_eq!(
             v.get_many_unchecked_mut([Some(&mut p1), None, Some(&mut p2)], |v| *v, |_, v| v,),
@@ -197,8 +370,8 @@ fn take_unchecked() {
     let mut v = PosVec::with_capacity(0);
     let p1 = v.create_pos();
     let p2 = v.create_pos();
-    let p1 = unsafe { v.store(p1, 1) };
-    let p2 = unsafe { v.store(p2, 2) };
+    let p1 = unsafe { v.store(p1, 0, 1) };
+    let p2 = unsafe { v.store(p2, 0, 2) };
     unsafe {
         assert_eq!(v.get_unchecked(&p1), &1);
         assert_eq!(v.get_unchecked(&p2), &2);
@@ -0,0 +1,22 @@
+/// The number of elements ahead of the current element that are prefetched by
+/// iterators.
+pub(crate) const DISTANCE: usize = 8;
+
+/// Hints to the CPU that the memory at `ptr` will be read soon.
+///
+/// This function never dereferences `ptr`, so the pointer does not have to be valid.
+/// This is a no-op unless the `prefetch` feature is enabled and the target supports
+/// software prefetching.
+#[inline(always)]
+pub(crate) fn prefetch_read<T>(ptr: *const T) {
+    #[cfg(all(feature = "prefetch", target_arch = "x86_64"))]
+    unsafe {
+        use core::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+        // SAFETY:
+        // - SSE is part of the x86_64 baseline.
+        // - Prefetching is only a hint and does not fault, even for invalid addresses.
+        _mm_prefetch::<_MM_HINT_T0>(ptr.cast());
+    }
+    #[cfg(not(all(feature = "prefetch", target_arch = "x86_64")))]
+    let _ = ptr;
+}
@@ -0,0 +1,891 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::{
+        key_map::{rehash, KeyMap},
+        linear_storage::LinearStorage,
+        pos_vec::pos::{InUse, Pos},
+    },
+    allocator_api2::alloc::{Allocator, Global},
+    core::{
+        fmt::{Debug, Formatter},
+        hash::{BuildHasher, Hash},
+        marker::PhantomData,
+        mem,
+    },
+    hashbrown::{hash_table, Equivalent, HashTable},
+};
+
+/// A builder for computing where in a [`StableMap`] a key-value pair would be stored.
+///
+/// This `struct` is constructed from the [`raw_entry`] method on [`StableMap`].
+///
+/// [`StableMap`]: crate::StableMap
+/// [`raw_entry`]: crate::StableMap::raw_entry
+///
+/// # Examples
+///
+/// ```
+/// use {
+///     core::hash::BuildHasher,
+///     stable_map::{RawEntryBuilder, StableMap},
+/// };
+///
+/// let mut map = StableMap::new();
+/// map.extend([(1, 10), (2, 20), (3, 30)]);
+///
+/// let hash = map.hasher().hash_one(&2);
+/// let builder: RawEntryBuilder<_, _, _> = map.raw_entry();
+/// assert_eq!(builder.from_key_hashed_nocheck(hash, &2), Some((&2, &20)));
+/// ```
+pub struct RawEntryBuilder<'a, K, V, S, A: Allocator = Global> {
+    pub(crate) map: &'a KeyMap<K, S, A>,
+    pub(crate) entries: &'a LinearStorage<V, A>,
+}
+
+/// A builder for computing where in a [`StableMap`] a key-value pair would be stored.
+///
+/// This `struct` is constructed from the [`raw_entry_mut`] method on [`StableMap`].
+///
+/// [`StableMap`]: crate::StableMap
+/// [`raw_entry_mut`]: crate::StableMap::raw_entry_mut
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{RawEntryBuilderMut, RawEntryMut, StableMap};
+///
+/// let mut map = StableMap::new();
+/// map.extend([(1, 10), (2, 20), (3, 30)]);
+///
+/// let builder: RawEntryBuilderMut<_, _, _> = map.raw_entry_mut();
+/// match builder.from_key(&4) {
+///     RawEntryMut::Occupied(_) => unreachable!(),
+///     RawEntryMut::Vacant(v) => {
+///         v.insert(4, 40);
+///     }
+/// }
+/// assert_eq!(map[&4], 40);
+/// ```
+pub struct RawEntryBuilderMut<'a, K, V, S, A: Allocator = Global> {
+    pub(crate) map: &'a mut KeyMap<K, S, A>,
+    pub(crate) entries: &'a mut LinearStorage<V, A>,
+}
+
+/// A view into a single entry in a map, which may either be vacant or occupied.
+///
+/// This is a lower-level version of [`Entry`](crate::Entry).
+///
+/// This `enum` is constructed through the [`raw_entry_mut`] method on [`StableMap`],
+/// then calling one of the methods of that [`RawEntryBuilderMut`].
+///
+/// [`StableMap`]: crate::StableMap
+/// [`raw_entry_mut`]: crate::StableMap::raw_entry_mut
+///
+/// # Examples
+///
+/// ```
+/// use {
+///     core::hash::BuildHasher,
+///     stable_map::{RawEntryMut, StableMap},
+/// };
+///
+/// let mut map = StableMap::new();
+/// map.extend([('a', 1), ('b', 2), ('c', 3)]);
+///
+/// // Existing key (and_modify)
+/// let hash = map.hasher().hash_one(&'a');
+/// map.raw_entry_mut()
+///     .from_key_hashed_nocheck(hash, &'a')
+///     .and_modify(|_, v| *v = 10);
+/// assert_eq!(map.len(), 3);
+///
+/// // Nonexistent key (or_insert)
+/// map.raw_entry_mut().from_key(&'d').or_insert('d', 40);
+/// assert_eq!(map.len(), 4);
+///
+/// // Existing key (or_insert)
+/// let hash = map.hasher().hash_one(&'b');
+/// let (_, v) = map
+///     .raw_entry_mut()
+///     .from_hash(hash, |q| *q == 'b')
+///     .or_insert('b', 20);
+/// assert_eq!(std::mem::replace(v, 20), 2);
+///
+/// // Nonexistent key (or_insert_with)
+/// map.raw_entry_mut().from_key(&'e').or_insert_with(|| ('e', 50));
+///
+/// let mut vec: Vec<_> = map.iter().map(|(&k, &v)| (k, v)).collect();
+/// vec.sort_unstable();
+/// assert_eq!(vec, [('a', 10), ('b', 20), ('c', 3), ('d', 40), ('e', 50)]);
+/// ```
+pub enum RawEntryMut<'a, K, V, S, A: Allocator = Global> {
+    /// An occupied entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    /// let mut map: StableMap<_, _> = [("a", 100), ("b", 200)].into();
+    ///
+    /// match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => unreachable!(),
+    ///     RawEntryMut::Occupied(_) => { }
+    /// }
+    /// ```
+    Occupied(RawOccupiedEntryMut<'a, K, V, S, A>),
+    /// A vacant entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    /// let mut map: StableMap<&str, i32> = StableMap::new();
+    ///
+    /// match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Occupied(_) => unreachable!(),
+    ///     RawEntryMut::Vacant(_) => { }
+    /// }
+    /// ```
+    Vacant(RawVacantEntryMut<'a, K, V, S, A>),
+}
+
+/// A view into an occupied entry in a [`StableMap`](crate::StableMap).
+/// It is part of the [`RawEntryMut`] enum.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{RawEntryMut, StableMap};
+///
+/// let mut map = StableMap::new();
+/// map.extend([("a", 10), ("b", 20), ("c", 30)]);
+///
+/// match map.raw_entry_mut().from_key(&"a") {
+///     RawEntryMut::Vacant(_) => unreachable!(),
+///     RawEntryMut::Occupied(mut o) => {
+///         assert_eq!(o.get_key_value(), (&"a", &10));
+///         *o.get_mut() *= 10;
+///         assert_eq!(o.insert(1111), 100);
+///     }
+/// }
+/// assert_eq!(map[&"a"], 1111);
+///
+/// match map.raw_entry_mut().from_key(&"c") {
+///     RawEntryMut::Vacant(_) => unreachable!(),
+///     RawEntryMut::Occupied(o) => {
+///         assert_eq!(o.remove_entry(), ("c", 30));
+///     }
+/// }
+/// assert_eq!(map.get(&"c"), None);
+/// assert_eq!(map.len(), 2);
+/// ```
+pub struct RawOccupiedEntryMut<'a, K, V, S, A: Allocator = Global> {
+    pub(crate) entry: hash_table::OccupiedEntry<'a, (K, Pos<InUse>), A>,
+    pub(crate) entries: &'a mut LinearStorage<V, A>,
+    pub(crate) _phantom: PhantomData<&'a mut S>,
+}
+
+/// A view into a vacant entry in a [`StableMap`](crate::StableMap).
+/// It is part of the [`RawEntryMut`] enum.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{RawEntryMut, StableMap};
+///
+/// let mut map = StableMap::<&str, i32>::new();
+///
+/// match map.raw_entry_mut().from_key(&"a") {
+///     RawEntryMut::Occupied(_) => unreachable!(),
+///     RawEntryMut::Vacant(v) => {
+///         let (k, v) = v.insert("a", 10);
+///         assert_eq!((*k, *v), ("a", 10));
+///     }
+/// }
+/// assert!(map[&"a"] == 10 && map.len() == 1);
+/// ```
+pub struct RawVacantEntryMut<'a, K, V, S, A: Allocator = Global> {
+    pub(crate) table: &'a mut HashTable<(K, Pos<InUse>), A>,
+    pub(crate) hash_builder: &'a S,
+    pub(crate) entries: &'a mut LinearStorage<V, A>,
+}
+
+impl<'a, K, V, S, A: Allocator> RawEntryBuilder<'a, K, V, S, A> {
+    /// Access an immutable entry by key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// assert_eq!(map.raw_entry().from_key(&"a"), Some((&"a", &100)));
+    /// assert_eq!(map.raw_entry().from_key(&"c"), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn from_key<Q>(self, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        S: BuildHasher,
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let hash = self.map.hasher().hash_one(k);
+        self.from_key_hashed_nocheck(hash, k)
+    }
+
+    /// Access an immutable entry by a key and its hash.
+    ///
+    /// The hash must have been computed with the hasher of the map. This is not checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {core::hash::BuildHasher, stable_map::StableMap};
+    ///
+    /// let map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// let hash = map.hasher().hash_one(&"a");
+    ///
+    /// assert_eq!(map.raw_entry().from_key_hashed_nocheck(hash, &"a"), Some((&"a", &100)));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn from_key_hashed_nocheck<Q>(self, hash: u64, k: &Q) -> Option<(&'a K, &'a V)>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        self.from_hash(hash, |q| k.equivalent(q))
+    }
+
+    /// Access an immutable entry by hash and matching function.
+    ///
+    /// The hash must have been computed with the hasher of the map. This is not checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {core::hash::BuildHasher, stable_map::StableMap};
+    ///
+    /// let map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// let hash = map.hasher().hash_one(&"a");
+    ///
+    /// assert_eq!(map.raw_entry().from_hash(hash, |k| *k == "a"), Some((&"a", &100)));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn from_hash<F>(self, hash: u64, is_match: F) -> Option<(&'a K, &'a V)>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let (k, pos) = self.map.find(hash, is_match)?;
+        let v = unsafe {
+            // SAFETY: By the invariants, all Pos<InUse> in the map are valid.
+            self.entries.get_unchecked(pos)
+        };
+        Some((k, v))
+    }
+}
+
+impl<'a, K, V, S, A: Allocator> RawEntryBuilderMut<'a, K, V, S, A> {
+    /// Creates a `RawEntryMut` from the given key.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// let key = "a";
+    /// let entry: RawEntryMut<&str, u32, _> = map.raw_entry_mut().from_key(&key);
+    /// assert_eq!(entry.or_insert(key, 300), (&mut "a", &mut 100));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn from_key<Q>(self, k: &Q) -> RawEntryMut<'a, K, V, S, A>
+    where
+        S: BuildHasher,
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        let hash = self.map.hasher().hash_one(k);
+        self.from_key_hashed_nocheck(hash, k)
+    }
+
+    /// Creates a `RawEntryMut` from the given key and its hash.
+    ///
+    /// The hash must have been computed with the hasher of the map. This is not checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {
+    ///     core::hash::BuildHasher,
+    ///     stable_map::{RawEntryMut, StableMap},
+    /// };
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// let key = "a";
+    /// let hash = map.hasher().hash_one(&key);
+    /// let entry: RawEntryMut<&str, u32, _> =
+    ///     map.raw_entry_mut().from_key_hashed_nocheck(hash, &key);
+    /// assert_eq!(entry.or_insert(key, 300), (&mut "a", &mut 100));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn from_key_hashed_nocheck<Q>(self, hash: u64, k: &Q) -> RawEntryMut<'a, K, V, S, A>
+    where
+        Q: Equivalent<K> + ?Sized,
+    {
+        self.from_hash(hash, |q| k.equivalent(q))
+    }
+
+    /// Creates a `RawEntryMut` from the given hash and matching function.
+    ///
+    /// The hash must have been computed with the hasher of the map. This is not checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {
+    ///     core::hash::BuildHasher,
+    ///     stable_map::{RawEntryMut, StableMap},
+    /// };
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// let key = "a";
+    /// let hash = map.hasher().hash_one(&key);
+    /// let entry: RawEntryMut<&str, u32, _> =
+    ///     map.raw_entry_mut().from_hash(hash, |k| k == &key);
+    /// assert_eq!(entry.or_insert(key, 300), (&mut "a", &mut 100));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn from_hash<F>(self, hash: u64, mut is_match: F) -> RawEntryMut<'a, K, V, S, A>
+    where
+        F: FnMut(&K) -> bool,
+    {
+        let (table, hash_builder) = self.map.table_mut();
+        match table.find_entry(hash, |(k, _)| is_match(k)) {
+            Ok(entry) => RawEntryMut::Occupied(RawOccupiedEntryMut {
+                entry,
+                entries: self.entries,
+                _phantom: PhantomData,
+            }),
+            Err(entry) => RawEntryMut::Vacant(RawVacantEntryMut {
+                table: entry.into_table(),
+                hash_builder,
+                entries: self.entries,
+            }),
+        }
+    }
+}
+
+impl<'a, K, V, S, A: Allocator> RawEntryMut<'a, K, V, S, A> {
+    /// Ensures a value is in the entry by inserting the default if empty, and returns
+    /// mutable references to the key and value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, u32> = StableMap::new();
+    ///
+    /// map.raw_entry_mut().from_key("poneyland").or_insert("poneyland", 3);
+    /// assert_eq!(map["poneyland"], 3);
+    ///
+    /// *map.raw_entry_mut().from_key("poneyland").or_insert("poneyland", 10).1 *= 2;
+    /// assert_eq!(map["poneyland"], 6);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn or_insert(self, default_key: K, default_val: V) -> (&'a mut K, &'a mut V)
+    where
+        K: Hash,
+        S: BuildHasher,
+    {
+        match self {
+            RawEntryMut::Occupied(o) => o.into_key_value(),
+            RawEntryMut::Vacant(v) => v.insert(default_key, default_val),
+        }
+    }
+
+    /// Ensures a value is in the entry by inserting the result of the default function if
+    /// empty, and returns mutable references to the key and value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, String> = StableMap::new();
+    ///
+    /// map.raw_entry_mut().from_key("poneyland").or_insert_with(|| {
+    ///     ("poneyland", "hoho".to_string())
+    /// });
+    ///
+    /// assert_eq!(map["poneyland"], "hoho".to_string());
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn or_insert_with<F>(self, default: F) -> (&'a mut K, &'a mut V)
+    where
+        F: FnOnce() -> (K, V),
+        K: Hash,
+        S: BuildHasher,
+    {
+        match self {
+            RawEntryMut::Occupied(o) => o.into_key_value(),
+            RawEntryMut::Vacant(v) => {
+                let (k, v2) = default();
+                v.insert(k, v2)
+            }
+        }
+    }
+
+    /// Provides in-place mutable access to an occupied entry before any
+    /// potential inserts into the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableMap;
+    ///
+    /// let mut map: StableMap<&str, u32> = StableMap::new();
+    ///
+    /// map.raw_entry_mut()
+    ///    .from_key("poneyland")
+    ///    .and_modify(|_k, v| { *v += 1 })
+    ///    .or_insert("poneyland", 42);
+    /// assert_eq!(map["poneyland"], 42);
+    ///
+    /// map.raw_entry_mut()
+    ///    .from_key("poneyland")
+    ///    .and_modify(|_k, v| { *v += 1 })
+    ///    .or_insert("poneyland", 0);
+    /// assert_eq!(map["poneyland"], 43);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn and_modify<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce(&mut K, &mut V),
+    {
+        if let RawEntryMut::Occupied(o) = &mut self {
+            let (k, v) = o.get_key_value_mut();
+            f(k, v);
+        }
+        self
+    }
+}
+
+impl<'a, K, V, S, A: Allocator> RawOccupiedEntryMut<'a, K, V, S, A> {
+    /// Gets a reference to the key in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(o) => assert_eq!(o.key(), &"a")
+    /// }
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn key(&self) -> &K {
+        &self.entry.get().0
+    }
+
+    /// Gets a mutable reference to the key in the entry.
+    ///
+    /// The new key must hash and compare equal to the old key. Otherwise the key can no
+    /// longer be found in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {
+    ///     std::rc::Rc,
+    ///     stable_map::{RawEntryMut, StableMap},
+    /// };
+    ///
+    /// let key_one = Rc::new("a");
+    /// let key_two = Rc::new("a");
+    ///
+    /// let mut map: StableMap<Rc<&str>, u32> = StableMap::new();
+    /// map.insert(key_one.clone(), 10);
+    ///
+    /// match map.raw_entry_mut().from_key(&key_one) {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(mut o) => {
+    ///         *o.key_mut() = key_two.clone();
+    ///     }
+    /// }
+    /// assert_eq!(Rc::strong_count(&key_one), 1);
+    /// assert_eq!(Rc::strong_count(&key_two), 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn key_mut(&mut self) -> &mut K {
+        &mut self.entry.get_mut().0
+    }
+
+    /// Gets a reference to the value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(o) => assert_eq!(o.get(), &100),
+    /// }
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get(&self) -> &V {
+        unsafe {
+            // SAFETY: By the invariants, self.entry.get() is valid.
+            self.entries.get_unchecked(&self.entry.get().1)
+        }
+    }
+
+    /// Gets a mutable reference to the value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(mut o) => *o.get_mut() += 900,
+    /// }
+    /// assert_eq!(map[&"a"], 1000);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_mut(&mut self) -> &mut V {
+        unsafe {
+            // SAFETY: By the invariants, self.entry.get() is valid.
+            self.entries.get_unchecked_mut(&self.entry.get().1)
+        }
+    }
+
+    /// Converts the entry into a mutable reference to the value in the entry
+    /// with a lifetime bound to the map itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// let value: &mut u32 = match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(o) => o.into_mut(),
+    /// };
+    /// *value += 900;
+    /// assert_eq!(map[&"a"], 1000);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn into_mut(self) -> &'a mut V {
+        unsafe {
+            // SAFETY: By the invariants, self.entry.get() is valid.
+            self.entries.get_unchecked_mut(&self.entry.get().1)
+        }
+    }
+
+    /// Gets a reference to the key and value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(o) => assert_eq!(o.get_key_value(), (&"a", &100)),
+    /// }
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_key_value(&self) -> (&K, &V) {
+        let (k, pos) = self.entry.get();
+        let v = unsafe {
+            // SAFETY: By the invariants, pos is valid.
+            self.entries.get_unchecked(pos)
+        };
+        (k, v)
+    }
+
+    /// Gets mutable references to the key and value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(mut o) => *o.get_key_value_mut().1 += 900,
+    /// }
+    /// assert_eq!(map[&"a"], 1000);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_key_value_mut(&mut self) -> (&mut K, &mut V) {
+        let (k, pos) = self.entry.get_mut();
+        let v = unsafe {
+            // SAFETY: By the invariants, pos is valid.
+            self.entries.get_unchecked_mut(pos)
+        };
+        (k, v)
+    }
+
+    /// Converts the entry into mutable references to the key and value in the entry
+    /// with a lifetime bound to the map itself.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// let (_, value) = match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(o) => o.into_key_value(),
+    /// };
+    /// *value += 900;
+    /// assert_eq!(map[&"a"], 1000);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn into_key_value(self) -> (&'a mut K, &'a mut V) {
+        let (k, pos) = self.entry.into_mut();
+        let v = unsafe {
+            // SAFETY: By the invariants, pos is valid.
+            self.entries.get_unchecked_mut(pos)
+        };
+        (k, v)
+    }
+
+    /// Sets the value of the entry, and returns the entry's old value.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(mut o) => assert_eq!(o.insert(1000), 100),
+    /// }
+    /// assert_eq!(map[&"a"], 1000);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert(&mut self, value: V) -> V {
+        mem::replace(self.get_mut(), value)
+    }
+
+    /// Sets the key of the entry, and returns the entry's old key.
+    ///
+    /// The new key must hash and compare equal to the old key. Otherwise the key can no
+    /// longer be found in the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {
+    ///     std::rc::Rc,
+    ///     stable_map::{RawEntryMut, StableMap},
+    /// };
+    ///
+    /// let key_one = Rc::new("a");
+    /// let key_two = Rc::new("a");
+    ///
+    /// let mut map: StableMap<Rc<&str>, u32> = StableMap::new();
+    /// map.insert(key_one.clone(), 10);
+    ///
+    /// match map.raw_entry_mut().from_key(&key_one) {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(mut o) => {
+    ///         let old_key = o.insert_key(key_two.clone());
+    ///         assert!(Rc::ptr_eq(&old_key, &key_one));
+    ///     }
+    /// }
+    /// assert_eq!(Rc::strong_count(&key_one), 1);
+    /// assert_eq!(Rc::strong_count(&key_two), 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert_key(&mut self, key: K) -> K {
+        mem::replace(&mut self.entry.get_mut().0, key)
+    }
+
+    /// Takes the value out of the entry, and returns it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(o) => assert_eq!(o.remove(), 100),
+    /// }
+    /// assert_eq!(map.get(&"a"), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn remove(self) -> V {
+        self.remove_entry().1
+    }
+
+    /// Takes the ownership of the key and value from the map.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// match map.raw_entry_mut().from_key(&"a") {
+    ///     RawEntryMut::Vacant(_) => panic!(),
+    ///     RawEntryMut::Occupied(o) => assert_eq!(o.remove_entry(), ("a", 100)),
+    /// }
+    /// assert_eq!(map.get(&"a"), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn remove_entry(self) -> (K, V) {
+        let ((k, pos), _) = self.entry.remove();
+        let value = unsafe {
+            // SAFETY: By the invariants, pos is valid.
+            self.entries.take_unchecked(pos)
+        };
+        (k, value)
+    }
+}
+
+impl<'a, K, V, S, A: Allocator> RawVacantEntryMut<'a, K, V, S, A> {
+    /// Sets the value of the entry with the `VacantEntry`'s key,
+    /// and returns mutable references to the key and value in the entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{RawEntryMut, StableMap};
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    ///
+    /// match map.raw_entry_mut().from_key(&"c") {
+    ///     RawEntryMut::Occupied(_) => panic!(),
+    ///     RawEntryMut::Vacant(v) => assert_eq!(v.insert("c", 300), (&mut "c", &mut 300)),
+    /// }
+    /// assert_eq!(map[&"c"], 300);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert(self, key: K, value: V) -> (&'a mut K, &'a mut V)
+    where
+        K: Hash,
+        S: BuildHasher,
+    {
+        let hash = self.hash_builder.hash_one(&key);
+        self.insert_hashed_nocheck(hash, key, value)
+    }
+
+    /// Sets the value of the entry with the `VacantEntry`'s key,
+    /// and returns mutable references to the key and value in the entry.
+    ///
+    /// The hash must have been computed with the hasher of the map. This is not checked.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use {
+    ///     core::hash::BuildHasher,
+    ///     stable_map::{RawEntryMut, StableMap},
+    /// };
+    ///
+    /// let mut map: StableMap<&str, u32> = [("a", 100), ("b", 200)].into();
+    /// let key = "c";
+    /// let hash = map.hasher().hash_one(&key);
+    ///
+    /// match map.raw_entry_mut().from_key_hashed_nocheck(hash, &key) {
+    ///     RawEntryMut::Occupied(_) => panic!(),
+    ///     RawEntryMut::Vacant(v) => assert_eq!(
+    ///         v.insert_hashed_nocheck(hash, key, 300),
+    ///         (&mut "c", &mut 300)
+    ///     ),
+    /// }
+    /// assert_eq!(map[&"c"], 300);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert_hashed_nocheck(self, hash: u64, key: K, value: V) -> (&'a mut K, &'a mut V)
+    where
+        K: Hash,
+        S: BuildHasher,
+    {
+        let pos = self.entries.insert_hashed(hash, value);
+        let hash_builder = self.hash_builder;
+        let entries = &*self.entries;
+        let (k, pos) = self
+            .table
+            .insert_unique(hash, (key, pos), |(k, pos)| {
+                rehash(hash_builder, entries, k, pos)
+            })
+            .into_mut();
+        let v = unsafe {
+            // SAFETY: pos was just returned by self.entries.
+            self.entries.get_unchecked_mut(pos)
+        };
+        (k, v)
+    }
+}
+
+impl<K, V, S, A: Allocator> Debug for RawEntryBuilder<'_, K, V, S, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RawEntryBuilder").finish()
+    }
+}
+
+impl<K, V, S, A: Allocator> Debug for RawEntryBuilderMut<'_, K, V, S, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RawEntryBuilderMut").finish()
+    }
+}
+
+impl<K, V, S, A: Allocator> Debug for RawEntryMut<'_, K, V, S, A>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            RawEntryMut::Vacant(ref v) => f.debug_tuple("RawEntry").field(v).finish(),
+            RawEntryMut::Occupied(ref o) => f.debug_tuple("RawEntry").field(o).finish(),
+        }
+    }
+}
+
+impl<K, V, S, A: Allocator> Debug for RawOccupiedEntryMut<'_, K, V, S, A>
+where
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RawOccupiedEntryMut")
+            .field("key", self.key())
+            .field("value", self.get())
+            .finish()
+    }
+}
+
+impl<K, V, S, A: Allocator> Debug for RawVacantEntryMut<'_, K, V, S, A> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RawVacantEntryMut").finish()
+    }
+}
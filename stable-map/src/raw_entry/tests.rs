@@ -0,0 +1,112 @@
+use {
+    crate::{raw_entry::RawEntryMut, StableMap},
+    core::hash::BuildHasher,
+};
+
+#[test]
+fn lookup() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.remove(&1);
+    let hash = map.hasher().hash_one(2);
+    assert_eq!(map.raw_entry().from_key(&2), Some((&2, &22)));
+    assert_eq!(
+        map.raw_entry().from_key_hashed_nocheck(hash, &2),
+        Some((&2, &22))
+    );
+    assert_eq!(
+        map.raw_entry().from_hash(hash, |k| *k == 2),
+        Some((&2, &22))
+    );
+    assert_eq!(map.raw_entry().from_key(&1), None);
+    assert_eq!(map.raw_entry().from_hash(hash, |_| false), None);
+}
+
+#[test]
+fn insert() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.remove(&1);
+    let hash = map.hasher().hash_one(2);
+    let RawEntryMut::Vacant(v) = map.raw_entry_mut().from_key_hashed_nocheck(hash, &2) else {
+        panic!();
+    };
+    assert_eq!(v.insert_hashed_nocheck(hash, 2, 22), (&mut 2, &mut 22));
+    assert_eq!(map.get(&2), Some(&22));
+    assert_eq!(map.get_by_index(0), Some(&22));
+    let RawEntryMut::Vacant(v) = map.raw_entry_mut().from_key(&3) else {
+        panic!();
+    };
+    assert_eq!(v.insert(3, 33), (&mut 3, &mut 33));
+    assert_eq!(map.get(&3), Some(&33));
+    assert_eq!(map.get_by_index(1), Some(&33));
+}
+
+#[test]
+fn occupied() {
+    let mut map = StableMap::new();
+    map.insert(1, 11);
+    map.insert(2, 22);
+    let hash = map.hasher().hash_one(1);
+    let RawEntryMut::Occupied(mut o) = map.raw_entry_mut().from_hash(hash, |k| *k == 1) else {
+        panic!();
+    };
+    assert_eq!(o.key(), &1);
+    assert_eq!(o.get(), &11);
+    assert_eq!(o.insert(33), 11);
+    assert_eq!(o.get_key_value(), (&1, &33));
+    *o.get_key_value_mut().1 += 1;
+    assert_eq!(o.into_key_value(), (&mut 1, &mut 34));
+    assert_eq!(map.get(&1), Some(&34));
+    let RawEntryMut::Occupied(o) = map.raw_entry_mut().from_key(&1) else {
+        panic!();
+    };
+    assert_eq!(o.remove_entry(), (1, 34));
+    assert_eq!(map.get(&1), None);
+    assert_eq!(map.get_by_index(0), None);
+    assert_eq!(map.len(), 1);
+    map.insert(3, 33);
+    assert_eq!(map.get_by_index(0), Some(&33));
+}
+
+#[test]
+fn or_insert() {
+    let mut map = StableMap::new();
+    map.raw_entry_mut()
+        .from_key(&1)
+        .and_modify(|_, v| *v += 1)
+        .or_insert(1, 11);
+    assert_eq!(map.get(&1), Some(&11));
+    map.raw_entry_mut()
+        .from_key(&1)
+        .and_modify(|_, v| *v += 1)
+        .or_insert(1, 11);
+    assert_eq!(map.get(&1), Some(&12));
+    let (_, v) = map.raw_entry_mut().from_key(&2).or_insert_with(|| (2, 22));
+    assert_eq!(*v, 22);
+    let (_, v) = map
+        .raw_entry_mut()
+        .from_key(&2)
+        .or_insert_with(|| unreachable!());
+    assert_eq!(*v, 22);
+    assert_eq!(map.len(), 2);
+}
+
+#[test]
+fn entry_with_hash() {
+    let mut map = StableMap::new();
+    map.insert((1, 2), 12);
+    map.insert((3, 4), 35);
+    let hash = map.hasher().hash_one((3, 4));
+    let RawEntryMut::Occupied(o) = map.entry_with_hash(hash, |k| *k == (3, 4)) else {
+        panic!();
+    };
+    assert_eq!(o.remove(), 35);
+    let RawEntryMut::Vacant(v) = map.entry_with_hash(hash, |k| *k == (3, 4)) else {
+        panic!();
+    };
+    v.insert_hashed_nocheck(hash, (3, 4), 36);
+    assert_eq!(map.get(&(3, 4)), Some(&36));
+    assert_eq!(map.get_by_index(1), Some(&36));
+}
@@ -0,0 +1,36 @@
+/// The order in which a [`StableMap`](crate::StableMap) reuses the indices of removed
+/// entries.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{ReusePolicy, StableMap};
+///
+/// let mut map = StableMap::new();
+/// map.set_reuse_policy(ReusePolicy::Fifo);
+/// for i in 0..4 {
+///     map.insert(i, i);
+/// }
+/// map.remove(&2);
+/// map.remove(&0);
+/// map.insert(4, 4);
+/// assert_eq!(map.get_index(&4), Some(2));
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ReusePolicy {
+    /// The smallest unused index is reused first.
+    ///
+    /// This keeps the used indices as dense as possible.
+    #[default]
+    LowestFirst,
+    /// The most recently freed index is reused first.
+    ///
+    /// The memory of this slot is likely still in the cache.
+    Lifo,
+    /// The least recently freed index is reused first.
+    ///
+    /// This delays the reuse of indices as long as possible, which makes it more likely
+    /// that bugs caused by stale indices surface as missing values rather than as
+    /// accesses to unrelated values.
+    Fifo,
+}
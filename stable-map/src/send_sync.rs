@@ -1,23 +1,25 @@
-use crate::StableMap;
+use {crate::StableMap, allocator_api2::alloc::Allocator};
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V, S> Send for StableMap<K, V, S>
+unsafe impl<K, V, S, A: Allocator> Send for StableMap<K, V, S, A>
 where
     K: Send,
     V: Send,
     S: Send,
+    A: Send,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V, S> Sync for StableMap<K, V, S>
+unsafe impl<K, V, S, A: Allocator> Sync for StableMap<K, V, S, A>
 where
     K: Sync,
     V: Sync,
     S: Sync,
+    A: Sync,
 {
 }
@@ -2,19 +2,24 @@
 mod tests;
 
 use {
-    crate::StableMap,
+    crate::{DiffEntry, MapDiff, StableMap},
+    alloc::vec::Vec,
+    allocator_api2::alloc::Allocator,
     core::{
+        cmp::min,
         fmt::Formatter,
         hash::{BuildHasher, Hash},
+        marker::PhantomData,
+        mem,
     },
     serde::{
-        de::{MapAccess, Visitor},
-        ser::SerializeMap,
+        de::{self, DeserializeSeed, MapAccess, SeqAccess, Unexpected, Visitor},
+        ser::{SerializeMap, SerializeStruct},
         Deserialize, Deserializer, Serialize, Serializer,
     },
 };
 
-impl<K, V, H> Serialize for StableMap<K, V, H>
+impl<K, V, H, A: Allocator> Serialize for StableMap<K, V, H, A>
 where
     K: Serialize,
     V: Serialize,
@@ -31,11 +36,12 @@ where
     }
 }
 
-impl<'de, K, V, S> Deserialize<'de> for StableMap<K, V, S>
+impl<'de, K, V, S, A> Deserialize<'de> for StableMap<K, V, S, A>
 where
     K: Eq + Hash + Deserialize<'de>,
     V: Deserialize<'de>,
     S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
 {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -45,27 +51,310 @@ where
     }
 }
 
-struct Vis<K, V, S>(StableMap<K, V, S>);
+struct Vis<K, V, S, A: Allocator>(StableMap<K, V, S, A>);
 
-impl<'de, K, V, S> Visitor<'de> for Vis<K, V, S>
+impl<'de, K, V, S, A> Visitor<'de> for Vis<K, V, S, A>
 where
     K: Eq + Hash + Deserialize<'de>,
     V: Deserialize<'de>,
     S: BuildHasher,
+    A: Allocator,
 {
-    type Value = StableMap<K, V, S>;
+    type Value = StableMap<K, V, S, A>;
 
     fn expecting(&self, formatter: &mut Formatter) -> core::fmt::Result {
         write!(formatter, "a map")
     }
 
-    fn visit_map<A>(mut self, mut map: A) -> Result<Self::Value, A::Error>
+    fn visit_map<M>(mut self, mut map: M) -> Result<Self::Value, M::Error>
     where
-        A: MapAccess<'de>,
+        M: MapAccess<'de>,
     {
+        self.0
+            .reserve(cautious_size_hint::<(K, V)>(map.size_hint()));
         while let Some((key, value)) = map.next_entry()? {
             self.0.insert(key, value);
         }
         Ok(self.0)
     }
 }
+
+/// Returns the number of elements to reserve for a size hint received from a
+/// deserializer.
+///
+/// The size hint is untrusted input. Therefore at most 1 MiB is pre-allocated.
+pub(crate) fn cautious_size_hint<T>(hint: Option<usize>) -> usize {
+    const MAX_PREALLOC_BYTES: usize = 1024 * 1024;
+    min(
+        hint.unwrap_or(0),
+        MAX_PREALLOC_BYTES / mem::size_of::<T>().max(1),
+    )
+}
+
+const MAP_DIFF_FIELDS: &[&str] = &["added", "removed", "changed"];
+const DIFF_ENTRY_FIELDS: &[&str] = &["index", "key", "value"];
+
+impl<K, V> Serialize for MapDiff<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("MapDiff", MAP_DIFF_FIELDS.len())?;
+        s.serialize_field("added", self.added.as_slice())?;
+        s.serialize_field("removed", self.removed.as_slice())?;
+        s.serialize_field("changed", self.changed.as_slice())?;
+        s.end()
+    }
+}
+
+impl<K, V> Serialize for DiffEntry<K, V>
+where
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("DiffEntry", DIFF_ENTRY_FIELDS.len())?;
+        s.serialize_field("index", &self.index)?;
+        s.serialize_field("key", &self.key)?;
+        s.serialize_field("value", &self.value)?;
+        s.end()
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for MapDiff<K, V>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("MapDiff", MAP_DIFF_FIELDS, MapDiffVis(PhantomData))
+    }
+}
+
+struct MapDiffVis<K, V>(PhantomData<fn() -> (K, V)>);
+
+impl<'de, K, V> Visitor<'de> for MapDiffVis<K, V>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    type Value = MapDiff<K, V>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> core::fmt::Result {
+        write!(formatter, "struct MapDiff")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let added = seq
+            .next_element_seed(VecSeed(PhantomData))?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let removed = seq
+            .next_element_seed(VecSeed(PhantomData))?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let changed = seq
+            .next_element_seed(VecSeed(PhantomData))?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        Ok(MapDiff {
+            added,
+            removed,
+            changed,
+        })
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut added = None;
+        let mut removed = None;
+        let mut changed = None;
+        while let Some(field) = map.next_key_seed(Field(MAP_DIFF_FIELDS))? {
+            match field {
+                0 => next_field(&mut map, &mut added, "added", VecSeed(PhantomData))?,
+                1 => next_field(&mut map, &mut removed, "removed", VecSeed(PhantomData))?,
+                _ => next_field(&mut map, &mut changed, "changed", VecSeed(PhantomData))?,
+            }
+        }
+        Ok(MapDiff {
+            added: added.ok_or_else(|| de::Error::missing_field("added"))?,
+            removed: removed.ok_or_else(|| de::Error::missing_field("removed"))?,
+            changed: changed.ok_or_else(|| de::Error::missing_field("changed"))?,
+        })
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for DiffEntry<K, V>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_struct("DiffEntry", DIFF_ENTRY_FIELDS, DiffEntryVis(PhantomData))
+    }
+}
+
+struct DiffEntryVis<K, V>(PhantomData<fn() -> (K, V)>);
+
+impl<'de, K, V> Visitor<'de> for DiffEntryVis<K, V>
+where
+    K: Deserialize<'de>,
+    V: Deserialize<'de>,
+{
+    type Value = DiffEntry<K, V>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> core::fmt::Result {
+        write!(formatter, "struct DiffEntry")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let index = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let key = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        let value = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(2, &self))?;
+        Ok(DiffEntry { index, key, value })
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: MapAccess<'de>,
+    {
+        let mut index = None;
+        let mut key = None;
+        let mut value = None;
+        while let Some(field) = map.next_key_seed(Field(DIFF_ENTRY_FIELDS))? {
+            match field {
+                0 => next_field(&mut map, &mut index, "index", PhantomData)?,
+                1 => next_field(&mut map, &mut key, "key", PhantomData)?,
+                _ => next_field(&mut map, &mut value, "value", PhantomData)?,
+            }
+        }
+        Ok(DiffEntry {
+            index: index.ok_or_else(|| de::Error::missing_field("index"))?,
+            key: key.ok_or_else(|| de::Error::missing_field("key"))?,
+            value: value.ok_or_else(|| de::Error::missing_field("value"))?,
+        })
+    }
+}
+
+/// Deserializes the value of a struct field that has not been seen before.
+fn next_field<'de, A, T>(
+    map: &mut A,
+    slot: &mut Option<T::Value>,
+    field: &'static str,
+    seed: T,
+) -> Result<(), A::Error>
+where
+    A: MapAccess<'de>,
+    T: DeserializeSeed<'de>,
+{
+    if slot.is_some() {
+        return Err(de::Error::duplicate_field(field));
+    }
+    *slot = Some(map.next_value_seed(seed)?);
+    Ok(())
+}
+
+/// Deserializes a struct field identifier into its position in the list of fields.
+struct Field(&'static [&'static str]);
+
+impl<'de> DeserializeSeed<'de> for Field {
+    type Value = usize;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_identifier(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Field {
+    type Value = usize;
+
+    fn expecting(&self, formatter: &mut Formatter) -> core::fmt::Result {
+        write!(formatter, "a field identifier")
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match usize::try_from(v) {
+            Ok(v) if v < self.0.len() => Ok(v),
+            _ => Err(E::invalid_value(Unexpected::Unsigned(v), &self)),
+        }
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: de::Error,
+    {
+        match self.0.iter().position(|f| *f == v) {
+            Some(v) => Ok(v),
+            _ => Err(E::unknown_field(v, self.0)),
+        }
+    }
+}
+
+/// Deserializes a sequence into a `Vec`.
+struct VecSeed<T>(PhantomData<fn() -> T>);
+
+impl<'de, T> DeserializeSeed<'de> for VecSeed<T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = Vec<T>;
+
+    fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, T> Visitor<'de> for VecSeed<T>
+where
+    T: Deserialize<'de>,
+{
+    type Value = Vec<T>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> core::fmt::Result {
+        write!(formatter, "a sequence")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+    where
+        A: SeqAccess<'de>,
+    {
+        let mut vec = Vec::with_capacity(cautious_size_hint::<T>(seq.size_hint()));
+        while let Some(value) = seq.next_element()? {
+            vec.push(value);
+        }
+        Ok(vec)
+    }
+}
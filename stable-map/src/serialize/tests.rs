@@ -1,4 +1,7 @@
-use {crate::StableMap, serde_json::json};
+use {
+    crate::{serialize::cautious_size_hint, MapDiff, StableMap},
+    serde_json::json,
+};
 
 #[test]
 fn test() {
@@ -12,3 +15,43 @@ fn test() {
     let map2: StableMap<_, _> = serde_json::from_value(value).unwrap();
     assert_eq!(map1, map2);
 }
+
+#[test]
+fn diff() {
+    let mut map1 = StableMap::new();
+    map1.insert(1, 11);
+    map1.insert(2, 22);
+    map1.insert(3, 33);
+    let mut map2 = StableMap::new();
+    map2.insert(2, 23);
+    map2.insert(3, 33);
+    map2.insert(4, 44);
+    let diff = map1.diff(&map2);
+    let value = serde_json::to_value(&diff).unwrap();
+    assert_eq!(
+        value,
+        json!({
+            "added": [{"index": 2, "key": 4, "value": 44}],
+            "removed": [[0, 1]],
+            "changed": [{"index": 0, "key": 2, "value": 23}],
+        }),
+    );
+    let diff2: MapDiff<i32, i32> = serde_json::from_value(value).unwrap();
+    assert_eq!(diff, diff2);
+    let diff3: MapDiff<i32, i32> =
+        serde_json::from_value(json!([[[2, 4, 44]], [[0, 1]], [[0, 2, 23]]])).unwrap();
+    assert_eq!(diff, diff3);
+    map1.apply_diff(diff2).unwrap();
+    assert_eq!(map1, map2);
+}
+
+#[test]
+fn size_hint() {
+    let map: StableMap<i32, i32> = serde_json::from_value(json!({"1": 11, "2": 22})).unwrap();
+    assert_eq!(map.len(), 2);
+    assert!(map.capacity() >= 2);
+    assert_eq!(cautious_size_hint::<u8>(None), 0);
+    assert_eq!(cautious_size_hint::<u8>(Some(10)), 10);
+    assert_eq!(cautious_size_hint::<u8>(Some(usize::MAX)), 1024 * 1024);
+    assert_eq!(cautious_size_hint::<()>(Some(usize::MAX)), 1024 * 1024);
+}
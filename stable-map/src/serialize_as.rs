@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::{serialize::cautious_size_hint, StableMap},
+    allocator_api2::alloc::Allocator,
+    core::{
+        fmt::Formatter,
+        hash::{BuildHasher, Hash},
+        marker::PhantomData,
+    },
+    serde::{
+        de::{MapAccess, SeqAccess, Visitor},
+        ser::SerializeMap,
+        Deserializer, Serializer,
+    },
+    serde_with::{
+        de::{DeserializeAs, DeserializeAsWrap},
+        ser::{SerializeAs, SerializeAsWrap},
+        Seq,
+    },
+};
+
+impl<K, V, KAs, VAs, H, A> SerializeAs<StableMap<K, V, H, A>> for StableMap<KAs, VAs, H, A>
+where
+    KAs: SerializeAs<K>,
+    VAs: SerializeAs<V>,
+    A: Allocator,
+{
+    fn serialize_as<S>(source: &StableMap<K, V, H, A>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut map = serializer.serialize_map(Some(source.len()))?;
+        for (key, value) in source {
+            map.serialize_entry(
+                &SerializeAsWrap::<K, KAs>::new(key),
+                &SerializeAsWrap::<V, VAs>::new(value),
+            )?;
+        }
+        map.end()
+    }
+}
+
+impl<'de, K, V, KAs, VAs, S, A> DeserializeAs<'de, StableMap<K, V, S, A>>
+    for StableMap<KAs, VAs, S, A>
+where
+    K: Eq + Hash,
+    KAs: DeserializeAs<'de, K>,
+    VAs: DeserializeAs<'de, V>,
+    S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
+{
+    fn deserialize_as<D>(deserializer: D) -> Result<StableMap<K, V, S, A>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_map(Vis::<_, _, _, _, KAs, VAs>(
+            StableMap::default(),
+            PhantomData,
+        ))
+    }
+}
+
+impl<K, V, KAs, VAs, H, A> SerializeAs<StableMap<K, V, H, A>> for Seq<(KAs, VAs)>
+where
+    KAs: SerializeAs<K>,
+    VAs: SerializeAs<V>,
+    A: Allocator,
+{
+    fn serialize_as<S>(source: &StableMap<K, V, H, A>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.collect_seq(source.iter().map(|(key, value)| {
+            (
+                SerializeAsWrap::<K, KAs>::new(key),
+                SerializeAsWrap::<V, VAs>::new(value),
+            )
+        }))
+    }
+}
+
+impl<'de, K, V, KAs, VAs, S, A> DeserializeAs<'de, StableMap<K, V, S, A>> for Seq<(KAs, VAs)>
+where
+    K: Eq + Hash,
+    KAs: DeserializeAs<'de, K>,
+    VAs: DeserializeAs<'de, V>,
+    S: BuildHasher + Default,
+    A: Allocator + Clone + Default,
+{
+    fn deserialize_as<D>(deserializer: D) -> Result<StableMap<K, V, S, A>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_seq(Vis::<_, _, _, _, KAs, VAs>(
+            StableMap::default(),
+            PhantomData,
+        ))
+    }
+}
+
+struct Vis<K, V, S, A: Allocator, KAs, VAs>(StableMap<K, V, S, A>, PhantomData<fn() -> (KAs, VAs)>);
+
+impl<'de, K, V, S, A, KAs, VAs> Visitor<'de> for Vis<K, V, S, A, KAs, VAs>
+where
+    K: Eq + Hash,
+    KAs: DeserializeAs<'de, K>,
+    VAs: DeserializeAs<'de, V>,
+    S: BuildHasher,
+    A: Allocator,
+{
+    type Value = StableMap<K, V, S, A>;
+
+    fn expecting(&self, formatter: &mut Formatter) -> core::fmt::Result {
+        write!(formatter, "a map or a sequence of key-value pairs")
+    }
+
+    fn visit_map<M>(mut self, mut map: M) -> Result<Self::Value, M::Error>
+    where
+        M: MapAccess<'de>,
+    {
+        self.0
+            .reserve(cautious_size_hint::<(K, V)>(map.size_hint()));
+        while let Some((key, value)) =
+            map.next_entry::<DeserializeAsWrap<K, KAs>, DeserializeAsWrap<V, VAs>>()?
+        {
+            self.0.insert(key.into_inner(), value.into_inner());
+        }
+        Ok(self.0)
+    }
+
+    fn visit_seq<M>(mut self, mut seq: M) -> Result<Self::Value, M::Error>
+    where
+        M: SeqAccess<'de>,
+    {
+        self.0
+            .reserve(cautious_size_hint::<(K, V)>(seq.size_hint()));
+        while let Some(entry) = seq.next_element::<DeserializeAsWrap<(K, V), (KAs, VAs)>>()? {
+            let (key, value) = entry.into_inner();
+            self.0.insert(key, value);
+        }
+        Ok(self.0)
+    }
+}
@@ -0,0 +1,40 @@
+use {
+    crate::StableMap,
+    serde_json::json,
+    serde_with::{de::DeserializeAsWrap, ser::SerializeAsWrap, DisplayFromStr, Same, Seq},
+};
+
+#[test]
+fn map() {
+    let mut map1 = StableMap::new();
+    map1.insert(1, 11);
+    map1.insert(3, 33);
+    let value = serde_json::to_value(SerializeAsWrap::<_, StableMap<Same, DisplayFromStr>>::new(
+        &map1,
+    ))
+    .unwrap();
+    assert_eq!(value, json!({"1": "11", "3": "33"}));
+    let map2 = serde_json::from_value::<
+        DeserializeAsWrap<StableMap<i32, i32>, StableMap<Same, DisplayFromStr>>,
+    >(value)
+    .unwrap()
+    .into_inner();
+    assert_eq!(map1, map2);
+}
+
+#[test]
+fn seq() {
+    let mut map1 = StableMap::new();
+    map1.insert(1, 11);
+    let value = serde_json::to_value(SerializeAsWrap::<_, Seq<(DisplayFromStr, Same)>>::new(
+        &map1,
+    ))
+    .unwrap();
+    assert_eq!(value, json!([["1", 11]]));
+    let map2 = serde_json::from_value::<
+        DeserializeAsWrap<StableMap<i32, i32>, Seq<(DisplayFromStr, Same)>>,
+    >(value)
+    .unwrap()
+    .into_inner();
+    assert_eq!(map1, map2);
+}
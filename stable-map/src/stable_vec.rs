@@ -0,0 +1,420 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::linear_storage::LinearStorage,
+    core::{
+        fmt::{Debug, Formatter},
+        ops::{Index, IndexMut},
+    },
+};
+
+/// A vector with temporarily-stable indices.
+///
+/// This is the storage layer of [`StableMap`](crate::StableMap) without the keyed layer.
+/// [insert](Self::insert) returns the index of the new value. This index stays the same
+/// until the value is removed or the vector is explicitly compacted. Indices of removed
+/// values are reused by later insertions.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableVec;
+///
+/// let mut vec = StableVec::new();
+/// let a = vec.insert("a");
+/// let b = vec.insert("b");
+/// assert_eq!(vec.remove(a), Some("a"));
+/// assert_eq!(vec[b], "b");
+/// assert_eq!(vec.get(a), None);
+/// ```
+//
+// This type upholds the following invariants:
+//
+// - len is the number of values stored in storage.
+// - No Pos<InUse> returned by storage is retained.
+pub struct StableVec<V> {
+    storage: LinearStorage<V>,
+    len: usize,
+}
+
+impl<V> StableVec<V> {
+    /// Creates an empty `StableVec`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    /// let vec: StableVec<i32> = StableVec::new();
+    /// assert_eq!(vec.len(), 0);
+    /// assert_eq!(vec.capacity(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Creates an empty `StableVec` with the specified capacity.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    /// let vec: StableVec<i32> = StableVec::with_capacity(10);
+    /// assert_eq!(vec.len(), 0);
+    /// assert!(vec.capacity() >= 10);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            storage: LinearStorage::with_capacity(capacity),
+            len: 0,
+        }
+    }
+
+    /// Returns the number of values the vector can hold without reallocating.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    /// let vec: StableVec<i32> = StableVec::with_capacity(100);
+    /// assert!(vec.capacity() >= 100);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn capacity(&self) -> usize {
+        self.storage.capacity()
+    }
+
+    /// Clears the vector, removing all values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// vec.insert(1);
+    /// vec.clear();
+    /// assert!(vec.is_empty());
+    /// assert_eq!(vec.index_len(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn clear(&mut self) {
+        self.storage.clear();
+        self.len = 0;
+        // SAFETY(invariants):
+        // - We do not retain any Pos<InUse>.
+    }
+
+    /// Returns `true` if there is a value at the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// let index = vec.insert(1);
+    /// assert!(vec.contains(index));
+    /// vec.remove(index);
+    /// assert!(!vec.contains(index));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn contains(&self, index: usize) -> bool {
+        self.storage.get(index).is_some()
+    }
+
+    /// Returns a reference to the value at the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// let index = vec.insert(1);
+    /// assert_eq!(vec.get(index), Some(&1));
+    /// assert_eq!(vec.get(index + 1), None);
+    /// ```
+    #[inline]
+    pub fn get(&self, index: usize) -> Option<&V> {
+        self.storage.get(index)
+    }
+
+    /// Returns a mutable reference to the value at the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// let index = vec.insert(1);
+    /// *vec.get_mut(index).unwrap() += 1;
+    /// assert_eq!(vec[index], 2);
+    /// ```
+    #[inline]
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut V> {
+        self.storage.get_mut(index)
+    }
+
+    /// Inserts a value and returns its index.
+    ///
+    /// The value is stored at the lowest unused index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// assert_eq!(vec.insert("a"), 0);
+    /// assert_eq!(vec.insert("b"), 1);
+    /// vec.remove(0);
+    /// assert_eq!(vec.insert("c"), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn insert(&mut self, value: V) -> usize {
+        let pos = self.storage.insert(value);
+        self.len += 1;
+        unsafe {
+            // SAFETY:
+            // - pos was just returned by storage and is therefore valid.
+            self.storage.index_unchecked(&pos)
+        }
+        // SAFETY(invariants):
+        // - We have inserted one value and incremented len.
+        // - pos is dropped at the end of this function.
+    }
+
+    /// Returns `true` if the vector contains no values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// assert!(vec.is_empty());
+    /// vec.insert(1);
+    /// assert!(!vec.is_empty());
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// An iterator visiting all values together with their indices in the order of their
+    /// indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// vec.insert("a");
+    /// vec.insert("b");
+    /// vec.insert("c");
+    /// vec.remove(1);
+    /// let values: Vec<_> = vec.iter().collect();
+    /// assert_eq!(values, [(0, &"a"), (2, &"c")]);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &V)> + use<'_, V> {
+        (0..self.storage.len()).filter_map(|i| self.storage.get(i).map(|v| (i, v)))
+    }
+
+    /// Returns the number of values in the vector.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// vec.insert(1);
+    /// vec.insert(2);
+    /// vec.remove(0);
+    /// assert_eq!(vec.len(), 1);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Removes the value at the index and returns it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// let index = vec.insert(1);
+    /// assert_eq!(vec.remove(index), Some(1));
+    /// assert_eq!(vec.remove(index), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn remove(&mut self, index: usize) -> Option<V> {
+        let value = unsafe {
+            // SAFETY:
+            // - By the invariants, we do not retain any Pos<InUse>.
+            self.storage.take(index)?
+        };
+        self.len -= 1;
+        Some(value)
+        // SAFETY(invariants):
+        // - We have removed one value and decremented len.
+    }
+
+    /// Reserves capacity for at least `additional` more values.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    /// let mut vec: StableVec<i32> = StableVec::new();
+    /// vec.reserve(10);
+    /// assert!(vec.capacity() >= 10);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn reserve(&mut self, additional: usize) {
+        self.storage.reserve(additional);
+    }
+
+    /// Shrinks the capacity of the vector as much as possible.
+    ///
+    /// Unused indices are not removed. Use [force_compact](Self::force_compact) first to
+    /// remove them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    /// let mut vec: StableVec<i32> = StableVec::with_capacity(100);
+    /// vec.insert(1);
+    /// vec.shrink_to_fit();
+    /// assert!(vec.capacity() >= 1);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn shrink_to_fit(&mut self) {
+        self.storage.shrink_to_fit();
+    }
+
+    /// Returns one more than the highest possible index of this vector.
+    ///
+    /// Using [get](Self::get) with higher indices will always return `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// vec.insert(1);
+    /// vec.insert(2);
+    /// vec.remove(0);
+    /// assert_eq!(vec.index_len(), 2);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn index_len(&self) -> usize {
+        self.storage.len()
+    }
+
+    /// Maybe compacts the vector, removing indices for which `get` would return `None`.
+    ///
+    /// This function does nothing if there are no more than 8 indices for which
+    /// [get](Self::get) returns `None` or if at least half of the indices are in use.
+    ///
+    /// Compaction moves values from the highest indices into the lowest unused indices.
+    /// Indices retrieved before calling this function must no longer be used. Use
+    /// [iter](Self::iter) to retrieve the new indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// for i in 0..32 {
+    ///     vec.insert(i);
+    /// }
+    /// for i in 0..17 {
+    ///     vec.remove(i);
+    /// }
+    /// vec.compact();
+    /// assert_eq!(vec.index_len(), 15);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn compact(&mut self) {
+        self.storage.compact();
+    }
+
+    /// Compacts the vector, removing indices for which `get` would return `None`.
+    ///
+    /// After this function returns, [index_len](Self::index_len) will be the same as
+    /// [len](Self::len).
+    ///
+    /// Compaction moves values from the highest indices into the lowest unused indices.
+    /// Indices retrieved before calling this function must no longer be used. Use
+    /// [iter](Self::iter) to retrieve the new indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::StableVec;
+    ///
+    /// let mut vec = StableVec::new();
+    /// vec.insert("a");
+    /// vec.insert("b");
+    /// vec.remove(0);
+    /// vec.force_compact();
+    /// assert_eq!(vec.index_len(), 1);
+    /// assert_eq!(vec[0], "b");
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn force_compact(&mut self) {
+        self.storage.force_compact();
+    }
+}
+
+impl<V> Default for StableVec<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> Debug for StableVec<V>
+where
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+impl<V> Index<usize> for StableVec<V> {
+    type Output = V;
+
+    fn index(&self, index: usize) -> &V {
+        self.get(index).expect("no value found at index")
+    }
+}
+
+impl<V> IndexMut<usize> for StableVec<V> {
+    fn index_mut(&mut self, index: usize) -> &mut V {
+        self.get_mut(index).expect("no value found at index")
+    }
+}
+
+// SAFETY:
+// - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
+//   but this API prevents this.
+unsafe impl<V> Send for StableVec<V> where V: Send {}
+
+// SAFETY:
+// - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
+//   but this API prevents this.
+unsafe impl<V> Sync for StableVec<V> where V: Sync {}
@@ -0,0 +1,37 @@
+use {crate::StableVec, alloc::vec::Vec};
+
+#[test]
+fn insert_remove() {
+    let mut vec = StableVec::new();
+    for i in 0..4 {
+        assert_eq!(vec.insert(i * 10), i);
+    }
+    assert_eq!(vec.remove(1), Some(10));
+    assert_eq!(vec.remove(1), None);
+    assert_eq!(vec.remove(9), None);
+    assert_eq!(vec.len(), 3);
+    assert_eq!(vec.index_len(), 4);
+    assert_eq!(vec.insert(40), 1);
+    assert_eq!(vec[1], 40);
+    assert_eq!(vec.len(), 4);
+}
+
+#[test]
+fn force_compact() {
+    let mut vec = StableVec::new();
+    for i in 0..6 {
+        vec.insert(i);
+    }
+    vec.remove(1);
+    vec.remove(2);
+    vec.force_compact();
+    assert_eq!(vec.index_len(), 4);
+    assert_eq!(
+        vec.iter().collect::<Vec<_>>(),
+        [(0, &0), (1, &5), (2, &4), (3, &3)]
+    );
+    assert_eq!(vec.insert(6), 4);
+    vec.clear();
+    assert!(vec.is_empty());
+    assert_eq!(vec.index_len(), 0);
+}
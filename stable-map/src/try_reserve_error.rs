@@ -0,0 +1,79 @@
+use {
+    allocator_api2::{alloc::Allocator, vec::Vec},
+    core::{
+        alloc::Layout,
+        fmt::{Display, Formatter},
+    },
+};
+
+/// The error returned by [`try_reserve`](crate::StableMap::try_reserve) when the map
+/// could not allocate the requested memory.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{StableMap, TryReserveError};
+///
+/// let mut map: StableMap<i32, i32> = StableMap::new();
+/// assert_eq!(map.try_reserve(usize::MAX), Err(TryReserveError::CapacityOverflow));
+/// assert_eq!(map.try_reserve(10), Ok(()));
+/// assert!(map.capacity() >= 10);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TryReserveError {
+    /// The requested capacity exceeds the maximum capacity of the map.
+    CapacityOverflow,
+    /// The allocator returned an error.
+    AllocError {
+        /// The layout of the allocation request that failed.
+        layout: Layout,
+    },
+}
+
+impl Display for TryReserveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TryReserveError::CapacityOverflow => {
+                write!(f, "the requested capacity exceeds the maximum capacity")
+            }
+            TryReserveError::AllocError { layout } => {
+                write!(
+                    f,
+                    "failed to allocate {} bytes with alignment {}",
+                    layout.size(),
+                    layout.align(),
+                )
+            }
+        }
+    }
+}
+
+impl From<hashbrown::TryReserveError> for TryReserveError {
+    fn from(value: hashbrown::TryReserveError) -> Self {
+        match value {
+            hashbrown::TryReserveError::CapacityOverflow => Self::CapacityOverflow,
+            hashbrown::TryReserveError::AllocError { layout } => Self::AllocError { layout },
+        }
+    }
+}
+
+/// Tries to reserve space for `additional` additional elements in `vec`.
+pub(crate) fn try_reserve_vec<T, A: Allocator>(
+    vec: &mut Vec<T, A>,
+    additional: usize,
+) -> Result<(), TryReserveError> {
+    // The cause of the error is not accessible if allocator-api2 re-exports the
+    // unstable types of the standard library. Instead, fall back to the exact request
+    // whose layout is known.
+    if vec.try_reserve(additional).is_ok() || vec.try_reserve_exact(additional).is_ok() {
+        return Ok(());
+    }
+    match vec
+        .len()
+        .checked_add(additional)
+        .and_then(|len| Layout::array::<T>(len).ok())
+    {
+        Some(layout) => Err(TryReserveError::AllocError { layout }),
+        None => Err(TryReserveError::CapacityOverflow),
+    }
+}
@@ -0,0 +1,257 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::{IndexType, StableMap},
+    allocator_api2::alloc::{Allocator, Global},
+    core::{
+        fmt::{Debug, Formatter},
+        hash::{BuildHasher, Hash},
+        marker::PhantomData,
+        ops::{Deref, DerefMut},
+    },
+    hashbrown::{DefaultHashBuilder, Equivalent},
+};
+
+/// A [`StableMap`] whose indices have the type `I`.
+///
+/// The index accessors of this type take and return `I` instead of `usize`. Since the
+/// index type is part of the type of the map, indices of one map cannot accidentally be
+/// used with a map of a different index type.
+///
+/// All other functions of `StableMap` are available via [`Deref`] and [`DerefMut`].
+/// Note that these functions still use `usize` indices.
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::{IndexType, TypedStableMap};
+///
+/// #[derive(Copy, Clone, Debug, PartialEq)]
+/// struct UserIdx(u32);
+///
+/// impl IndexType for UserIdx {
+///     fn from_index(index: usize) -> Self {
+///         Self(index.try_into().unwrap())
+///     }
+///
+///     fn index(self) -> usize {
+///         self.0 as usize
+///     }
+/// }
+///
+/// let mut users = TypedStableMap::<UserIdx, _, _>::new();
+/// users.insert("alice", 30);
+///
+/// let idx = users.get_index("alice").unwrap();
+/// assert_eq!(idx, UserIdx(0));
+/// assert_eq!(users.get_by_index(idx), Some(&30));
+/// ```
+pub struct TypedStableMap<I, K, V, S = DefaultHashBuilder, A: Allocator = Global> {
+    map: StableMap<K, V, S, A>,
+    _phantom: PhantomData<fn(I) -> I>,
+}
+
+#[cfg(feature = "default-hasher")]
+impl<I, K, V> TypedStableMap<I, K, V, DefaultHashBuilder> {
+    /// Creates an empty `TypedStableMap`.
+    ///
+    /// The map is initially created with a capacity of 0, so it will not allocate until it
+    /// is first inserted into.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::TypedStableMap;
+    /// let mut map: TypedStableMap<usize, &str, i32> = TypedStableMap::new();
+    /// assert_eq!(map.len(), 0);
+    /// assert_eq!(map.capacity(), 0);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn new() -> Self {
+        Self::from_map(StableMap::new())
+    }
+}
+
+impl<I, K, V, S, A: Allocator> TypedStableMap<I, K, V, S, A> {
+    /// Creates a `TypedStableMap` from a `StableMap`.
+    ///
+    /// The entries keep their indices.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::{StableMap, TypedStableMap};
+    ///
+    /// let mut map = StableMap::new();
+    /// map.insert("a", 1);
+    /// let map = TypedStableMap::<usize, _, _>::from_map(map);
+    /// assert_eq!(map.get_by_index(0), Some(&1));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn from_map(map: StableMap<K, V, S, A>) -> Self {
+        Self {
+            map,
+            _phantom: PhantomData,
+        }
+    }
+
+    /// Returns the underlying `StableMap`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::TypedStableMap;
+    ///
+    /// let mut map = TypedStableMap::<usize, _, _>::new();
+    /// map.insert("a", 1);
+    /// let map = map.into_map();
+    /// assert_eq!(map.get_index("a"), Some(0));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn into_map(self) -> StableMap<K, V, S, A> {
+        self.map
+    }
+
+    /// Returns the index that the key maps to.
+    ///
+    /// This is the typed counterpart of [`StableMap::get_index`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::TypedStableMap;
+    ///
+    /// let mut map = TypedStableMap::<usize, _, _>::new();
+    /// map.insert(1, "a");
+    /// assert_eq!(map.get_index(&1), Some(0));
+    /// assert_eq!(map.get_index(&2), None);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_index<Q>(&self, q: &Q) -> Option<I>
+    where
+        I: IndexType,
+        S: BuildHasher,
+        K: Eq + Hash,
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        self.map.get_index(q).map(I::from_index)
+    }
+
+    /// Returns `true` if an entry is stored at the index.
+    ///
+    /// This is the typed counterpart of [`StableMap::contains_index`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::TypedStableMap;
+    ///
+    /// let mut map = TypedStableMap::<usize, _, _>::new();
+    /// map.insert(1, "a");
+    /// assert!(map.contains_index(0));
+    /// assert!(!map.contains_index(1));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn contains_index(&self, index: I) -> bool
+    where
+        I: IndexType,
+    {
+        self.map.contains_index(index.index())
+    }
+
+    /// Returns a reference to the value stored at the index.
+    ///
+    /// This is the typed counterpart of [`StableMap::get_by_index`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::TypedStableMap;
+    ///
+    /// let mut map = TypedStableMap::<usize, _, _>::new();
+    /// map.insert(1, "a");
+    /// let index = map.get_index(&1).unwrap();
+    /// assert_eq!(map.get_by_index(index), Some(&"a"));
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_by_index(&self, index: I) -> Option<&V>
+    where
+        I: IndexType,
+    {
+        self.map.get_by_index(index.index())
+    }
+
+    /// Returns a mutable reference to the value stored at the index.
+    ///
+    /// This is the typed counterpart of [`StableMap::get_by_index_mut`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use stable_map::TypedStableMap;
+    ///
+    /// let mut map = TypedStableMap::<usize, _, _>::new();
+    /// map.insert(1, 10);
+    /// let index = map.get_index(&1).unwrap();
+    /// *map.get_by_index_mut(index).unwrap() += 1;
+    /// assert_eq!(map[&1], 11);
+    /// ```
+    #[cfg_attr(feature = "inline-more", inline)]
+    pub fn get_by_index_mut(&mut self, index: I) -> Option<&mut V>
+    where
+        I: IndexType,
+    {
+        self.map.get_by_index_mut(index.index())
+    }
+}
+
+impl<I, K, V, S, A: Allocator> Deref for TypedStableMap<I, K, V, S, A> {
+    type Target = StableMap<K, V, S, A>;
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+
+impl<I, K, V, S, A: Allocator> DerefMut for TypedStableMap<I, K, V, S, A> {
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.map
+    }
+}
+
+impl<I, K, V, S, A: Allocator> From<StableMap<K, V, S, A>> for TypedStableMap<I, K, V, S, A> {
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn from(map: StableMap<K, V, S, A>) -> Self {
+        Self::from_map(map)
+    }
+}
+
+impl<I, K, V, S, A: Allocator> Clone for TypedStableMap<I, K, V, S, A>
+where
+    StableMap<K, V, S, A>: Clone,
+{
+    fn clone(&self) -> Self {
+        Self::from_map(self.map.clone())
+    }
+}
+
+impl<I, K, V, S, A: Allocator> Default for TypedStableMap<I, K, V, S, A>
+where
+    StableMap<K, V, S, A>: Default,
+{
+    fn default() -> Self {
+        Self::from_map(StableMap::default())
+    }
+}
+
+impl<I, K, V, S, A: Allocator> Debug for TypedStableMap<I, K, V, S, A>
+where
+    StableMap<K, V, S, A>: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        self.map.fmt(f)
+    }
+}
@@ -0,0 +1,46 @@
+use crate::{IndexType, StableMap, TypedStableMap};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Idx(u16);
+
+impl IndexType for Idx {
+    fn from_index(index: usize) -> Self {
+        Self(index as u16)
+    }
+
+    fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+#[test]
+fn typed_index() {
+    let mut map = TypedStableMap::<Idx, _, _>::new();
+    for i in 0..4 {
+        map.insert(i, i * 10);
+    }
+    map.remove(&1);
+    assert_eq!(map.get_index(&2), Some(Idx(2)));
+    assert_eq!(map.get_index(&1), None);
+    assert!(map.contains_index(Idx(3)));
+    assert!(!map.contains_index(Idx(1)));
+    assert_eq!(map.get_by_index(Idx(3)), Some(&30));
+    assert_eq!(map.get_by_index(Idx(1)), None);
+    *map.get_by_index_mut(Idx(0)).unwrap() += 1;
+    assert_eq!(map[&0], 1);
+    let map = map.into_map();
+    assert_eq!(map.get_index(&3), Some(3));
+    let map = TypedStableMap::<Idx, _, _>::from(map);
+    assert_eq!(map.get_index(&3), Some(Idx(3)));
+}
+
+#[test]
+fn into_map() {
+    let mut map = StableMap::new();
+    map.insert(1, 1);
+    map.insert(2, 2);
+    map.remove(&1);
+    let map: TypedStableMap<Idx, _, _> = map.into();
+    assert_eq!(map.get_index(&2), Some(Idx(1)));
+    assert_eq!(map.into_map().get_index(&2), Some(1));
+}
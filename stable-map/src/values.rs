@@ -4,13 +4,17 @@ mod tests;
 use {
     crate::{
         linear_storage::LinearStorage,
-        pos_vec::pos::{InUse, Pos},
+        pos_vec::{
+            pos::{InUse, Pos},
+            Slots,
+        },
     },
+    allocator_api2::alloc::{Allocator, Global},
     core::{
         fmt::{Debug, Formatter},
         iter::FusedIterator,
     },
-    hashbrown::hash_map,
+    hashbrown::hash_table,
 };
 
 /// An iterator over the values of a `StableMap` in arbitrary order.
@@ -41,35 +45,72 @@ use {
 /// assert_eq!(values.next(), None);
 /// assert_eq!(values.next(), None);
 /// ```
-pub struct Values<'a, K, V> {
-    pub(crate) iter: hash_map::Values<'a, K, Pos<InUse>>,
-    pub(crate) storage: &'a LinearStorage<V>,
+pub struct Values<'a, K, V, A: Allocator = Global> {
+    pub(crate) inner: ValuesInner<'a, K, V, A>,
 }
 
-impl<'a, K, V> Iterator for Values<'a, K, V> {
+pub(crate) enum ValuesInner<'a, K, V, A: Allocator> {
+    /// Looks up the position of each value in the hash map.
+    Sparse {
+        iter: hash_table::Iter<'a, (K, Pos<InUse>)>,
+        storage: &'a LinearStorage<V, A>,
+    },
+    /// Walks the storage directly. Only used if the storage has no unused slots.
+    Dense(Slots<'a, V>),
+}
+
+impl<'a, K, V, A: Allocator> Values<'a, K, V, A> {
+    pub(crate) fn new(
+        iter: hash_table::Iter<'a, (K, Pos<InUse>)>,
+        storage: &'a LinearStorage<V, A>,
+    ) -> Self {
+        let inner = if storage.vacant_len() == 0 {
+            ValuesInner::Dense(storage.slots())
+        } else {
+            ValuesInner::Sparse { iter, storage }
+        };
+        Self { inner }
+    }
+}
+
+impl<'a, K, V, A: Allocator> Iterator for Values<'a, K, V, A> {
     type Item = &'a V;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let pos = self.iter.next()?;
-        let value = unsafe { self.storage.get_unchecked(pos) };
-        Some(value)
+        match &mut self.inner {
+            ValuesInner::Sparse { iter, storage } => {
+                let (_, pos) = iter.next()?;
+                let value = unsafe {
+                    // SAFETY:
+                    // - By the invariants of the map, pos is valid for storage.
+                    storage.get_unchecked(pos)
+                };
+                Some(value)
+            }
+            ValuesInner::Dense(slots) => slots.next(),
+        }
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
+        let len = self.len();
+        (len, Some(len))
     }
 }
 
-impl<K, V> Clone for Values<'_, K, V> {
+impl<K, V, A: Allocator> Clone for Values<'_, K, V, A> {
     fn clone(&self) -> Self {
-        Self {
-            iter: self.iter.clone(),
-            storage: self.storage,
-        }
+        let inner = match &self.inner {
+            ValuesInner::Sparse { iter, storage } => ValuesInner::Sparse {
+                iter: iter.clone(),
+                storage: *storage,
+            },
+            ValuesInner::Dense(slots) => ValuesInner::Dense(slots.clone()),
+        };
+        Self { inner }
     }
 }
 
-impl<K, V> Debug for Values<'_, K, V>
+impl<K, V, A: Allocator> Debug for Values<'_, K, V, A>
 where
     K: Debug,
     V: Debug,
@@ -79,30 +120,36 @@ where
     }
 }
 
-impl<K, V> FusedIterator for Values<'_, K, V> {}
+impl<K, V, A: Allocator> FusedIterator for Values<'_, K, V, A> {}
 
-impl<K, V> ExactSizeIterator for Values<'_, K, V> {
+impl<K, V, A: Allocator> ExactSizeIterator for Values<'_, K, V, A> {
     fn len(&self) -> usize {
-        self.iter.len()
+        match &self.inner {
+            ValuesInner::Sparse { iter, .. } => iter.len(),
+            // Since there are no unused slots, each remaining slot contains a value.
+            ValuesInner::Dense(slots) => slots.len(),
+        }
     }
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V> Send for Values<'_, K, V>
+unsafe impl<K, V, A: Allocator> Send for Values<'_, K, V, A>
 where
     K: Send,
     V: Send,
+    A: Sync,
 {
 }
 
 // SAFETY:
 // - This impl is required because Pos<InUse>, Pos<Stored> allow for conflicting access
 //   but this API prevents this.
-unsafe impl<K, V> Sync for Values<'_, K, V>
+unsafe impl<K, V, A: Allocator> Sync for Values<'_, K, V, A>
 where
     K: Sync,
     V: Sync,
+    A: Sync,
 {
 }
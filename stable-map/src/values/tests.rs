@@ -9,3 +9,23 @@ fn test() {
     values.sort();
     assert_eq!(values, [&11, &22]);
 }
+
+#[test]
+fn dense() {
+    let mut map = StableMap::new();
+    for i in 0..4 {
+        map.insert(i, i * 10);
+    }
+    let values = map.values();
+    assert_eq!(values.len(), 4);
+    assert_eq!(values.clone().collect::<Vec<_>>(), [&0, &10, &20, &30]);
+    map.remove(&1);
+    let mut values = map.values().collect::<Vec<_>>();
+    values.sort();
+    assert_eq!(values, [&0, &20, &30]);
+    map.insert(4, 40);
+    let mut values = map.values();
+    assert_eq!(values.len(), 4);
+    values.next();
+    assert_eq!(values.len(), 3);
+}
@@ -0,0 +1,78 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::pos_vec::SparseSlots,
+    core::{
+        fmt::{Debug, Formatter},
+        iter::FusedIterator,
+    },
+};
+
+/// An iterator over the values of a `StableMap` in the order of their indices, starting
+/// at a given index. The iterator element type is `(usize, &'a V)`.
+///
+/// This `struct` is created by the [`values_from_index`] method on [`StableMap`]. See its
+/// documentation for more.
+///
+/// [`values_from_index`]: crate::StableMap::values_from_index
+/// [`StableMap`]: crate::StableMap
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMap;
+///
+/// let mut map: StableMap<_, _> = [(1, "a"), (2, "b"), (3, "c")].into();
+/// map.remove(&2);
+///
+/// let mut iter = map.values_from_index(1);
+/// assert_eq!(iter.next(), Some((2, &"c")));
+///
+/// // It is fused iterator
+/// assert_eq!(iter.next(), None);
+/// assert_eq!(iter.next(), None);
+/// ```
+pub struct ValuesFromIndex<'a, V> {
+    pub(crate) index: usize,
+    pub(crate) slots: SparseSlots<'a, V>,
+}
+
+impl<'a, V> Iterator for ValuesFromIndex<'a, V> {
+    type Item = (usize, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let slot = self.slots.next()?;
+            let index = self.index;
+            self.index += 1;
+            if let Some(value) = slot {
+                return Some((index, value));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.slots.len()))
+    }
+}
+
+impl<V> Clone for ValuesFromIndex<'_, V> {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            slots: self.slots.clone(),
+        }
+    }
+}
+
+impl<V> Debug for ValuesFromIndex<'_, V>
+where
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<V> FusedIterator for ValuesFromIndex<'_, V> {}
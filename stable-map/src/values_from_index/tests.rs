@@ -0,0 +1,41 @@
+use {crate::StableMap, alloc::vec::Vec};
+
+#[test]
+fn values_from_index() {
+    let mut map = StableMap::new();
+    for i in 0..6 {
+        map.insert(i, i * 10);
+    }
+    map.remove(&1);
+    map.remove(&4);
+    assert_eq!(
+        map.values_from_index(0).collect::<Vec<_>>(),
+        [(0, &0), (2, &20), (3, &30), (5, &50)]
+    );
+    assert_eq!(
+        map.values_from_index(3).collect::<Vec<_>>(),
+        [(3, &30), (5, &50)]
+    );
+    assert_eq!(map.values_from_index(4).collect::<Vec<_>>(), [(5, &50)]);
+    assert_eq!(map.values_from_index(6).next(), None);
+    assert_eq!(map.values_from_index(usize::MAX).next(), None);
+}
+
+#[test]
+fn resume() {
+    let mut map = StableMap::new();
+    for i in 0..10 {
+        map.insert(i, i);
+    }
+    let mut visited = Vec::new();
+    let mut start = 0;
+    loop {
+        let chunk: Vec<_> = map.values_from_index(start).take(3).collect();
+        let Some(&(last, _)) = chunk.last() else {
+            break;
+        };
+        visited.extend(chunk.iter().map(|&(_, v)| *v));
+        start = last + 1;
+    }
+    assert_eq!(visited, (0..10).collect::<Vec<_>>());
+}
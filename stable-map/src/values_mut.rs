@@ -2,15 +2,19 @@
 mod tests;
 
 use {
-    crate::pos_vec::{
-        pos::{InUse, Pos},
-        PosVecRawAccess,
+    crate::{
+        linear_storage::LinearStorage,
+        pos_vec::{
+            pos::{InUse, Pos},
+            PosVecRawAccess, SlotsMut,
+        },
     },
+    allocator_api2::alloc::Allocator,
     core::{
         fmt::{Debug, Formatter},
         iter::FusedIterator,
     },
-    hashbrown::hash_map,
+    hashbrown::hash_table,
 };
 
 /// A mutable iterator over the values of a `StableMap` in arbitrary order.
@@ -41,21 +45,58 @@ use {
 /// assert_eq!(map.get(&2).unwrap(), &"Two Mississippi".to_owned());
 /// ```
 pub struct ValuesMut<'a, K, V> {
-    pub(crate) iter: hash_map::ValuesMut<'a, K, Pos<InUse>>,
-    pub(crate) storage: PosVecRawAccess<'a, V>,
+    pub(crate) inner: ValuesMutInner<'a, K, V>,
+}
+
+pub(crate) enum ValuesMutInner<'a, K, V> {
+    /// Looks up the position of each value in the hash map.
+    Sparse {
+        iter: hash_table::IterMut<'a, (K, Pos<InUse>)>,
+        storage: PosVecRawAccess<'a, V>,
+    },
+    /// Walks the storage directly. Only used if the storage has no unused slots.
+    Dense(SlotsMut<'a, V>),
+}
+
+impl<'a, K, V> ValuesMut<'a, K, V> {
+    pub(crate) fn new<A: Allocator>(
+        iter: hash_table::IterMut<'a, (K, Pos<InUse>)>,
+        storage: &'a mut LinearStorage<V, A>,
+    ) -> Self {
+        let inner = if storage.vacant_len() == 0 {
+            ValuesMutInner::Dense(storage.slots_mut())
+        } else {
+            ValuesMutInner::Sparse {
+                iter,
+                storage: storage.raw_access(),
+            }
+        };
+        Self { inner }
+    }
 }
 
 impl<'a, K, V> Iterator for ValuesMut<'a, K, V> {
     type Item = &'a mut V;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let pos = self.iter.next()?;
-        let value = unsafe { self.storage.get_unchecked_mut(pos) };
-        Some(value)
+        match &mut self.inner {
+            ValuesMutInner::Sparse { iter, storage } => {
+                let (_, pos) = iter.next()?;
+                let value = unsafe {
+                    // SAFETY:
+                    // - By the invariants of the map, pos is valid for storage.
+                    // - Each pos is yielded once by iter, so no value is borrowed twice.
+                    storage.get_unchecked_mut(pos)
+                };
+                Some(value)
+            }
+            ValuesMutInner::Dense(slots) => slots.next(),
+        }
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        self.iter.size_hint()
+        let len = self.len();
+        (len, Some(len))
     }
 }
 
@@ -69,7 +110,11 @@ impl<K, V> FusedIterator for ValuesMut<'_, K, V> {}
 
 impl<K, V> ExactSizeIterator for ValuesMut<'_, K, V> {
     fn len(&self) -> usize {
-        self.iter.len()
+        match &self.inner {
+            ValuesMutInner::Sparse { iter, .. } => iter.len(),
+            // Since there are no unused slots, each remaining slot contains a value.
+            ValuesMutInner::Dense(slots) => slots.len(),
+        }
     }
 }
 
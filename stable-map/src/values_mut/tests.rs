@@ -9,3 +9,25 @@ fn test() {
     values.sort();
     assert_eq!(values, [&mut 11, &mut 22]);
 }
+
+#[test]
+fn dense() {
+    let mut map = StableMap::new();
+    for i in 0..4 {
+        map.insert(i, i * 10);
+    }
+    let mut values = map.values_mut();
+    assert_eq!(values.len(), 4);
+    values.next();
+    assert_eq!(values.len(), 3);
+    for v in map.values_mut() {
+        *v += 1;
+    }
+    map.remove(&1);
+    for v in map.values_mut() {
+        *v += 1;
+    }
+    assert_eq!(map[&0], 2);
+    assert_eq!(map[&2], 22);
+    assert_eq!(map[&3], 32);
+}
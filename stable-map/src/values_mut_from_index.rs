@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::pos_vec::SparseSlotsMut,
+    core::{
+        fmt::{Debug, Formatter},
+        iter::FusedIterator,
+    },
+};
+
+/// A mutable iterator over the values of a `StableMap` in the order of their indices,
+/// starting at a given index. The iterator element type is `(usize, &'a mut V)`.
+///
+/// This `struct` is created by the [`values_mut_from_index`] method on [`StableMap`]. See
+/// its documentation for more.
+///
+/// [`values_mut_from_index`]: crate::StableMap::values_mut_from_index
+/// [`StableMap`]: crate::StableMap
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMap;
+///
+/// let mut map: StableMap<_, _> = [(1, 10), (2, 20), (3, 30)].into();
+/// map.remove(&2);
+///
+/// let mut iter = map.values_mut_from_index(1);
+/// iter.next().map(|(_, v)| *v += 1);
+///
+/// // It is fused iterator
+/// assert_eq!(iter.next(), None);
+/// assert_eq!(iter.next(), None);
+///
+/// assert_eq!(map[&3], 31);
+/// ```
+pub struct ValuesMutFromIndex<'a, V> {
+    pub(crate) index: usize,
+    pub(crate) slots: SparseSlotsMut<'a, V>,
+}
+
+impl<'a, V> Iterator for ValuesMutFromIndex<'a, V> {
+    type Item = (usize, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let slot = self.slots.next()?;
+            let index = self.index;
+            self.index += 1;
+            if let Some(value) = slot {
+                return Some((index, value));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.slots.len()))
+    }
+}
+
+impl<V> Debug for ValuesMutFromIndex<'_, V> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("ValuesMutFromIndex")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<V> FusedIterator for ValuesMutFromIndex<'_, V> {}
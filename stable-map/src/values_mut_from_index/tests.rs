@@ -0,0 +1,20 @@
+use {crate::StableMap, alloc::vec::Vec};
+
+#[test]
+fn values_mut_from_index() {
+    let mut map = StableMap::new();
+    for i in 0..6 {
+        map.insert(i, i * 10);
+    }
+    map.remove(&1);
+    map.remove(&4);
+    for (index, value) in map.values_mut_from_index(2) {
+        *value += index;
+    }
+    assert_eq!(
+        map.values_from_index(0).collect::<Vec<_>>(),
+        [(0, &0), (2, &22), (3, &33), (5, &55)]
+    );
+    assert_eq!(map.values_mut_from_index(6).next(), None);
+    assert_eq!(map.values_mut_from_index(usize::MAX).next(), None);
+}
@@ -0,0 +1,87 @@
+#[cfg(test)]
+mod tests;
+
+use {
+    crate::pos_vec::SparseSlots,
+    core::{
+        fmt::{Debug, Formatter},
+        iter::FusedIterator,
+    },
+};
+
+/// An iterator over the indices of a `StableMap` in ascending order.
+/// The iterator element type is `Option<&'a V>`.
+///
+/// The iterator yields one element for each index below
+/// [`index_len`](crate::StableMap::index_len). The element is `None` if the index is
+/// unused.
+///
+/// This `struct` is created by the [`values_sparse`] method on [`StableMap`]. See its
+/// documentation for more.
+///
+/// [`values_sparse`]: crate::StableMap::values_sparse
+/// [`StableMap`]: crate::StableMap
+///
+/// # Examples
+///
+/// ```
+/// use stable_map::StableMap;
+///
+/// let mut map: StableMap<_, _> = [(1, "a"), (2, "b"), (3, "c")].into();
+/// map.remove(&2);
+///
+/// let mut values = map.values_sparse();
+/// assert_eq!(values.len(), 3);
+/// assert_eq!(values.next(), Some(Some(&"a")));
+/// assert_eq!(values.next(), Some(None));
+/// assert_eq!(values.next(), Some(Some(&"c")));
+///
+/// // It is fused iterator
+/// assert_eq!(values.next(), None);
+/// assert_eq!(values.next(), None);
+/// ```
+pub struct ValuesSparse<'a, V> {
+    pub(crate) slots: SparseSlots<'a, V>,
+}
+
+impl<'a, V> Iterator for ValuesSparse<'a, V> {
+    type Item = Option<&'a V>;
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots.next()
+    }
+
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.slots.size_hint()
+    }
+}
+
+impl<V> DoubleEndedIterator for ValuesSparse<'_, V> {
+    #[cfg_attr(feature = "inline-more", inline)]
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.slots.next_back()
+    }
+}
+
+impl<V> Clone for ValuesSparse<'_, V> {
+    fn clone(&self) -> Self {
+        Self {
+            slots: self.slots.clone(),
+        }
+    }
+}
+
+impl<V> Debug for ValuesSparse<'_, V>
+where
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.clone()).finish()
+    }
+}
+
+impl<V> FusedIterator for ValuesSparse<'_, V> {}
+
+impl<V> ExactSizeIterator for ValuesSparse<'_, V> {}
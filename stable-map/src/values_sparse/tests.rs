@@ -0,0 +1,30 @@
+use {
+    crate::StableMap,
+    alloc::{format, vec::Vec},
+};
+
+#[test]
+fn values_sparse() {
+    let mut map = StableMap::new();
+    assert_eq!(map.values_sparse().len(), 0);
+    map.insert(1, 11);
+    map.insert(2, 22);
+    map.insert(3, 33);
+    map.remove(&2);
+    let values = map.values_sparse();
+    assert_eq!(values.len(), map.index_len());
+    assert_eq!(values.collect::<Vec<_>>(), [Some(&11), None, Some(&33)]);
+    assert_eq!(
+        map.values_sparse().rev().collect::<Vec<_>>(),
+        [Some(&33), None, Some(&11)]
+    );
+    assert_eq!(
+        format!("{:?}", map.values_sparse()),
+        "[Some(11), None, Some(33)]"
+    );
+    map.force_compact();
+    assert_eq!(
+        map.values_sparse().collect::<Vec<_>>(),
+        [Some(&11), Some(&33)]
+    );
+}